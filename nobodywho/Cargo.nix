@@ -109,6 +109,16 @@ rec {
         packageId = "nobodywho-uniffi";
       };
 
+      # Debug support which might change between releases.
+      # File a bug if you depend on any for non-debug work!
+      debug = internal.debugCrate { inherit packageId; };
+    };
+    "tool-macro" = rec {
+      packageId = "tool-macro";
+      build = internal.buildRustCrateWithFeatures {
+        packageId = "tool-macro";
+      };
+
       # Debug support which might change between releases.
       # File a bug if you depend on any for non-debug work!
       debug = internal.debugCrate { inherit packageId; };
@@ -9782,6 +9792,10 @@ rec {
             name = "tokio-stream";
             packageId = "tokio-stream";
           }
+          {
+            name = "tool-macro";
+            packageId = "tool-macro";
+          }
           {
             name = "tracing";
             packageId = "tracing";
@@ -16422,6 +16436,30 @@ rec {
           }
         ];
 
+      };
+      "tool-macro" = rec {
+        crateName = "tool-macro";
+        version = "0.1.0";
+        edition = "2024";
+        src = lib.cleanSourceWith { filter = sourceFilter;  src = ./tool-macro; };
+        procMacro = true;
+        libName = "tool_macro";
+        dependencies = [
+          {
+            name = "proc-macro2";
+            packageId = "proc-macro2";
+          }
+          {
+            name = "quote";
+            packageId = "quote";
+          }
+          {
+            name = "syn";
+            packageId = "syn 2.0.117";
+            features = [ "full" ];
+          }
+        ];
+
       };
       "toml 0.5.11" = rec {
         crateName = "toml";
@@ -15,40 +15,90 @@
 //!
 //! let chat = ChatBuilder::new(model)
 //!     .with_system_prompt(Some("You are a helpful assistant"))
-//!     .build();
+//!     .build()?;
 //!
 //! let response = chat.ask("Hello!").completed()?;
 //! # Ok(())
 //! # }
 //! ```
 //!
+//! # Async
+//!
+//! [`ChatHandle`] blocks the calling thread, which must not happen inside an async runtime.
+//! For async applications, e.g. a web server built on tokio and axum, build a
+//! [`ChatHandleAsync`] instead. It has the same methods, with the ones that wait for the
+//! worker being `async`, and runs on the same kind of background worker thread, so awaiting
+//! never blocks the runtime. The handle is cheap to clone; all clones share one conversation,
+//! and messages from them are handled in the order they arrive.
+//!
+//! ```
+//! use nobodywho::chat::ChatBuilder;
+//! use nobodywho::llm;
+//! use std::sync::Arc;
+//!
+//! # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+//! let model = Arc::new(llm::get_model("model.gguf", true, None, None, None)?);
+//!
+//! let chat = ChatBuilder::new(model)
+//!     .with_system_prompt(Some("You are a helpful assistant"))
+//!     .build_async()?;
+//!
+//! let mut stream = chat.ask("Hello!");
+//! while let Some(token) = stream.next_token().await? {
+//!     print!("{token}");
+//! }
+//!
+//! let history = chat.get_chat_history().await?;
+//! chat.reset_history().await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
 
+use crate::completion::StopWordFilter;
 use crate::errors::{
     ChatWorkerError, ContextSyncError, GenerateResponseError, InitWorkerError, MultimodalError,
-    RenderError, SayError, SelectTemplateError, SetToolsError, ShiftError, TokenizeError,
-    WrappedResponseError,
+    ReadError, RenderError, RenderPromptError, SayError, SelectTemplateError, SetToolsError,
+    ShiftError, TokenizeError, WarmupError, WrappedResponseError,
 };
 use crate::inference::{acquire_inference_lock, InferenceEngine};
 use crate::llm;
-use crate::llm::{GlobalInferenceLockToken, Worker, WorkerGuard, WriteOutput};
+use crate::llm::{ContextOptions, GlobalInferenceLockToken, Worker, WorkerGuard, WriteOutput};
 use crate::sampler::read_sampler_from_metadata;
 use crate::sampler::{SamplerConfig, ShiftStep};
 use crate::template::{select_template, ChatTemplate, ChatTemplateContext};
-use crate::tokenizer::{ChunkId, Prompt, PromptPart, Promptable, TokenizerChunk, TokenizerChunks};
-use crate::tool_calling::{detect_tool_format, Tool, ToolCall, ToolFormat};
+use crate::tokenizer::{
+    break_special_tokens, special_token_texts, token_to_piece_bytes, ChunkId, Prompt, PromptPart,
+    Promptable, TokenizerChunk, TokenizerChunks, TOKEN_BREAK,
+};
+use crate::tool_calling::{detect_tool_format, Tool, ToolCall, ToolError, ToolFormat};
 use ahash::AHasher;
 use indexmap::IndexMap;
+use llama_cpp_2::model::AddBos;
 use llama_cpp_2::mtmd::MtmdBitmap;
 use llama_cpp_2::token::LlamaToken;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::borrow::Cow;
 use std::cmp::min;
 use std::collections::HashSet;
 use std::fmt;
 use std::hash::Hasher;
 use std::path::PathBuf;
+use std::str::FromStr;
 use std::sync::atomic::AtomicBool;
 use std::sync::{Arc, MutexGuard};
-use tracing::{debug, error, info, trace};
+use tracing::{debug, error, info, trace, warn};
+
+/// Tool response given in place of running the tools once [`ChatConfig::max_tool_iterations`]
+/// is exceeded.
+const TOOL_CALL_LIMIT_MESSAGE: &str =
+    "Tool call limit reached. Answer with the information you already have.";
+
+/// Instruction the model gets in front of the transcript it summarizes for
+/// [`ContextPolicy::Summarize`].
+const SUMMARIZE_INSTRUCTION: &str = "Summarize the following conversation. Keep names, facts, \
+    decisions and open threads, so the conversation can go on from the summary alone. \
+    Answer with the summary only.";
 
 #[derive(Deserialize, Serialize, Clone, PartialEq, Eq, Debug, Hash)]
 pub struct Asset {
@@ -116,6 +166,10 @@ pub enum Message {
     // https://github.com/QwenLM/Qwen3/blob/e5a1d326/docs/source/framework/function_call.md
     Assistant {
         content: String,
+        // The thinking before the answer, split off from content. Rendered back in
+        // front of the content for the template, unless dropped (see ChatConfig::keep_thinking).
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        reasoning_content: Option<String>,
         #[serde(default, skip_serializing_if = "Option::is_none")]
         tool_calls: Option<Vec<ToolCall>>,
     },
@@ -181,10 +235,34 @@ impl Message {
     pub fn new_assistant(content: String) -> Self {
         Self::Assistant {
             content,
+            reasoning_content: None,
             tool_calls: None,
         }
     }
 
+    /// An assistant message for a generated `response`, with a leading
+    /// `<think>...</think>` block split off into `reasoning_content`.
+    pub fn from_response(response: String) -> Self {
+        match split_thinking(&response) {
+            (Some(thinking), answer) => Self::Assistant {
+                content: answer.to_string(),
+                reasoning_content: Some(thinking.to_string()),
+                tool_calls: None,
+            },
+            (None, _) => Self::new_assistant(response),
+        }
+    }
+
+    /// The thinking of an assistant message, if any was split off. See [`Message::from_response`].
+    pub fn reasoning_content(&self) -> Option<&str> {
+        match self {
+            Message::Assistant {
+                reasoning_content, ..
+            } => reasoning_content.as_deref(),
+            _ => None,
+        }
+    }
+
     pub fn new_system(content: String) -> Self {
         Self::System { content }
     }
@@ -230,6 +308,17 @@ pub struct ChatConfig {
     pub n_ctx: u32,
     /// System prompt for the chat session.
     pub system_prompt: Option<String>,
+    /// Static first part of the system prompt, e.g. lore that never changes.
+    /// When this or [`system_suffix`](Self::system_suffix) is set, the system prompt is the
+    /// prefix followed by the suffix, and [`system_prompt`](Self::system_prompt) is ignored.
+    ///
+    /// The prefix is tokenized on its own, so replacing the suffix with
+    /// [`ChatHandle::set_system_suffix`] keeps the prefix in the KV cache and only reads
+    /// the context again from the boundary on.
+    pub system_prefix: Option<String>,
+    /// Changing last part of the system prompt, e.g. the current world state.
+    /// See [`system_prefix`](Self::system_prefix).
+    pub system_suffix: Option<String>,
     /// Variables to add to the chat template context.
     pub template_variables: std::collections::HashMap<String, bool>,
     /// Sampler configuration for inference.
@@ -240,21 +329,270 @@ pub struct ChatConfig {
     /// (see `llm::get_model`) — otherwise worker construction fails with
     /// `InitWorkerError::MtpDraftModelNotLoaded`.
     pub mtp: Option<MtpConfig>,
+    /// Number of leading tokens that are never discarded when the context fills up.
+    /// The system prompt and the first user message are always kept; messages starting
+    /// within the first `n_keep` tokens are kept as well.
+    pub n_keep: u32,
+    /// Called with the number of removed messages whenever a context shift discards
+    /// old history, or [`ContextPolicy::Summarize`] replaces it with a summary, so the
+    /// application can let the user know.
+    pub on_context_shift: Option<ContextShiftObserver>,
+    /// Called with every tool call the model makes, right before the tool runs.
+    pub on_tool_call: Option<ToolCallObserver>,
+    /// Maximum rounds of tool calls in a single turn. When the model still calls tools after
+    /// that, the calls are answered with "tool call limit reached" instead of running, and the
+    /// model has to give its final answer without tools. Defaults to 10.
+    pub max_tool_iterations: u32,
+    /// Tool calling format to use instead of detecting one from the model.
+    pub tool_format: Option<ToolFormat>,
+    /// Options for the llama.cpp context, such as the number of CPU threads.
+    pub context_options: ContextOptions,
+    /// Whether to add a BOS token at the start of the rendered chat.
+    /// `None` (the default) follows the `tokenizer.ggml.add_bos_token` GGUF metadata.
+    /// Some conversions get this wrong, which shows up as garbage in the first tokens.
+    pub add_bos: Option<bool>,
+    /// Whether text in user messages that spells out a special token (e.g. `<|im_end|>`)
+    /// is read as that token. Defaults to `true`. Turn it off when user content is
+    /// untrusted, so it can't end its own message or fake other roles.
+    pub parse_special_in_user_content: bool,
+    /// Chat template to use instead of the one embedded in the model.
+    /// Without an override, models that have no chat template get a generic ChatML template.
+    pub template_override: Option<ChatTemplate>,
+    /// Whether the model re-reads its thinking from earlier turns. Defaults to `false`, since
+    /// old thinking takes up context and can confuse the model. Thinking since the last user
+    /// message, e.g. between tool calls, is always kept. Either way it stays in the chat
+    /// history as `reasoning_content`. Note that some chat templates (e.g. Qwen3's) drop old
+    /// thinking themselves, even when this is `true`.
+    pub keep_thinking: bool,
+    /// What to do when the chat no longer fits in the context. Defaults to
+    /// [`ContextPolicy::Summarize`].
+    pub context_policy: ContextPolicy,
+}
+
+/// What the chat does when its history grows too long for the context.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ContextPolicy {
+    /// Fail with [`ShiftError::ContextFull`] instead of removing anything from the history.
+    Error,
+    /// Drop the oldest turns until the chat takes up half the context.
+    ///
+    /// A turn is a user message with everything up to the next user message: the response,
+    /// tool calls and tool responses. Turns are only ever dropped whole, oldest first, so
+    /// no message is cut short and the history still alternates between user and assistant.
+    /// Never dropped are the system prompt, the first turn, turns starting within the first
+    /// [`ChatConfig::n_keep`] tokens, and the last two turns. If dropping every other turn
+    /// doesn't make enough room, only those are left.
+    Shift,
+    /// Once the chat takes up more than `trigger_ratio` of the context when a turn starts,
+    /// the same model summarizes everything before the last `keep_recent` user turns, and
+    /// the summary replaces those messages as a single system message right after the
+    /// system prompt. Older summaries are folded into the new one.
+    ///
+    /// Summarizing generates a whole extra response before the turn, and afterwards the
+    /// chat is read again from the system prompt on, so the turn that triggers it takes
+    /// much longer than usual. Falls back to shifting if the context still fills up.
+    ///
+    /// The summary is a second system message, which some chat templates don't allow.
+    ///
+    /// This is the default, with a `trigger_ratio` of 0.8 and a `keep_recent` of 2.
+    Summarize {
+        /// Fraction of the context, above 0 and at most 1, above which older turns are
+        /// summarized.
+        trigger_ratio: f32,
+        /// Number of most recent user turns that are kept word for word. At least the
+        /// current turn is always kept.
+        keep_recent: usize,
+    },
+}
+
+impl Default for ContextPolicy {
+    fn default() -> Self {
+        ContextPolicy::Summarize {
+            trigger_ratio: 0.8,
+            keep_recent: 2,
+        }
+    }
+}
+
+/// Split a response into its thinking and its answer, at the first `</think>`.
+/// The opening `<think>` is optional, since some chat templates put it in the prompt.
+pub fn split_thinking(response: &str) -> (Option<&str>, &str) {
+    match response.split_once("</think>") {
+        Some((thinking, answer)) => {
+            let thinking = thinking.trim_start();
+            let thinking = thinking.strip_prefix("<think>").unwrap_or(thinking);
+            (Some(thinking.trim()), answer.trim_start())
+        }
+        None => (None, response),
+    }
+}
+
+/// `messages` written out as plain text, for the model to summarize.
+fn summary_transcript(messages: &[Message]) -> String {
+    messages
+        .iter()
+        .map(|msg| match msg {
+            Message::System { content } => format!("Earlier summary: {content}"),
+            Message::User { content, .. } => format!("User: {content}"),
+            Message::Assistant {
+                tool_calls: Some(tool_calls),
+                ..
+            } => {
+                let names: Vec<&str> = tool_calls.iter().map(|c| c.name.as_str()).collect();
+                format!("Assistant called tools: {}", names.join(", "))
+            }
+            Message::Assistant { content, .. } => format!("Assistant: {content}"),
+            Message::Tool { name, content } => format!("Tool {name}: {content}"),
+        })
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+/// Put `thinking` back in front of `answer`, the inverse of [`split_thinking`].
+pub fn join_thinking(thinking: &str, answer: &str) -> String {
+    format!("<think>\n{thinking}\n</think>\n\n{answer}")
+}
+
+/// How [`display_history`] shows tool calls and their responses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ToolCallDisplay {
+    /// Leave tool calls and tool responses out.
+    #[default]
+    Omit,
+    /// Replace each round of tool calls and their responses with one assistant message,
+    /// with a line like `Called get_weather({"city":"Paris"}): 18 degrees` per call.
+    Collapse,
+}
+
+impl FromStr for ToolCallDisplay {
+    type Err = ();
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().as_str() {
+            "omit" => Ok(Self::Omit),
+            "collapse" => Ok(Self::Collapse),
+            _ => Err(()),
+        }
+    }
+}
+
+/// A chat history for showing to people: the messages, with tool calls and tool responses
+/// omitted or collapsed. The raw history is what should be passed to `set_chat_history`.
+pub fn display_history(messages: &[Message], tool_calls: ToolCallDisplay) -> Vec<Message> {
+    let mut displayed = Vec::with_capacity(messages.len());
+    let mut i = 0;
+    while i < messages.len() {
+        match &messages[i] {
+            Message::Assistant {
+                content,
+                reasoning_content,
+                tool_calls: Some(calls),
+            } => {
+                let n_responses = messages[i + 1..]
+                    .iter()
+                    .take_while(|msg| msg.is_tool())
+                    .count();
+                // text the model wrote along with the calls is kept either way
+                let mut lines: Vec<String> = vec![];
+                if !content.trim().is_empty() {
+                    lines.push(content.clone());
+                }
+                if tool_calls == ToolCallDisplay::Collapse {
+                    let responses = &messages[i + 1..i + 1 + n_responses];
+                    lines.extend(calls.iter().enumerate().map(
+                        |(j, call)| match responses.get(j) {
+                            Some(response) => format!(
+                                "Called {}({}): {}",
+                                call.name,
+                                call.arguments,
+                                response.content()
+                            ),
+                            None => format!("Called {}({})", call.name, call.arguments),
+                        },
+                    ));
+                }
+                if !lines.is_empty() {
+                    displayed.push(Message::Assistant {
+                        content: lines.join("\n"),
+                        reasoning_content: reasoning_content.clone(),
+                        tool_calls: None,
+                    });
+                }
+                i += 1 + n_responses;
+            }
+            Message::Tool { .. } => i += 1,
+            msg => {
+                displayed.push(msg.clone());
+                i += 1;
+            }
+        }
+    }
+    displayed
 }
 
+/// Callback invoked with the number of messages dropped by a context shift.
+pub type ContextShiftObserver = Arc<dyn Fn(usize) + Send + Sync>;
+
+/// Callback invoked with each tool call just before the tool is called.
+pub type ToolCallObserver = Arc<dyn Fn(&ToolCall) + Send + Sync>;
+
 impl Default for ChatConfig {
     fn default() -> Self {
         Self {
             n_ctx: 4096,
             template_variables: std::collections::HashMap::new(),
             system_prompt: None,
+            system_prefix: None,
+            system_suffix: None,
             tools: Vec::new(),
             sampler_config: None,
             mtp: None,
+            n_keep: 0,
+            on_context_shift: None,
+            on_tool_call: None,
+            max_tool_iterations: 10,
+            tool_format: None,
+            context_options: ContextOptions::default(),
+            add_bos: None,
+            parse_special_in_user_content: true,
+            template_override: None,
+            keep_thinking: false,
+            context_policy: ContextPolicy::default(),
         }
     }
 }
 
+/// Options that only apply to a single `ask` turn.
+#[derive(Debug, Clone, Default)]
+pub struct AskOptions {
+    /// Text the assistant's reply continues from, as if the model had already generated it.
+    /// The completed response includes it.
+    pub assistant_prefix: Option<String>,
+    /// Maximum wall-clock time for the turn. When exceeded, generation stops, the partial
+    /// response is kept in the chat history and emitted as usual, preceded by a
+    /// [`SayError::Timeout`] error on the stream.
+    pub timeout: Option<std::time::Duration>,
+    /// Sampler to use for this turn instead of the chat's sampler, which is left untouched.
+    pub sampler_config: Option<SamplerConfig>,
+    /// Grammar constraining the reply for this turn only. It is added on top of the turn's
+    /// sampler, so it combines with [`sampler_config`](Self::sampler_config).
+    pub grammar: Option<gbnf::GbnfGrammar>,
+    /// Text that switches [`grammar`](Self::grammar) on. Until the model generates it, the
+    /// reply is free-form, which allows e.g. some reasoning before a structured answer.
+    /// The grammar has to match the trigger itself too. Without a trigger the grammar applies
+    /// from the first token.
+    pub grammar_trigger: Option<String>,
+    /// Generation stops right before the first occurrence of any of these strings. The stop
+    /// word itself is not part of the response.
+    pub stop_words: Vec<String>,
+    /// Maximum number of tokens to generate for this turn. `None` means no limit.
+    pub max_tokens: Option<u32>,
+    /// RNG seed for this turn instead of the sampler's [`seed`](SamplerConfig::seed), so a
+    /// turn can be reproduced without changing the sampler. With the same seed, sampler,
+    /// history and prompt, the turn generates the same tokens.
+    pub seed: Option<u32>,
+}
+
 /// Builder for creating a [`ChatHandle`] with a fluent API.
 ///
 /// # Example
@@ -278,7 +616,7 @@ impl Default for ChatConfig {
 ///     .with_context_size(4096)
 ///     .with_system_prompt(Some("You're a helpful assistant"))
 ///     .with_tool(my_tool)
-///     .build();
+///     .build()?;
 /// # Ok(())
 /// # }
 /// ```
@@ -296,6 +634,15 @@ impl ChatBuilder {
         }
     }
 
+    /// Create a new chat builder with the model registered as `name`, shared with the other
+    /// handles using it. See [`ModelRegistry`](crate::model_registry::ModelRegistry).
+    pub fn from_registry(
+        registry: &crate::model_registry::ModelRegistry,
+        name: &str,
+    ) -> Result<Self, crate::errors::ModelRegistryError> {
+        Ok(Self::new(registry.require(name)?))
+    }
+
     /// Set the context size for the chat session.
     pub fn with_context_size(mut self, n_ctx: u32) -> Self {
         self.config.n_ctx = n_ctx;
@@ -308,6 +655,18 @@ impl ChatBuilder {
         self
     }
 
+    /// Split the system prompt into a static prefix and a changing suffix.
+    /// See [`ChatConfig::system_prefix`].
+    pub fn with_system_prompt_parts(
+        mut self,
+        prefix: impl Into<String>,
+        suffix: impl Into<String>,
+    ) -> Self {
+        self.config.system_prefix = Some(prefix.into());
+        self.config.system_suffix = Some(suffix.into());
+        self
+    }
+
     /// Add a tool that the model can use.
     pub fn with_tool(mut self, tool: Tool) -> Self {
         self.config.tools.push(tool);
@@ -359,6 +718,79 @@ impl ChatBuilder {
         self
     }
 
+    /// Always keep the messages starting within the first `n_keep` tokens when shifting context.
+    pub fn with_n_keep(mut self, n_keep: u32) -> Self {
+        self.config.n_keep = n_keep;
+        self
+    }
+
+    /// Get notified with the number of dropped messages whenever a context shift happens.
+    pub fn with_context_shift_observer(mut self, observer: ContextShiftObserver) -> Self {
+        self.config.on_context_shift = Some(observer);
+        self
+    }
+
+    /// Get notified of every tool call, e.g. for logging, without wrapping each tool.
+    /// The observer runs right before the tool and doesn't affect its execution.
+    pub fn with_tool_observer(mut self, observer: ToolCallObserver) -> Self {
+        self.config.on_tool_call = Some(observer);
+        self
+    }
+
+    /// Limit the rounds of tool calls in a single turn. See [`ChatConfig::max_tool_iterations`].
+    pub fn with_max_tool_iterations(mut self, max_tool_iterations: u32) -> Self {
+        self.config.max_tool_iterations = max_tool_iterations;
+        self
+    }
+
+    /// Use `format` for tool calls instead of detecting it from the model's chat template and
+    /// metadata. See [`supported_tool_formats`](crate::tool_calling::supported_tool_formats).
+    pub fn with_tool_format(mut self, format: ToolFormat) -> Self {
+        self.config.tool_format = Some(format);
+        self
+    }
+
+    /// Set options for the llama.cpp context, such as the number of CPU threads.
+    pub fn with_context_options(mut self, options: ContextOptions) -> Self {
+        self.config.context_options = options;
+        self
+    }
+
+    /// Add a BOS token at the start of the rendered chat, or not, regardless of the model's
+    /// metadata. See [`ChatConfig::add_bos`].
+    pub fn with_add_bos(mut self, add_bos: bool) -> Self {
+        self.config.add_bos = Some(add_bos);
+        self
+    }
+
+    /// Set whether special tokens spelled out in user messages are read as special tokens.
+    /// See [`ChatConfig::parse_special_in_user_content`].
+    pub fn with_special_tokens_in_user_content(mut self, parse_special: bool) -> Self {
+        self.config.parse_special_in_user_content = parse_special;
+        self
+    }
+
+    /// Use `template` instead of the model's own chat template, e.g. for models that don't
+    /// have one. See [`ChatConfig::template_override`].
+    pub fn with_template_override(mut self, template: ChatTemplate) -> Self {
+        self.config.template_override = Some(template);
+        self
+    }
+
+    /// Set whether the model re-reads its thinking from earlier turns.
+    /// See [`ChatConfig::keep_thinking`].
+    pub fn with_keep_thinking(mut self, keep_thinking: bool) -> Self {
+        self.config.keep_thinking = keep_thinking;
+        self
+    }
+
+    /// Set what happens when the chat no longer fits in the context.
+    /// See [`ContextPolicy`].
+    pub fn with_context_policy(mut self, context_policy: ContextPolicy) -> Self {
+        self.config.context_policy = context_policy;
+        self
+    }
+
     /// Build a blocking chat handle and start the background worker.
     pub fn build(self) -> Result<ChatHandle, InitWorkerError> {
         ChatHandle::new(self.model, self.config)
@@ -373,8 +805,12 @@ impl ChatBuilder {
 /// Interact with a ChatWorker in a blocking manner.
 ///
 /// Use [`ChatBuilder`] to create a new instance with a fluent API.
+///
+/// Dropping the handle stops any generation in progress and waits for the worker thread
+/// to exit.
 pub struct ChatHandle {
     guard: WorkerGuard<ChatMsg>,
+    cancel_requested: Arc<AtomicBool>,
 }
 
 impl ChatHandle {
@@ -385,6 +821,8 @@ impl ChatHandle {
 
         let should_stop = Arc::new(AtomicBool::new(false));
         let should_stop_clone = Arc::clone(&should_stop);
+        let cancel_requested = Arc::new(AtomicBool::new(false));
+        let cancel_requested_clone = Arc::clone(&cancel_requested);
 
         let join_handle = std::thread::spawn(move || {
             let worker = Chat::new_chat_worker(&model, config, should_stop_clone);
@@ -398,6 +836,7 @@ impl ChatHandle {
                     return;
                 }
             };
+            worker_state.cancel_requested = cancel_requested_clone;
 
             while let Ok(msg) = msg_rx.recv() {
                 if let Err(e) = process_worker_msg(&mut worker_state, msg) {
@@ -410,9 +849,17 @@ impl ChatHandle {
 
         Ok(Self {
             guard: WorkerGuard::new(msg_tx, join_handle, Some(should_stop)),
+            cancel_requested,
         })
     }
 
+    /// Whether the worker thread is still running. A worker that crashed fails every
+    /// following request with [`SayError::WorkerDead`], so a supervisor can check this and
+    /// replace the handle.
+    pub fn is_alive(&self) -> bool {
+        self.guard.is_alive()
+    }
+
     /// Send a message and get a tokio channel
     /// TODO: deprecate this in favor of plain `ask` once integrations are updated
     pub fn ask_channel(
@@ -420,7 +867,11 @@ impl ChatHandle {
         prompt: Prompt,
     ) -> tokio::sync::mpsc::UnboundedReceiver<llm::WriteOutput> {
         let (output_tx, output_rx) = tokio::sync::mpsc::unbounded_channel();
-        self.guard.send(ChatMsg::Ask { prompt, output_tx });
+        self.guard.send(ChatMsg::Ask {
+            prompt,
+            options: AskOptions::default(),
+            output_tx,
+        });
         output_rx
     }
 
@@ -428,18 +879,214 @@ impl ChatHandle {
     ///
     /// # Example
     /// ```
-    /// # use nobodywho::chat::ChatHandleAsync;
-    /// # async fn example(chat: &ChatHandleAsync) {
+    /// # use nobodywho::chat::ChatHandle;
+    /// # fn example(chat: &ChatHandle) -> Result<(), nobodywho::errors::CompletionError> {
     /// let mut stream = chat.ask("Tell me a story");
-    /// while let Some(token) = stream.next_token().await {
+    /// while let Some(token) = stream.next_token()? {
     ///     print!("{}", token);
     /// }
+    /// let full_response = stream.completed()?;
+    /// # Ok(())
     /// # }
     /// ```
     pub fn ask(&self, prompt: impl Promptable) -> TokenStream {
         TokenStream::new(forward_write_output(self.ask_channel(prompt.to_prompt())))
     }
 
+    /// Send a message and let the assistant's reply continue from `assistant_prefix`.
+    ///
+    /// The prefix is placed right after the assistant turn header, as if the model had
+    /// already generated it. It is emitted as the first token of the stream, and the
+    /// completed response (and the message stored in the chat history) includes it.
+    pub fn ask_with_prefill(
+        &self,
+        prompt: impl Promptable,
+        assistant_prefix: impl Into<String>,
+    ) -> TokenStream {
+        self.ask_with_options(
+            prompt,
+            AskOptions {
+                assistant_prefix: Some(assistant_prefix.into()),
+                ..Default::default()
+            },
+        )
+    }
+
+    /// Send a message and constrain only this reply with `grammar`, e.g. to force a yes/no
+    /// answer. The chat's sampler is left untouched.
+    pub fn ask_with_grammar(
+        &self,
+        prompt: impl Promptable,
+        grammar: gbnf::GbnfGrammar,
+    ) -> TokenStream {
+        self.ask_with_options(
+            prompt,
+            AskOptions {
+                grammar: Some(grammar),
+                ..Default::default()
+            },
+        )
+    }
+
+    /// Send a message with options that only apply to this turn. See [`AskOptions`].
+    pub fn ask_with_options(&self, prompt: impl Promptable, options: AskOptions) -> TokenStream {
+        let (output_tx, output_rx) = tokio::sync::mpsc::unbounded_channel();
+        self.guard.send(ChatMsg::Ask {
+            prompt: prompt.to_prompt(),
+            options,
+            output_tx,
+        });
+        TokenStream::new(forward_write_output(output_rx))
+    }
+
+    /// Answer several independent prompts, each against the current chat history, e.g.
+    /// lines for many NPCs that share one system prompt. Returns one stream per prompt.
+    ///
+    /// The prompts and their responses are not added to the chat history. They are
+    /// generated one after another, and each one branches off the history in the KV cache,
+    /// so the shared history is only read once instead of once per prompt. No extra
+    /// context memory is needed for this.
+    ///
+    /// [`stop_generation`](Self::stop_generation) stops the current prompt and skips the
+    /// rest; their streams finish with an empty response.
+    ///
+    /// ```
+    /// # use nobodywho::chat::ChatHandle;
+    /// # fn example(chat: &ChatHandle) -> Result<(), nobodywho::errors::CompletionError> {
+    /// let streams = chat.ask_many(vec!["Greet the player.", "Insult the player."]);
+    /// for mut stream in streams {
+    ///     println!("{}", stream.completed()?);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn ask_many(&self, prompts: Vec<impl Promptable>) -> Vec<TokenStream> {
+        let (branches, streams) = prompts
+            .into_iter()
+            .map(|prompt| {
+                let (output_tx, output_rx) = tokio::sync::mpsc::unbounded_channel();
+                (
+                    (prompt.to_prompt(), output_tx),
+                    TokenStream::new(forward_write_output(output_rx)),
+                )
+            })
+            .unzip();
+        self.guard.send(ChatMsg::AskMany { branches });
+        streams
+    }
+
+    /// Generate a new response to the last user message, replacing the previous one along
+    /// with any tool calls it made. `sampler` is used for this response only; `None` uses
+    /// the chat's sampler.
+    pub fn regenerate(&self, sampler: Option<SamplerConfig>) -> TokenStream {
+        TokenStream::new(forward_write_output(self.regenerate_channel(sampler)))
+    }
+
+    /// Like [`regenerate`](Self::regenerate), but returns the raw output channel.
+    pub fn regenerate_channel(
+        &self,
+        sampler: Option<SamplerConfig>,
+    ) -> tokio::sync::mpsc::UnboundedReceiver<llm::WriteOutput> {
+        let (output_tx, output_rx) = tokio::sync::mpsc::unbounded_channel();
+        self.guard.send(ChatMsg::Regenerate {
+            options: AskOptions {
+                sampler_config: sampler,
+                ..Default::default()
+            },
+            output_tx,
+        });
+        output_rx
+    }
+
+    /// Continue the last response where it stopped after reaching its `max_tokens` budget.
+    ///
+    /// The stream only yields the newly generated text, which is appended to the same
+    /// assistant message in the chat history. `max_tokens` caps the length of the new text;
+    /// if it is reached again, the response can be continued again. Fails with
+    /// [`SayError::NothingToContinue`] if the last response didn't stop on its budget.
+    pub fn continue_generation(&self, max_tokens: Option<u32>) -> TokenStream {
+        TokenStream::new(forward_write_output(
+            self.continue_generation_channel(max_tokens),
+        ))
+    }
+
+    /// Like [`continue_generation`](Self::continue_generation), but returns the raw output
+    /// channel.
+    pub fn continue_generation_channel(
+        &self,
+        max_tokens: Option<u32>,
+    ) -> tokio::sync::mpsc::UnboundedReceiver<llm::WriteOutput> {
+        let (output_tx, output_rx) = tokio::sync::mpsc::unbounded_channel();
+        self.guard.send(ChatMsg::ContinueGeneration {
+            options: AskOptions {
+                max_tokens,
+                ..Default::default()
+            },
+            output_tx,
+        });
+        output_rx
+    }
+
+    /// Replace the last user message with `prompt` and generate a response to it. The old
+    /// user message and everything after it is removed from the chat history.
+    pub fn edit_last_user_message(&self, prompt: impl Promptable) -> TokenStream {
+        TokenStream::new(forward_write_output(
+            self.edit_last_user_message_channel(prompt),
+        ))
+    }
+
+    /// Like [`edit_last_user_message`](Self::edit_last_user_message), but returns the raw
+    /// output channel.
+    pub fn edit_last_user_message_channel(
+        &self,
+        prompt: impl Promptable,
+    ) -> tokio::sync::mpsc::UnboundedReceiver<llm::WriteOutput> {
+        let (output_tx, output_rx) = tokio::sync::mpsc::unbounded_channel();
+        self.guard.send(ChatMsg::EditLastUserMessage {
+            prompt: prompt.to_prompt(),
+            options: AskOptions::default(),
+            output_tx,
+        });
+        output_rx
+    }
+
+    /// Send a message and receive the generated tokens interleaved with tool call events.
+    ///
+    /// Unlike [`ask`](Self::ask), which hides tool activity, this reports every tool call
+    /// before it runs and its response once it has finished.
+    pub fn ask_events(
+        &self,
+        prompt: impl Promptable,
+        options: AskOptions,
+    ) -> tokio::sync::mpsc::UnboundedReceiver<ChatEvent> {
+        let (output_tx, output_rx) = tokio::sync::mpsc::unbounded_channel();
+        self.guard.send(ChatMsg::AskEvents {
+            prompt: prompt.to_prompt(),
+            options,
+            output_tx,
+        });
+        output_rx
+    }
+
+    /// Send a message and wait for the whole turn: the final response along with every
+    /// tool call the model made on the way and what each tool returned.
+    ///
+    /// Useful for debugging agent behavior, where the final text alone doesn't show how
+    /// the model got there.
+    pub fn ask_traced(
+        &self,
+        prompt: impl Promptable,
+    ) -> Result<TurnTrace, crate::errors::CompletionError> {
+        let mut events = self.ask_events(prompt, AskOptions::default());
+        let mut trace = TurnTrace::default();
+        while let Some(event) = events.blocking_recv() {
+            if let Some(result) = trace.record(event) {
+                return result.map(|()| trace);
+            }
+        }
+        Err(crate::errors::CompletionError::WorkerCrashed)
+    }
+
     fn set_and_wait_blocking<F>(&self, make_msg: F) -> Option<()>
     where
         F: FnOnce(tokio::sync::mpsc::Sender<()>) -> ChatMsg,
@@ -555,10 +1202,29 @@ impl ChatHandle {
     }
 
     /// Stop the current generation if one is in progress.
+    ///
+    /// This also works while the prompt is still being read into the context. In that case
+    /// the half-read prompt is rolled back out of the context and the unanswered user message
+    /// is removed from the chat history, so the next `ask` starts clean. The response stream
+    /// then finishes with an empty response.
     pub fn stop_generation(&self) {
         self.guard.stop();
     }
 
+    /// Stop the current generation like [`stop_generation`](Self::stop_generation), and
+    /// also remove the stopped turn from the chat history, as if it never happened: the user
+    /// message and whatever the assistant had generated so far are both dropped. The response
+    /// stream still finishes as usual.
+    ///
+    /// If the worker hasn't started on a queued request yet, that request is cancelled once
+    /// it starts. If nothing is running or queued, this does nothing.
+    pub fn cancel_generation(&self) {
+        self.cancel_requested
+            .store(true, std::sync::atomic::Ordering::Relaxed);
+        self.guard.stop();
+        self.guard.send(ChatMsg::ClearCancel);
+    }
+
     /// Get the chat history without the system prompt (lower-level API).
     pub fn get_chat_history(&self) -> Result<Vec<Message>, crate::errors::GetterError> {
         let (output_tx, mut output_rx) = tokio::sync::mpsc::channel(1);
@@ -570,6 +1236,15 @@ impl ChatHandle {
             ))
     }
 
+    /// Get the chat history without the system prompt, for showing in a chat UI.
+    /// Tool calls and their responses are omitted or collapsed, see [`display_history`].
+    pub fn get_display_history(
+        &self,
+        tool_calls: ToolCallDisplay,
+    ) -> Result<Vec<Message>, crate::errors::GetterError> {
+        Ok(display_history(&self.get_chat_history()?, tool_calls))
+    }
+
     /// Set the chat history (lower-level API).
     pub fn set_chat_history(
         &self,
@@ -639,7 +1314,7 @@ impl ChatHandle {
     /// # use nobodywho::llm::get_model;
     /// # use std::sync::Arc;
     /// # let model = Arc::new(get_model("model.gguf", true, None, None, None).unwrap());
-    /// # let chat = ChatBuilder::new(model).build();
+    /// # let chat = ChatBuilder::new(model).build().unwrap();
     /// chat.set_system_prompt(Some("You are a helpful coding assistant.".to_string()))?;
     /// # Ok::<(), nobodywho::errors::SetterError>(())
     /// ```
@@ -656,6 +1331,16 @@ impl ChatHandle {
         ))
     }
 
+    /// Replace the part of the system prompt after the static prefix set with
+    /// [`ChatBuilder::with_system_prompt_parts`]. Only the context from the end of the
+    /// prefix on is read again.
+    pub fn set_system_suffix(&self, suffix: String) -> Result<(), crate::errors::SetterError> {
+        self.set_and_wait_blocking(|output_tx| ChatMsg::SetSystemSuffix { suffix, output_tx })
+            .ok_or(crate::errors::SetterError::SetterError(
+                "set_system_suffix".into(),
+            ))
+    }
+
     /// Get the system prompt
     pub fn get_system_prompt(&self) -> Result<Option<String>, crate::errors::GetterError> {
         let (output_tx, mut output_rx) = tokio::sync::mpsc::channel(1);
@@ -667,6 +1352,18 @@ impl ChatHandle {
             ))
     }
 
+    /// Get the content of the most recent assistant reply, or `None` if there is none yet.
+    /// While a reply is being generated, this waits for it to finish.
+    pub fn last_response(&self) -> Result<Option<String>, crate::errors::GetterError> {
+        let (output_tx, mut output_rx) = tokio::sync::mpsc::channel(1);
+        self.guard.send(ChatMsg::GetLastResponse { output_tx });
+        output_rx
+            .blocking_recv()
+            .ok_or(crate::errors::GetterError::GetterError(
+                "last_response".into(),
+            ))
+    }
+
     /// Tokenize a prompt and return token IDs. Text tokens are `Some(id)`, media embedding
     /// slots are `None` (one per slot consumed in the context window).
     pub fn tokenize(&self, prompt: impl Promptable) -> Result<Vec<Option<i32>>, TokenizeError> {
@@ -679,14 +1376,56 @@ impl ChatHandle {
             .blocking_recv()
             .ok_or(TokenizeError::WorkerTerminated)?
     }
+
+    /// Number of tokens the whole chat would take up in the context with `prompt` as the next
+    /// user message, chat template included. The chat itself is left unchanged.
+    ///
+    /// Compare it to the context size to trim the history before a message would overflow it.
+    pub fn would_be_context_tokens(&self, prompt: impl Promptable) -> Result<usize, TokenizeError> {
+        let (output_tx, mut output_rx) = tokio::sync::mpsc::channel(1);
+        self.guard.send(ChatMsg::CountContextTokens {
+            prompt: prompt.to_prompt(),
+            output_tx,
+        });
+        output_rx
+            .blocking_recv()
+            .ok_or(TokenizeError::WorkerTerminated)?
+    }
+
+    /// Render the current chat history with the chat template, without generating anything.
+    /// This is the exact text the model reads, which helps when debugging templates and tools.
+    pub fn render_current_prompt(&self) -> Result<String, RenderPromptError> {
+        let (output_tx, mut output_rx) = tokio::sync::mpsc::channel(1);
+        self.guard.send(ChatMsg::RenderPrompt { output_tx });
+        output_rx
+            .blocking_recv()
+            .ok_or(RenderPromptError::WorkerTerminated)?
+    }
+
+    /// Read the system prompt (and any history) into the context ahead of time, so the first
+    /// `ask` only has to read the new message. Blocks until done.
+    ///
+    /// Call it during a loading screen, since reading a long system prompt can take a while.
+    pub fn warmup(&self) -> Result<(), WarmupError> {
+        let (output_tx, mut output_rx) = tokio::sync::mpsc::channel(1);
+        self.guard.send(ChatMsg::Warmup { output_tx });
+        output_rx
+            .blocking_recv()
+            .ok_or(WarmupError::WorkerTerminated)?
+    }
 }
 
 /// Interact with a ChatWorker in an asynchronous manner.
 ///
-/// Use [`ChatBuilder`] to create a new instance with a fluent API.
+/// Use [`ChatBuilder::build_async`] to create a new instance with a fluent API.
+/// See the [module docs](self#async) for an example.
+///
+/// Dropping the last clone of the handle stops any generation in progress and waits for
+/// the worker thread to exit.
 #[derive(Clone)]
 pub struct ChatHandleAsync {
     guard: Arc<WorkerGuard<ChatMsg>>,
+    cancel_requested: Arc<AtomicBool>,
 }
 
 impl ChatHandleAsync {
@@ -697,6 +1436,8 @@ impl ChatHandleAsync {
 
         let should_stop = Arc::new(AtomicBool::new(false));
         let should_stop_clone = Arc::clone(&should_stop);
+        let cancel_requested = Arc::new(AtomicBool::new(false));
+        let cancel_requested_clone = Arc::clone(&cancel_requested);
 
         let join_handle = std::thread::spawn(move || {
             let worker = Chat::new_chat_worker(&model, config, should_stop_clone);
@@ -710,6 +1451,7 @@ impl ChatHandleAsync {
                     return;
                 }
             };
+            worker_state.cancel_requested = cancel_requested_clone;
 
             while let Ok(msg) = msg_rx.recv() {
                 if let Err(e) = process_worker_msg(&mut worker_state, msg) {
@@ -722,9 +1464,17 @@ impl ChatHandleAsync {
 
         Ok(Self {
             guard: Arc::new(WorkerGuard::new(msg_tx, join_handle, Some(should_stop))),
+            cancel_requested,
         })
     }
 
+    /// Whether the worker thread is still running. A worker that crashed fails every
+    /// following request with [`SayError::WorkerDead`], so a supervisor can check this and
+    /// replace the handle.
+    pub fn is_alive(&self) -> bool {
+        self.guard.is_alive()
+    }
+
     /// Send a message and get a tokio channel
     /// TODO: deprecate this in favor of plain `ask` once integrations are updated
     pub fn ask_channel(
@@ -732,7 +1482,11 @@ impl ChatHandleAsync {
         prompt: Prompt,
     ) -> tokio::sync::mpsc::UnboundedReceiver<llm::WriteOutput> {
         let (output_tx, output_rx) = tokio::sync::mpsc::unbounded_channel();
-        self.guard.send(ChatMsg::Ask { prompt, output_tx });
+        self.guard.send(ChatMsg::Ask {
+            prompt,
+            options: AskOptions::default(),
+            output_tx,
+        });
         output_rx
     }
 
@@ -741,17 +1495,189 @@ impl ChatHandleAsync {
     /// # Example
     /// ```
     /// # use nobodywho::chat::ChatHandleAsync;
-    /// # async fn example(chat: &ChatHandleAsync) {
+    /// # async fn example(chat: &ChatHandleAsync) -> Result<(), Box<dyn std::error::Error>> {
     /// let mut stream = chat.ask("Tell me a story");
-    /// while let Some(token) = stream.next_token().await {
+    /// while let Some(token) = stream.next_token().await? {
     ///     print!("{}", token);
     /// }
+    /// # Ok(())
     /// # }
     /// ```
     pub fn ask(&self, prompt: impl Promptable) -> TokenStreamAsync {
         TokenStreamAsync::new(forward_write_output(self.ask_channel(prompt.to_prompt())))
     }
 
+    /// Send a message and let the assistant's reply continue from `assistant_prefix`.
+    ///
+    /// The prefix is placed right after the assistant turn header, as if the model had
+    /// already generated it. It is emitted as the first token of the stream, and the
+    /// completed response (and the message stored in the chat history) includes it.
+    pub fn ask_with_prefill(
+        &self,
+        prompt: impl Promptable,
+        assistant_prefix: impl Into<String>,
+    ) -> TokenStreamAsync {
+        self.ask_with_options(
+            prompt,
+            AskOptions {
+                assistant_prefix: Some(assistant_prefix.into()),
+                ..Default::default()
+            },
+        )
+    }
+
+    /// Send a message and constrain only this reply with `grammar`, e.g. to force a yes/no
+    /// answer. The chat's sampler is left untouched.
+    pub fn ask_with_grammar(
+        &self,
+        prompt: impl Promptable,
+        grammar: gbnf::GbnfGrammar,
+    ) -> TokenStreamAsync {
+        self.ask_with_options(
+            prompt,
+            AskOptions {
+                grammar: Some(grammar),
+                ..Default::default()
+            },
+        )
+    }
+
+    /// Send a message with options that only apply to this turn. See [`AskOptions`].
+    pub fn ask_with_options(
+        &self,
+        prompt: impl Promptable,
+        options: AskOptions,
+    ) -> TokenStreamAsync {
+        let (output_tx, output_rx) = tokio::sync::mpsc::unbounded_channel();
+        self.guard.send(ChatMsg::Ask {
+            prompt: prompt.to_prompt(),
+            options,
+            output_tx,
+        });
+        TokenStreamAsync::new(forward_write_output(output_rx))
+    }
+
+    /// Answer several independent prompts against the current chat history, reading the
+    /// shared history only once. See [`ChatHandle::ask_many`].
+    pub fn ask_many(&self, prompts: Vec<impl Promptable>) -> Vec<TokenStreamAsync> {
+        let (branches, streams) = prompts
+            .into_iter()
+            .map(|prompt| {
+                let (output_tx, output_rx) = tokio::sync::mpsc::unbounded_channel();
+                (
+                    (prompt.to_prompt(), output_tx),
+                    TokenStreamAsync::new(forward_write_output(output_rx)),
+                )
+            })
+            .unzip();
+        self.guard.send(ChatMsg::AskMany { branches });
+        streams
+    }
+
+    /// Generate a new response to the last user message, replacing the previous one.
+    /// See [`ChatHandle::regenerate`].
+    pub fn regenerate(&self, sampler: Option<SamplerConfig>) -> TokenStreamAsync {
+        TokenStreamAsync::new(forward_write_output(self.regenerate_channel(sampler)))
+    }
+
+    /// Like [`regenerate`](Self::regenerate), but returns the raw output channel.
+    pub fn regenerate_channel(
+        &self,
+        sampler: Option<SamplerConfig>,
+    ) -> tokio::sync::mpsc::UnboundedReceiver<llm::WriteOutput> {
+        let (output_tx, output_rx) = tokio::sync::mpsc::unbounded_channel();
+        self.guard.send(ChatMsg::Regenerate {
+            options: AskOptions {
+                sampler_config: sampler,
+                ..Default::default()
+            },
+            output_tx,
+        });
+        output_rx
+    }
+
+    /// Continue the last response where it stopped after reaching its `max_tokens` budget.
+    /// See [`ChatHandle::continue_generation`].
+    pub fn continue_generation(&self, max_tokens: Option<u32>) -> TokenStreamAsync {
+        TokenStreamAsync::new(forward_write_output(
+            self.continue_generation_channel(max_tokens),
+        ))
+    }
+
+    /// Like [`continue_generation`](Self::continue_generation), but returns the raw output
+    /// channel.
+    pub fn continue_generation_channel(
+        &self,
+        max_tokens: Option<u32>,
+    ) -> tokio::sync::mpsc::UnboundedReceiver<llm::WriteOutput> {
+        let (output_tx, output_rx) = tokio::sync::mpsc::unbounded_channel();
+        self.guard.send(ChatMsg::ContinueGeneration {
+            options: AskOptions {
+                max_tokens,
+                ..Default::default()
+            },
+            output_tx,
+        });
+        output_rx
+    }
+
+    /// Replace the last user message with `prompt` and generate a response to it.
+    /// See [`ChatHandle::edit_last_user_message`].
+    pub fn edit_last_user_message(&self, prompt: impl Promptable) -> TokenStreamAsync {
+        TokenStreamAsync::new(forward_write_output(
+            self.edit_last_user_message_channel(prompt),
+        ))
+    }
+
+    /// Like [`edit_last_user_message`](Self::edit_last_user_message), but returns the raw
+    /// output channel.
+    pub fn edit_last_user_message_channel(
+        &self,
+        prompt: impl Promptable,
+    ) -> tokio::sync::mpsc::UnboundedReceiver<llm::WriteOutput> {
+        let (output_tx, output_rx) = tokio::sync::mpsc::unbounded_channel();
+        self.guard.send(ChatMsg::EditLastUserMessage {
+            prompt: prompt.to_prompt(),
+            options: AskOptions::default(),
+            output_tx,
+        });
+        output_rx
+    }
+
+    /// Send a message and receive the generated tokens interleaved with tool call events.
+    ///
+    /// Unlike [`ask`](Self::ask), which hides tool activity, this reports every tool call
+    /// before it runs and its response once it has finished.
+    pub fn ask_events(
+        &self,
+        prompt: impl Promptable,
+        options: AskOptions,
+    ) -> tokio::sync::mpsc::UnboundedReceiver<ChatEvent> {
+        let (output_tx, output_rx) = tokio::sync::mpsc::unbounded_channel();
+        self.guard.send(ChatMsg::AskEvents {
+            prompt: prompt.to_prompt(),
+            options,
+            output_tx,
+        });
+        output_rx
+    }
+
+    /// Send a message and wait for the whole turn, including its tool calls.
+    /// See [`ChatHandle::ask_traced`].
+    pub async fn ask_traced(
+        &self,
+        prompt: impl Promptable,
+    ) -> Result<TurnTrace, crate::errors::CompletionError> {
+        let mut events = self.ask_events(prompt, AskOptions::default());
+        let mut trace = TurnTrace::default();
+        while let Some(event) = events.recv().await {
+            if let Some(result) = trace.record(event) {
+                return result.map(|()| trace);
+            }
+        }
+        Err(crate::errors::CompletionError::WorkerCrashed)
+    }
+
     // internal helper function for async setters
     async fn set_and_wait_async<F>(&self, make_msg: F) -> Option<()>
     where
@@ -876,10 +1802,29 @@ impl ChatHandleAsync {
     }
 
     /// Stop the current generation if one is in progress.
+    ///
+    /// This also works while the prompt is still being read into the context. In that case
+    /// the half-read prompt is rolled back out of the context and the unanswered user message
+    /// is removed from the chat history, so the next `ask` starts clean. The response stream
+    /// then finishes with an empty response.
     pub fn stop_generation(&self) {
         self.guard.stop();
     }
 
+    /// Stop the current generation like [`stop_generation`](Self::stop_generation), and
+    /// also remove the stopped turn from the chat history, as if it never happened: the user
+    /// message and whatever the assistant had generated so far are both dropped. The response
+    /// stream still finishes as usual.
+    ///
+    /// If the worker hasn't started on a queued request yet, that request is cancelled once
+    /// it starts. If nothing is running or queued, this does nothing.
+    pub fn cancel_generation(&self) {
+        self.cancel_requested
+            .store(true, std::sync::atomic::Ordering::Relaxed);
+        self.guard.stop();
+        self.guard.send(ChatMsg::ClearCancel);
+    }
+
     /// Get the chat history without the system prompt (lower-level API).
     pub async fn get_chat_history(&self) -> Result<Vec<Message>, crate::errors::GetterError> {
         let (output_tx, mut output_rx) = tokio::sync::mpsc::channel(1);
@@ -892,6 +1837,14 @@ impl ChatHandleAsync {
             ))
     }
 
+    /// Get the chat history for showing in a chat UI. See [`ChatHandle::get_display_history`].
+    pub async fn get_display_history(
+        &self,
+        tool_calls: ToolCallDisplay,
+    ) -> Result<Vec<Message>, crate::errors::GetterError> {
+        Ok(display_history(&self.get_chat_history().await?, tool_calls))
+    }
+
     /// Set the chat history (lower-level API).
     pub async fn set_chat_history(
         &self,
@@ -984,6 +1937,19 @@ impl ChatHandleAsync {
         ))
     }
 
+    /// Replace the part of the system prompt after the static prefix.
+    /// See [`ChatHandle::set_system_suffix`].
+    pub async fn set_system_suffix(
+        &self,
+        suffix: String,
+    ) -> Result<(), crate::errors::SetterError> {
+        self.set_and_wait_async(|output_tx| ChatMsg::SetSystemSuffix { suffix, output_tx })
+            .await
+            .ok_or(crate::errors::SetterError::SetterError(
+                "set_system_suffix".into(),
+            ))
+    }
+
     /// Get the system prompt
     pub async fn get_system_prompt(&self) -> Result<Option<String>, crate::errors::GetterError> {
         let (output_tx, mut output_rx) = tokio::sync::mpsc::channel(1);
@@ -996,6 +1962,19 @@ impl ChatHandleAsync {
             ))
     }
 
+    /// Get the content of the most recent assistant reply, or `None` if there is none yet.
+    /// While a reply is being generated, this waits for it to finish.
+    pub async fn last_response(&self) -> Result<Option<String>, crate::errors::GetterError> {
+        let (output_tx, mut output_rx) = tokio::sync::mpsc::channel(1);
+        self.guard.send(ChatMsg::GetLastResponse { output_tx });
+        output_rx
+            .recv()
+            .await
+            .ok_or(crate::errors::GetterError::GetterError(
+                "last_response".into(),
+            ))
+    }
+
     /// Tokenize a prompt and return token IDs. Text tokens are `Some(id)`, media embedding
     /// slots are `None` (one per slot consumed in the context window).
     pub async fn tokenize(
@@ -1012,6 +1991,204 @@ impl ChatHandleAsync {
             .await
             .ok_or(TokenizeError::WorkerTerminated)?
     }
+
+    /// Number of tokens the whole chat would take up in the context with `prompt` as the next
+    /// user message, chat template included. The chat itself is left unchanged.
+    ///
+    /// Compare it to the context size to trim the history before a message would overflow it.
+    pub async fn would_be_context_tokens(
+        &self,
+        prompt: impl Promptable,
+    ) -> Result<usize, TokenizeError> {
+        let (output_tx, mut output_rx) = tokio::sync::mpsc::channel(1);
+        self.guard.send(ChatMsg::CountContextTokens {
+            prompt: prompt.to_prompt(),
+            output_tx,
+        });
+        output_rx
+            .recv()
+            .await
+            .ok_or(TokenizeError::WorkerTerminated)?
+    }
+
+    /// Render the current chat history with the chat template, without generating anything.
+    /// This is the exact text the model reads, which helps when debugging templates and tools.
+    pub async fn render_current_prompt(&self) -> Result<String, RenderPromptError> {
+        let (output_tx, mut output_rx) = tokio::sync::mpsc::channel(1);
+        self.guard.send(ChatMsg::RenderPrompt { output_tx });
+        output_rx
+            .recv()
+            .await
+            .ok_or(RenderPromptError::WorkerTerminated)?
+    }
+
+    /// Read the system prompt (and any history) into the context ahead of time, so the first
+    /// `ask` only has to read the new message.
+    pub async fn warmup(&self) -> Result<(), WarmupError> {
+        let (output_tx, mut output_rx) = tokio::sync::mpsc::channel(1);
+        self.guard.send(ChatMsg::Warmup { output_tx });
+        output_rx
+            .recv()
+            .await
+            .ok_or(WarmupError::WorkerTerminated)?
+    }
+}
+
+/// Several independent chats on one model, sharing a single llama.cpp context.
+///
+/// Each chat has its own [`ChatHandle`], history and settings, and its own sequence in the
+/// context's KV cache. Compared to a [`ChatHandle`] per conversation, the context is
+/// allocated once and there is one worker thread, which saves a lot of memory when many
+/// conversations are kept around, e.g. one per NPC.
+///
+/// The chats take turns: a request waits until the pool has finished the one in front of
+/// it, whichever chat that was for. Stopping generation only affects the chat it's called on.
+///
+/// ```
+/// use nobodywho::chat::ChatPool;
+/// use nobodywho::llm;
+/// use std::sync::Arc;
+///
+/// # fn example() -> Result<(), Box<dyn std::error::Error>> {
+/// let model = Arc::new(llm::get_model("model.gguf", true, None, None, None)?);
+/// let pool = ChatPool::new(model, 2, 2048)?;
+/// let [blacksmith, innkeeper] = pool.slots() else {
+///     unreachable!()
+/// };
+/// blacksmith.set_system_prompt(Some("You are a grumpy blacksmith.".into()))?;
+/// innkeeper.set_system_prompt(Some("You are a cheerful innkeeper.".into()))?;
+///
+/// let greeting = innkeeper.ask("Any rooms free tonight?").completed()?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct ChatPool {
+    slots: Vec<ChatHandle>,
+}
+
+impl ChatPool {
+    /// Start a pool of `n_slots` chats with `n_ctx_per_slot` tokens of context each.
+    ///
+    /// The chats start out with the default [`ChatConfig`]. Set their system prompt, tools
+    /// and sampler through their handles.
+    pub fn new(
+        model: Arc<llm::Model>,
+        n_slots: u32,
+        n_ctx_per_slot: u32,
+    ) -> Result<Self, InitWorkerError> {
+        if n_slots == 0 {
+            return Err(InitWorkerError::NoSlots);
+        }
+        let (pool_tx, pool_rx) = std::sync::mpsc::channel::<(usize, ChatMsg)>();
+        let (init_tx, init_rx) = std::sync::mpsc::channel::<Result<(), InitWorkerError>>();
+
+        let stop_flags: Vec<Arc<AtomicBool>> = (0..n_slots)
+            .map(|_| Arc::new(AtomicBool::new(false)))
+            .collect();
+        let worker_stop_flags = stop_flags.clone();
+        let cancel_flags: Vec<Arc<AtomicBool>> = (0..n_slots)
+            .map(|_| Arc::new(AtomicBool::new(false)))
+            .collect();
+        let worker_cancel_flags = cancel_flags.clone();
+
+        let join_handle = std::thread::spawn(move || {
+            let workers = Chat::new_pool_workers(&model, n_ctx_per_slot, worker_stop_flags);
+            let mut slots = match workers {
+                Ok(w) => {
+                    let _ = init_tx.send(Ok(()));
+                    w
+                }
+                Err(e) => {
+                    let _ = init_tx.send(Err(e));
+                    return;
+                }
+            };
+            for (slot, cancel_requested) in slots.iter_mut().zip(worker_cancel_flags) {
+                slot.cancel_requested = cancel_requested;
+            }
+
+            // the slot whose engine currently holds the context
+            let mut active = 0;
+            while let Ok((slot, msg)) = pool_rx.recv() {
+                if slot != active {
+                    let (from, to) = two_mut(&mut slots, active, slot);
+                    from.engine.pass_context_to(&mut to.engine);
+                    active = slot;
+                }
+                if let Err(e) = process_worker_msg(&mut slots[slot], msg) {
+                    return error!(slot, "Pool worker crashed: {e}");
+                }
+            }
+        });
+
+        init_rx.recv().map_err(|_| InitWorkerError::NoResponse)??;
+
+        // Every slot forwards its messages to the pool worker, tagged with the slot number.
+        // Whichever slot is dropped last joins the pool worker.
+        let pool_worker = Arc::new(PoolWorker(Some(join_handle)));
+        let slots = stop_flags
+            .into_iter()
+            .zip(cancel_flags)
+            .enumerate()
+            .map(|(slot, (should_stop, cancel_requested))| {
+                let (msg_tx, msg_rx) = std::sync::mpsc::channel();
+                let pool_tx = pool_tx.clone();
+                let pool_worker = Arc::clone(&pool_worker);
+                let forwarder = std::thread::spawn(move || {
+                    while let Ok(msg) = msg_rx.recv() {
+                        if pool_tx.send((slot, msg)).is_err() {
+                            break;
+                        }
+                    }
+                    // the worker only exits once every sender is gone
+                    drop(pool_tx);
+                    drop(pool_worker);
+                });
+                ChatHandle {
+                    guard: WorkerGuard::new(msg_tx, forwarder, Some(should_stop)),
+                    cancel_requested,
+                }
+            })
+            .collect();
+
+        Ok(Self { slots })
+    }
+
+    /// The handles of the pool's chats, one per slot.
+    pub fn slots(&self) -> &[ChatHandle] {
+        &self.slots
+    }
+
+    /// Take the handles out of the pool, e.g. to give them to different owners.
+    /// The shared context is freed once all of them are dropped.
+    pub fn into_slots(self) -> Vec<ChatHandle> {
+        self.slots
+    }
+}
+
+/// Joins the pool worker thread when the last slot lets go of it.
+struct PoolWorker(Option<std::thread::JoinHandle<()>>);
+
+impl Drop for PoolWorker {
+    fn drop(&mut self) {
+        if let Some(handle) = self.0.take() {
+            if let Err(e) = handle.join() {
+                error!("Pool worker panicked: {:?}", e);
+            }
+        }
+    }
+}
+
+/// Mutable references to two different items of `items`.
+fn two_mut<T>(items: &mut [T], a: usize, b: usize) -> (&mut T, &mut T) {
+    debug_assert_ne!(a, b);
+    if a < b {
+        let (left, right) = items.split_at_mut(b);
+        (&mut left[a], &mut right[0])
+    } else {
+        let (left, right) = items.split_at_mut(a);
+        (&mut right[0], &mut left[b])
+    }
 }
 
 /// A stream of tokens from the model.
@@ -1024,7 +2201,7 @@ pub type TokenStreamAsync = crate::stream::TokenStreamAsync<crate::errors::Compl
 /// `ask_channel` intentionally stays as `WriteOutput` so the Godot binding
 /// (which pattern-matches on it directly) is not broken. `ask` uses this
 /// forwarder to serve the generic `TokenStream`.
-fn forward_write_output(
+pub(crate) fn forward_write_output(
     rx: tokio::sync::mpsc::UnboundedReceiver<llm::WriteOutput>,
 ) -> tokio::sync::mpsc::UnboundedReceiver<crate::stream::StreamOutput<crate::errors::CompletionError>>
 {
@@ -1034,7 +2211,12 @@ fn forward_write_output(
     // this thread is not inside any async executor.
     std::thread::spawn(move || {
         let mut rx = rx;
+        let mut finished = false;
         while let Some(output) = rx.blocking_recv() {
+            finished = matches!(
+                output,
+                llm::WriteOutput::Done(_) | llm::WriteOutput::Error(_)
+            );
             let item = match output {
                 llm::WriteOutput::Token(t) => crate::stream::StreamOutput::Token(t),
                 llm::WriteOutput::Done(s) => crate::stream::StreamOutput::Done(s),
@@ -1043,23 +2225,130 @@ fn forward_write_output(
                 ),
             };
             if tx.send(item).is_err() {
-                break;
+                return;
             }
         }
+        // the worker dropped the request without answering it: it is gone
+        if !finished {
+            let _ = tx.send(crate::stream::StreamOutput::Error(
+                crate::errors::CompletionError::WorkerError(Box::new(SayError::WorkerDead)),
+            ));
+        }
     });
     new_rx
 }
 
+/// Whether reading the rendered chat into the context was aborted by `stop_generation`.
+fn is_read_aborted(error: &WrappedResponseError) -> bool {
+    matches!(
+        error,
+        WrappedResponseError::ReadError(ContextSyncError::KVCacheUpdate(ReadError::Aborted))
+    )
+}
+
+/// Whether `error` is [`ContextPolicy::Error`] refusing to make room in the context.
+fn is_context_full(error: &WrappedResponseError) -> bool {
+    matches!(
+        error,
+        WrappedResponseError::Shift(ShiftError::ContextFull)
+            | WrappedResponseError::ReadError(ContextSyncError::Shift(ShiftError::ContextFull))
+            | WrappedResponseError::GenerateResponse(GenerateResponseError::Shift(
+                ShiftError::ContextFull
+            ))
+    )
+}
+
 pub struct ChatStats {
     pub context_size: u32,
     pub context_used: u32,
 }
 
+/// An item on the event stream returned by [`ChatHandle::ask_events`]: generated text
+/// interleaved with the tool calls the model makes while answering.
+#[derive(Debug)]
+pub enum ChatEvent {
+    /// One decoded token piece.
+    Token(String),
+    /// The model called a tool, which is about to run.
+    ToolCallStarted(ToolCall),
+    /// A tool finished running and returned `response`. If the tool failed, `error` holds
+    /// the [`ToolError`] and `response` is the error message the model is shown.
+    ToolCallFinished {
+        tool_call: ToolCall,
+        response: String,
+        error: Option<ToolError>,
+    },
+    /// Generation finished; carries the full response.
+    Done(String),
+    /// An error occurred during generation.
+    Error(crate::errors::CompletionError),
+}
+
+/// The full record of one turn, returned by [`ChatHandle::ask_traced`].
+#[derive(Debug, Clone, Default)]
+pub struct TurnTrace {
+    /// The tool calls the model made, in order, each with the response the tool returned.
+    pub tool_calls: Vec<(ToolCall, String)>,
+    /// The tool calls that failed, in order, each with the error the tool returned.
+    /// These are in `tool_calls` as well, with the error message as the response.
+    pub tool_errors: Vec<(ToolCall, ToolError)>,
+    /// The final response.
+    pub response: String,
+}
+
+impl TurnTrace {
+    /// Add an event to the trace. Returns `Some` once the turn is over.
+    fn record(&mut self, event: ChatEvent) -> Option<Result<(), crate::errors::CompletionError>> {
+        match event {
+            ChatEvent::Token(_) | ChatEvent::ToolCallStarted(_) => None,
+            ChatEvent::ToolCallFinished {
+                tool_call,
+                response,
+                error,
+            } => {
+                if let Some(error) = error {
+                    self.tool_errors.push((tool_call.clone(), error));
+                }
+                self.tool_calls.push((tool_call, response));
+                None
+            }
+            ChatEvent::Done(response) => {
+                self.response = response;
+                Some(Ok(()))
+            }
+            ChatEvent::Error(e) => Some(Err(e)),
+        }
+    }
+}
+
 enum ChatMsg {
     Ask {
         prompt: Prompt,
+        options: AskOptions,
+        output_tx: tokio::sync::mpsc::UnboundedSender<llm::WriteOutput>,
+    },
+    AskMany {
+        branches: Vec<(Prompt, tokio::sync::mpsc::UnboundedSender<llm::WriteOutput>)>,
+    },
+    AskEvents {
+        prompt: Prompt,
+        options: AskOptions,
+        output_tx: tokio::sync::mpsc::UnboundedSender<ChatEvent>,
+    },
+    Regenerate {
+        options: AskOptions,
+        output_tx: tokio::sync::mpsc::UnboundedSender<llm::WriteOutput>,
+    },
+    EditLastUserMessage {
+        prompt: Prompt,
+        options: AskOptions,
+        output_tx: tokio::sync::mpsc::UnboundedSender<llm::WriteOutput>,
+    },
+    ContinueGeneration {
+        options: AskOptions,
         output_tx: tokio::sync::mpsc::UnboundedSender<llm::WriteOutput>,
     },
+    ClearCancel,
     ResetChat {
         system_prompt: Option<String>,
         tools: Vec<Tool>,
@@ -1073,9 +2362,16 @@ enum ChatMsg {
         system_prompt: Option<String>,
         output_tx: tokio::sync::mpsc::Sender<()>,
     },
+    SetSystemSuffix {
+        suffix: String,
+        output_tx: tokio::sync::mpsc::Sender<()>,
+    },
     GetSystemPrompt {
         output_tx: tokio::sync::mpsc::Sender<Option<String>>,
     },
+    GetLastResponse {
+        output_tx: tokio::sync::mpsc::Sender<Option<String>>,
+    },
     SetThinking {
         allow_thinking: bool,
         output_tx: tokio::sync::mpsc::Sender<()>,
@@ -1116,12 +2412,57 @@ enum ChatMsg {
         prompt: Prompt,
         output_tx: tokio::sync::mpsc::Sender<Result<Vec<Option<i32>>, TokenizeError>>,
     },
+    CountContextTokens {
+        prompt: Prompt,
+        output_tx: tokio::sync::mpsc::Sender<Result<usize, TokenizeError>>,
+    },
+    RenderPrompt {
+        output_tx: tokio::sync::mpsc::Sender<Result<String, RenderPromptError>>,
+    },
+    Warmup {
+        output_tx: tokio::sync::mpsc::Sender<Result<(), WarmupError>>,
+    },
 }
 
 impl std::fmt::Debug for ChatMsg {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            ChatMsg::Ask { prompt, .. } => f.debug_struct("Ask").field("text", prompt).finish(),
+            ChatMsg::Ask {
+                prompt, options, ..
+            } => f
+                .debug_struct("Ask")
+                .field("text", prompt)
+                .field("options", options)
+                .finish(),
+            ChatMsg::AskMany { branches } => {
+                let prompts: Vec<&Prompt> = branches.iter().map(|(prompt, _)| prompt).collect();
+                f.debug_struct("AskMany")
+                    .field("prompts", &prompts)
+                    .finish()
+            }
+            ChatMsg::AskEvents {
+                prompt, options, ..
+            } => f
+                .debug_struct("AskEvents")
+                .field("text", prompt)
+                .field("options", options)
+                .finish(),
+            ChatMsg::Regenerate { options, .. } => f
+                .debug_struct("Regenerate")
+                .field("options", options)
+                .finish(),
+            ChatMsg::EditLastUserMessage {
+                prompt, options, ..
+            } => f
+                .debug_struct("EditLastUserMessage")
+                .field("text", prompt)
+                .field("options", options)
+                .finish(),
+            ChatMsg::ContinueGeneration { options, .. } => f
+                .debug_struct("ContinueGeneration")
+                .field("options", options)
+                .finish(),
+            ChatMsg::ClearCancel => f.debug_struct("ClearCancel").finish(),
             ChatMsg::ResetChat {
                 system_prompt,
                 tools,
@@ -1139,7 +2480,12 @@ impl std::fmt::Debug for ChatMsg {
                 .debug_struct("SetSystemPrompt")
                 .field("system_prompt", system_prompt)
                 .finish(),
+            ChatMsg::SetSystemSuffix { suffix, .. } => f
+                .debug_struct("SetSystemSuffix")
+                .field("suffix", suffix)
+                .finish(),
             ChatMsg::GetSystemPrompt { .. } => f.debug_struct("GetSystemPrompt").finish(),
+            ChatMsg::GetLastResponse { .. } => f.debug_struct("GetLastResponse").finish(),
             ChatMsg::SetThinking { allow_thinking, .. } => f
                 .debug_struct("SetThinking")
                 .field("allow_thinking", allow_thinking)
@@ -1173,6 +2519,15 @@ impl std::fmt::Debug for ChatMsg {
                     &prompt.to_string().chars().take(50).collect::<String>(),
                 )
                 .finish(),
+            ChatMsg::CountContextTokens { prompt, .. } => f
+                .debug_struct("CountContextTokens")
+                .field(
+                    "prompt",
+                    &prompt.to_string().chars().take(50).collect::<String>(),
+                )
+                .finish(),
+            ChatMsg::RenderPrompt { .. } => f.debug_struct("RenderPrompt").finish(),
+            ChatMsg::Warmup { .. } => f.debug_struct("Warmup").finish(),
         }
     }
 }
@@ -1180,7 +2535,11 @@ impl std::fmt::Debug for ChatMsg {
 fn process_worker_msg(worker_state: &mut Chat<'_>, msg: ChatMsg) -> Result<(), ChatWorkerError> {
     info!(?msg, "Worker processing:");
     match msg {
-        ChatMsg::Ask { prompt, output_tx } => {
+        ChatMsg::Ask {
+            prompt,
+            options,
+            output_tx,
+        } => {
             let should_stop = Arc::clone(&worker_state.should_stop);
             let error_tx = output_tx.clone();
             let callback = move |out| {
@@ -1190,13 +2549,117 @@ fn process_worker_msg(worker_state: &mut Chat<'_>, msg: ChatMsg) -> Result<(), C
                     should_stop.store(true, std::sync::atomic::Ordering::Relaxed);
                 }
             };
-            if let Err(e) = worker_state.ask(prompt, callback) {
+            if let Err(e) = worker_state.ask_with_options(prompt, options, callback) {
                 let _ = error_tx.send(llm::WriteOutput::Error(Box::new(e)));
                 // Return Ok — error is communicated through the channel, worker stays alive.
             }
         }
-        ChatMsg::ResetChat {
-            system_prompt,
+        ChatMsg::AskMany { branches } => {
+            let mut branches = branches.into_iter();
+            for (prompt, output_tx) in branches.by_ref() {
+                let should_stop = Arc::clone(&worker_state.should_stop);
+                let receiver_dropped = Arc::new(AtomicBool::new(false));
+                let error_tx = output_tx.clone();
+                let callback = {
+                    let receiver_dropped = Arc::clone(&receiver_dropped);
+                    move |out| {
+                        if output_tx.send(out).is_err() {
+                            receiver_dropped.store(true, std::sync::atomic::Ordering::Relaxed);
+                            should_stop.store(true, std::sync::atomic::Ordering::Relaxed);
+                        }
+                    }
+                };
+                if let Err(e) = worker_state.ask_branch(prompt, AskOptions::default(), callback) {
+                    let _ = error_tx.send(llm::WriteOutput::Error(Box::new(e)));
+                }
+                // a stop from the handle ends the remaining branches too, a dropped stream
+                // only its own
+                if worker_state.should_stop()
+                    && !receiver_dropped.load(std::sync::atomic::Ordering::Relaxed)
+                {
+                    break;
+                }
+            }
+            for (_, output_tx) in branches {
+                let _ = output_tx.send(llm::WriteOutput::Done(String::new()));
+            }
+        }
+        ChatMsg::Regenerate { options, output_tx } => {
+            let should_stop = Arc::clone(&worker_state.should_stop);
+            let error_tx = output_tx.clone();
+            let callback = move |out| {
+                if output_tx.send(out).is_err() {
+                    should_stop.store(true, std::sync::atomic::Ordering::Relaxed);
+                }
+            };
+            if let Err(e) = worker_state.regenerate(options, callback) {
+                let _ = error_tx.send(llm::WriteOutput::Error(Box::new(e)));
+            }
+        }
+        ChatMsg::EditLastUserMessage {
+            prompt,
+            options,
+            output_tx,
+        } => {
+            let should_stop = Arc::clone(&worker_state.should_stop);
+            let error_tx = output_tx.clone();
+            let callback = move |out| {
+                if output_tx.send(out).is_err() {
+                    should_stop.store(true, std::sync::atomic::Ordering::Relaxed);
+                }
+            };
+            if let Err(e) = worker_state.edit_last_user_message(prompt, options, callback) {
+                let _ = error_tx.send(llm::WriteOutput::Error(Box::new(e)));
+            }
+        }
+        ChatMsg::ContinueGeneration { options, output_tx } => {
+            let should_stop = Arc::clone(&worker_state.should_stop);
+            let error_tx = output_tx.clone();
+            let callback = move |out| {
+                if output_tx.send(out).is_err() {
+                    should_stop.store(true, std::sync::atomic::Ordering::Relaxed);
+                }
+            };
+            if let Err(e) = worker_state.continue_generation(options, callback) {
+                let _ = error_tx.send(llm::WriteOutput::Error(Box::new(e)));
+            }
+        }
+        ChatMsg::AskEvents {
+            prompt,
+            options,
+            output_tx,
+        } => {
+            let should_stop = Arc::clone(&worker_state.should_stop);
+            let error_tx = output_tx.clone();
+            let tool_event_tx = output_tx.clone();
+            let callback = move |out| {
+                let event = match out {
+                    llm::WriteOutput::Token(t) => ChatEvent::Token(t),
+                    llm::WriteOutput::Done(s) => ChatEvent::Done(s),
+                    llm::WriteOutput::Error(e) => {
+                        ChatEvent::Error(crate::errors::CompletionError::WorkerError(e))
+                    }
+                };
+                if output_tx.send(event).is_err() {
+                    should_stop.store(true, std::sync::atomic::Ordering::Relaxed);
+                }
+            };
+            let on_tool_event = move |event| {
+                let _ = tool_event_tx.send(event);
+            };
+            if let Err(e) =
+                worker_state.ask_with_tool_events(prompt, options, callback, on_tool_event)
+            {
+                let _ = error_tx.send(ChatEvent::Error(
+                    crate::errors::CompletionError::WorkerError(Box::new(e)),
+                ));
+            }
+        }
+        ChatMsg::ClearCancel => {
+            worker_state.clear_cancel();
+        }
+        ChatMsg::ResetChat {
+            system_prompt,
             tools,
             output_tx,
         } => {
@@ -1214,10 +2677,17 @@ fn process_worker_msg(worker_state: &mut Chat<'_>, msg: ChatMsg) -> Result<(), C
             worker_state.set_system_prompt(system_prompt)?;
             let _ = output_tx.blocking_send(());
         }
+        ChatMsg::SetSystemSuffix { suffix, output_tx } => {
+            worker_state.set_system_suffix(suffix)?;
+            let _ = output_tx.blocking_send(());
+        }
         ChatMsg::GetSystemPrompt { output_tx } => {
             let system_prompt = worker_state.get_system_prompt();
             let _ = output_tx.blocking_send(system_prompt);
         }
+        ChatMsg::GetLastResponse { output_tx } => {
+            let _ = output_tx.blocking_send(worker_state.last_response());
+        }
         ChatMsg::SetThinking {
             allow_thinking,
             output_tx,
@@ -1268,7 +2738,7 @@ fn process_worker_msg(worker_state: &mut Chat<'_>, msg: ChatMsg) -> Result<(), C
         }
         ChatMsg::GetStats { output_tx } => {
             let stats = ChatStats {
-                context_size: worker_state.engine.ctx.n_ctx(),
+                context_size: worker_state.engine.context_size(),
                 context_used: worker_state.engine.n_past(),
             };
             let _ = output_tx.blocking_send(stats);
@@ -1286,6 +2756,18 @@ fn process_worker_msg(worker_state: &mut Chat<'_>, msg: ChatMsg) -> Result<(), C
             let result = worker_state.tokenize(prompt);
             let _ = output_tx.blocking_send(result);
         }
+        ChatMsg::CountContextTokens { prompt, output_tx } => {
+            let result = worker_state.would_be_context_tokens(prompt);
+            let _ = output_tx.blocking_send(result);
+        }
+        ChatMsg::RenderPrompt { output_tx } => {
+            let result = worker_state.render_prompt();
+            let _ = output_tx.blocking_send(result);
+        }
+        ChatMsg::Warmup { output_tx } => {
+            let result = worker_state.warmup().map_err(WarmupError::from);
+            let _ = output_tx.blocking_send(result);
+        }
     };
 
     Ok(())
@@ -1362,6 +2844,17 @@ impl ChatContext {
     }
 }
 
+fn check_generative(model: &llm::Model) -> Result<(), InitWorkerError> {
+    if !model.is_generative_model() {
+        let architecture = model
+            .language_model
+            .meta_val_str("general.architecture")
+            .unwrap_or_else(|_| "unknown".into());
+        return Err(InitWorkerError::NotAnLLM { architecture });
+    }
+    Ok(())
+}
+
 /// A chat session: owns an [`InferenceEngine`] plus all the conversational state
 /// (messages, tools, template, sampler config).
 struct Chat<'a> {
@@ -1374,7 +2867,38 @@ struct Chat<'a> {
     template_variables: std::collections::HashMap<String, bool>,
     tools: Vec<Tool>,
     chat_template: ChatTemplate,
+    /// Configured chat template, which stays in place when the tools change.
+    template_override: Option<ChatTemplate>,
     context: ChatContext,
+    /// Text appended after the rendered chat while generating a prefilled response.
+    assistant_prefix: Option<String>,
+    /// Point in time after which the current turn stops generating.
+    deadline: Option<std::time::Instant>,
+    /// Strings that end the current turn's response.
+    stop_words: Vec<String>,
+    /// Set by `cancel_generation`. The next turn to end discards itself and clears it;
+    /// otherwise the `ClearCancel` message queued behind the cancel does.
+    cancel_requested: Arc<AtomicBool>,
+    /// Token budget of the current turn's response.
+    max_tokens: Option<u32>,
+    /// Whether the last generated response ended on its token budget.
+    hit_max_tokens: bool,
+    /// The last response, if it ended on its token budget and can be continued.
+    truncated_response: Option<String>,
+    /// Response being continued by the current turn, rendered after the assistant header
+    /// like `assistant_prefix` but not emitted again.
+    continued_response: Option<String>,
+    n_keep: u32,
+    on_context_shift: Option<ContextShiftObserver>,
+    on_tool_call: Option<ToolCallObserver>,
+    max_tool_iterations: u32,
+    /// Special token texts to read as plain text in user messages.
+    /// `None` when special tokens in user messages are parsed.
+    special_tokens: Option<Vec<String>>,
+    /// Static start of the system prompt, tokenized on its own. Empty if not split.
+    system_prefix: String,
+    keep_thinking: bool,
+    context_policy: ContextPolicy,
 }
 
 impl<'a> Chat<'a> {
@@ -1383,52 +2907,135 @@ impl<'a> Chat<'a> {
         config: ChatConfig,
         should_stop: Arc<AtomicBool>,
     ) -> Result<Chat<'a>, InitWorkerError> {
-        if !model.is_generative_model() {
-            let architecture = model
-                .language_model
-                .meta_val_str("general.architecture")
-                .unwrap_or_else(|_| "unknown".into());
-            return Err(InitWorkerError::NotAnLLM { architecture });
+        check_generative(model)?;
+
+        // Build the low-level inference engine via the shared Worker constructor,
+        // then take ownership of just the engine for the chat session.
+        let Worker { engine, extra: () } = Worker::new_with_type(
+            model,
+            config.n_ctx,
+            false,
+            config.mtp,
+            config.context_options.clone(),
+            (),
+        )?;
+        Self::with_engine(model, engine, config, should_stop)
+    }
+
+    /// Chat sessions for a [`ChatPool`], one per stop flag, each on its own sequence of one
+    /// shared context. The first one holds the context.
+    fn new_pool_workers(
+        model: &'a llm::Model,
+        n_ctx_per_slot: u32,
+        stop_flags: Vec<Arc<AtomicBool>>,
+    ) -> Result<Vec<Chat<'a>>, InitWorkerError> {
+        check_generative(model)?;
+
+        let n_slots = stop_flags.len() as u32;
+        let Worker { engine, extra: () } = Worker::new_with_sequences(
+            model,
+            n_ctx_per_slot,
+            n_slots,
+            false,
+            None,
+            ContextOptions::default(),
+            (),
+        )?;
+        let mut engines = vec![];
+        for seq_id in 1..n_slots as i32 {
+            engines.push(engine.share_sequence(seq_id));
         }
+        engines.insert(0, engine);
+
+        engines
+            .into_iter()
+            .zip(stop_flags)
+            .map(|(engine, should_stop)| {
+                let config = ChatConfig {
+                    n_ctx: n_ctx_per_slot,
+                    ..Default::default()
+                };
+                Self::with_engine(model, engine, config, should_stop)
+            })
+            .collect()
+    }
 
-        let template = select_template(&model.language_model, !config.tools.is_empty())?;
+    /// A chat session on an already created `engine`. The context settings in `config`
+    /// (`n_ctx`, `mtp`, `context_options`) are not used.
+    fn with_engine(
+        model: &'a llm::Model,
+        mut engine: InferenceEngine<'a>,
+        config: ChatConfig,
+        should_stop: Arc<AtomicBool>,
+    ) -> Result<Chat<'a>, InitWorkerError> {
+        if let ContextPolicy::Summarize { trigger_ratio, .. } = config.context_policy {
+            if !(trigger_ratio > 0.0 && trigger_ratio <= 1.0) {
+                return Err(InitWorkerError::InvalidTriggerRatio(trigger_ratio));
+            }
+        }
+        let template = match &config.template_override {
+            Some(template) => {
+                debug!("Using configured chat template");
+                template.clone()
+            }
+            None => select_template(&model.language_model, !config.tools.is_empty())?,
+        };
 
-        // Only detect tool calling format if tools are provided
-        let (tool_format, grammar) = if !config.tools.is_empty() {
-            match detect_tool_format(&model.language_model) {
+        // A configured format skips detection entirely.
+        // Otherwise, only detect tool calling format if tools are provided
+        let tool_format = match config.tool_format {
+            Some(format) => {
+                debug!(format = ?format, "Using configured tool calling format");
+                Some(format)
+            }
+            None if !config.tools.is_empty() => match detect_tool_format(&model.language_model) {
                 Ok(format) => {
                     debug!(format = ?format, "Detected tool calling format");
-
-                    let grammar = match format.generate_grammar(&config.tools) {
-                        Ok(g) => {
-                            debug!(grammar = %g.as_str(), root = %g.root_name, "Generated tool calling grammar");
-                            Some(g)
-                        }
-                        Err(e) => {
-                            debug!(error = %e, "Failed to generate grammar from tools");
-                            None
-                        }
-                    };
-
-                    (Some(format), grammar)
+                    Some(format)
                 }
                 Err(e) => {
                     debug!(error = %e, "Failed to detect tool format, tools will not work");
-                    (None, None)
+                    None
+                }
+            },
+            None => None,
+        };
+        let grammar = match &tool_format {
+            Some(format) if !config.tools.is_empty() => {
+                match format.generate_grammar(&config.tools) {
+                    Ok(g) => {
+                        debug!(grammar = %g.as_str(), root = %g.root_name, "Generated tool calling grammar");
+                        Some(g)
+                    }
+                    Err(e) => {
+                        debug!(error = %e, "Failed to generate grammar from tools");
+                        None
+                    }
                 }
             }
-        } else {
-            (None, None)
+            _ => None,
         };
         let sampler_config = match config.sampler_config {
             Some(sc) => sc,
             None => read_sampler_from_metadata(&model.language_model).unwrap_or_default(),
         };
 
-        // Build the low-level inference engine via the shared Worker constructor,
-        // then take ownership of just the engine for the chat session.
-        let Worker { engine, extra: () } =
-            Worker::new_with_type(model, config.n_ctx, false, config.mtp, ())?;
+        if let Some(add_bos) = config.add_bos {
+            debug!(add_bos, "Overriding add_bos from the chat config");
+            engine.set_add_bos(if add_bos {
+                AddBos::Always
+            } else {
+                AddBos::Never
+            });
+        }
+        let special_tokens = (!config.parse_special_in_user_content)
+            .then(|| special_token_texts(&model.language_model));
+        let system_prefix = config.system_prefix.unwrap_or_default();
+        let system_prompt = if !system_prefix.is_empty() || config.system_suffix.is_some() {
+            Some(system_prefix.clone() + config.system_suffix.as_deref().unwrap_or_default())
+        } else {
+            config.system_prompt
+        };
 
         Ok(Chat {
             engine,
@@ -1436,21 +3043,103 @@ impl<'a> Chat<'a> {
             tool_grammar: grammar,
             tool_format,
             sampler_config,
-            messages: match config.system_prompt {
+            messages: match system_prompt {
                 Some(msg) => vec![Message::System { content: msg }],
                 None => vec![],
             },
             chat_template: template,
+            template_override: config.template_override,
             template_variables: config.template_variables,
             tools: config.tools,
             context: ChatContext::new(),
+            assistant_prefix: None,
+            deadline: None,
+            stop_words: vec![],
+            cancel_requested: Arc::new(AtomicBool::new(false)),
+            max_tokens: None,
+            hit_max_tokens: false,
+            truncated_response: None,
+            continued_response: None,
+            n_keep: config.n_keep,
+            on_context_shift: config.on_context_shift,
+            on_tool_call: config.on_tool_call,
+            max_tool_iterations: config.max_tool_iterations,
+            special_tokens,
+            system_prefix,
+            keep_thinking: config.keep_thinking,
+            context_policy: config.context_policy,
         })
     }
 
+    /// `messages` the way they go into the template: with a [`TOKEN_BREAK`] after the
+    /// system prefix, with special tokens in user content broken up so they're read as
+    /// plain text, if so configured, and with thinking put back in front of the content,
+    /// unless it's from before the last user message and not kept.
+    fn messages_for_render<'m>(&self, messages: &'m [Message]) -> Cow<'m, [Message]> {
+        let split_system_prefix = !self.system_prefix.is_empty()
+            && matches!(
+                messages.first(),
+                Some(Message::System { content }) if content.starts_with(&self.system_prefix)
+            );
+        let drop_thinking_before = if self.keep_thinking {
+            0
+        } else {
+            messages.iter().rposition(Message::is_user).unwrap_or(0)
+        };
+        let has_thinking = messages.iter().any(|msg| msg.reasoning_content().is_some());
+        if !split_system_prefix && self.special_tokens.is_none() && !has_thinking {
+            return Cow::Borrowed(messages);
+        }
+        messages
+            .iter()
+            .enumerate()
+            .map(|(i, msg)| match (msg, &self.special_tokens) {
+                (Message::System { content }, _) if i == 0 && split_system_prefix => {
+                    let suffix = &content[self.system_prefix.len()..];
+                    Message::System {
+                        content: format!("{}{TOKEN_BREAK}{suffix}", self.system_prefix),
+                    }
+                }
+                (
+                    Message::User {
+                        content: MessageContent::Text(text),
+                        assets,
+                    },
+                    Some(special_tokens),
+                ) => Message::User {
+                    content: MessageContent::Text(break_special_tokens(text, special_tokens)),
+                    assets: assets.clone(),
+                },
+                (
+                    Message::Assistant {
+                        content,
+                        reasoning_content: Some(thinking),
+                        tool_calls,
+                    },
+                    _,
+                ) => Message::Assistant {
+                    content: if i < drop_thinking_before {
+                        content.clone()
+                    } else {
+                        join_thinking(thinking, content)
+                    },
+                    reasoning_content: None,
+                    tool_calls: tool_calls.clone(),
+                },
+                (other, _) => other.clone(),
+            })
+            .collect()
+    }
+
     fn should_stop(&self) -> bool {
         self.should_stop.load(std::sync::atomic::Ordering::Relaxed)
     }
 
+    fn timed_out(&self) -> bool {
+        self.deadline
+            .is_some_and(|deadline| std::time::Instant::now() >= deadline)
+    }
+
     pub fn add_system_message(&mut self, content: String) {
         self.messages.push(Message::System { content });
     }
@@ -1469,6 +3158,7 @@ impl<'a> Chat<'a> {
     pub fn add_tool_calls(&mut self, tool_calls: Vec<ToolCall>) {
         self.messages.push(Message::Assistant {
             content: "".into(),
+            reasoning_content: None,
             tool_calls: Some(tool_calls),
         });
     }
@@ -1480,13 +3170,28 @@ impl<'a> Chat<'a> {
     /// Compare tokens from a template-rendered chat history with the tokens in the LLM's context,
     /// and perform the LLM 'reading' to make the LLM's context match the rendered tokens exactly.
     /// Because this invokes the model, this is potentially an expensive method to call.
+    ///
+    /// Token accounting: the tokens of the new render are compared with the tokens in the
+    /// KV cache from the start. Everything up to the first difference stays in the cache,
+    /// everything after it is removed, and the rest of the render is read. So the cost
+    /// of a change is the number of tokens from the change to the end of the chat. E.g.
+    /// changing the system prompt means reading the whole chat again, while a new message
+    /// only costs its own tokens.
+    ///
+    /// A system prefix (see [`ChatConfig::system_prefix`]) is tokenized on its own, so its
+    /// tokens don't depend on the suffix after it. Changing only the suffix keeps the
+    /// template's opening tokens and the whole prefix, and reads from the boundary on.
+    ///
+    /// If `abortable` is set, reading stops as soon as generation is stopped, and the
+    /// half-read tokens are rolled back out of the context (see [`ReadError::Aborted`]).
     #[tracing::instrument(level = "debug", skip_all)]
     fn sync_context_with_render(
         &mut self,
+        abortable: bool,
         inference_lock_token: &MutexGuard<'_, GlobalInferenceLockToken>,
     ) -> Result<(), ContextSyncError> {
         let mut chunks = self.render_as_chunks(true)?;
-        if chunks.n_tokens() > self.engine.ctx.n_ctx() as usize {
+        if chunks.n_tokens() > self.engine.context_size() as usize {
             self.context_shift()?;
             chunks = self.render_as_chunks(true)?;
         }
@@ -1496,9 +3201,20 @@ impl<'a> Chat<'a> {
 
         // Diff against the chunks currently in the KV cache and load only the new tail.
         let prev = std::mem::take(&mut self.context.chunks);
-        let new_chunks = self
-            .engine
-            .sync_context(chunks, &prev, inference_lock_token)?;
+        let should_stop = abortable.then_some(self.should_stop.as_ref());
+        let new_chunks =
+            match self
+                .engine
+                .sync_context(chunks, &prev, should_stop, inference_lock_token)
+            {
+                Ok(new_chunks) => new_chunks,
+                Err(ContextSyncError::KVCacheUpdate(ReadError::Aborted)) => {
+                    // the engine rolled back to a common prefix of `prev`, mirror that
+                    self.context.chunks = prev.head(self.engine.n_past() as usize);
+                    return Err(ReadError::Aborted.into());
+                }
+                Err(e) => return Err(e),
+            };
         self.context.chunks = new_chunks;
         self.context.garbage_collect_bitmaps(&self.messages);
 
@@ -1506,8 +3222,11 @@ impl<'a> Chat<'a> {
     }
 
     fn context_shift(&mut self) -> Result<(), ShiftError> {
+        if self.context_policy == ContextPolicy::Error {
+            return Err(ShiftError::ContextFull);
+        }
         info!("Context shift happens!");
-        let target_token_size = (self.engine.ctx.n_ctx() / 2) as usize;
+        let target_token_size = (self.engine.context_size() / 2) as usize;
         let mut messages = self.messages.clone();
 
         // Find indices to preserve
@@ -1515,13 +3234,29 @@ impl<'a> Chat<'a> {
         let first_user_message_index = self
             .find_next_user_message(&messages, system_end)
             .ok_or(ShiftError::NoUserMessages)?;
-        let first_deletable_index = self
+        let mut first_deletable_index = self
             .find_next_user_message(&messages, first_user_message_index + 1)
             .ok_or(ShiftError::TooFewMessages)?;
         let mut last_deletable_index = self
             .find_start_of_last_n_user_messages(&messages, 2)
             .ok_or(ShiftError::TooFewMessages)?
             - 1;
+        // Messages starting within the first n_keep tokens are kept as well, as long as
+        // there is something left to drop
+        while self.n_keep > 0
+            && self.count_message_tokens(&messages[..first_deletable_index])? < self.n_keep as usize
+        {
+            match self.find_next_user_message(&messages, first_deletable_index + 1) {
+                Some(index) if index <= last_deletable_index => first_deletable_index = index,
+                _ => {
+                    debug!(
+                        n_keep = self.n_keep,
+                        "n_keep covers every droppable turn, so the newest of them are dropped anyway"
+                    );
+                    break;
+                }
+            }
+        }
 
         // Two is the smallest number of messages we can delete as we need to preserve the message structure.
         // There might be a better start guess here.
@@ -1537,8 +3272,7 @@ impl<'a> Chat<'a> {
                 break;
             }
 
-            let chunks = self.render_as_chunks(false)?;
-            if chunks.n_tokens() <= target_token_size {
+            if self.count_message_tokens(&messages)? <= target_token_size {
                 break;
             }
 
@@ -1565,7 +3299,154 @@ impl<'a> Chat<'a> {
             last_deletable_index -= messages_deleted;
         }
 
+        let removed = self.messages.len() - messages.len();
+        if removed > 0 {
+            warn!(
+                removed,
+                "Context shift dropped messages from the chat history"
+            );
+            if let Some(observer) = &self.on_context_shift {
+                observer(removed);
+            }
+        }
+
+        self.messages = messages;
+        Ok(())
+    }
+
+    /// Replace everything between the system prompt and the last `keep_recent` user turns
+    /// with a summary written by the model, if the chat takes up more than `trigger_ratio`
+    /// of the context. See [`ContextPolicy::Summarize`].
+    fn summarize_old_turns(
+        &mut self,
+        trigger_ratio: f32,
+        keep_recent: usize,
+    ) -> Result<(), WrappedResponseError> {
+        let n_tokens = self.render_as_chunks(true)?.n_tokens();
+        if n_tokens as f32 <= trigger_ratio * self.engine.context_size() as f32 {
+            return Ok(());
+        }
+        let start = usize::from(self.messages.first().is_some_and(Message::is_system));
+        let Some(end) = self.find_start_of_last_n_user_messages(&self.messages, keep_recent.max(1))
+        else {
+            return Ok(());
+        };
+        if end <= start {
+            return Ok(());
+        }
+        info!(n_tokens, n_messages = end - start, "Summarizing old turns");
+
+        // generate the summary on a chat of its own, with the turn's limits put aside
+        let messages = std::mem::take(&mut self.messages);
+        let stop_words = std::mem::take(&mut self.stop_words);
+        let summary_tokens = self.engine.context_size() / 4;
+        let max_tokens = self.max_tokens.replace(summary_tokens);
+        let summary = self
+            .set_summary_prompt(&messages, start, end, summary_tokens)
+            .and_then(|end| match end {
+                Some(end) => self
+                    .wrapped_update_context_and_generate_response(
+                        self.sampler_config.clone(),
+                        |_| (),
+                        None,
+                    )
+                    .map(|summary| Some((end, summary))),
+                None => Ok(None),
+            });
         self.messages = messages;
+        self.stop_words = stop_words;
+        self.max_tokens = max_tokens;
+        let Some((end, summary)) = summary? else {
+            debug!("Not even the oldest turn fits in a summary prompt, leaving it to shifting");
+            return Ok(());
+        };
+        if self.should_stop() {
+            // a cut off summary would lose history, keep the turns as they are
+            return Ok(());
+        }
+
+        let summary = split_thinking(&summary).1.trim().to_string();
+        self.messages
+            .splice(start..end, [Message::System { content: summary }]);
+        self.context.garbage_collect_bitmaps(&self.messages);
+        if let Some(observer) = &self.on_context_shift {
+            observer(end - start);
+        }
+        Ok(())
+    }
+
+    /// Replace the chat history with a prompt to summarize `messages[start..end]`. The newest
+    /// of those turns are left out until the prompt fits in the context along with a summary
+    /// of `summary_tokens`; they stay in the history for a later summary. Returns the end of
+    /// the summarized messages, or `None` if not even the oldest turn fits.
+    fn set_summary_prompt(
+        &mut self,
+        messages: &[Message],
+        start: usize,
+        mut end: usize,
+        summary_tokens: u32,
+    ) -> Result<Option<usize>, WrappedResponseError> {
+        while end > start {
+            let transcript = summary_transcript(&messages[start..end]);
+            self.messages = vec![Message::new_user(format!(
+                "{SUMMARIZE_INSTRUCTION}\n\n{transcript}"
+            ))];
+            let n_prompt = self.render_as_chunks(true)?.n_tokens();
+            if n_prompt + summary_tokens as usize <= self.engine.context_size() as usize {
+                return Ok(Some(end));
+            }
+            end = messages[start + 1..end]
+                .iter()
+                .rposition(Message::is_user)
+                .map_or(start, |index| start + 1 + index);
+        }
+        Ok(None)
+    }
+
+    /// Number of tokens `messages` take up when rendered on their own.
+    fn count_message_tokens(&self, messages: &[Message]) -> Result<usize, ShiftError> {
+        let template_context = ChatTemplateContext::new(
+            self.template_variables.clone(),
+            if self.tools.is_empty() {
+                None
+            } else {
+                Some(self.tools.clone())
+            },
+        );
+        let rendered = self
+            .chat_template
+            .render_unhandled(&self.messages_for_render(messages), &template_context)
+            .map_err(RenderError::from)?;
+        let bitmaps: Vec<&MtmdBitmap> = messages
+            .iter()
+            .flat_map(|msg| msg.assets())
+            .filter_map(|asset| self.context.bitmaps.get(&asset.id))
+            .collect();
+        Ok(self.engine.tokenize(rendered, bitmaps)?.n_tokens())
+    }
+
+    /// Context shifting keeps the system prompt and the last user message, so if those two
+    /// don't fit on their own, there is no way to make room for them.
+    fn check_prompt_fits(&self) -> Result<(), SayError> {
+        let Some(last_user_index) = self.messages.iter().rposition(Message::is_user) else {
+            return Ok(());
+        };
+        let mut essential: Vec<Message> = self
+            .messages
+            .first()
+            .filter(|msg| msg.is_system())
+            .cloned()
+            .into_iter()
+            .collect();
+        essential.extend_from_slice(&self.messages[last_user_index..]);
+
+        let needed = self
+            .count_message_tokens(&essential)
+            .map_err(WrappedResponseError::from)?;
+        let available = self.engine.context_size() as usize;
+        if needed >= available {
+            return Err(SayError::ContextOverflow { needed, available });
+        }
         Ok(())
     }
 
@@ -1614,6 +3495,9 @@ impl<'a> Chat<'a> {
         // 4096 is a very randomly chosen number. how does this affect performance?
         let mut full_response: String = String::with_capacity(4096);
         let mut tokens_written_until_now = TokenizerChunks::new();
+        let mut n_generated: u32 = 0;
+
+        let mut stop_filter = StopWordFilter::new(&self.stop_words);
 
         // initialize sampler
         // stateful samplers only live for one response
@@ -1622,15 +3506,18 @@ impl<'a> Chat<'a> {
         // init statefull decoder for split up tokens like emojis
         let mut decoder = encoding_rs::UTF_8.new_decoder();
 
-        while !self.should_stop() {
+        while !self.should_stop() && !self.timed_out() {
             // Check if the context is full
             if self.engine.is_context_full() {
                 // pending should be preserved during context shift
                 let deferred_pending = self.engine.take_pending();
                 self.context_shift()?;
-                self.sync_context_with_render(inference_lock_token)?;
-                self.engine
-                    .read_chunks(tokens_written_until_now.clone(), inference_lock_token)?;
+                self.sync_context_with_render(false, inference_lock_token)?;
+                self.engine.read_chunks(
+                    tokens_written_until_now.clone(),
+                    None,
+                    inference_lock_token,
+                )?;
                 self.engine.restore_pending(deferred_pending);
                 // do not update tokens_in_context as this is done later by ask
             }
@@ -1643,24 +3530,12 @@ impl<'a> Chat<'a> {
             tokens_written_until_now.append(TokenizerChunk::new_text(new_tokens.clone()));
 
             let mut hit_eog = false;
+            let mut hit_stop = false;
             for new_token in new_tokens {
+                n_generated += 1;
+
                 // Attempt to convert token(s) to bytes
-                let token_bytes = match self
-                    .engine
-                    .ctx
-                    .model
-                    .token_to_piece_bytes(new_token, 8, true, None)
-                {
-                    Err(llama_cpp_2::TokenToStringError::InsufficientBufferSpace(i)) => {
-                        self.engine.ctx.model.token_to_piece_bytes(
-                            new_token,
-                            (-i).try_into().expect("Error buffer size is positive"),
-                            true,
-                            None,
-                        )
-                    }
-                    x => x,
-                }?;
+                let token_bytes = token_to_piece_bytes(self.engine.ctx.model, new_token, true)?;
 
                 // Attempt to convert bytes to utf8 string.
                 let max_len = decoder
@@ -1686,21 +3561,50 @@ impl<'a> Chat<'a> {
 
                 if !has_eog {
                     full_response.push_str(&token_str);
-                    trace!(?token_str, "Sending out token:");
-                    respond(WriteOutput::Token(token_str.to_string()));
+
+                    let (emit, hit) = stop_filter.update(&mut full_response);
+                    if hit {
+                        debug!("Hit a stop word, ending the response");
+                        hit_stop = true;
+                    }
+                    if let Some(token_str) = emit {
+                        trace!(?token_str, "Sending out token:");
+                        respond(WriteOutput::Token(token_str));
+                    }
                 }
 
                 if has_eog {
                     hit_eog = true;
                     break;
                 }
+
+                if hit_stop {
+                    break;
+                }
+
+                if self.max_tokens.is_some_and(|max| n_generated >= max) {
+                    debug!(n_generated, "Reached max_tokens, ending the response");
+                    hit_stop = true;
+                    self.hit_max_tokens = true;
+                    break;
+                }
             }
 
-            if hit_eog {
+            if hit_eog || hit_stop {
                 break;
             }
         }
 
+        // send out whatever was held back for stop word matching
+        if let Some(rest) = stop_filter.flush(&full_response) {
+            respond(WriteOutput::Token(rest));
+        }
+
+        if self.timed_out() {
+            warn!("Generation timed out, keeping the partial response");
+            respond(WriteOutput::Error(Box::new(SayError::Timeout)));
+        }
+
         // we're done!
         debug!(%full_response, "Sending out");
         respond(WriteOutput::Done(full_response));
@@ -1711,32 +3615,133 @@ impl<'a> Chat<'a> {
     where
         F: Fn(llm::WriteOutput) + Clone,
     {
-        // reset the stop flag
-        self.should_stop
-            .store(false, std::sync::atomic::Ordering::Relaxed);
+        self.ask_with_options(prompt, AskOptions::default(), respond)
+    }
 
-        // Get the tool call begin token from the format if tools are configured
-        let tool_call_begin = self
-            .tool_format
-            .as_ref()
-            .map(|fmt| fmt.begin_token().to_string());
+    pub fn ask_with_options<F>(
+        &mut self,
+        prompt: Prompt,
+        options: AskOptions,
+        respond: F,
+    ) -> Result<&mut Self, SayError>
+    where
+        F: Fn(llm::WriteOutput) + Clone,
+    {
+        self.ask_with_tool_events(prompt, options, respond, |_| ())
+    }
 
-        let prompt_text = prompt.to_string();
+    /// Like [`ask_with_options`](Self::ask_with_options), additionally reporting
+    /// [`ChatEvent::ToolCallStarted`] / [`ChatEvent::ToolCallFinished`] to `on_tool_event`.
+    pub fn ask_with_tool_events<F, G>(
+        &mut self,
+        prompt: Prompt,
+        options: AskOptions,
+        respond: F,
+        on_tool_event: G,
+    ) -> Result<&mut Self, SayError>
+    where
+        F: Fn(llm::WriteOutput) + Clone,
+        G: Fn(ChatEvent),
+    {
+        self.add_user_prompt(prompt)?;
+        self.respond_to_history(options, true, respond, on_tool_event)
+    }
 
-        let media_assets = prompt.extract_media_assets();
-        let bitmaps = media_assets
+    /// Drop the response to the last user message, including any tool calls it made,
+    /// and generate a new one.
+    pub fn regenerate<F>(&mut self, options: AskOptions, respond: F) -> Result<&mut Self, SayError>
+    where
+        F: Fn(llm::WriteOutput) + Clone,
+    {
+        let last_user_index = self
+            .messages
             .iter()
-            .map(|part| match part {
-                PromptPart::Image(path) => self.engine.load_image(path),
-                PromptPart::Audio(path) => self.engine.load_audio(path),
-                PromptPart::Text(_) => unreachable!(),
-            })
-            .collect::<Result<Vec<MtmdBitmap>, MultimodalError>>()?;
+            .rposition(Message::is_user)
+            .ok_or(SayError::NoUserMessage)?;
+        self.messages.truncate(last_user_index + 1);
+        self.respond_to_history(options, false, respond, |_| ())
+    }
 
-        debug!("Detected bitmaps: {:?}", bitmaps);
+    /// Generate more of the last response, which stopped on its `max_tokens` budget. Only
+    /// the new text is emitted; the chat history keeps the joined response.
+    pub fn continue_generation<F>(
+        &mut self,
+        options: AskOptions,
+        respond: F,
+    ) -> Result<&mut Self, SayError>
+    where
+        F: Fn(llm::WriteOutput) + Clone,
+    {
+        let response = self
+            .truncated_response
+            .take()
+            .filter(|_| matches!(self.messages.last(), Some(Message::Assistant { .. })))
+            .ok_or(SayError::NothingToContinue)?;
+        self.messages.pop();
+        self.continued_response = Some(response);
+        self.respond_to_history(options, false, respond, |_| ())
+    }
 
-        let bitmap_ids = self.context.add_bitmaps(bitmaps)?;
-        let assets = bitmap_ids
+    /// Replace the last user message, and everything after it, with `prompt` and generate a
+    /// response to it. The edited message is kept even if generation is stopped early.
+    pub fn edit_last_user_message<F>(
+        &mut self,
+        prompt: Prompt,
+        options: AskOptions,
+        respond: F,
+    ) -> Result<&mut Self, SayError>
+    where
+        F: Fn(llm::WriteOutput) + Clone,
+    {
+        let last_user_index = self
+            .messages
+            .iter()
+            .rposition(Message::is_user)
+            .ok_or(SayError::NoUserMessage)?;
+        self.messages.truncate(last_user_index);
+        self.add_user_prompt(prompt)?;
+        self.respond_to_history(options, false, respond, |_| ())
+    }
+
+    /// Like [`ask_with_options`](Self::ask_with_options), but the chat history is left as
+    /// it was. The KV cache keeps the history, so the next branch only reads its own prompt.
+    pub fn ask_branch<F>(
+        &mut self,
+        prompt: Prompt,
+        options: AskOptions,
+        respond: F,
+    ) -> Result<&mut Self, SayError>
+    where
+        F: Fn(llm::WriteOutput) + Clone,
+    {
+        // a context shift may change the history, so keep all of it rather than its length
+        let messages = self.messages.clone();
+        let result = self.ask_with_options(prompt, options, respond).map(|_| ());
+        self.messages = messages;
+        self.truncated_response = None;
+        self.context.garbage_collect_bitmaps(&self.messages);
+        result?;
+        Ok(self)
+    }
+
+    /// Add `prompt` as a user message, loading its media.
+    fn add_user_prompt(&mut self, prompt: Prompt) -> Result<(), MultimodalError> {
+        let prompt_text = prompt.to_string();
+
+        let media_assets = prompt.extract_media_assets();
+        let bitmaps = media_assets
+            .iter()
+            .map(|part| match part {
+                PromptPart::Image(path) => self.engine.load_image(path),
+                PromptPart::Audio(path) => self.engine.load_audio(path),
+                PromptPart::Text(_) => unreachable!(),
+            })
+            .collect::<Result<Vec<MtmdBitmap>, MultimodalError>>()?;
+
+        debug!("Detected bitmaps: {:?}", bitmaps);
+
+        let bitmap_ids = self.context.add_bitmaps(bitmaps)?;
+        let assets = bitmap_ids
             .iter()
             .zip(media_assets.iter())
             .map(|(id, part)| Asset {
@@ -1753,94 +3758,316 @@ impl<'a> Chat<'a> {
             Prompt::Parts(_) => MessageContent::Text(prompt_text),
         };
         self.add_user_message(content, assets);
+        Ok(())
+    }
+
+    /// Generate the assistant's response to the chat history, calling tools as needed.
+    /// If it's stopped before anything was generated, the last user message is dropped
+    /// again when it was `new_user_message`. A cancelled turn is dropped entirely.
+    fn respond_to_history<F, G>(
+        &mut self,
+        options: AskOptions,
+        new_user_message: bool,
+        respond: F,
+        on_tool_event: G,
+    ) -> Result<&mut Self, SayError>
+    where
+        F: Fn(llm::WriteOutput) + Clone,
+        G: Fn(ChatEvent),
+    {
+        // reset the stop flag, unless a cancel was issued while this request was queued
+        if !self
+            .cancel_requested
+            .load(std::sync::atomic::Ordering::Relaxed)
+        {
+            self.should_stop
+                .store(false, std::sync::atomic::Ordering::Relaxed);
+        }
+        self.deadline = options
+            .timeout
+            .map(|timeout| std::time::Instant::now() + timeout);
+        self.stop_words = options.stop_words;
+        self.max_tokens = options.max_tokens;
+        self.truncated_response = None;
+        let continued = self.continued_response.take();
+
+        let fits = self
+            .check_prompt_fits()
+            .and_then(|()| match self.context_policy {
+                ContextPolicy::Summarize {
+                    trigger_ratio,
+                    keep_recent,
+                } => Ok(self.summarize_old_turns(trigger_ratio, keep_recent)?),
+                ContextPolicy::Error | ContextPolicy::Shift => Ok(()),
+            });
+        if let Err(e) = fits {
+            if new_user_message {
+                self.messages.pop();
+                self.context.garbage_collect_bitmaps(&self.messages);
+            }
+            self.restore_continued_response(continued);
+            self.deadline = None;
+            return Err(e);
+        }
+        // Get the tool call begin token from the format if tools are configured
+        let tool_call_begin = self
+            .tool_format
+            .as_ref()
+            .map(|fmt| fmt.begin_token().to_string());
+
+        // A sampler given for this turn only takes precedence over the stored one
+        let mut base_sampler = options
+            .sampler_config
+            .unwrap_or_else(|| self.sampler_config.clone());
+        if let Some(seed) = options.seed {
+            base_sampler.seed = seed;
+        }
+        if let Some(grammar) = options.grammar {
+            base_sampler.steps.push(ShiftStep::Grammar {
+                trigger_on: options.grammar_trigger,
+                root: grammar.root_name.clone(),
+                grammar: grammar.as_str().into(),
+            });
+        }
 
         // Modify sampler with tool grammar if we have tools
-        let sampler =
-            self.tool_grammar
-                .as_ref()
-                .map_or(self.sampler_config.clone(), |tool_grammar| {
-                    let mut steps = self.sampler_config.steps.clone();
-                    steps.insert(
-                        0,
-                        ShiftStep::Grammar {
-                            trigger_on: tool_call_begin.clone(),
-                            root: tool_grammar.root_name.to_string(),
-                            grammar: tool_grammar.as_str().into(),
-                        },
-                    );
-                    SamplerConfig::new(
-                        steps,
-                        self.sampler_config.sample_step.clone(),
-                        self.sampler_config.seed,
-                    )
-                });
+        let sampler = self
+            .tool_grammar
+            .as_ref()
+            .map_or(base_sampler.clone(), |tool_grammar| {
+                let mut steps = base_sampler.steps.clone();
+                steps.insert(
+                    0,
+                    ShiftStep::Grammar {
+                        trigger_on: tool_call_begin.clone(),
+                        root: tool_grammar.root_name.to_string(),
+                        grammar: tool_grammar.as_str().into(),
+                    },
+                );
+                SamplerConfig {
+                    steps,
+                    ..base_sampler.clone()
+                }
+            });
 
         // get the finished response
-        let mut response: String = self.wrapped_update_context_and_generate_response(
-            sampler.clone(),
-            respond.clone(),
-            tool_call_begin.clone(),
-        )?;
+        let first_response = match (options.assistant_prefix, &continued) {
+            (_, Some(continued)) => {
+                // the response so far is rendered after the assistant header, so generation
+                // picks up where it stopped. only the new text is emitted.
+                self.assistant_prefix = Some(continued.clone());
+                let result = self.wrapped_update_context_and_generate_response(
+                    sampler.clone(),
+                    respond.clone(),
+                    tool_call_begin.clone(),
+                );
+                self.assistant_prefix = None;
+                result.map(|resp| format!("{continued}{resp}"))
+            }
+            (Some(prefix), None) => {
+                // the prefix counts as already generated: render it after the assistant
+                // header, emit it first and make it part of the completed response
+                respond(llm::WriteOutput::Token(prefix.clone()));
+                self.assistant_prefix = Some(prefix.clone());
+                let prefixed_respond = {
+                    let respond = respond.clone();
+                    let prefix = prefix.clone();
+                    move |out| match out {
+                        llm::WriteOutput::Done(resp) => {
+                            respond(llm::WriteOutput::Done(format!("{prefix}{resp}")))
+                        }
+                        out => respond(out),
+                    }
+                };
+                let result = self.wrapped_update_context_and_generate_response(
+                    sampler.clone(),
+                    prefixed_respond,
+                    tool_call_begin.clone(),
+                );
+                self.assistant_prefix = None;
+                // the captured response is the generated text only
+                result.map(|resp| format!("{prefix}{resp}"))
+            }
+            (None, None) => self.wrapped_update_context_and_generate_response(
+                sampler.clone(),
+                respond.clone(),
+                tool_call_begin.clone(),
+            ),
+        };
+        let mut response = match first_response {
+            Err(e) if is_read_aborted(&e) => {
+                // stopped while still reading the prompt: nothing was generated, so drop the
+                // unanswered user message. The half-read prompt was already rolled back out of
+                // the context, so the next turn starts from a clean state.
+                debug!("Generation stopped while reading the prompt");
+                if new_user_message {
+                    self.messages.pop();
+                    self.context.garbage_collect_bitmaps(&self.messages);
+                }
+                self.restore_continued_response(continued);
+                self.deadline = None;
+                if self.take_cancel() && !new_user_message {
+                    self.discard_last_turn();
+                }
+                respond(llm::WriteOutput::Done(String::new()));
+                return Ok(self);
+            }
+            Err(e) if is_context_full(&e) => {
+                // the user message got no reply, so it isn't kept, as when it doesn't fit at all
+                if new_user_message {
+                    self.messages.pop();
+                    self.context.garbage_collect_bitmaps(&self.messages);
+                }
+                self.restore_continued_response(continued);
+                self.deadline = None;
+                return Err(e.into());
+            }
+            result => result?,
+        };
 
+        let mut tool_iterations = 0;
+        let mut tool_limit_reached = false;
         // Process tool calls if tool format is configured
         // Clone to avoid borrow issues in the loop
         if let Some(tool_format) = self.tool_format.clone() {
             while let Some(tool_calls) = tool_format.extract_tool_calls(&response) {
+                if self.timed_out() {
+                    // don't start another round of tool calls on an expired turn
+                    break;
+                }
                 debug!(?tool_calls, "Got tool calls:");
 
                 self.add_tool_calls(tool_calls.clone());
 
-                for tool_call in tool_calls {
-                    // find the tool
-                    // this is just a stupid linear search
-                    // but I think it's probably faster than something fancy as long as we have few tools
-                    // /shrug I'm happy to be wrong
-                    let Some(tool) = self.tools.iter().find(|t| t.name == tool_call.name) else {
-                        // in case the tool isn't found.
-                        // I *think* this should be impossible, as long as the tool calling grammar
-                        // works.
-                        error!(
-                            tool_name = tool_call.name,
-                            "Model triggered tool call for invalid tool name:",
-                        );
-                        let errmsg = format!("ERROR - Invalid tool name: {}", tool_call.name);
-                        self.add_tool_resp(tool_call.name, errmsg);
-                        continue;
-                    };
-
-                    // call the tool
-                    debug!("Calling the tool now!");
-                    let response = (tool.function)(tool_call.arguments);
-                    debug!(%tool_call.name, %response, "Tool call result:");
-
-                    // add to chat history
-                    self.add_tool_resp(tool_call.name, response);
+                tool_limit_reached = tool_iterations >= self.max_tool_iterations;
+                if tool_limit_reached {
+                    warn!(
+                        max_tool_iterations = self.max_tool_iterations,
+                        "Tool call limit reached, asking for a final answer"
+                    );
+                    for tool_call in tool_calls {
+                        self.add_tool_resp(tool_call.name, TOOL_CALL_LIMIT_MESSAGE.into());
+                    }
+                } else {
+                    tool_iterations += 1;
+                    for tool_call in tool_calls {
+                        // find the tool
+                        // this is just a stupid linear search
+                        // but I think it's probably faster than something fancy as long as we have few tools
+                        // /shrug I'm happy to be wrong
+                        let Some(tool) = self.tools.iter().find(|t| t.name == tool_call.name)
+                        else {
+                            // in case the tool isn't found.
+                            // I *think* this should be impossible, as long as the tool calling grammar
+                            // works.
+                            error!(
+                                tool_name = tool_call.name,
+                                "Model triggered tool call for invalid tool name:",
+                            );
+                            let errmsg = format!("ERROR - Invalid tool name: {}", tool_call.name);
+                            self.add_tool_resp(tool_call.name, errmsg);
+                            continue;
+                        };
+
+                        // call the tool
+                        debug!("Calling the tool now!");
+                        if let Some(observer) = &self.on_tool_call {
+                            observer(&tool_call);
+                        }
+                        on_tool_event(ChatEvent::ToolCallStarted(tool_call.clone()));
+                        let (response, error) = match (tool.function)(tool_call.arguments.clone()) {
+                            Ok(response) => (response, None),
+                            Err(e) => {
+                                warn!(%tool_call.name, error = %e, "Tool call failed");
+                                (e.response(), Some(e))
+                            }
+                        };
+                        debug!(%tool_call.name, %response, "Tool call result:");
+                        on_tool_event(ChatEvent::ToolCallFinished {
+                            tool_call: tool_call.clone(),
+                            response: response.clone(),
+                            error,
+                        });
+
+                        // add to chat history
+                        self.add_tool_resp(tool_call.name, response);
+                    }
                 }
 
-                // get the finished response
-                response = self.wrapped_update_context_and_generate_response(
-                    sampler.clone(),
+                // get the finished response. past the limit, without the tool grammar
+                let next_sampler = if tool_limit_reached {
+                    base_sampler.clone()
+                } else {
+                    sampler.clone()
+                };
+                response = match self.wrapped_update_context_and_generate_response(
+                    next_sampler,
                     respond.clone(),
                     tool_call_begin.clone(),
-                )?;
+                ) {
+                    Err(e) if is_read_aborted(&e) => {
+                        // stopped while reading the tool responses, end the turn here
+                        respond(llm::WriteOutput::Done(String::new()));
+                        String::new()
+                    }
+                    result => result?,
+                };
+                if tool_limit_reached {
+                    break;
+                }
             }
         } // Close if let Some(tool_format)
 
-        debug_assert!(tool_call_begin
-            .as_ref()
-            .is_none_or(|t| !response.contains(t.as_str())));
-        self.add_assistant_message(response);
+        debug_assert!(
+            self.timed_out()
+                || tool_limit_reached
+                || self.max_tokens.is_some()
+                || !self.stop_words.is_empty()
+                || tool_call_begin
+                    .as_ref()
+                    .is_none_or(|t| !response.contains(t.as_str()))
+        );
+        self.deadline = None;
+        if self.hit_max_tokens {
+            self.truncated_response = Some(response.clone());
+        }
+        self.messages.push(Message::from_response(response));
+        if self.take_cancel() {
+            self.discard_last_turn();
+        }
 
         self.context.chunks = self.render_as_chunks(true)?;
 
         Ok(self)
     }
 
+    /// Put back the response a failed continuation removed from the chat history, so it
+    /// can be continued again.
+    fn restore_continued_response(&mut self, continued: Option<String>) {
+        if let Some(response) = continued {
+            self.messages.push(Message::from_response(response.clone()));
+            self.truncated_response = Some(response);
+        }
+    }
+
     /// Go for the unhandled mode when you are context shifting.
     /// That is for avoiding the render will concat system message with the first user message.
     /// Otherwise please handle stuff.
     fn render_as_chunks(&mut self, handled: bool) -> Result<TokenizerChunks, RenderError> {
-        let messages = &self.messages;
+        let rendered_chat = self.render_text(handled)?;
+        let bitmaps: Vec<&MtmdBitmap> = self
+            .messages
+            .iter()
+            .flat_map(|msg| msg.assets())
+            .filter_map(|asset| self.context.bitmaps.get(&asset.id))
+            .collect();
+        Ok(self.engine.tokenize(rendered_chat, bitmaps)?)
+    }
+
+    /// The chat history rendered with the chat template, as in [`Self::render_as_chunks`].
+    fn render_text(&self, handled: bool) -> Result<String, minijinja::Error> {
+        let messages = self.messages_for_render(&self.messages);
+        let messages = messages.as_ref();
         let template_context = ChatTemplateContext::new(
             self.template_variables.clone(),
             if self.tools.is_empty() {
@@ -1850,20 +4077,33 @@ impl<'a> Chat<'a> {
             },
         );
 
-        let rendered_chat = if handled {
+        let mut rendered_chat = if handled {
             self.chat_template.render(messages, &template_context)?
         } else {
             self.chat_template
                 .render_unhandled(messages, &template_context)?
         };
+        if let Some(prefix) = &self.assistant_prefix {
+            rendered_chat.push_str(prefix);
+        }
+        Ok(rendered_chat)
+    }
 
-        let bitmaps: Vec<&MtmdBitmap> = self
-            .messages
-            .iter()
-            .flat_map(|msg| msg.assets())
-            .filter_map(|asset| self.context.bitmaps.get(&asset.id))
-            .collect();
-        Ok(self.engine.tokenize(rendered_chat, bitmaps)?)
+    /// The current chat history rendered with the chat template, without the token break
+    /// markers that only guide tokenization.
+    pub fn render_prompt(&self) -> Result<String, RenderPromptError> {
+        Ok(self.render_text(true)?.replace(TOKEN_BREAK, ""))
+    }
+
+    /// Read the current history into the KV cache without generating anything.
+    pub fn warmup(&mut self) -> Result<(), ContextSyncError> {
+        // templates may refuse to render a chat without user messages, so render one with an
+        // empty user message. the next turn keeps every token before it.
+        self.add_user_message(String::new(), vec![]);
+        let inference_lock_token = acquire_inference_lock();
+        let result = self.sync_context_with_render(false, &inference_lock_token);
+        self.messages.pop();
+        result
     }
 
     fn wrapped_update_context_and_generate_response<F>(
@@ -1875,9 +4115,11 @@ impl<'a> Chat<'a> {
     where
         F: Fn(llm::WriteOutput) + Clone,
     {
+        self.hit_max_tokens = false;
+
         // Check how much of the current KVCache we can keep
         let inference_lock_token = acquire_inference_lock();
-        self.sync_context_with_render(&inference_lock_token)?;
+        self.sync_context_with_render(true, &inference_lock_token)?;
 
         // wrap the response callback to keep a copy of the completed response
         // and to avoid emitting tool calls
@@ -1927,7 +4169,9 @@ impl<'a> Chat<'a> {
         };
         self.tools = tools;
         self.messages = Vec::new();
+        self.truncated_response = None;
         self.context = ChatContext::new();
+        self.system_prefix.clear();
         if let Some(sys_msg) = system_prompt {
             self.add_system_message(sys_msg);
         }
@@ -1966,6 +4210,20 @@ impl<'a> Chat<'a> {
         &mut self,
         system_prompt: Option<String>,
     ) -> Result<(), ContextSyncError> {
+        // a whole new system prompt isn't split anymore
+        self.system_prefix.clear();
+        self.replace_system_message(system_prompt);
+        Ok(())
+    }
+
+    /// Replace the system prompt after the system prefix, keeping the prefix.
+    pub fn set_system_suffix(&mut self, suffix: String) -> Result<(), ContextSyncError> {
+        let system_prompt = format!("{}{suffix}", self.system_prefix);
+        self.replace_system_message((!system_prompt.is_empty()).then_some(system_prompt));
+        Ok(())
+    }
+
+    fn replace_system_message(&mut self, system_prompt: Option<String>) {
         match system_prompt {
             Some(sys_msg) => {
                 let system_message = Message::System { content: sys_msg };
@@ -1983,8 +4241,18 @@ impl<'a> Chat<'a> {
                 }
             }
         }
+    }
 
-        Ok(())
+    /// The content of the latest assistant message that isn't a tool call.
+    pub fn last_response(&self) -> Option<String> {
+        self.messages.iter().rev().find_map(|msg| match msg {
+            Message::Assistant {
+                content,
+                tool_calls: None,
+                ..
+            } => Some(content.clone()),
+            _ => None,
+        })
     }
 
     pub fn get_system_prompt(&self) -> Option<String> {
@@ -2028,7 +4296,9 @@ impl<'a> Chat<'a> {
         };
         self.tools = tools;
 
-        self.chat_template = select_template(self.engine.ctx.model, !self.tools.is_empty())?;
+        if self.template_override.is_none() {
+            self.chat_template = select_template(self.engine.ctx.model, !self.tools.is_empty())?;
+        }
 
         Ok(())
     }
@@ -2041,6 +4311,7 @@ impl<'a> Chat<'a> {
         };
 
         self.messages = system_msg.into_iter().chain(messages).collect();
+        self.truncated_response = None;
 
         // We used to call sync_context_with_render here but this can
         // crash as some chat templates will attempt to access fields on
@@ -2053,6 +4324,30 @@ impl<'a> Chat<'a> {
         Ok(())
     }
 
+    /// Whether the current turn was cancelled. Clears the cancel, so it only applies to
+    /// this turn.
+    fn take_cancel(&self) -> bool {
+        self.cancel_requested
+            .swap(false, std::sync::atomic::Ordering::Relaxed)
+    }
+
+    /// Forget a cancel that no turn was discarded for.
+    pub fn clear_cancel(&self) {
+        self.cancel_requested
+            .store(false, std::sync::atomic::Ordering::Relaxed);
+    }
+
+    /// Remove the last turn from the chat history, as if it never happened: the last user
+    /// message and everything after it.
+    fn discard_last_turn(&mut self) {
+        if let Some(turn_start) = self.messages.iter().rposition(Message::is_user) {
+            debug!(turn_start, "Discarding cancelled turn");
+            self.messages.truncate(turn_start);
+        }
+        self.truncated_response = None;
+        self.context.garbage_collect_bitmaps(&self.messages);
+    }
+
     pub fn get_chat_history(&self) -> Vec<Message> {
         match self.messages.as_slice() {
             [Message::System { .. }, rest @ ..] => rest.to_vec(),
@@ -2079,12 +4374,28 @@ impl<'a> Chat<'a> {
         let chunks = self.engine.tokenize(prompt.to_string(), bitmap_refs)?;
         Ok(chunks.to_token_ids())
     }
+
+    /// Number of tokens the chat would take up with `prompt` as the next user message,
+    /// chat template included. The chat is left as it was.
+    pub fn would_be_context_tokens(&mut self, prompt: Prompt) -> Result<usize, TokenizeError> {
+        let n_messages = self.messages.len();
+        let result = match self.add_user_prompt(prompt) {
+            Ok(()) => self
+                .render_as_chunks(true)
+                .map(|chunks| chunks.n_tokens())
+                .map_err(TokenizeError::from),
+            Err(e) => Err(e.into()),
+        };
+        self.messages.truncate(n_messages);
+        self.context.garbage_collect_bitmaps(&self.messages);
+        result
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::sampler::SamplerPresets;
+    use crate::sampler::{default_seed, SamplerPresets};
     use crate::test_utils;
 
     // Helper function to verify message structure is valid
@@ -2133,116 +4444,724 @@ mod tests {
     }
 
     #[test]
-    fn test_chat_worker() -> Result<(), Box<dyn std::error::Error>> {
-        // test_utils::init_test_tracing();
+    fn test_token_stream_iterator() {
         let model = test_utils::load_test_model();
+        let chat = ChatBuilder::new(model)
+            .with_context_size(2048)
+            .with_template_variable("enable_thinking".to_string(), false)
+            .build()
+            .expect("chat build failed in test");
 
-        let mut worker = Chat::new_chat_worker(
-            &model,
-            ChatConfig {
-                n_ctx: 1024,
-                ..Default::default()
-            },
-            Arc::new(AtomicBool::new(false)),
-        )?;
-
-        let (sender, receiver) = std::sync::mpsc::channel();
-        let f = move |x| {
-            if let llm::WriteOutput::Done(resp) = x {
-                sender.send(resp).unwrap();
-            }
-        };
-
-        worker.ask("What is the capital of Denmark?".into(), f.clone())?;
+        let tokens: Vec<String> = chat
+            .ask("What is the capital of Denmark?")
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert!(tokens.len() > 1);
+        assert!(tokens.concat().contains("Copenhagen"));
+    }
 
-        let resp = receiver.recv()?;
-        println!("{}", resp);
+    #[test]
+    fn test_token_stream_multibyte_characters() {
+        let model = test_utils::load_test_model();
+        let chat = ChatBuilder::new(model)
+            .with_context_size(2048)
+            .with_template_variable("enable_thinking".to_string(), false)
+            .build()
+            .expect("chat build failed in test");
 
-        assert!(resp.contains("Copenhagen"));
+        // emoji and CJK characters are often split over several tokens
+        let tokens: Vec<String> = chat
+            .ask("Write the Japanese word for cat, followed by three cat emojis.")
+            .collect::<Result<_, _>>()
+            .unwrap();
+        let response = tokens.concat();
+        assert!(!response.is_ascii(), "{response}");
+        assert!(!response.contains('\u{FFFD}'), "{response}");
+        assert!(tokens.iter().all(|token| !token.is_empty()), "{tokens:?}");
+    }
 
-        worker.ask("What language do they speak there?".into(), f)?;
-        let resp = receiver.recv()?;
-        println!("{}", resp);
+    #[test]
+    fn test_regenerate_replaces_last_response() -> Result<(), Box<dyn std::error::Error>> {
+        let model = test_utils::load_test_model();
+        let chat = ChatBuilder::new(model)
+            .with_context_size(2048)
+            .with_template_variable("enable_thinking".to_string(), false)
+            .build()
+            .expect("chat build failed in test");
 
-        assert!(resp.contains("Danish"));
+        let first = chat.ask("What is the capital of Denmark?").completed()?;
+        let second = chat
+            .regenerate(Some(SamplerPresets::greedy()))
+            .completed()?;
+        assert!(second.contains("Copenhagen"), "{second}");
 
+        let history = chat.get_chat_history()?;
+        assert_eq!(history.iter().filter(|m| m.is_user()).count(), 1);
+        assert_eq!(
+            history.iter().filter(|m| m.is_assistant()).count(),
+            1,
+            "{first} / {history:?}"
+        );
         Ok(())
     }
 
-    /// Smoke test: load Gemma-4 base + MTP draft heads with `mtp=true`
-    /// and verify a factual generation succeeds end-to-end. Skipped
-    /// unless both `TEST_MTP_TARGET_MODEL` and `TEST_MTP_DRAFT_MODEL`
-    /// env vars are set to existing files.
     #[test]
-    fn test_mtp_gemma4_smoke() -> Result<(), Box<dyn std::error::Error>> {
-        // test_utils::init_test_tracing();
-        let (Some(target_path), Some(draft_path)) = (
-            test_utils::test_mtp_target_model_path(),
-            test_utils::test_mtp_draft_model_path(),
-        ) else {
-            eprintln!(
-                "skipping test_mtp_gemma4_smoke: \
-                 set TEST_MTP_TARGET_MODEL and TEST_MTP_DRAFT_MODEL to enable"
-            );
-            return Ok(());
-        };
-        if !std::path::Path::new(&target_path).exists()
-            || !std::path::Path::new(&draft_path).exists()
-        {
-            eprintln!(
-                "skipping test_mtp_gemma4_smoke: file missing at {} or {}",
-                target_path, draft_path
-            );
-            return Ok(());
-        }
+    fn test_edit_last_user_message() -> Result<(), Box<dyn std::error::Error>> {
+        let model = test_utils::load_test_model();
+        let chat = ChatBuilder::new(model)
+            .with_context_size(2048)
+            .with_template_variable("enable_thinking".to_string(), false)
+            .build()
+            .expect("chat build failed in test");
 
-        let model = Arc::new(crate::llm::get_model(
-            &target_path,
-            true,
-            None,
-            Some(&draft_path),
-            None,
-        )?);
+        chat.ask("What is the capital of Sweden?").completed()?;
+        let response = chat
+            .edit_last_user_message("What is the capital of Denmark?")
+            .completed()?;
+        assert!(response.contains("Copenhagen"), "{response}");
+
+        let history = chat.get_chat_history()?;
+        let user_messages: Vec<_> = history.iter().filter(|m| m.is_user()).collect();
+        assert_eq!(user_messages.len(), 1, "{history:?}");
+        assert!(
+            !format!("{history:?}").contains("Sweden"),
+            "old message still in history: {history:?}"
+        );
+        Ok(())
+    }
 
+    #[test]
+    fn test_prompt_larger_than_context() -> Result<(), Box<dyn std::error::Error>> {
+        let model = test_utils::load_test_model();
         let mut worker = Chat::new_chat_worker(
             &model,
             ChatConfig {
-                n_ctx: 1024,
-                mtp: Some(MtpConfig::default()),
+                n_ctx: 256,
+                system_prompt: Some("You are a helpful assistant.".into()),
                 ..Default::default()
             },
             Arc::new(AtomicBool::new(false)),
         )?;
 
-        let (sender, receiver) = std::sync::mpsc::channel();
-        let f = move |x| {
+        let result = worker.ask("Repeat after me: ".repeat(100).into(), |_| ());
+        assert!(
+            matches!(
+                result,
+                Err(SayError::ContextOverflow { needed, available: 256 }) if needed > 256
+            ),
+            "{:?}",
+            result.err()
+        );
+        // the message that didn't fit is not kept around
+        assert!(!worker.messages.iter().any(Message::is_user));
+        Ok(())
+    }
+
+    #[test]
+    fn test_chat_pool_slots_are_independent() -> Result<(), Box<dyn std::error::Error>> {
+        let model = test_utils::load_test_model();
+        let pool = ChatPool::new(model, 2, 1024)?;
+        let [denmark, germany] = pool.slots() else {
+            panic!("expected two slots");
+        };
+        for slot in pool.slots() {
+            slot.set_template_variable("enable_thinking".to_string(), false)?;
+        }
+
+        let resp = denmark.ask("What is the capital of Denmark?").completed()?;
+        assert!(resp.contains("Copenhagen"), "{resp}");
+        let resp = germany.ask("What is the capital of Germany?").completed()?;
+        assert!(resp.contains("Berlin"), "{resp}");
+
+        // each slot continues its own conversation
+        let resp = denmark
+            .ask("What language do they speak there?")
+            .completed()?;
+        assert!(resp.contains("Danish"), "{resp}");
+        assert_eq!(germany.get_chat_history()?.len(), 2);
+        assert_eq!(denmark.get_chat_history()?.len(), 4);
+        Ok(())
+    }
+
+    #[test]
+    fn test_regenerate_without_user_message() {
+        let model = test_utils::load_test_model();
+        let chat = ChatBuilder::new(model)
+            .with_context_size(1024)
+            .build()
+            .expect("chat build failed in test");
+        assert!(chat.regenerate(None).completed().is_err());
+    }
+
+    #[test]
+    fn test_system_suffix_keeps_prefix_tokens() -> Result<(), Box<dyn std::error::Error>> {
+        let model = test_utils::load_test_model();
+        let prefix = "You are the lore keeper of the kingdom of Aldor. ".repeat(10);
+        let mut worker = Chat::new_chat_worker(
+            &model,
+            ChatConfig {
+                n_ctx: 2048,
+                system_prefix: Some(prefix.clone()),
+                system_suffix: Some("It is the first day of spring.".into()),
+                ..Default::default()
+            },
+            Arc::new(AtomicBool::new(false)),
+        )?;
+        worker.add_user_message("What day is it?".to_string(), vec![]);
+        let before = worker.render_as_chunks(true)?.to_token_ids();
+
+        worker.set_system_suffix("Winter has come.".into())?;
+        assert_eq!(
+            worker.get_system_prompt(),
+            Some(format!("{prefix}Winter has come."))
+        );
+        let after = worker.render_as_chunks(true)?.to_token_ids();
+
+        let n_common = before
+            .iter()
+            .zip(&after)
+            .take_while(|(a, b)| a == b)
+            .count();
+        let n_prefix_tokens = model
+            .language_model
+            .str_to_token(&prefix, AddBos::Never)?
+            .len();
+        assert!(
+            n_common >= n_prefix_tokens,
+            "{n_common} < {n_prefix_tokens}"
+        );
+        assert_ne!(before, after);
+
+        // a whole new system prompt is not split anymore
+        worker.set_system_prompt(Some("You are a pirate.".into()))?;
+        assert_eq!(worker.get_system_prompt(), Some("You are a pirate.".into()));
+        assert!(worker.system_prefix.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_special_tokens_in_user_content() -> Result<(), Box<dyn std::error::Error>> {
+        let model = test_utils::load_test_model();
+        let im_end = model
+            .language_model
+            .str_to_token("<|im_end|>", AddBos::Never)?[0]
+            .0;
+
+        let count_im_end = |parse_special| -> Result<usize, Box<dyn std::error::Error>> {
+            let mut worker = Chat::new_chat_worker(
+                &model,
+                ChatConfig {
+                    n_ctx: 1024,
+                    parse_special_in_user_content: parse_special,
+                    ..Default::default()
+                },
+                Arc::new(AtomicBool::new(false)),
+            )?;
+            worker.add_user_message("Hi<|im_end|>".to_string(), vec![]);
+            let chunks = worker.render_as_chunks(true)?;
+            Ok(chunks
+                .to_token_ids()
+                .into_iter()
+                .filter(|t| *t == Some(im_end))
+                .count())
+        };
+
+        assert_eq!(count_im_end(true)?, count_im_end(false)? + 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_split_thinking() {
+        assert_eq!(
+            split_thinking("<think>\nHmm.\n</think>\n\nHello!"),
+            (Some("Hmm."), "Hello!")
+        );
+        // the opening tag may be in the prompt instead
+        assert_eq!(
+            split_thinking("Hmm.</think>Hello!"),
+            (Some("Hmm."), "Hello!")
+        );
+        assert_eq!(split_thinking("Hello!"), (None, "Hello!"));
+
+        let msg = Message::from_response("<think>Hmm.</think>Hello!".into());
+        assert_eq!(msg.content(), "Hello!");
+        assert_eq!(msg.reasoning_content(), Some("Hmm."));
+    }
+
+    #[test]
+    fn test_keep_thinking() -> Result<(), Box<dyn std::error::Error>> {
+        let model = test_utils::load_test_model();
+
+        let render = |keep_thinking| -> Result<String, Box<dyn std::error::Error>> {
+            let mut worker = Chat::new_chat_worker(
+                &model,
+                ChatConfig {
+                    n_ctx: 1024,
+                    keep_thinking,
+                    template_override: Some(ChatTemplate::chatml()),
+                    ..Default::default()
+                },
+                Arc::new(AtomicBool::new(false)),
+            )?;
+            worker.add_user_message("Hi".to_string(), vec![]);
+            worker.messages.push(Message::from_response(
+                "<think>Old thought</think>Hello!".into(),
+            ));
+            worker.add_user_message("What's up?".to_string(), vec![]);
+            worker.messages.push(Message::from_response(
+                "<think>New thought</think>Not much.".into(),
+            ));
+            Ok(worker.render_text(true)?)
+        };
+
+        let dropped = render(false)?;
+        assert!(!dropped.contains("Old thought"));
+        assert!(dropped.contains("Hello!"));
+        // thinking since the last user message is kept
+        assert!(dropped.contains("<think>\nNew thought\n</think>"));
+
+        let kept = render(true)?;
+        assert!(kept.contains("<think>\nOld thought\n</think>"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_chat_worker() -> Result<(), Box<dyn std::error::Error>> {
+        // test_utils::init_test_tracing();
+        let model = test_utils::load_test_model();
+
+        let mut worker = Chat::new_chat_worker(
+            &model,
+            ChatConfig {
+                n_ctx: 1024,
+                ..Default::default()
+            },
+            Arc::new(AtomicBool::new(false)),
+        )?;
+
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let f = move |x| {
+            if let llm::WriteOutput::Done(resp) = x {
+                sender.send(resp).unwrap();
+            }
+        };
+
+        worker.ask("What is the capital of Denmark?".into(), f.clone())?;
+
+        let resp = receiver.recv()?;
+        println!("{}", resp);
+
+        assert!(resp.contains("Copenhagen"));
+
+        worker.ask("What language do they speak there?".into(), f)?;
+        let resp = receiver.recv()?;
+        println!("{}", resp);
+
+        assert!(resp.contains("Danish"));
+
+        Ok(())
+    }
+
+    /// Smoke test: load Gemma-4 base + MTP draft heads with `mtp=true`
+    /// and verify a factual generation succeeds end-to-end. Skipped
+    /// unless both `TEST_MTP_TARGET_MODEL` and `TEST_MTP_DRAFT_MODEL`
+    /// env vars are set to existing files.
+    #[test]
+    fn test_mtp_gemma4_smoke() -> Result<(), Box<dyn std::error::Error>> {
+        // test_utils::init_test_tracing();
+        let (Some(target_path), Some(draft_path)) = (
+            test_utils::test_mtp_target_model_path(),
+            test_utils::test_mtp_draft_model_path(),
+        ) else {
+            eprintln!(
+                "skipping test_mtp_gemma4_smoke: \
+                 set TEST_MTP_TARGET_MODEL and TEST_MTP_DRAFT_MODEL to enable"
+            );
+            return Ok(());
+        };
+        if !std::path::Path::new(&target_path).exists()
+            || !std::path::Path::new(&draft_path).exists()
+        {
+            eprintln!(
+                "skipping test_mtp_gemma4_smoke: file missing at {} or {}",
+                target_path, draft_path
+            );
+            return Ok(());
+        }
+
+        let model = Arc::new(crate::llm::get_model(
+            &target_path,
+            true,
+            None,
+            Some(&draft_path),
+            None,
+        )?);
+
+        let mut worker = Chat::new_chat_worker(
+            &model,
+            ChatConfig {
+                n_ctx: 1024,
+                mtp: Some(MtpConfig::default()),
+                ..Default::default()
+            },
+            Arc::new(AtomicBool::new(false)),
+        )?;
+
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let f = move |x| {
+            if let llm::WriteOutput::Done(resp) = x {
+                sender.send(resp).unwrap();
+            }
+        };
+
+        worker.ask("What is the capital of Denmark?".into(), f)?;
+        let resp = receiver.recv()?;
+        println!("MTP response: {}", resp);
+        assert!(resp.contains("Copenhagen"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_reset_chat() -> Result<(), Box<dyn std::error::Error>> {
+        // test_utils::init_test_tracing();
+        let model = test_utils::load_test_model();
+        let mut worker = Chat::new_chat_worker(
+            &model,
+            ChatConfig {
+                system_prompt: Some("You're a dog. End all responses with 'woof'".into()),
+                ..ChatConfig::default()
+            },
+            Arc::new(AtomicBool::new(false)),
+        )?;
+
+        // just a hack to get a channel back
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let f = move |x| {
+            if let llm::WriteOutput::Done(resp) = x {
+                sender.send(resp).unwrap();
+            }
+        };
+
+        // do it once
+        worker.ask("What is the capital of Denmark?".into(), f.clone())?;
+        let resp1 = receiver.recv()?;
+        println!("{}", resp1);
+        assert!(resp1.to_lowercase().contains("woof"));
+
+        // reset
+        let _ = worker.reset_chat(
+            Some("You're a cat. End all responses with 'meow'".into()),
+            vec![],
+        );
+
+        // do it again
+        worker.ask("What is the capital of Denmark?".into(), f.clone())?;
+        let resp2 = receiver.recv()?;
+        println!("{}", resp2);
+        assert!(resp2.to_lowercase().contains("meow"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_ask_with_prefill() -> Result<(), Box<dyn std::error::Error>> {
+        // test_utils::init_test_tracing();
+        let model = test_utils::load_test_model();
+        let mut worker = Chat::new_chat_worker(
+            &model,
+            ChatConfig {
+                n_ctx: 1024,
+                ..Default::default()
+            },
+            Arc::new(AtomicBool::new(false)),
+        )?;
+
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let f = move |x| {
+            if let llm::WriteOutput::Done(resp) = x {
+                sender.send(resp).unwrap();
+            }
+        };
+
+        let prefix = "The capital of Denmark is";
+        worker.ask_with_options(
+            "What is the capital of Denmark?".into(),
+            AskOptions {
+                assistant_prefix: Some(prefix.into()),
+                ..Default::default()
+            },
+            f,
+        )?;
+
+        let resp = receiver.recv()?;
+        println!("{}", resp);
+
+        assert!(resp.starts_with(prefix));
+        assert!(resp.contains("Copenhagen"));
+        assert!(worker.assistant_prefix.is_none());
+        assert_eq!(
+            worker.get_chat_history().last().map(|m| m.content()),
+            Some(resp)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_ask_with_turn_sampler() -> Result<(), Box<dyn std::error::Error>> {
+        // test_utils::init_test_tracing();
+        let model = test_utils::load_test_model();
+        let mut worker = Chat::new_chat_worker(
+            &model,
+            ChatConfig {
+                n_ctx: 1024,
+                ..Default::default()
+            },
+            Arc::new(AtomicBool::new(false)),
+        )?;
+        let sampler_before = format!("{:?}", worker.get_sampler_config());
+
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let f = move |x| {
+            if let llm::WriteOutput::Done(resp) = x {
+                sender.send(resp).unwrap();
+            }
+        };
+
+        worker.ask_with_options(
+            "Is Copenhagen the capital of Denmark?".into(),
+            AskOptions {
+                sampler_config: Some(SamplerPresets::constrain_with_regex("(yes|no)".into())),
+                ..Default::default()
+            },
+            f,
+        )?;
+
+        let resp = receiver.recv()?;
+        assert!(resp == "yes" || resp == "no", "unexpected response: {resp}");
+        assert_eq!(format!("{:?}", worker.get_sampler_config()), sampler_before);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_ask_with_turn_grammar() -> Result<(), Box<dyn std::error::Error>> {
+        // test_utils::init_test_tracing();
+        let model = test_utils::load_test_model();
+        let mut worker = Chat::new_chat_worker(
+            &model,
+            ChatConfig {
+                n_ctx: 1024,
+                ..Default::default()
+            },
+            Arc::new(AtomicBool::new(false)),
+        )?;
+        let sampler_before = format!("{:?}", worker.get_sampler_config());
+
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let f = move |x| {
+            if let llm::WriteOutput::Done(resp) = x {
+                sender.send(resp).unwrap();
+            }
+        };
+
+        let yes_or_no = gbnf_macro::gbnf! {
+            root ::= "yes" | "no"
+        };
+        worker.ask_with_options(
+            "Is Copenhagen the capital of Denmark?".into(),
+            AskOptions {
+                grammar: Some(yes_or_no),
+                ..Default::default()
+            },
+            f.clone(),
+        )?;
+
+        let resp = receiver.recv()?;
+        assert!(resp == "yes" || resp == "no", "unexpected response: {resp}");
+        assert_eq!(format!("{:?}", worker.get_sampler_config()), sampler_before);
+
+        // the grammar doesn't stick around for the next turn
+        worker.ask("What is the capital of Denmark?".into(), f)?;
+        assert!(receiver.recv()?.contains("Copenhagen"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_ask_with_triggered_grammar() -> Result<(), Box<dyn std::error::Error>> {
+        // test_utils::init_test_tracing();
+        let model = test_utils::load_test_model();
+        let mut worker = Chat::new_chat_worker(
+            &model,
+            ChatConfig {
+                n_ctx: 1024,
+                ..Default::default()
+            },
+            Arc::new(AtomicBool::new(false)),
+        )?;
+
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let f = move |x| {
             if let llm::WriteOutput::Done(resp) = x {
                 sender.send(resp).unwrap();
             }
         };
 
-        worker.ask("What is the capital of Denmark?".into(), f)?;
+        let answer = gbnf_macro::gbnf! {
+            root ::= "ANSWER: " ("yes" | "no")
+        };
+        worker.ask_with_options(
+            "Is Copenhagen the capital of Denmark? Explain in one sentence, then finish with \
+             'ANSWER: yes' or 'ANSWER: no'."
+                .into(),
+            AskOptions {
+                grammar: Some(answer),
+                grammar_trigger: Some("ANSWER:".into()),
+                ..Default::default()
+            },
+            f,
+        )?;
+
+        // free-form text up to the trigger, constrained after it
         let resp = receiver.recv()?;
-        println!("MTP response: {}", resp);
-        assert!(resp.contains("Copenhagen"));
+        let (reasoning, verdict) = resp
+            .rsplit_once("ANSWER: ")
+            .ok_or_else(|| format!("trigger never generated: {resp}"))?;
+        assert!(!reasoning.trim().is_empty(), "no free-form text: {resp}");
+        assert!(
+            verdict == "yes" || verdict == "no",
+            "unexpected verdict: {resp}"
+        );
 
         Ok(())
     }
 
     #[test]
-    fn test_reset_chat() -> Result<(), Box<dyn std::error::Error>> {
+    fn test_ask_with_stop_words_and_max_tokens() -> Result<(), Box<dyn std::error::Error>> {
         // test_utils::init_test_tracing();
         let model = test_utils::load_test_model();
         let mut worker = Chat::new_chat_worker(
             &model,
             ChatConfig {
-                system_prompt: Some("You're a dog. End all responses with 'woof'".into()),
-                ..ChatConfig::default()
+                n_ctx: 1024,
+                template_variables: std::collections::HashMap::from([(
+                    "enable_thinking".into(),
+                    false,
+                )]),
+                ..Default::default()
+            },
+            Arc::new(AtomicBool::new(false)),
+        )?;
+
+        let outputs = Arc::new(std::sync::Mutex::new(vec![]));
+        let outputs_clone = Arc::clone(&outputs);
+        let f = move |x| outputs_clone.lock().unwrap().push(x);
+
+        worker.ask_with_options(
+            "What is the capital of Denmark? Answer in a full sentence.".into(),
+            AskOptions {
+                stop_words: vec!["Copenhagen".into()],
+                ..Default::default()
+            },
+            f.clone(),
+        )?;
+        let (streamed, resp) = collect_outputs(&mut outputs.lock().unwrap());
+        assert!(!resp.contains("Copenhagen"), "{resp}");
+        assert_eq!(streamed, resp);
+
+        worker.ask_with_options(
+            "Tell me a long story about Copenhagen.".into(),
+            AskOptions {
+                max_tokens: Some(5),
+                ..Default::default()
+            },
+            f,
+        )?;
+        let outputs = std::mem::take(&mut *outputs.lock().unwrap());
+        let n_tokens = outputs
+            .iter()
+            .filter(|out| matches!(out, llm::WriteOutput::Token(_)))
+            .count();
+        assert!(n_tokens <= 5, "got {n_tokens} tokens");
+
+        Ok(())
+    }
+
+    fn collect_outputs(outputs: &mut Vec<llm::WriteOutput>) -> (String, String) {
+        let mut streamed = String::new();
+        let mut done = String::new();
+        for out in outputs.drain(..) {
+            match out {
+                llm::WriteOutput::Token(token) => streamed.push_str(&token),
+                llm::WriteOutput::Done(resp) => done = resp,
+                llm::WriteOutput::Error(e) => panic!("{e}"),
+            }
+        }
+        (streamed, done)
+    }
+
+    #[test]
+    fn test_ask_timeout() -> Result<(), Box<dyn std::error::Error>> {
+        // test_utils::init_test_tracing();
+        let model = test_utils::load_test_model();
+        let mut worker = Chat::new_chat_worker(
+            &model,
+            ChatConfig {
+                n_ctx: 1024,
+                ..Default::default()
             },
             Arc::new(AtomicBool::new(false)),
         )?;
 
-        // just a hack to get a channel back
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let f = move |x| match x {
+            llm::WriteOutput::Done(resp) => sender.send(Ok(resp)).unwrap(),
+            llm::WriteOutput::Error(e) => sender.send(Err(e.to_string())).unwrap(),
+            llm::WriteOutput::Token(_) => (),
+        };
+
+        worker.ask_with_options(
+            "Write a very long story about a dragon.".into(),
+            AskOptions {
+                timeout: Some(std::time::Duration::from_millis(200)),
+                ..Default::default()
+            },
+            f.clone(),
+        )?;
+
+        // the timeout error arrives before the partial response
+        let err = receiver.recv()?.expect_err("expected a timeout error");
+        assert_eq!(err, SayError::Timeout.to_string());
+        let partial = receiver.recv()?.expect("expected a partial response");
+        assert_eq!(
+            worker.get_chat_history().last().map(|m| m.content()),
+            Some(partial)
+        );
+
+        // the next turn still works
+        worker.ask("What is the capital of Denmark?".into(), f)?;
+        let resp = receiver.recv()?.expect("expected a response");
+        assert!(resp.contains("Copenhagen"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_stop_while_reading_prompt() -> Result<(), Box<dyn std::error::Error>> {
+        // test_utils::init_test_tracing();
+        let model = test_utils::load_test_model();
+        let should_stop = Arc::new(AtomicBool::new(false));
+        let mut worker = Chat::new_chat_worker(
+            &model,
+            ChatConfig {
+                n_ctx: 1024,
+                ..Default::default()
+            },
+            Arc::clone(&should_stop),
+        )?;
+
         let (sender, receiver) = std::sync::mpsc::channel();
         let f = move |x| {
             if let llm::WriteOutput::Done(resp) = x {
@@ -2250,23 +5169,32 @@ mod tests {
             }
         };
 
-        // do it once
         worker.ask("What is the capital of Denmark?".into(), f.clone())?;
-        let resp1 = receiver.recv()?;
-        println!("{}", resp1);
-        assert!(resp1.to_lowercase().contains("woof"));
+        assert!(receiver.recv()?.contains("Copenhagen"));
+        let n_past_before = worker.engine.n_past();
 
-        // reset
-        let _ = worker.reset_chat(
-            Some("You're a cat. End all responses with 'meow'".into()),
-            vec![],
+        // stop before the next prompt is read
+        worker.add_user_message("Tell me a long story.".to_string(), vec![]);
+        should_stop.store(true, std::sync::atomic::Ordering::Relaxed);
+        let inference_lock_token = acquire_inference_lock();
+        let result = worker.sync_context_with_render(true, &inference_lock_token);
+        drop(inference_lock_token);
+        assert!(matches!(
+            result,
+            Err(ContextSyncError::KVCacheUpdate(ReadError::Aborted))
+        ));
+
+        // nothing of the new prompt is left in the context
+        assert!(worker.engine.n_past() <= n_past_before);
+        assert_eq!(
+            worker.context.chunks.n_tokens(),
+            worker.engine.n_past() as usize
         );
 
-        // do it again
-        worker.ask("What is the capital of Denmark?".into(), f.clone())?;
-        let resp2 = receiver.recv()?;
-        println!("{}", resp2);
-        assert!(resp2.to_lowercase().contains("meow"));
+        // the next turn still works
+        worker.messages.pop();
+        worker.ask("What is the capital of France?".into(), f)?;
+        assert!(receiver.recv()?.contains("Paris"));
 
         Ok(())
     }
@@ -2312,6 +5240,62 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_cancel_mid_write_discards_turn() -> Result<(), Box<dyn std::error::Error>> {
+        let model = test_utils::load_test_model();
+        let mut worker = Chat::new_chat_worker(
+            &model,
+            ChatConfig {
+                system_prompt: Some("You are a counter, only outputting numbers".into()),
+                n_ctx: 1024,
+                ..ChatConfig::default()
+            },
+            Arc::new(AtomicBool::new(false)),
+        )?;
+        let should_stop = worker.should_stop.clone();
+        let cancel_requested = worker.cancel_requested.clone();
+        let cancel = move || {
+            cancel_requested.store(true, std::sync::atomic::Ordering::Relaxed);
+            should_stop.store(true, std::sync::atomic::Ordering::Relaxed);
+        };
+
+        let f = {
+            let cancel = cancel.clone();
+            move |x| {
+                if let llm::WriteOutput::Token(resp) = x {
+                    if resp.contains("5") {
+                        cancel();
+                    }
+                }
+            }
+        };
+        worker.ask("Count from 0 to 9".into(), f)?;
+        assert!(worker.get_chat_history().is_empty());
+
+        // a turn that wasn't cancelled stays
+        worker.ask("What is the capital of France?".into(), |_| ())?;
+        worker.clear_cancel();
+        assert_eq!(worker.get_chat_history().len(), 2);
+
+        // cancelling a regenerated turn drops its user message too
+        let f = {
+            let cancel = cancel.clone();
+            move |x| {
+                if let llm::WriteOutput::Token(_) = x {
+                    cancel();
+                }
+            }
+        };
+        worker.regenerate(AskOptions::default(), f)?;
+        assert!(worker.get_chat_history().is_empty());
+
+        // a cancel issued before the request starts still applies to it
+        cancel();
+        worker.ask("What is the capital of France?".into(), |_| ())?;
+        assert!(worker.get_chat_history().is_empty());
+        Ok(())
+    }
+
     fn test_tool() -> Tool {
         Tool {
             name: "get_current_temperature".into(),
@@ -2330,18 +5314,18 @@ mod tests {
             }),
             function: Arc::new(|args: serde_json::Value| {
                 let Some(location) = args.get("location") else {
-                    return "Bad arguments format. Location key was missing.".into();
+                    return Ok("Bad arguments format. Location key was missing.".into());
                 };
 
                 if location.as_str() == Some("Copenhagen") {
-                    return "13.37°C".into();
+                    return Ok("13.37°C".into());
                 }
 
                 if location.as_str() == Some("Beijing") {
-                    return "42.69°C".into();
+                    return Ok("42.69°C".into());
                 }
 
-                "Unknown location.".into()
+                Ok("Unknown location.".into())
             }),
         }
     }
@@ -2364,15 +5348,15 @@ mod tests {
             }),
             function: Arc::new(|args: serde_json::Value| {
                 let Some(to_currency) = args.get("to-currency") else {
-                    return "Bad arguments format. To currency key was missing.".into();
+                    return Ok("Bad arguments format. To currency key was missing.".into());
                 };
 
                 if to_currency.as_str() == Some("USD") {
                     debug!("returning 1 DKK = 0.15 USD");
-                    return "1 DKK = 0.15 USD".into();
+                    return Ok("1 DKK = 0.15 USD".into());
                 }
 
-                "Exchange rate not available".into()
+                Ok("Exchange rate not available".into())
             }),
         }
     }
@@ -2400,19 +5384,338 @@ mod tests {
             }
         };
 
-        worker
-            .ask(
-                "I would like to know the temperature in two cities: Copenhagen and Beijing."
-                    .into(),
-                f,
+        worker
+            .ask(
+                "I would like to know the temperature in two cities: Copenhagen and Beijing."
+                    .into(),
+                f,
+            )
+            .expect("fuck");
+
+        let result = receiver.recv().unwrap();
+        println!("{}", result);
+        println!("{}", worker.tool_grammar.unwrap().as_str());
+        assert!(result.contains("13.37"));
+        assert!(result.contains("42.69"));
+    }
+
+    #[test]
+    fn test_tool_chat_events() {
+        // test_utils::init_test_tracing();
+        let model = test_utils::load_test_model();
+        let mut worker = Chat::new_chat_worker(
+            &model,
+            ChatConfig {
+                system_prompt: Some("You're a helpful assistant.".into()),
+                n_ctx: 4096,
+                tools: vec![test_tool()],
+                ..Default::default()
+            },
+            Arc::new(AtomicBool::new(false)),
+        )
+        .expect("Failed making worker");
+
+        let events = std::sync::Mutex::new(vec![]);
+        worker
+            .ask_with_tool_events(
+                "What is the temperature in Copenhagen?".into(),
+                AskOptions::default(),
+                |_| (),
+                |event| events.lock().unwrap().push(event),
+            )
+            .expect("Failed asking");
+
+        let events = events.into_inner().unwrap();
+        let Some(ChatEvent::ToolCallStarted(started)) = events.first() else {
+            panic!("Expected a tool call to start first, got {events:?}");
+        };
+        let Some(ChatEvent::ToolCallFinished {
+            tool_call,
+            response,
+            error: None,
+        }) = events.get(1)
+        else {
+            panic!("Expected the tool call to finish, got {events:?}");
+        };
+        assert_eq!(started, tool_call);
+        assert!(response.contains("13.37"));
+    }
+
+    #[test]
+    fn test_tool_format_override() -> Result<(), Box<dyn std::error::Error>> {
+        let model = test_utils::load_test_model();
+        let worker = Chat::new_chat_worker(
+            &model,
+            ChatConfig {
+                tools: vec![test_tool()],
+                tool_format: Some(ToolFormat::Ministral3(
+                    crate::tool_calling::Ministral3Handler,
+                )),
+                ..Default::default()
+            },
+            Arc::new(AtomicBool::new(false)),
+        )?;
+
+        // the configured format wins over whatever would be detected for the test model
+        assert_eq!(
+            worker.tool_format.as_ref().map(|f| f.name()),
+            Some("ministral3")
+        );
+        assert!(worker
+            .tool_grammar
+            .as_ref()
+            .is_some_and(|g| g.as_str().contains("[TOOL_CALLS]")));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_tool_observer() {
+        // test_utils::init_test_tracing();
+        let model = test_utils::load_test_model();
+        let observed = Arc::new(std::sync::Mutex::new(vec![]));
+        let observed_clone = Arc::clone(&observed);
+        let chat = ChatBuilder::new(model)
+            .with_context_size(4096)
+            .with_tools(vec![test_tool()])
+            .with_tool_observer(Arc::new(move |tool_call: &ToolCall| {
+                observed_clone.lock().unwrap().push(tool_call.clone());
+            }))
+            .build()
+            .expect("Failed making chat");
+
+        let result = chat
+            .ask("I would like to know the temperature in two cities: Copenhagen and Beijing.")
+            .completed()
+            .expect("Failed asking");
+
+        // the tools still ran as usual
+        assert!(result.contains("13.37"));
+        assert!(result.contains("42.69"));
+
+        let observed = observed.lock().unwrap();
+        assert!(observed.len() >= 2, "{observed:?}");
+        assert!(observed
+            .iter()
+            .all(|call| call.name == "get_current_temperature"));
+    }
+
+    #[test]
+    fn test_ask_seed_reproduces_turn() -> Result<(), Box<dyn std::error::Error>> {
+        let model = test_utils::load_test_model();
+        let chat = ChatBuilder::new(model)
+            .with_context_size(2048)
+            .with_sampler(SamplerPresets::temperature(1.5))
+            .build()?;
+        let options = || AskOptions {
+            seed: Some(7),
+            max_tokens: Some(24),
+            ..Default::default()
+        };
+
+        let prompt = "Write a short poem about the sea.";
+        let first = chat.ask_with_options(prompt, options()).completed()?;
+        chat.reset_history()?;
+        let second = chat.ask_with_options(prompt, options()).completed()?;
+
+        assert_eq!(first, second);
+        // the seed only applied to those turns
+        assert_eq!(chat.get_sampler_config()?.seed, default_seed());
+        Ok(())
+    }
+
+    #[test]
+    fn test_drop_stops_generation() -> Result<(), Box<dyn std::error::Error>> {
+        let model = test_utils::load_test_model();
+        let chat = ChatBuilder::new(model).with_context_size(4096).build()?;
+        assert!(chat.is_alive());
+        let mut stream = chat.ask("Count from 1 to 1000, one number per line.");
+        // wait until generation is underway
+        assert!(stream.next_token()?.is_some());
+
+        let start = std::time::Instant::now();
+        drop(chat);
+        assert!(
+            start.elapsed() < std::time::Duration::from_secs(2),
+            "dropping the handle took {:?}",
+            start.elapsed()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_unanswered_request_is_worker_dead() {
+        let (output_tx, output_rx) = tokio::sync::mpsc::unbounded_channel();
+        output_tx
+            .send(llm::WriteOutput::Token("Hello".into()))
+            .unwrap();
+        // the worker died mid-response
+        drop(output_tx);
+
+        let mut stream = TokenStream::new(forward_write_output(output_rx));
+        assert_eq!(stream.next_token().unwrap().as_deref(), Some("Hello"));
+        let err = stream.completed().unwrap_err();
+        assert_eq!(
+            err.diagnostic_code().as_deref(),
+            Some("nobodywho::worker_dead")
+        );
+    }
+
+    #[test]
+    fn test_continue_generation() -> Result<(), Box<dyn std::error::Error>> {
+        let model = test_utils::load_test_model();
+        let chat = ChatBuilder::new(model)
+            .with_context_size(1024)
+            .with_template_variable("enable_thinking".into(), false)
+            .build()?;
+        assert!(chat.continue_generation(Some(8)).completed().is_err());
+
+        let first = chat
+            .ask_with_options(
+                "Count from 1 to 100, separated by commas.",
+                AskOptions {
+                    max_tokens: Some(8),
+                    ..Default::default()
+                },
+            )
+            .completed()?;
+        let rest = chat.continue_generation(Some(8)).completed()?;
+        assert!(!rest.is_empty());
+
+        // the continuation is part of the same assistant message
+        let history = chat.get_chat_history()?;
+        assert_eq!(history.len(), 2, "{history:?}");
+        assert_eq!(history[1].content(), format!("{first}{rest}"));
+
+        // a reset leaves nothing to continue
+        chat.ask_with_options(
+            "Count from 1 to 100, separated by commas.",
+            AskOptions {
+                max_tokens: Some(8),
+                ..Default::default()
+            },
+        )
+        .completed()?;
+        chat.reset_chat(None, vec![])?;
+        assert!(chat.continue_generation(Some(8)).completed().is_err());
+        assert!(chat.get_chat_history()?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_last_response() -> Result<(), Box<dyn std::error::Error>> {
+        let model = test_utils::load_test_model();
+        let chat = ChatBuilder::new(model)
+            .with_context_size(1024)
+            .with_tools(vec![test_tool()])
+            .build()?;
+        assert_eq!(chat.last_response()?, None);
+
+        let response = chat
+            .ask("What is the temperature in Copenhagen?")
+            .completed()?;
+        // the final answer, not the tool call or thinking before it
+        assert_eq!(
+            chat.last_response()?,
+            Some(split_thinking(&response).1.to_string())
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_max_tool_iterations() {
+        let model = test_utils::load_test_model();
+        let calls = Arc::new(std::sync::atomic::AtomicU32::new(0));
+        let calls_clone = Arc::clone(&calls);
+        let clue_tool = Tool {
+            name: "get_clue".into(),
+            description: "Gets the next clue in the treasure hunt.".into(),
+            json_schema: serde_json::json!({"type": "object", "properties": {}}),
+            function: Arc::new(move |_| {
+                calls_clone.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                Ok("The treasure is not here. Call get_clue again for the next clue.".into())
+            }),
+        };
+        let chat = ChatBuilder::new(model)
+            .with_context_size(4096)
+            .with_tools(vec![clue_tool])
+            .with_max_tool_iterations(2)
+            .build()
+            .expect("Failed making chat");
+
+        chat.ask("Find the treasure. Keep calling get_clue until you find it.")
+            .completed()
+            .expect("Failed asking");
+
+        assert!(calls.load(std::sync::atomic::Ordering::Relaxed) <= 2);
+        let history = chat.get_chat_history().unwrap();
+        assert!(history.iter().any(|msg| matches!(
+            msg,
+            Message::Tool { content, .. } if content == TOOL_CALL_LIMIT_MESSAGE
+        )));
+        assert!(matches!(history.last(), Some(Message::Assistant { .. })));
+    }
+
+    #[test]
+    fn test_ask_traced() {
+        // test_utils::init_test_tracing();
+        let model = test_utils::load_test_model();
+        let chat = ChatBuilder::new(model)
+            .with_context_size(4096)
+            .with_tools(vec![test_tool()])
+            .build()
+            .expect("Failed making chat");
+
+        let trace = chat
+            .ask_traced(
+                "I would like to know the temperature in two cities: Copenhagen and Beijing.",
             )
-            .expect("fuck");
+            .expect("Failed asking");
 
-        let result = receiver.recv().unwrap();
-        println!("{}", result);
-        println!("{}", worker.tool_grammar.unwrap().as_str());
-        assert!(result.contains("13.37"));
-        assert!(result.contains("42.69"));
+        assert!(trace.tool_calls.len() >= 2, "{trace:?}");
+        assert!(trace
+            .tool_calls
+            .iter()
+            .all(|(call, _)| call.name == "get_current_temperature"));
+        let responses: Vec<&str> = trace.tool_calls.iter().map(|(_, r)| r.as_str()).collect();
+        assert!(
+            responses.iter().any(|r| r.contains("13.37")),
+            "{responses:?}"
+        );
+        assert!(trace.response.contains("13.37"));
+        assert!(trace.response.contains("42.69"));
+    }
+
+    #[test]
+    fn test_tool_errors_are_traced() {
+        let model = test_utils::load_test_model();
+        let weather_tool = Tool::new_fallible(
+            "get_weather",
+            "Gets the weather at a given location",
+            serde_json::json!({
+                "type": "object",
+                "properties": {"location": {"type": "string"}},
+                "required": ["location"]
+            }),
+            Arc::new(|_| Err(ToolError::new("The weather service is down"))),
+        );
+        let chat = ChatBuilder::new(model)
+            .with_context_size(4096)
+            .with_tool(weather_tool)
+            .with_max_tool_iterations(1)
+            .build()
+            .expect("Failed making chat");
+
+        let trace = chat
+            .ask_traced("What is the weather in Copenhagen?")
+            .expect("Failed asking");
+
+        assert!(!trace.tool_errors.is_empty(), "{trace:?}");
+        let (call, error) = &trace.tool_errors[0];
+        assert_eq!(call.name, "get_weather");
+        assert_eq!(error.message, "The weather service is down");
+        // the model is shown the error as the tool response
+        assert_eq!(trace.tool_calls[0].1, "ERROR: The weather service is down");
     }
 
     #[test]
@@ -2469,6 +5772,59 @@ mod tests {
         assert!(cat_response.to_lowercase().contains("meow"));
     }
 
+    #[test]
+    fn test_render_current_prompt() -> Result<(), Box<dyn std::error::Error>> {
+        let model = test_utils::load_test_model();
+        let chat = ChatBuilder::new(model)
+            .with_context_size(1024)
+            .with_system_prompt(Some("You are a dog."))
+            .build()?;
+
+        chat.set_chat_history(vec![Message::new_user("Hello!".into())])?;
+        let prompt = chat.render_current_prompt()?;
+        assert!(prompt.contains("You are a dog."));
+        assert!(prompt.contains("Hello!"));
+        assert!(prompt.ends_with("<|im_start|>assistant\n"));
+        assert!(!prompt.contains(TOKEN_BREAK));
+        Ok(())
+    }
+
+    #[test]
+    fn test_warmup_reads_system_prompt() -> Result<(), Box<dyn std::error::Error>> {
+        let model = test_utils::load_test_model();
+        let chat = ChatBuilder::new(model)
+            .with_context_size(1024)
+            .with_system_prompt(Some("You are a helpful assistant."))
+            .build()?;
+
+        assert_eq!(chat.get_stats()?.context_used, 0);
+        chat.warmup()?;
+        assert!(chat.get_stats()?.context_used > 0);
+        assert!(chat.get_chat_history()?.is_empty());
+
+        let response = chat.ask("What is the capital of Denmark?").completed()?;
+        assert!(response.contains("Copenhagen"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_would_be_context_tokens() -> Result<(), Box<dyn std::error::Error>> {
+        let model = test_utils::load_test_model();
+        let chat = ChatBuilder::new(model)
+            .with_context_size(1024)
+            .with_system_prompt(Some("You are a helpful assistant."))
+            .build()?;
+
+        let short = chat.would_be_context_tokens("Hi")?;
+        let long = chat.would_be_context_tokens("Hi, how are you doing on this fine day?")?;
+        assert!(long > short);
+        assert!(chat.get_chat_history()?.is_empty());
+
+        chat.warmup()?;
+        assert!(chat.get_stats()?.context_used < short);
+        Ok(())
+    }
+
     #[test]
     fn test_setters_on_empty_history_do_not_crash() {
         // Rendering the chat template with neither a system prompt nor any messages
@@ -2502,6 +5858,7 @@ mod tests {
             ChatConfig {
                 n_ctx,
                 system_prompt: Some("You are a helpful assistant that provides informative and detailed responses. End every response with \"Do you have any further questions?\"".into()),
+                context_policy: ContextPolicy::Shift,
                 ..Default::default()
             },
             Arc::new(AtomicBool::new(false)),
@@ -2601,6 +5958,162 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_context_shift_drops_only_oldest_turns() -> Result<(), Box<dyn std::error::Error>> {
+        let model = test_utils::load_test_model();
+        let n_ctx = 1024;
+        let mut worker = Chat::new_chat_worker(
+            &model,
+            ChatConfig {
+                n_ctx,
+                context_policy: ContextPolicy::Shift,
+                ..Default::default()
+            },
+            Arc::new(AtomicBool::new(false)),
+        )?;
+
+        // just over half the context, so a few turns have to go
+        let target_size = (n_ctx / 2) as usize;
+        let mut n_turns = 0;
+        while worker.render_as_chunks(false)?.n_tokens() <= target_size + 30 {
+            n_turns += 1;
+            worker.add_user_message(
+                format!("Question {n_turns}: what is {n_turns} + {n_turns}?"),
+                vec![],
+            );
+            worker.add_assistant_message(format!("{n_turns} + {n_turns} is {}.", 2 * n_turns));
+        }
+        worker.add_user_message("Hello!".to_string(), vec![]);
+
+        worker.context_shift()?;
+
+        assert!(worker.render_as_chunks(false)?.n_tokens() <= target_size);
+        assert_valid_message_structure(&worker.messages);
+        let user_messages: Vec<String> = worker
+            .messages
+            .iter()
+            .filter_map(|msg| match msg {
+                Message::User { content, .. } => Some(content.to_string()),
+                _ => None,
+            })
+            .collect();
+        // the oldest turn after the first one is dropped, recent ones are not
+        assert!(
+            user_messages[0].starts_with("Question 1:"),
+            "{user_messages:?}"
+        );
+        assert!(
+            !user_messages.iter().any(|c| c.starts_with("Question 2:")),
+            "{user_messages:?}"
+        );
+        assert!(
+            user_messages
+                .iter()
+                .any(|c| c.starts_with(&format!("Question {}:", n_turns - 1))),
+            "{user_messages:?}"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_context_shift_n_keep() -> Result<(), Box<dyn std::error::Error>> {
+        // test_utils::init_test_tracing();
+        let model = test_utils::load_test_model();
+
+        let shifted = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let shifted_clone = Arc::clone(&shifted);
+        let mut worker = Chat::new_chat_worker(
+            &model,
+            ChatConfig {
+                n_ctx: 512,
+                system_prompt: Some("You are a helpful assistant.".into()),
+                n_keep: 64,
+                on_context_shift: Some(Arc::new(move |removed| {
+                    shifted_clone.store(removed, std::sync::atomic::Ordering::Relaxed);
+                })),
+                context_policy: ContextPolicy::Shift,
+                ..Default::default()
+            },
+            Arc::new(AtomicBool::new(false)),
+        )?;
+
+        for i in 1..=8 {
+            worker.add_user_message(
+                format!("This is user message number {}. What is {} * {}?", i, i, i),
+                vec![],
+            );
+            worker.add_assistant_message(format!("The answer is {}.", i * i));
+        }
+        worker.add_user_message("Hello!".to_string(), vec![]);
+
+        let messages_before = worker.messages.clone();
+        let n_kept = (1..messages_before.len())
+            .find(|&i| {
+                messages_before[i].is_user()
+                    && worker.count_message_tokens(&messages_before[..i]).unwrap() >= 64
+            })
+            .unwrap();
+
+        worker.context_shift()?;
+
+        // everything starting within the first n_keep tokens survives the shift
+        assert_eq!(
+            worker.messages[..n_kept]
+                .iter()
+                .map(|m| m.content())
+                .collect::<Vec<_>>(),
+            messages_before[..n_kept]
+                .iter()
+                .map(|m| m.content())
+                .collect::<Vec<_>>()
+        );
+        assert!(worker.messages.len() < messages_before.len());
+        assert_eq!(
+            shifted.load(std::sync::atomic::Ordering::Relaxed),
+            messages_before.len() - worker.messages.len()
+        );
+        assert_valid_message_structure(&worker.messages);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_context_shift_n_keep_covering_history() -> Result<(), Box<dyn std::error::Error>> {
+        let model = test_utils::load_test_model();
+        let mut worker = Chat::new_chat_worker(
+            &model,
+            ChatConfig {
+                n_ctx: 512,
+                system_prompt: Some("You are a helpful assistant.".into()),
+                n_keep: 100_000,
+                context_policy: ContextPolicy::Shift,
+                ..Default::default()
+            },
+            Arc::new(AtomicBool::new(false)),
+        )?;
+
+        for i in 1..=8 {
+            worker.add_user_message(
+                format!("This is user message number {}. What is {} * {}?", i, i, i),
+                vec![],
+            );
+            worker.add_assistant_message(format!("The answer is {}.", i * i));
+        }
+        worker.add_user_message("Hello!".to_string(), vec![]);
+        let n_before = worker.messages.len();
+
+        // n_keep can't protect more than there is to drop, so the shift still makes room
+        worker.context_shift()?;
+        assert!(worker.messages.len() < n_before);
+        assert_eq!(
+            worker.messages.last().map(|m| m.content()),
+            Some("Hello!".to_string())
+        );
+        assert_valid_message_structure(&worker.messages);
+
+        Ok(())
+    }
+
     #[test]
     fn test_context_shift_with_tool_calls() -> Result<(), Box<dyn std::error::Error>> {
         test_utils::init_test_tracing();
@@ -2615,6 +6128,7 @@ mod tests {
                 n_ctx,
                 system_prompt: Some("You are a helpful assistant.".into()),
                 tools: vec![test_tool()],
+                context_policy: ContextPolicy::Shift,
                 ..Default::default()
             },
             Arc::new(AtomicBool::new(false)),
@@ -2728,6 +6242,7 @@ mod tests {
             ChatConfig {
                 system_prompt: Some("You are a helpful assistant.".into()),
                 n_ctx: 512, // Use a small context size to force shifting
+                context_policy: ContextPolicy::Shift,
                 ..Default::default()
             },
             Arc::new(AtomicBool::new(false)),
@@ -2796,6 +6311,193 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_display_history() {
+        let messages = vec![
+            Message::new_user("What's the weather in Paris?".into()),
+            Message::Assistant {
+                content: String::new(),
+                reasoning_content: None,
+                tool_calls: Some(vec![ToolCall {
+                    name: "get_weather".into(),
+                    arguments: serde_json::json!({"city": "Paris"}),
+                }]),
+            },
+            Message::Tool {
+                name: "get_weather".into(),
+                content: "18 degrees".into(),
+            },
+            Message::new_assistant("It's 18 degrees in Paris.".into()),
+        ];
+
+        let omitted = display_history(&messages, ToolCallDisplay::Omit);
+        assert_eq!(omitted.len(), 2);
+        assert_eq!(omitted[1].content(), "It's 18 degrees in Paris.");
+
+        let collapsed = display_history(&messages, ToolCallDisplay::Collapse);
+        assert_eq!(collapsed.len(), 3);
+        assert!(!collapsed[1].has_tool_calls());
+        assert_eq!(
+            collapsed[1].content(),
+            r#"Called get_weather({"city":"Paris"}): 18 degrees"#
+        );
+
+        // text written along with the tool calls stays
+        let mut with_text = messages.clone();
+        if let Message::Assistant { content, .. } = &mut with_text[1] {
+            *content = "Let me check.".into();
+        }
+        let omitted = display_history(&with_text, ToolCallDisplay::Omit);
+        assert_eq!(omitted.len(), 3);
+        assert_eq!(omitted[1].content(), "Let me check.");
+        assert!(!omitted[1].has_tool_calls());
+        let collapsed = display_history(&with_text, ToolCallDisplay::Collapse);
+        assert_eq!(
+            collapsed[1].content(),
+            "Let me check.\nCalled get_weather({\"city\":\"Paris\"}): 18 degrees"
+        );
+    }
+
+    #[test]
+    fn test_ask_branch_keeps_history() -> Result<(), Box<dyn std::error::Error>> {
+        test_utils::init_test_tracing();
+        let model = test_utils::load_test_model();
+        let mut worker = Chat::new_chat_worker(
+            &model,
+            ChatConfig {
+                system_prompt: Some("You are a grumpy blacksmith.".into()),
+                ..Default::default()
+            },
+            Arc::new(AtomicBool::new(false)),
+        )?;
+
+        let mut responses = vec![];
+        for prompt in ["Greet the player.", "Say goodbye to the player."] {
+            let (sender, receiver) = std::sync::mpsc::channel();
+            worker.ask_branch(prompt.into(), AskOptions::default(), move |out| {
+                if let llm::WriteOutput::Done(resp) = out {
+                    sender.send(resp).unwrap();
+                }
+            })?;
+            responses.push(receiver.recv()?);
+        }
+
+        assert!(responses.iter().all(|resp| !resp.is_empty()));
+        assert_eq!(worker.messages.len(), 1);
+        assert!(worker.messages[0].is_system());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_context_policy_summarize() -> Result<(), Box<dyn std::error::Error>> {
+        test_utils::init_test_tracing();
+        let model = test_utils::load_test_model();
+
+        let mut worker = Chat::new_chat_worker(
+            &model,
+            ChatConfig {
+                system_prompt: Some("You are a helpful assistant.".into()),
+                n_ctx: 1024,
+                context_policy: ContextPolicy::Summarize {
+                    trigger_ratio: 0.1,
+                    keep_recent: 2,
+                },
+                ..Default::default()
+            },
+            Arc::new(AtomicBool::new(false)),
+        )?;
+        for i in 1..=6 {
+            worker.add_user_message(format!("My pet number {i} is called Rex{i}."), vec![]);
+            worker.add_assistant_message(format!("Nice, Rex{i} is a great name."));
+        }
+
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let f = move |x| {
+            if let llm::WriteOutput::Done(resp) = x {
+                sender.send(resp).unwrap();
+            }
+        };
+        worker.ask("What was my first pet called?".into(), f)?;
+        let _response = receiver.recv()?;
+
+        // system prompt, summary, the last kept turn and the new one
+        let messages = &worker.messages;
+        assert_eq!(messages.len(), 6, "{messages:?}");
+        assert_eq!(messages[0].content(), "You are a helpful assistant.");
+        assert!(messages[1].is_system());
+        assert!(!messages[1].content().is_empty());
+        assert!(messages[2].content().contains("Rex6"));
+        assert!(messages[4].content().contains("first pet"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_context_policy_invalid_trigger_ratio() {
+        let model = test_utils::load_test_model();
+        for trigger_ratio in [0.0, 1.5, f32::NAN] {
+            let result = Chat::new_chat_worker(
+                &model,
+                ChatConfig {
+                    context_policy: ContextPolicy::Summarize {
+                        trigger_ratio,
+                        keep_recent: 2,
+                    },
+                    ..Default::default()
+                },
+                Arc::new(AtomicBool::new(false)),
+            );
+            assert!(matches!(
+                result,
+                Err(InitWorkerError::InvalidTriggerRatio(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_context_policy_error() -> Result<(), Box<dyn std::error::Error>> {
+        test_utils::init_test_tracing();
+        let model = test_utils::load_test_model();
+
+        let mut worker = Chat::new_chat_worker(
+            &model,
+            ChatConfig {
+                n_ctx: 512,
+                context_policy: ContextPolicy::Error,
+                ..Default::default()
+            },
+            Arc::new(AtomicBool::new(false)),
+        )?;
+        for i in 1..=14 {
+            worker.add_user_message(
+                format!("This is user message number {}. What is {} * {}?", i, i, i),
+                vec![],
+            );
+            worker.add_assistant_message(format!("The answer is {}.", i * i));
+        }
+        let n_messages = worker.messages.len();
+
+        let result = worker.ask(
+            "This is a new question that will not fit in the context! What is 10 * 10?".into(),
+            |_| (),
+        );
+        assert!(
+            matches!(
+                &result,
+                Err(SayError::WrappedResponse(WrappedResponseError::ReadError(
+                    ContextSyncError::Shift(ShiftError::ContextFull)
+                )))
+            ),
+            "{:?}",
+            result.err()
+        );
+        // nothing was dropped, and the rejected message wasn't kept
+        assert_eq!(worker.messages.len(), n_messages);
+
+        Ok(())
+    }
+
     #[test]
     fn test_context_while_writing() -> Result<(), Box<dyn std::error::Error>> {
         test_utils::init_test_tracing();
@@ -3006,5 +6708,29 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_flash_attention_and_small_batches() {
+        test_utils::init_test_tracing();
+        let model = test_utils::load_test_model();
+        let chat = ChatBuilder::new(model)
+            .with_context_size(2048)
+            .with_template_variable("enable_thinking".to_string(), false)
+            .with_system_prompt(Some("You are a helpful assistant. ".repeat(40)))
+            .with_context_options(ContextOptions {
+                flash_attention: true,
+                // smaller than the prompt, so it's read in several batches
+                n_batch: Some(64),
+                ..Default::default()
+            })
+            .build()
+            .expect("chat build failed in test");
+
+        let resp = chat
+            .ask("What is the capital of Denmark?")
+            .completed()
+            .unwrap();
+        assert!(resp.contains("Copenhagen"), "{resp}");
+    }
+
     // Template rendering tests have been moved to template.rs module
 }
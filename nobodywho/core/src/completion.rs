@@ -0,0 +1,458 @@
+//! Raw text completion, without any chat template.
+//!
+//! Everything in [`crate::chat`] goes through the model's chat template. That doesn't work
+//! for base models, which often don't have one, and it gets in the way when you just want
+//! the model to continue a piece of text. A completion feeds the prompt to the model as-is
+//! and streams back whatever follows it.
+//!
+//! Each completion is independent: the context is cleared before the prompt is read.
+//!
+//! # Quick Start
+//!
+//! ```
+//! use nobodywho::completion::CompletionHandle;
+//! use nobodywho::llm;
+//! use nobodywho::sampler::SamplerConfig;
+//! use std::sync::Arc;
+//!
+//! # fn example() -> Result<(), Box<dyn std::error::Error>> {
+//! let model = Arc::new(llm::get_model("model.gguf", true, None, None, None)?);
+//! let completion = CompletionHandle::new(model, 2048)?;
+//!
+//! let text = completion
+//!     .complete("Once upon a time", SamplerConfig::default(), vec!["\n\n".into()])
+//!     .completed()?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::chat::{forward_write_output, TokenStream, TokenStreamAsync};
+use crate::errors::{CompleteError, InitWorkerError, ReadError};
+use crate::inference::acquire_inference_lock;
+use crate::llm;
+use crate::llm::{ContextOptions, Worker, WorkerGuard, WriteOutput};
+use crate::sampler::SamplerConfig;
+use crate::tokenizer::token_to_piece_bytes;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tracing::{debug, error, warn};
+
+/// Generate raw text completions in a blocking manner.
+pub struct CompletionHandle {
+    guard: WorkerGuard<CompletionMsg>,
+}
+
+impl CompletionHandle {
+    /// Start a completion worker for `model` with a context of `n_ctx` tokens.
+    pub fn new(model: Arc<llm::Model>, n_ctx: u32) -> Result<Self, InitWorkerError> {
+        Ok(Self {
+            guard: spawn_completion_worker(model, n_ctx)?,
+        })
+    }
+
+    /// Continue `prompt`, streaming the generated text.
+    ///
+    /// Generation ends at an end-of-generation token, when the context is full, or right
+    /// before the first occurrence of any of `stop_words`. The stop word itself is not part
+    /// of the output.
+    pub fn complete(
+        &self,
+        prompt: impl Into<String>,
+        sampler: SamplerConfig,
+        stop_words: Vec<String>,
+    ) -> TokenStream {
+        TokenStream::new(forward_write_output(
+            self.complete_channel(prompt, sampler, stop_words),
+        ))
+    }
+
+    /// Like [`complete`](Self::complete), but returns the raw output channel.
+    pub fn complete_channel(
+        &self,
+        prompt: impl Into<String>,
+        sampler: SamplerConfig,
+        stop_words: Vec<String>,
+    ) -> tokio::sync::mpsc::UnboundedReceiver<WriteOutput> {
+        send_complete(&self.guard, prompt.into(), sampler, stop_words)
+    }
+
+    /// Stop the current completion if one is in progress.
+    pub fn stop_generation(&self) {
+        self.guard.stop();
+    }
+
+    /// Whether the worker thread is still running. See [`ChatHandle::is_alive`](crate::chat::ChatHandle::is_alive).
+    pub fn is_alive(&self) -> bool {
+        self.guard.is_alive()
+    }
+}
+
+/// Generate raw text completions asynchronously.
+#[derive(Clone)]
+pub struct CompletionHandleAsync {
+    guard: Arc<WorkerGuard<CompletionMsg>>,
+}
+
+impl CompletionHandleAsync {
+    /// Start a completion worker for `model` with a context of `n_ctx` tokens.
+    pub fn new(model: Arc<llm::Model>, n_ctx: u32) -> Result<Self, InitWorkerError> {
+        Ok(Self {
+            guard: Arc::new(spawn_completion_worker(model, n_ctx)?),
+        })
+    }
+
+    /// Continue `prompt`, streaming the generated text.
+    /// See [`CompletionHandle::complete`] for when generation ends.
+    pub fn complete(
+        &self,
+        prompt: impl Into<String>,
+        sampler: SamplerConfig,
+        stop_words: Vec<String>,
+    ) -> TokenStreamAsync {
+        TokenStreamAsync::new(forward_write_output(
+            self.complete_channel(prompt, sampler, stop_words),
+        ))
+    }
+
+    /// Like [`complete`](Self::complete), but returns the raw output channel.
+    pub fn complete_channel(
+        &self,
+        prompt: impl Into<String>,
+        sampler: SamplerConfig,
+        stop_words: Vec<String>,
+    ) -> tokio::sync::mpsc::UnboundedReceiver<WriteOutput> {
+        send_complete(&self.guard, prompt.into(), sampler, stop_words)
+    }
+
+    /// Stop the current completion if one is in progress.
+    pub fn stop_generation(&self) {
+        self.guard.stop();
+    }
+
+    /// Whether the worker thread is still running. See [`ChatHandle::is_alive`](crate::chat::ChatHandle::is_alive).
+    pub fn is_alive(&self) -> bool {
+        self.guard.is_alive()
+    }
+}
+
+fn spawn_completion_worker(
+    model: Arc<llm::Model>,
+    n_ctx: u32,
+) -> Result<WorkerGuard<CompletionMsg>, InitWorkerError> {
+    let (msg_tx, msg_rx) = std::sync::mpsc::channel();
+    let (init_tx, init_rx) = std::sync::mpsc::channel::<Result<(), InitWorkerError>>();
+
+    let should_stop = Arc::new(AtomicBool::new(false));
+    let should_stop_clone = Arc::clone(&should_stop);
+
+    let join_handle = std::thread::spawn(move || {
+        let mut worker_state = match Worker::new_completion_worker(&model, n_ctx) {
+            Ok(w) => {
+                let _ = init_tx.send(Ok(()));
+                w
+            }
+            Err(e) => {
+                let _ = init_tx.send(Err(e));
+                return;
+            }
+        };
+
+        while let Ok(msg) = msg_rx.recv() {
+            process_worker_msg(&mut worker_state, msg, &should_stop_clone);
+        }
+    });
+
+    init_rx.recv().map_err(|_| InitWorkerError::NoResponse)??;
+
+    Ok(WorkerGuard::new(msg_tx, join_handle, Some(should_stop)))
+}
+
+fn send_complete(
+    guard: &WorkerGuard<CompletionMsg>,
+    prompt: String,
+    sampler: SamplerConfig,
+    stop_words: Vec<String>,
+) -> tokio::sync::mpsc::UnboundedReceiver<WriteOutput> {
+    let (output_tx, output_rx) = tokio::sync::mpsc::unbounded_channel();
+    guard.send(CompletionMsg::Complete {
+        prompt,
+        sampler,
+        stop_words,
+        output_tx,
+    });
+    output_rx
+}
+
+enum CompletionMsg {
+    Complete {
+        prompt: String,
+        sampler: SamplerConfig,
+        stop_words: Vec<String>,
+        output_tx: tokio::sync::mpsc::UnboundedSender<WriteOutput>,
+    },
+}
+
+fn process_worker_msg(
+    worker_state: &mut Worker<'_, ()>,
+    msg: CompletionMsg,
+    should_stop: &AtomicBool,
+) {
+    match msg {
+        CompletionMsg::Complete {
+            prompt,
+            sampler,
+            stop_words,
+            output_tx,
+        } => {
+            should_stop.store(false, Ordering::Relaxed);
+            let respond = |out| {
+                let _ = output_tx.send(out);
+            };
+            if let Err(e) =
+                worker_state.complete(prompt, &sampler, &stop_words, should_stop, respond)
+            {
+                error!(error = %e, "Completion failed");
+                let _ = output_tx.send(WriteOutput::Error(Box::new(e)));
+            }
+        }
+    }
+}
+
+impl<'a> Worker<'a, ()> {
+    fn new_completion_worker(
+        model: &'a llm::Model,
+        n_ctx: u32,
+    ) -> Result<Worker<'a, ()>, InitWorkerError> {
+        if !model.is_generative_model() {
+            let architecture = model
+                .language_model
+                .meta_val_str("general.architecture")
+                .unwrap_or_else(|_| "unknown".into());
+            return Err(InitWorkerError::NotAnLLM { architecture });
+        }
+        Worker::new_with_type(model, n_ctx, false, None, ContextOptions::default(), ())
+    }
+
+    fn complete<F>(
+        &mut self,
+        prompt: String,
+        sampler_config: &SamplerConfig,
+        stop_words: &[String],
+        should_stop: &AtomicBool,
+        respond: F,
+    ) -> Result<(), CompleteError>
+    where
+        F: Fn(WriteOutput),
+    {
+        // every completion starts from an empty context
+        self.reset_context();
+
+        let chunks = self.engine.tokenize(prompt, vec![])?;
+        let inference_lock_token = acquire_inference_lock();
+        if chunks.n_tokens() > 0 {
+            match self
+                .engine
+                .read_chunks(chunks, Some(should_stop), &inference_lock_token)
+            {
+                Err(ReadError::Aborted) => {
+                    debug!("Completion stopped while reading the prompt");
+                    self.reset_context();
+                    respond(WriteOutput::Done(String::new()));
+                    return Ok(());
+                }
+                result => {
+                    result?;
+                }
+            }
+        }
+
+        let model = self.engine.ctx.model;
+        let mut sampler = sampler_config.to_stateful(model)?;
+        let mut decoder = encoding_rs::UTF_8.new_decoder();
+
+        let mut stop_filter = StopWordFilter::new(stop_words);
+        let mut text = String::new();
+
+        'generate: while !should_stop.load(Ordering::Relaxed) {
+            if self.engine.is_context_full() {
+                warn!("Context is full, ending the completion");
+                break;
+            }
+
+            for token in self.engine.sample_and_decode_next_tokens(&mut sampler)? {
+                if model.is_eog_token(token) {
+                    break 'generate;
+                }
+
+                let token_bytes = token_to_piece_bytes(model, token, true)?;
+                text.reserve(
+                    decoder
+                        .max_utf8_buffer_length(token_bytes.len())
+                        .unwrap_or(32),
+                );
+                let _ = decoder.decode_to_string(&token_bytes, &mut text, false);
+
+                let (emit, hit_stop) = stop_filter.update(&mut text);
+                if let Some(emit) = emit {
+                    respond(WriteOutput::Token(emit));
+                }
+                if hit_stop {
+                    break 'generate;
+                }
+            }
+        }
+
+        if let Some(rest) = stop_filter.flush(&text) {
+            respond(WriteOutput::Token(rest));
+        }
+        debug!(%text, "Completion done");
+        respond(WriteOutput::Done(text));
+        Ok(())
+    }
+}
+
+/// Byte index of the earliest stop word occurrence in `text`, searching from `from`.
+fn find_stop_word(text: &str, from: usize, stop_words: &[String]) -> Option<usize> {
+    stop_words
+        .iter()
+        .filter(|word| !word.is_empty())
+        .filter_map(|word| text[from..].find(word.as_str()))
+        .min()
+        .map(|index| from + index)
+}
+
+/// Length of the longest end of `text` that is the start of a stop word, but not all of it.
+fn partial_stop_word_len(text: &str, stop_words: &[String]) -> usize {
+    stop_words
+        .iter()
+        .filter_map(|word| {
+            (1..word.len())
+                .rev()
+                .filter(|&len| word.is_char_boundary(len))
+                .find(|&len| text.ends_with(&word[..len]))
+        })
+        .max()
+        .unwrap_or(0)
+}
+
+/// Decides how much of a growing response can be streamed out, so no part of a stop word
+/// is ever emitted, even when the stop word is split across several tokens.
+///
+/// Text at the end that could be the start of a stop word is held back until the next
+/// tokens show whether it is one.
+pub(crate) struct StopWordFilter {
+    stop_words: Vec<String>,
+    n_emitted: usize,
+}
+
+impl StopWordFilter {
+    pub(crate) fn new(stop_words: &[String]) -> Self {
+        Self {
+            stop_words: stop_words.to_vec(),
+            n_emitted: 0,
+        }
+    }
+
+    /// Check `text` after new output was appended to it. Truncates `text` at the first
+    /// complete stop word. Returns the text that can be emitted now, and whether a stop word
+    /// was hit.
+    pub(crate) fn update(&mut self, text: &mut String) -> (Option<String>, bool) {
+        // a stop word can't start in text that was already emitted
+        if let Some(stop_index) = find_stop_word(text, self.n_emitted, &self.stop_words) {
+            text.truncate(stop_index);
+            return (self.emit_until(text, text.len()), true);
+        }
+        let held_back = partial_stop_word_len(&text[self.n_emitted..], &self.stop_words);
+        (self.emit_until(text, text.len() - held_back), false)
+    }
+
+    /// The held back rest of `text`, once the response ended without a stop word.
+    pub(crate) fn flush(&mut self, text: &str) -> Option<String> {
+        self.emit_until(text, text.len())
+    }
+
+    fn emit_until(&mut self, text: &str, mut until: usize) -> Option<String> {
+        while !text.is_char_boundary(until) {
+            until -= 1;
+        }
+        if until <= self.n_emitted {
+            return None;
+        }
+        let emit = text[self.n_emitted..until].to_string();
+        self.n_emitted = until;
+        Some(emit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sampler::SamplerPresets;
+    use crate::test_utils;
+
+    #[test]
+    fn test_find_stop_word() {
+        let stop_words = vec!["\n".to_string(), "END".to_string()];
+        assert_eq!(find_stop_word("one two", 0, &stop_words), None);
+        assert_eq!(find_stop_word("one END two\n", 0, &stop_words), Some(4));
+        assert_eq!(find_stop_word("one\ntwo END", 4, &stop_words), Some(8));
+        assert_eq!(find_stop_word("anything", 0, &[String::new()]), None);
+    }
+
+    #[test]
+    fn test_stop_word_split_across_tokens() {
+        let stop_words = vec!["</s>".to_string()];
+        let mut filter = StopWordFilter::new(&stop_words);
+        let mut text = String::new();
+        let mut streamed = String::new();
+        let mut hit_stop = false;
+        for token in ["Hello", " there", "<", "/", "s", "> and more"] {
+            text.push_str(token);
+            let (emit, hit) = filter.update(&mut text);
+            streamed.extend(emit);
+            if hit {
+                hit_stop = true;
+                break;
+            }
+        }
+        assert!(hit_stop);
+        assert_eq!(streamed, "Hello there");
+        assert_eq!(text, "Hello there");
+
+        // a partial match that doesn't complete is emitted once that's clear
+        let mut filter = StopWordFilter::new(&stop_words);
+        let mut text = String::new();
+        let mut streamed = String::new();
+        for token in ["a </", "b"] {
+            text.push_str(token);
+            let (emit, hit) = filter.update(&mut text);
+            assert!(!hit);
+            streamed.extend(emit);
+            if token == "a </" {
+                assert_eq!(streamed, "a ");
+            }
+        }
+        streamed.extend(filter.flush(&text));
+        assert_eq!(streamed, "a </b");
+    }
+
+    #[test]
+    fn test_complete() -> Result<(), Box<dyn std::error::Error>> {
+        test_utils::init_test_tracing();
+        let model = test_utils::load_test_model();
+        let completion = CompletionHandle::new(model, 1024)?;
+
+        let text = completion
+            .complete(
+                "The capital of Denmark is the city of",
+                SamplerPresets::greedy(),
+                vec![".".into(), "\n".into()],
+            )
+            .completed()?;
+
+        assert!(text.contains("Copenhagen"), "{text}");
+        assert!(!text.contains('.') && !text.contains('\n'), "{text}");
+
+        Ok(())
+    }
+}
@@ -38,6 +38,28 @@ impl CrossEncoder {
             self.async_handle.rank_and_sort(query, documents).await
         })
     }
+
+    /// Like [`CrossEncoder::rank`], but yields `(index, score)` for each document as soon as
+    /// it has been scored, instead of waiting for the whole set.
+    pub fn rank_stream(&self, query: String, documents: Vec<String>) -> RankStream {
+        RankStream {
+            rx: self.async_handle.rank_stream(query, documents),
+        }
+    }
+}
+
+/// Blocking iterator over `(index, score)` pairs, returned by [`CrossEncoder::rank_stream`].
+/// Documents are scored in order. The iterator ends early if the worker fails.
+pub struct RankStream {
+    rx: tokio::sync::mpsc::Receiver<(usize, f32)>,
+}
+
+impl Iterator for RankStream {
+    type Item = (usize, f32);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.rx.blocking_recv()
+    }
 }
 
 impl CrossEncoderAsync {
@@ -79,31 +101,61 @@ impl CrossEncoderAsync {
             .ok_or(CrossEncoderWorkerError::NoResponse)
     }
 
+    /// Score documents one at a time, sending `(index, score)` over the returned channel as
+    /// each is computed. The channel closes once every document is scored, or early if the
+    /// worker fails.
+    pub fn rank_stream(
+        &self,
+        query: String,
+        documents: Vec<String>,
+    ) -> tokio::sync::mpsc::Receiver<(usize, f32)> {
+        // sized so the worker never has to wait on a slow consumer
+        let (scores_tx, scores_rx) = tokio::sync::mpsc::channel(documents.len().max(1));
+        self.guard
+            .send(CrossEncoderMsg::RankStream(query, documents, scores_tx));
+        scores_rx
+    }
+
     pub async fn rank_and_sort(
         &self,
         query: String,
         documents: Vec<String>,
     ) -> Result<Vec<(String, f32)>, CrossEncoderWorkerError> {
         let scores = self.rank(query, documents.clone()).await?;
+        Ok(sort_by_score(documents, scores))
+    }
+}
 
-        let mut docs_with_scores: Vec<(String, f32)> = documents
-            .iter()
-            .zip(scores.iter())
-            .map(|(doc, score)| (doc.clone(), *score))
-            .collect();
+/// Pair documents with their scores, sorted from the highest score to the lowest.
+///
+/// Documents with equal scores keep their original order, and NaN scores always go last,
+/// so the result is the same every time for the same input.
+pub fn sort_by_score(documents: Vec<String>, scores: Vec<f32>) -> Vec<(String, f32)> {
+    if scores.iter().any(|score| score.is_nan()) {
+        warn!("Got NaN while sorting cross-encoded documents.");
+    }
 
-        docs_with_scores.sort_by(|a, b| {
-            b.1.partial_cmp(&a.1).unwrap_or_else(|| {
-                warn!("Got NaN while sorting cross-encoded documents.");
-                std::cmp::Ordering::Equal
-            })
-        });
-        Ok(docs_with_scores)
+    let mut docs_with_scores: Vec<(usize, (String, f32))> =
+        documents.into_iter().zip(scores).enumerate().collect();
+    docs_with_scores.sort_by(|(a_index, (_, a)), (b_index, (_, b))| {
+        compare_scores(*a, *b).then(a_index.cmp(b_index))
+    });
+    docs_with_scores.into_iter().map(|(_, doc)| doc).collect()
+}
+
+/// Descending order, with NaN after every number.
+fn compare_scores(a: f32, b: f32) -> std::cmp::Ordering {
+    match (a.is_nan(), b.is_nan()) {
+        (true, true) => std::cmp::Ordering::Equal,
+        (true, false) => std::cmp::Ordering::Greater,
+        (false, true) => std::cmp::Ordering::Less,
+        (false, false) => b.partial_cmp(&a).unwrap_or(std::cmp::Ordering::Equal),
     }
 }
 
 enum CrossEncoderMsg {
     Rank(String, Vec<String>, tokio::sync::mpsc::Sender<Vec<f32>>),
+    RankStream(String, Vec<String>, tokio::sync::mpsc::Sender<(usize, f32)>),
 }
 
 fn process_worker_msg(
@@ -119,6 +171,14 @@ fn process_worker_msg(
 
             let _ = respond.blocking_send(scores);
         }
+        CrossEncoderMsg::RankStream(query, documents, respond) => {
+            worker_state.reset_context();
+
+            // stop scoring once nobody is listening anymore
+            worker_state.rank_each(query, documents, |index, score| {
+                respond.blocking_send((index, score)).is_ok()
+            })?;
+        }
     }
 
     Ok(())
@@ -137,7 +197,14 @@ impl<'a> Worker<'a, CrossEncoderWorker> {
         model: &llm::Model,
         n_ctx: u32,
     ) -> Result<Worker<'_, CrossEncoderWorker>, InitWorkerError> {
-        Worker::new_with_type(model, n_ctx, true, None, CrossEncoderWorker {})
+        Worker::new_with_type(
+            model,
+            n_ctx,
+            true,
+            None,
+            llm::ContextOptions::default(),
+            CrossEncoderWorker {},
+        )
     }
 
     pub fn get_classification_score(&self) -> Result<f32, CrossEncoderWorkerError> {
@@ -159,6 +226,22 @@ impl<'a> Worker<'a, CrossEncoderWorker> {
         query: String,
         documents: Vec<String>,
     ) -> Result<Vec<f32>, CrossEncoderWorkerError> {
+        let mut scores = Vec::with_capacity(documents.len());
+        self.rank_each(query, documents, |_, score| {
+            scores.push(score);
+            true
+        })?;
+        Ok(scores)
+    }
+
+    /// Score each document against the query, calling `on_score(index, score)` as soon as
+    /// each score is available. Stops early once `on_score` returns `false`.
+    pub fn rank_each(
+        &mut self,
+        query: String,
+        documents: Vec<String>,
+        mut on_score: impl FnMut(usize, f32) -> bool,
+    ) -> Result<(), CrossEncoderWorkerError> {
         // Get CLS and SEP tokens from the model (CLS = BOS per llama.cpp, the current CLS token is deprecated.)
         let mut decoder = encoding_rs::UTF_8.new_decoder();
         let cls = self
@@ -181,15 +264,16 @@ impl<'a> Worker<'a, CrossEncoderWorker> {
                 "</s>".to_string()
             });
 
-        let mut scores = Vec::new();
-        for document in documents {
+        for (index, document) in documents.into_iter().enumerate() {
             self.reset_context();
             // Format as: [CLS] query [SEP] document [SEP]
             let input = format!("{cls}{query}{sep}{document}{sep}");
             let score = self.read_string(input)?.get_classification_score()?;
-            scores.push(score);
+            if !on_score(index, score) {
+                break;
+            }
         }
-        Ok(scores)
+        Ok(())
     }
 }
 
@@ -199,6 +283,21 @@ mod tests {
     use crate::test_utils;
     use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
 
+    #[test]
+    fn test_sort_by_score_is_deterministic() {
+        let documents: Vec<String> = ["a", "b", "c", "d", "e", "f"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let scores = vec![0.5, f32::NAN, 0.9, 0.5, f32::NAN, 0.5];
+
+        let sorted: Vec<String> = sort_by_score(documents, scores)
+            .into_iter()
+            .map(|(doc, _)| doc)
+            .collect();
+        assert_eq!(sorted, vec!["c", "a", "d", "f", "b", "e"]);
+    }
+
     #[tokio::test]
     async fn test_crossencoder_async() -> Result<(), Box<dyn std::error::Error>> {
         test_utils::init_test_tracing();
@@ -278,4 +377,35 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_crossencoder_rank_stream() -> Result<(), Box<dyn std::error::Error>> {
+        test_utils::init_test_tracing();
+        let model = test_utils::load_crossencoder_model();
+        let encoder = CrossEncoder::new(model, 4096);
+
+        let query = "What is the capital of France?".to_string();
+        let documents = vec![
+            "France is a country in Europe.".to_string(),
+            "Paris is the capital of France.".to_string(),
+            "The capital of Germany is Berlin.".to_string(),
+        ];
+
+        let expected = encoder.rank(query.clone(), documents.clone())?;
+        let streamed: Vec<(usize, f32)> = encoder.rank_stream(query, documents).collect();
+
+        assert_eq!(
+            streamed.iter().map(|(index, _)| *index).collect::<Vec<_>>(),
+            vec![0, 1, 2]
+        );
+        assert_eq!(
+            streamed
+                .into_iter()
+                .map(|(_, score)| score)
+                .collect::<Vec<_>>(),
+            expected
+        );
+
+        Ok(())
+    }
 }
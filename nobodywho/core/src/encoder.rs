@@ -1,9 +1,90 @@
-use crate::errors::{EncoderWorkerError, InitWorkerError};
+use crate::errors::{EncoderWorkerError, InitWorkerError, ReadError};
+use crate::inference::acquire_inference_lock;
 use crate::llm;
 use crate::llm::{Worker, WorkerGuard};
 use llama_cpp_2::context::params::LlamaPoolingType;
+use std::str::FromStr;
 use std::sync::Arc;
-use tracing::error;
+use tracing::{error, warn};
+
+/// How the per-token embeddings are combined into a single vector.
+///
+/// Which one is right depends on how the model was trained: BERT-style models such as
+/// BGE are trained with `Cls`, most sentence-transformers models (e.g. all-MiniLM) with
+/// `Mean`, and decoder-based embedding models (e.g. Qwen3-Embedding) with `Last`.
+/// Using the wrong one still produces vectors, but their similarities are much less meaningful.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pooling {
+    Mean,
+    Cls,
+    Last,
+}
+
+impl FromStr for Pooling {
+    type Err = ();
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().as_str() {
+            "mean" => Ok(Self::Mean),
+            "cls" => Ok(Self::Cls),
+            "last" => Ok(Self::Last),
+            _ => Err(()),
+        }
+    }
+}
+
+impl From<Pooling> for LlamaPoolingType {
+    fn from(pooling: Pooling) -> Self {
+        match pooling {
+            Pooling::Mean => LlamaPoolingType::Mean,
+            Pooling::Cls => LlamaPoolingType::Cls,
+            Pooling::Last => LlamaPoolingType::Last,
+        }
+    }
+}
+
+/// What to do with a text that has more tokens than the encoder can read at once.
+///
+/// `Truncate` embeds only the first tokens that fit, which is fine for search over documents
+/// whose opening is representative, but anything past the cut has no effect on the vector.
+/// For RAG, prefer `Error` and chunk your documents to fit: that way no text silently drops
+/// out of the index.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Overflow {
+    /// Keep the first tokens that fit and log a warning.
+    #[default]
+    Truncate,
+    /// Fail with [`EncoderWorkerError::InputTooLong`].
+    Error,
+}
+
+impl FromStr for Overflow {
+    type Err = ();
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().as_str() {
+            "truncate" => Ok(Self::Truncate),
+            "error" => Ok(Self::Error),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct EncoderConfig {
+    /// Pooling strategy. `None` uses the pooling recommended in the model's GGUF metadata.
+    pub pooling: Option<Pooling>,
+    /// What to do with texts longer than the encoder can read. The limit is the smaller of
+    /// `n_ctx` and the batch size, since the whole text must be decoded in one go.
+    pub on_overflow: Overflow,
+    /// Embedding the same text always yields a bit-identical vector.
+    /// See [`llm::ContextOptions::deterministic`] for the throughput tradeoff.
+    pub deterministic: bool,
+    /// See [`llm::ContextOptions::n_threads`].
+    pub n_threads: Option<u32>,
+    /// See [`llm::ContextOptions::n_threads_batch`].
+    pub n_threads_batch: Option<u32>,
+}
 
 #[derive(Clone)]
 pub struct Encoder {
@@ -13,6 +94,7 @@ pub struct Encoder {
 #[derive(Clone)]
 pub struct EncoderAsync {
     guard: Arc<WorkerGuard<EncoderMsg>>,
+    embedding_dim: usize,
 }
 
 impl Encoder {
@@ -21,6 +103,16 @@ impl Encoder {
         Self { async_handle }
     }
 
+    pub fn with_config(model: Arc<llm::Model>, n_ctx: u32, config: EncoderConfig) -> Self {
+        let async_handle = EncoderAsync::with_config(model, n_ctx, config);
+        Self { async_handle }
+    }
+
+    /// Length of the vectors returned by [`Encoder::encode`], read from the model metadata.
+    pub fn embedding_dim(&self) -> usize {
+        self.async_handle.embedding_dim()
+    }
+
     pub fn encode(&self, text: String) -> Result<Vec<f32>, EncoderWorkerError> {
         futures::executor::block_on(async { self.async_handle.encode(text).await })
     }
@@ -28,10 +120,15 @@ impl Encoder {
 
 impl EncoderAsync {
     pub fn new(model: Arc<llm::Model>, n_ctx: u32) -> Self {
+        Self::with_config(model, n_ctx, EncoderConfig::default())
+    }
+
+    pub fn with_config(model: Arc<llm::Model>, n_ctx: u32, config: EncoderConfig) -> Self {
         let (msg_tx, msg_rx) = std::sync::mpsc::channel();
+        let embedding_dim = model.language_model.n_embd() as usize;
 
         let join_handle = std::thread::spawn(move || {
-            let worker = Worker::new_encoder_worker(&model, n_ctx);
+            let worker = Worker::new_encoder_worker(&model, n_ctx, config);
             let mut worker_state = match worker {
                 Ok(worker_state) => worker_state,
                 Err(errmsg) => {
@@ -48,20 +145,29 @@ impl EncoderAsync {
 
         Self {
             guard: Arc::new(WorkerGuard::new(msg_tx, join_handle, None)),
+            embedding_dim,
         }
     }
 
+    /// Length of the vectors returned by [`EncoderAsync::encode`], read from the model metadata.
+    pub fn embedding_dim(&self) -> usize {
+        self.embedding_dim
+    }
+
     pub async fn encode(&self, text: String) -> Result<Vec<f32>, EncoderWorkerError> {
         let (embedding_tx, mut embedding_rx) = tokio::sync::mpsc::channel(1);
         self.guard.send(EncoderMsg::Encode(text, embedding_tx));
         embedding_rx.recv().await.ok_or(EncoderWorkerError::Encode(
             "Could not encode the text. Worker never responded.".into(),
-        ))
+        ))?
     }
 }
 
 enum EncoderMsg {
-    Encode(String, tokio::sync::mpsc::Sender<Vec<f32>>),
+    Encode(
+        String,
+        tokio::sync::mpsc::Sender<Result<Vec<f32>, EncoderWorkerError>>,
+    ),
 }
 
 fn process_worker_msg(
@@ -72,7 +178,12 @@ fn process_worker_msg(
         EncoderMsg::Encode(text, respond) => {
             worker_state.reset_context();
 
-            let embedding = worker_state.read_string(text)?.get_embedding()?;
+            let embedding = match worker_state.read_input(text) {
+                Ok(worker_state) => Ok(worker_state.get_embedding()?),
+                // too long for this encoder; the worker itself is fine
+                Err(e @ EncoderWorkerError::InputTooLong { .. }) => Err(e),
+                Err(e) => return Err(e),
+            };
             let _ = respond.blocking_send(embedding);
         }
     }
@@ -82,6 +193,7 @@ fn process_worker_msg(
 
 struct EncoderWorker {
     pooling: LlamaPoolingType,
+    on_overflow: Overflow,
 }
 
 impl llm::PoolingType for EncoderWorker {
@@ -94,20 +206,66 @@ impl<'a> Worker<'a, EncoderWorker> {
     pub fn new_encoder_worker(
         model: &llm::Model,
         n_ctx: u32,
+        config: EncoderConfig,
     ) -> Result<Worker<'_, EncoderWorker>, InitWorkerError> {
-        let arch = model
-            .language_model
-            .meta_val_str("general.architecture")
-            .unwrap_or_default();
-        let key = format!("{arch}.pooling_type");
-        let pooling = model
-            .language_model
-            .meta_val_str(&key)
-            .ok()
-            .and_then(|val| val.parse::<i32>().ok())
-            .map(LlamaPoolingType::from)
-            .unwrap_or(LlamaPoolingType::Unspecified);
-        Worker::new_with_type(model, n_ctx, true, None, EncoderWorker { pooling })
+        let pooling = match config.pooling {
+            Some(pooling) => pooling.into(),
+            None => {
+                let arch = model
+                    .language_model
+                    .meta_val_str("general.architecture")
+                    .unwrap_or_default();
+                let key = format!("{arch}.pooling_type");
+                model
+                    .language_model
+                    .meta_val_str(&key)
+                    .ok()
+                    .and_then(|val| val.parse::<i32>().ok())
+                    .map(LlamaPoolingType::from)
+                    .unwrap_or(LlamaPoolingType::Unspecified)
+            }
+        };
+        let options = llm::ContextOptions {
+            deterministic: config.deterministic,
+            n_threads: config.n_threads,
+            n_threads_batch: config.n_threads_batch,
+            ..Default::default()
+        };
+        let extra = EncoderWorker {
+            pooling,
+            on_overflow: config.on_overflow,
+        };
+        Worker::new_with_type(model, n_ctx, true, None, options, extra)
+    }
+
+    /// Read `text` into the context, applying the configured [`Overflow`] policy.
+    fn read_input(&mut self, text: String) -> Result<&mut Self, EncoderWorkerError> {
+        let chunks = self
+            .engine
+            .tokenize(text, vec![])
+            .map_err(ReadError::from)?;
+        let n_tokens = chunks.n_tokens();
+        let max_tokens = self.engine.max_read_tokens();
+        let chunks = if n_tokens <= max_tokens {
+            chunks
+        } else {
+            match self.extra.on_overflow {
+                Overflow::Error => {
+                    return Err(EncoderWorkerError::InputTooLong {
+                        n_tokens,
+                        max_tokens,
+                    })
+                }
+                Overflow::Truncate => {
+                    warn!(n_tokens, max_tokens, "Input too long to encode, truncating");
+                    chunks.head(max_tokens)
+                }
+            }
+        };
+        let inference_lock_token = acquire_inference_lock();
+        self.engine
+            .read_chunks(chunks, None, &inference_lock_token)?;
+        Ok(self)
     }
 
     pub fn get_embedding(&self) -> Result<Vec<f32>, llama_cpp_2::EmbeddingsError> {
@@ -129,6 +287,34 @@ pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
     dotproduct(a, b) / (norm_a * norm_b)
 }
 
+/// Whether the cosine similarity of `a` and `b` is above `threshold`.
+pub fn is_similar(a: &[f32], b: &[f32], threshold: f32) -> bool {
+    cosine_similarity(a, b) > threshold
+}
+
+/// The candidate most similar to `query`, as its index and cosine similarity,
+/// if that similarity is above `threshold`.
+///
+/// ```
+/// use nobodywho::encoder::best_match;
+///
+/// let candidates = vec![vec![0.0, 1.0], vec![1.0, 0.1], vec![1.0, 0.5]];
+/// assert_eq!(best_match(&[1.0, 0.0], &candidates, 0.9).map(|(i, _)| i), Some(1));
+/// assert_eq!(best_match(&[-1.0, 0.0], &candidates, 0.9), None);
+/// ```
+pub fn best_match<C: AsRef<[f32]>>(
+    query: &[f32],
+    candidates: &[C],
+    threshold: f32,
+) -> Option<(usize, f32)> {
+    candidates
+        .iter()
+        .map(|candidate| cosine_similarity(query, candidate.as_ref()))
+        .enumerate()
+        .filter(|(_, similarity)| *similarity > threshold)
+        .max_by(|(_, a), (_, b)| a.total_cmp(b))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -174,12 +360,37 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_embedding_dim() -> Result<(), Box<dyn std::error::Error>> {
+        let model = test_utils::load_embeddings_model();
+        let encoder = Encoder::new(model, 1024);
+
+        let embedding = encoder.encode("Copenhagen is the capital of Denmark.".to_string())?;
+        assert_eq!(encoder.embedding_dim(), embedding.len());
+        Ok(())
+    }
+
+    #[test]
+    fn test_is_similar_and_best_match() {
+        assert!(is_similar(&[1.0, 0.0], &[1.0, 0.1], 0.9));
+        assert!(!is_similar(&[1.0, 0.0], &[0.0, 1.0], 0.5));
+        // zero vectors have no direction, so they're never similar
+        assert!(!is_similar(&[0.0, 0.0], &[1.0, 0.0], -1.0));
+
+        let candidates = [vec![0.0, 1.0], vec![1.0, 0.5], vec![1.0, 0.1]];
+        let (index, similarity) = best_match(&[1.0, 0.0], &candidates, 0.5).unwrap();
+        assert_eq!(index, 2);
+        assert!(similarity > 0.99);
+        assert_eq!(best_match(&[1.0, 0.0], &candidates, 0.999), None);
+        assert_eq!(best_match::<Vec<f32>>(&[1.0, 0.0], &[], 0.0), None);
+    }
+
     #[test]
     fn test_encoder_worker_direct() -> Result<(), Box<dyn std::error::Error>> {
         test_utils::init_test_tracing();
         let model = test_utils::load_embeddings_model();
 
-        let mut worker = Worker::new_encoder_worker(&model, 1024)?;
+        let mut worker = Worker::new_encoder_worker(&model, 1024, EncoderConfig::default())?;
 
         let copenhagen_embedding = worker
             .read_string("Copenhagen is the capital of Denmark.".to_string())?
@@ -238,4 +449,86 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_deterministic_mode_encoder() -> Result<(), Box<dyn std::error::Error>> {
+        test_utils::init_test_tracing();
+        let model = test_utils::load_embeddings_model();
+        let config = EncoderConfig {
+            deterministic: true,
+            ..Default::default()
+        };
+        let encoder = Encoder::with_config(model, 1024, config);
+
+        let input = "I don't want to be different";
+
+        let embeddings = (0..3)
+            .map(|_| encoder.encode(input.to_string()))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        assert!(
+            embeddings.windows(2).all(|pair| pair[0] == pair[1]),
+            "Deterministic encoder should produce bit-identical embeddings for '{}'.",
+            input
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_encoder_explicit_pooling() -> Result<(), Box<dyn std::error::Error>> {
+        test_utils::init_test_tracing();
+        let model = test_utils::load_embeddings_model();
+        let config = EncoderConfig {
+            pooling: Some(Pooling::Mean),
+            ..Default::default()
+        };
+        let encoder = Encoder::with_config(model, 1024, config);
+
+        let copenhagen = encoder.encode("Copenhagen is the capital of Denmark.".to_string())?;
+        let berlin = encoder.encode("Berlin is the capital of Germany.".to_string())?;
+        let insult = encoder.encode(
+            "Your mother was a hamster and your father smelt of elderberries!".to_string(),
+        )?;
+
+        assert!(cosine_similarity(&copenhagen, &insult) < cosine_similarity(&copenhagen, &berlin));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_encoder_overflow() -> Result<(), Box<dyn std::error::Error>> {
+        test_utils::init_test_tracing();
+        let model = test_utils::load_embeddings_model();
+        let long_text = "The quick brown fox jumps over the lazy dog. ".repeat(100);
+
+        let truncating = Encoder::new(model.clone(), 64);
+        let embedding = truncating.encode(long_text.clone())?;
+        assert_eq!(embedding.len(), truncating.embedding_dim());
+
+        let config = EncoderConfig {
+            on_overflow: Overflow::Error,
+            ..Default::default()
+        };
+        let strict = Encoder::with_config(model, 64, config);
+        match strict.encode(long_text) {
+            Err(EncoderWorkerError::InputTooLong {
+                n_tokens,
+                max_tokens,
+            }) => assert!(n_tokens > max_tokens),
+            other => panic!("expected InputTooLong, got {other:?}"),
+        }
+        // the worker survives the error
+        strict.encode("Short enough.".to_string())?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_pooling_from_str() {
+        assert_eq!("mean".parse(), Ok(Pooling::Mean));
+        assert_eq!("CLS".parse(), Ok(Pooling::Cls));
+        assert_eq!("last".parse(), Ok(Pooling::Last));
+        assert_eq!("max".parse::<Pooling>(), Err(()));
+    }
 }
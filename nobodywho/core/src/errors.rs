@@ -221,6 +221,19 @@ pub enum LoadModelError {
     AutomaticModelSelectionMemory(#[from] MemoryDetectionError),
 }
 
+#[derive(Debug, thiserror::Error, miette::Diagnostic)]
+pub enum ModelRegistryError {
+    #[error("No model is registered as {name:?}")]
+    #[diagnostic(
+        code(nobodywho::unknown_model),
+        help("The registered models are: {registered:?}")
+    )]
+    UnknownModel {
+        name: String,
+        registered: Vec<String>,
+    },
+}
+
 #[derive(Debug, thiserror::Error)]
 pub enum GetCacheDirError {
     #[error("Could not determine cache directory")]
@@ -412,6 +425,14 @@ pub enum InitWorkerError {
         )
     )]
     MtpDraftModelNotLoaded,
+
+    #[error("A chat pool needs at least one slot")]
+    NoSlots,
+
+    #[error(
+        "The trigger_ratio of ContextPolicy::Summarize must be above 0 and at most 1, got {0}"
+    )]
+    InvalidTriggerRatio(f32),
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -468,6 +489,22 @@ pub enum GetterError {
     GetterError(String),
 }
 
+#[derive(Debug, thiserror::Error)]
+pub enum RenderPromptError {
+    #[error("Worker terminated before processing render request")]
+    WorkerTerminated,
+    #[error("Template failed to render: {0}")]
+    Render(#[from] minijinja::Error),
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum WarmupError {
+    #[error("Worker terminated before processing warmup request")]
+    WorkerTerminated,
+    #[error("Error reading the prompt into the context: {0}")]
+    ContextSync(#[from] ContextSyncError),
+}
+
 #[derive(Debug, thiserror::Error)]
 pub enum TokenizeError {
     #[error("Worker terminated before processing tokenize request")]
@@ -476,6 +513,8 @@ pub enum TokenizeError {
     Tokenization(#[from] TokenizationError),
     #[error("Multimodal error: {0}")]
     Multimodal(#[from] MultimodalError),
+    #[error("Could not render the chat: {0}")]
+    Render(#[from] RenderError),
 }
 
 #[derive(Debug, thiserror::Error, miette::Diagnostic)]
@@ -507,6 +546,9 @@ pub enum ReadError {
 
     #[error("MTP speculative decode call failed: {0}")]
     MtpSpeculative(#[from] llama_cpp_2::speculative::MtpSpeculativeError),
+
+    #[error("Reading was aborted before the whole input was read")]
+    Aborted,
 }
 
 // CrossEncoderWorker errors
@@ -544,6 +586,30 @@ pub enum EncoderWorkerError {
 
     #[error("Error encoding: {0}")]
     Encode(String),
+
+    #[error("Input is too long to encode: {n_tokens} tokens but the encoder fits {max_tokens}")]
+    InputTooLong { n_tokens: usize, max_tokens: usize },
+}
+
+// CompletionWorker errors
+
+#[derive(Debug, thiserror::Error, miette::Diagnostic)]
+pub enum CompleteError {
+    #[error("Could not tokenize the prompt: {0}")]
+    Tokenize(#[from] TokenizationError),
+
+    #[error("Error reading the prompt: {0}")]
+    #[diagnostic(transparent)]
+    Read(#[from] ReadError),
+
+    #[error("Invalid sampler configuration: {0}")]
+    InvalidSamplerConfig(#[from] SamplerError),
+
+    #[error("Error while decoding next token: {0}")]
+    Decoding(#[from] DecodingError),
+
+    #[error("Error converting token to bytes: {0}")]
+    TokenToString(#[from] llama_cpp_2::TokenToStringError),
 }
 
 // HuggingFace download errors
@@ -928,6 +994,9 @@ pub enum SamplerError {
 
     #[error("Could not convert GBNF grammar to Lark: {0}")]
     GbnfConversionError(String),
+
+    #[error("{0}")]
+    UnknownGrammarToken(#[from] gbnf::token_refs::UnknownTokenError),
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -973,6 +1042,32 @@ pub enum SayError {
     #[error("Error generating response: {0}")]
     #[diagnostic(transparent)]
     GenerateResponse(#[from] GenerateResponseError),
+
+    #[error("Generation timed out before the response was finished")]
+    Timeout,
+
+    #[error("There is no user message to respond to")]
+    NoUserMessage,
+
+    #[error("The last response didn't stop on its token budget, so there is nothing to continue")]
+    NothingToContinue,
+
+    #[error("The worker thread is no longer running")]
+    #[diagnostic(
+        code(nobodywho::worker_dead),
+        help("The worker crashed on an earlier request; see the log for why. Create a new handle to continue.")
+    )]
+    WorkerDead,
+
+    #[error("The prompt needs {needed} tokens, but the context only holds {available}")]
+    #[diagnostic(
+        code(nobodywho::context_overflow),
+        help(
+            "The system prompt and the last message alone don't fit in the context window.\n\
+             Either shorten them, or increase the context size by setting a larger n_ctx."
+        )
+    )]
+    ContextOverflow { needed: usize, available: usize },
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -1017,6 +1112,9 @@ pub enum TokenizationError {
     #[error("Projection model failed to tokenize image bitmap: {0}")]
     ProjectionTokenizationError(String),
 
+    #[error("Could not detokenize token: {0}")]
+    TokenToString(#[from] llama_cpp_2::TokenToStringError),
+
     #[error(
         "Media marker mismatch: found {n_markers} media markers in template but received {n_bitmaps} media items. Each media placeholder in the prompt must have a corresponding media item.\n\nTemplate preview: {template_preview}"
     )]
@@ -1049,6 +1147,16 @@ pub enum ShiftError {
     )]
     TooFewMessages,
 
+    #[error("The context is full")]
+    #[diagnostic(
+        code(nobodywho::context_full),
+        help(
+            "The chat uses ContextPolicy::Error, so no messages are removed to make room.\n\
+             Start a new chat, remove messages with set_chat_history(), or increase n_ctx."
+        )
+    )]
+    ContextFull,
+
     #[error("Context shift failed: internal error: {0}")]
     InternalError(String),
 
@@ -1146,3 +1254,11 @@ pub enum CompletionError {
     #[diagnostic(transparent)]
     WorkerError(Box<dyn miette::Diagnostic + Send + Sync + 'static>),
 }
+
+impl CompletionError {
+    /// The diagnostic code of the underlying error, like `nobodywho::context_overflow`,
+    /// for bindings that map errors to their own exception types.
+    pub fn diagnostic_code(&self) -> Option<String> {
+        miette::Diagnostic::code(self).map(|code| code.to_string())
+    }
+}
@@ -15,9 +15,15 @@ use llama_cpp_2::speculative::MtpSpeculative;
 use llama_cpp_2::token::LlamaToken;
 use std::path::Path;
 use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::MutexGuard;
 use tracing::{debug, debug_span, trace, trace_span, warn};
 
+/// Number of prompt tokens decoded between checks of the stop flag while reading an
+/// abortable prompt. Small enough that aborting a long prefill is responsive, large enough
+/// that the extra `decode` calls don't noticeably slow it down.
+const ABORTABLE_READ_STEP: usize = 512;
+
 pub(crate) fn acquire_inference_lock() -> MutexGuard<'static, GlobalInferenceLockToken> {
     GLOBAL_INFERENCE_LOCK.lock().unwrap()
 }
@@ -44,7 +50,8 @@ where
             }
             WriteOutput::Token(_) | WriteOutput::Error(_) => (),
         }
-        if emitting {
+        // errors are always forwarded, even while a tool call is being hidden
+        if emitting || matches!(x, WriteOutput::Error(_)) {
             respond(x)
         }
     };
@@ -63,10 +70,15 @@ where
 /// [`MtpSpeculative`]; call sites that just need "the target context"
 /// go through [`Deref`] / [`DerefMut`], so most of the engine code is
 /// unchanged.
+///
+/// Engines for different sequences of one context take turns holding it: the engine that
+/// doesn't currently have it holds [`Lent`](Self::Lent), see
+/// [`InferenceEngine::pass_context_to`].
 #[derive(Debug)]
 pub(crate) enum EngineContext<'a> {
     Solo(LlamaContext<'a>),
     Speculative(MtpSpeculative<'a>),
+    Lent,
 }
 
 impl<'a> std::ops::Deref for EngineContext<'a> {
@@ -75,6 +87,7 @@ impl<'a> std::ops::Deref for EngineContext<'a> {
         match self {
             Self::Solo(c) => c,
             Self::Speculative(s) => s.target_context(),
+            Self::Lent => panic!("Context used by an engine that lent it to another sequence"),
         }
     }
 }
@@ -84,6 +97,7 @@ impl<'a> std::ops::DerefMut for EngineContext<'a> {
         match self {
             Self::Solo(c) => c,
             Self::Speculative(s) => s.target_context_mut(),
+            Self::Lent => panic!("Context used by an engine that lent it to another sequence"),
         }
     }
 }
@@ -106,12 +120,20 @@ pub(crate) struct InferenceEngine<'a> {
     projection_model: Option<&'a ProjectionModel>,
     n_past: i32,
     tokenizer: Tokenizer<'a>,
-    // The configured n_batch (= planned n_ctx before llama.cpp's internal rounding).
-    // Used to guard against sending more tokens than the context can decode in one batch.
+    // The planned n_ctx (before llama.cpp's internal rounding).
+    // Used to guard against reading more tokens than the context can hold.
+    n_ctx: usize,
+    // The configured n_batch: the max number of tokens decoded in one batch.
     n_batch: usize,
     big_batch: LlamaBatch<'a>,
     small_batch: LlamaBatch<'a>,
     use_embeddings: bool,
+    /// The KV cache sequence this engine reads into.
+    seq_id: i32,
+    /// Number of sequences sharing the context. Each gets `n_ctx` tokens of it.
+    n_seqs: u32,
+    /// Whether the context's logits may belong to another sequence's last decode.
+    stale_logits: bool,
     /// Deferred-decode "pending sample": a token sampled from the
     /// target but not yet decoded into the KV cache
     /// Invariants:
@@ -130,6 +152,7 @@ impl<'a> InferenceEngine<'a> {
         big_batch: LlamaBatch<'a>,
         small_batch: LlamaBatch<'a>,
         projection_model: Option<&'a ProjectionModel>,
+        n_ctx: usize,
         n_batch: usize,
         tokenizer: Tokenizer<'a>,
         use_embeddings: bool,
@@ -140,18 +163,84 @@ impl<'a> InferenceEngine<'a> {
             big_batch,
             small_batch,
             projection_model,
+            n_ctx,
             n_batch,
             tokenizer,
             use_embeddings,
+            seq_id: 0,
+            n_seqs: 1,
+            stale_logits: false,
             pending: None,
             mtp_drafts_proposed: 0,
             mtp_drafts_accepted: 0,
         }
     }
 
+    /// Set how many sequences share the context, see [`Worker::new_with_sequences`].
+    ///
+    /// [`Worker::new_with_sequences`]: crate::llm::Worker::new_with_sequences
+    pub(crate) fn with_n_seqs(mut self, n_seqs: u32) -> Self {
+        self.n_seqs = n_seqs;
+        self
+    }
+
+    /// An engine for sequence `seq_id` of this engine's context, starting out empty.
+    /// It can only run once this engine has passed it the context with
+    /// [`pass_context_to`](Self::pass_context_to).
+    pub(crate) fn share_sequence(&self, seq_id: i32) -> InferenceEngine<'a> {
+        debug_assert!((seq_id as u32) < self.n_seqs);
+        Self {
+            ctx: EngineContext::Lent,
+            projection_model: self.projection_model,
+            n_past: 0,
+            tokenizer: self.tokenizer.clone(),
+            n_ctx: self.n_ctx,
+            n_batch: self.n_batch,
+            big_batch: LlamaBatch::new(self.n_ctx, 1),
+            small_batch: LlamaBatch::new(1, 1),
+            use_embeddings: self.use_embeddings,
+            seq_id,
+            n_seqs: self.n_seqs,
+            stale_logits: false,
+            pending: None,
+            mtp_drafts_proposed: 0,
+            mtp_drafts_accepted: 0,
+        }
+    }
+
+    /// Hand the shared context over to `other`, an engine for another sequence of it.
+    /// The KV cache of every sequence stays as it is.
+    pub(crate) fn pass_context_to(&mut self, other: &mut InferenceEngine<'a>) {
+        debug_assert!(matches!(other.ctx, EngineContext::Lent));
+        std::mem::swap(&mut self.ctx, &mut other.ctx);
+        other.stale_logits = true;
+    }
+
+    /// The most tokens a text chunk can have and still be read.
+    pub(crate) fn max_read_tokens(&self) -> usize {
+        // encoders need the whole sequence in one decode for pooling
+        if self.use_embeddings {
+            self.n_batch.min(self.n_ctx)
+        } else {
+            self.n_ctx
+        }
+    }
+
+    /// Number of tokens this engine's sequence can hold.
+    pub(crate) fn context_size(&self) -> u32 {
+        self.ctx.n_ctx() / self.n_seqs
+    }
+
     #[tracing::instrument(level = "trace", skip(self))]
     pub(crate) fn reset_context(&mut self) -> &mut Self {
-        self.ctx.clear_kv_cache();
+        if self.n_seqs == 1 {
+            self.ctx.clear_kv_cache();
+        } else if let Err(e) = self
+            .ctx
+            .clear_kv_cache_seq(Some(self.seq_id as u32), None, None)
+        {
+            warn!(seq_id = self.seq_id, error = %e, "Failed to clear the KV cache sequence");
+        }
         self.n_past = 0;
         self.pending = None;
         self
@@ -162,15 +251,25 @@ impl<'a> InferenceEngine<'a> {
         self.mtp_drafts_accepted = 0;
     }
 
+    /// Read `chunks` into the KV cache.
+    ///
+    /// When a `should_stop` flag is given, it is checked between chunks and between
+    /// sub-batches of long text chunks, and reading stops early with [`ReadError::Aborted`]
+    /// once it is set. Whatever was read before that stays in the KV cache; rolling it back
+    /// is up to the caller.
     pub(crate) fn read_chunks(
         &mut self,
         chunks: TokenizerChunks,
+        should_stop: Option<&AtomicBool>,
         inference_lock_token: &MutexGuard<'_, GlobalInferenceLockToken>,
     ) -> Result<&mut Self, ReadError> {
         for chunk in chunks.into_iter() {
+            if should_stop.is_some_and(|flag| flag.load(Ordering::Relaxed)) {
+                return Err(ReadError::Aborted);
+            }
             match chunk {
                 TokenizerChunk::Text(tokens, _) => {
-                    self.read_text_tokens(tokens, inference_lock_token)?;
+                    self.read_text_tokens(tokens, should_stop, inference_lock_token)?;
                 }
                 TokenizerChunk::Image(embeddings, _) | TokenizerChunk::Audio(embeddings, _) => {
                     self.read_media_embeddings(embeddings, inference_lock_token)?;
@@ -197,13 +296,12 @@ impl<'a> InferenceEngine<'a> {
 
         let decode_span = debug_span!("read media embeddings", n_tokens = n_tokens);
         let decode_guard = decode_span.enter();
-        let n_ctx = self.ctx.n_ctx() as i32;
         self.n_past = embeddings.eval_chunks(
             &projection_model.ctx,
             &self.ctx,
             self.n_past,
-            0,
-            n_ctx,
+            self.seq_id,
+            self.n_batch as i32,
             true,
         )?;
 
@@ -225,6 +323,7 @@ impl<'a> InferenceEngine<'a> {
     fn read_text_tokens(
         &mut self,
         tokens: Vec<LlamaToken>,
+        should_stop: Option<&AtomicBool>,
         _inference_lock_token: &MutexGuard<'_, GlobalInferenceLockToken>,
     ) -> Result<&mut Self, ReadError> {
         let n_tokens = tokens.len();
@@ -233,41 +332,60 @@ impl<'a> InferenceEngine<'a> {
         // can't read nothing
         debug_assert!(!tokens.is_empty());
 
-        if n_tokens > self.n_batch {
+        let max_tokens = self.max_read_tokens();
+        if n_tokens > max_tokens {
             return Err(ReadError::InputExceedsContext {
                 n_tokens,
-                n_ctx: self.n_batch,
+                n_ctx: max_tokens,
             });
         }
 
-        {
-            debug!("Populating batch");
-            // make batch
-            self.big_batch.clear();
-            let seq_ids = &[0];
-            for (i, token) in (0..).zip(tokens.iter()) {
-                // For LLM workers only the last token's logits are needed (sampling).
-                // For encoder workers every token must be marked as an output so the
-                // pooling layer has hidden states to work with — otherwise llama.cpp
-                // logs "embeddings required but some input tokens were not marked as
-                // outputs -> overriding" and silently flips them on for us.
-                let output_logits = self.use_embeddings || i == n_tokens - 1;
-                self.big_batch
-                    .add(*token, self.n_past + i as i32, seq_ids, output_logits)?;
-            }
+        // Abortable reads are decoded in steps so the stop flag gets checked in between.
+        // Everything else goes in as few batches as n_batch allows.
+        let step = if should_stop.is_some() {
+            ABORTABLE_READ_STEP
+        } else {
+            n_tokens
         }
+        .min(self.n_batch);
 
-        // llm go brr
-        let decode_span = debug_span!("read decode", n_tokens = n_tokens);
-        let decode_guard = decode_span.enter();
-        self.ctx.decode(&mut self.big_batch)?;
-        drop(decode_guard);
-        // brrr
+        for (i_step, step_tokens) in tokens.chunks(step).enumerate() {
+            if i_step > 0 && should_stop.is_some_and(|flag| flag.load(Ordering::Relaxed)) {
+                debug!(n_past = self.n_past, "Reading tokens aborted");
+                return Err(ReadError::Aborted);
+            }
 
-        // Keep the MTP draft ctx's hidden state in sync (no-op on solo).
-        self.ctx.mtp_process(&self.big_batch)?;
+            {
+                debug!("Populating batch");
+                // make batch
+                self.big_batch.clear();
+                let seq_ids = &[self.seq_id];
+                let first_index = i_step * step;
+                for (i, token) in (0..).zip(step_tokens.iter()) {
+                    // For LLM workers only the last token's logits are needed (sampling).
+                    // For encoder workers every token must be marked as an output so the
+                    // pooling layer has hidden states to work with — otherwise llama.cpp
+                    // logs "embeddings required but some input tokens were not marked as
+                    // outputs -> overriding" and silently flips them on for us.
+                    let output_logits = self.use_embeddings || first_index + i == n_tokens - 1;
+                    self.big_batch
+                        .add(*token, self.n_past + i as i32, seq_ids, output_logits)?;
+                }
+            }
+
+            // llm go brr
+            let decode_span = debug_span!("read decode", n_tokens = step_tokens.len());
+            let decode_guard = decode_span.enter();
+            self.ctx.decode(&mut self.big_batch)?;
+            drop(decode_guard);
+            // brrr
+
+            // Keep the MTP draft ctx's hidden state in sync (no-op on solo).
+            self.ctx.mtp_process(&self.big_batch)?;
+
+            self.n_past += step_tokens.len() as i32;
+        }
 
-        self.n_past += tokens.len() as i32;
         // A new prompt (or context-shift replay) invalidates any deferred
         // pending sample from a previous generation.
         self.pending = None;
@@ -287,9 +405,9 @@ impl<'a> InferenceEngine<'a> {
         }
 
         let before = self.n_past;
-        let seq_rm_success = self
-            .ctx
-            .clear_kv_cache_seq(Some(0), Some(index as u32), None)?;
+        let seq_rm_success =
+            self.ctx
+                .clear_kv_cache_seq(Some(self.seq_id as u32), Some(index as u32), None)?;
 
         if seq_rm_success {
             self.n_past = index as i32;
@@ -309,10 +427,15 @@ impl<'a> InferenceEngine<'a> {
 
     /// Diff `target` chunks against `prev` and load only the new tail into the KV cache.
     /// Returns the new KV-cache mirror; the caller is responsible for storing it.
+    ///
+    /// If `should_stop` is set while the tail is being read, the partially read tail is
+    /// removed from the KV cache again and [`ReadError::Aborted`] is returned. The cache then
+    /// holds exactly the first [`n_past`](Self::n_past) tokens of `prev`.
     pub(crate) fn sync_context(
         &mut self,
         target: TokenizerChunks,
         prev: &TokenizerChunks,
+        should_stop: Option<&AtomicBool>,
         inference_lock_token: &MutexGuard<'_, GlobalInferenceLockToken>,
     ) -> Result<TokenizerChunks, ContextSyncError> {
         let prefix_index = find_chunks_prefix_difference(prev, &target);
@@ -323,14 +446,29 @@ impl<'a> InferenceEngine<'a> {
 
         let chunks_to_read = target.tail(self.n_past as usize);
         if chunks_to_read.n_tokens() > 0 {
-            self.read_chunks(chunks_to_read, inference_lock_token)?;
-        } else if trimmed > 0 {
-            // Truncate-only: KV cache was trimmed but no new tokens need appending.
+            let n_kept = self.n_past as usize;
+            match self.read_chunks(chunks_to_read, should_stop, inference_lock_token) {
+                Ok(_) => (),
+                Err(ReadError::Aborted) => {
+                    // roll back the half-read tail, so the next sync starts from a clean prefix
+                    self.remove_all_tokens_from_index_from_ctx(n_kept)?;
+                    return Err(ReadError::Aborted.into());
+                }
+                Err(e) => return Err(e.into()),
+            }
+        } else if trimmed > 0 || (self.stale_logits && self.n_past > 0) {
+            // Truncate-only: KV cache was trimmed but no new tokens need appending, or the
+            // logits are from another sequence sharing the context.
             // Re-decode the last token to refresh stale logits — llama.cpp requires
             // consecutive positions so we must evict it before re-reading.
             self.remove_all_tokens_from_index_from_ctx(self.n_past as usize - 1)?;
-            self.read_chunks(target.tail(self.n_past as usize), inference_lock_token)?;
+            self.read_chunks(
+                target.tail(self.n_past as usize),
+                None,
+                inference_lock_token,
+            )?;
         }
+        self.stale_logits = false;
 
         Ok(target)
     }
@@ -350,7 +488,11 @@ impl<'a> InferenceEngine<'a> {
     }
 
     pub(crate) fn is_context_full(&self) -> bool {
-        self.n_past as u32 == self.ctx.n_ctx()
+        self.n_past as u32 == self.context_size()
+    }
+
+    pub(crate) fn set_add_bos(&mut self, add_bos: llama_cpp_2::model::AddBos) {
+        self.tokenizer.set_add_bos(add_bos);
     }
 
     pub(crate) fn tokenize(
@@ -380,7 +522,7 @@ impl<'a> InferenceEngine<'a> {
         sampler: &mut LlamaSampler,
     ) -> Result<Vec<LlamaToken>, DecodingError> {
         match &self.ctx {
-            EngineContext::Solo(_) => self.sample_and_decode_solo(sampler),
+            EngineContext::Solo(_) | EngineContext::Lent => self.sample_and_decode_solo(sampler),
             EngineContext::Speculative(_) => self.sample_and_decode_speculative(sampler),
         }
     }
@@ -393,7 +535,8 @@ impl<'a> InferenceEngine<'a> {
         let new_token: LlamaToken = sampler.sample(&self.ctx, -1);
 
         self.small_batch.clear();
-        self.small_batch.add(new_token, self.n_past, &[0], true)?;
+        self.small_batch
+            .add(new_token, self.n_past, &[self.seq_id], true)?;
 
         let decode_span = trace_span!("write decode", n_past = self.n_past);
         let decode_guard = decode_span.enter();
@@ -430,14 +573,15 @@ impl<'a> InferenceEngine<'a> {
 
         // Clamp drafts so the verify batch [pending, drafts...] stays
         // within the context window:
-        let room = usize::try_from(self.ctx.n_ctx() as i32 - self.n_past - 1).unwrap_or(0);
+        let room = usize::try_from(self.context_size() as i32 - self.n_past - 1).unwrap_or(0);
         drafts.truncate(room);
         let k_max = drafts.len();
 
         if k_max == 0 {
             trace!(?pending, "MTP: no draft proposals to verify");
             self.small_batch.clear();
-            self.small_batch.add(pending, self.n_past, &[0], true)?;
+            self.small_batch
+                .add(pending, self.n_past, &[self.seq_id], true)?;
             self.ctx.decode(&mut self.small_batch)?;
             self.ctx.mtp_process(&self.small_batch)?;
             if accept_owed {
@@ -453,10 +597,11 @@ impl<'a> InferenceEngine<'a> {
         }
 
         self.big_batch.clear();
-        self.big_batch.add(pending, self.n_past, &[0], true)?;
+        self.big_batch
+            .add(pending, self.n_past, &[self.seq_id], true)?;
         for (i, &d) in drafts.iter().enumerate() {
             self.big_batch
-                .add(d, self.n_past + 1 + i as i32, &[0], true)?;
+                .add(d, self.n_past + 1 + i as i32, &[self.seq_id], true)?;
         }
         {
             let decode_span = trace_span!("mtp verify decode", n_past = self.n_past, k_max);
@@ -485,9 +630,9 @@ impl<'a> InferenceEngine<'a> {
 
         if j < k_max {
             let keep_up_to = (self.n_past + 1 + j as i32) as u32;
-            let rolled_back = self
-                .ctx
-                .clear_kv_cache_seq(Some(0), Some(keep_up_to), None)?;
+            let rolled_back =
+                self.ctx
+                    .clear_kv_cache_seq(Some(self.seq_id as u32), Some(keep_up_to), None)?;
             if !rolled_back {
                 // Recurrent / hybrid-recurrent memory types reject partial
                 // removal (Ok(false)). Unlike `remove_all_tokens_from_index_from_ctx`
@@ -1,4 +1,8 @@
+// lets the code generated by `tool_calling::nobodywho_tool` refer to `::nobodywho` in here too
+extern crate self as nobodywho;
+
 pub mod chat;
+pub mod completion;
 pub mod crossencoder;
 pub mod encoder;
 pub mod errors;
@@ -7,6 +11,7 @@ pub mod huggingface;
 pub mod inference;
 pub mod llm;
 pub mod memory;
+pub mod model_registry;
 mod model_selection;
 pub mod onnx;
 pub mod sampler;
@@ -17,6 +22,10 @@ pub mod tokenizer;
 pub mod tool_calling;
 pub mod tts;
 
+/// Re-exported so grammars for [`chat::AskOptions::grammar`] can be built without depending
+/// on the grammar crate directly.
+pub use gbnf;
+
 /// Render a miette diagnostic to a plain-text string, including any `help` text,
 /// error codes, and related errors. Falls back to `to_string()` if rendering fails.
 ///
@@ -1,18 +1,22 @@
-use crate::errors::{InitWorkerError, LoadModelError, ReadError};
+use crate::errors::{InitWorkerError, LoadModelError, ReadError, TokenizationError};
 use crate::huggingface::{download_gguf, parse_model_path};
 use crate::inference::{acquire_inference_lock, EngineContext, InferenceEngine};
 use crate::memory;
 use crate::model_selection;
-use crate::tokenizer::{ProjectionModel, Tokenizer};
+use crate::tokenizer::{token_to_piece_bytes, ProjectionModel, Tokenizer};
 use lazy_static::lazy_static;
-use llama_cpp_2::context::params::{LlamaContextParams, LlamaContextType, LlamaPoolingType};
+use llama_cpp_2::context::params::{
+    LlamaContextParams, LlamaContextType, LlamaPoolingType, RopeScalingType,
+};
 use llama_cpp_2::llama_backend::LlamaBackend;
 use llama_cpp_2::llama_batch::LlamaBatch;
 use llama_cpp_2::model::params::LlamaModelParams;
 use llama_cpp_2::model::AddBos;
 use llama_cpp_2::model::LlamaModel;
 use llama_cpp_2::speculative::{MtpSpeculative, MtpSpeculativeParams};
+use llama_cpp_2::token::LlamaToken;
 use std::pin::pin;
+use std::str::FromStr;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, LazyLock, Mutex};
 use tracing::{debug, error, info, info_span, warn};
@@ -34,6 +38,10 @@ lazy_static! {
 static LLAMA_BACKEND: LazyLock<LlamaBackend> =
     LazyLock::new(|| LlamaBackend::init().expect("Failed to initialize llama backend"));
 
+/// `LLAMA_FLASH_ATTN_TYPE_ENABLED` from llama.h, the raw value
+/// `with_flash_attention_policy` takes.
+const FLASH_ATTN_TYPE_ENABLED: i32 = 1;
+
 #[derive(Debug)]
 pub struct Model {
     pub(crate) language_model: LlamaModel,
@@ -65,6 +73,66 @@ impl Model {
             .unwrap_or(LlamaPoolingType::Unspecified)
             == LlamaPoolingType::Unspecified
     }
+
+    /// Tokenize plain text with the model's vocabulary.
+    ///
+    /// No chat template is applied and no BOS token is added, so this does not include the
+    /// overhead of rendering the text as a chat message.
+    pub fn tokenize(&self, text: &str) -> Result<Vec<i32>, TokenizationError> {
+        Ok(self
+            .language_model
+            .str_to_token(text, AddBos::Never)?
+            .into_iter()
+            .map(|token| token.0)
+            .collect())
+    }
+
+    /// Count the tokens in `text`. See [`tokenize`](Self::tokenize).
+    pub fn count_tokens(&self, text: &str) -> Result<usize, TokenizationError> {
+        Ok(self.tokenize(text)?.len())
+    }
+
+    /// Turn token ids back into text. Special tokens are rendered as text.
+    pub fn detokenize(&self, tokens: &[i32]) -> Result<String, TokenizationError> {
+        let mut bytes = Vec::new();
+        for &token in tokens {
+            let token = LlamaToken::new(token);
+            let piece = token_to_piece_bytes(&self.language_model, token, true)?;
+            bytes.extend(piece);
+        }
+        Ok(String::from_utf8_lossy(&bytes).into_owned())
+    }
+
+    /// The number of tokens in the vocabulary. Valid token ids are `0..n_vocab()`.
+    pub fn n_vocab(&self) -> usize {
+        self.language_model.n_vocab().try_into().unwrap_or(0)
+    }
+
+    /// The text of token `id`, with special tokens rendered as text.
+    /// Returns `None` for ids outside the vocabulary.
+    pub fn id_to_token(&self, id: i32) -> Option<String> {
+        if !(0..self.language_model.n_vocab()).contains(&id) {
+            return None;
+        }
+        self.detokenize(&[id]).ok()
+    }
+
+    /// The id of the token whose text is exactly `piece`, special tokens included.
+    /// Returns `None` if no single token has that text.
+    ///
+    /// Falls back to scanning the whole vocabulary, so cache the result if you need it often.
+    pub fn token_to_id(&self, piece: &str) -> Option<i32> {
+        // tokenizing the piece finds the token directly in the common case
+        if let Ok(tokens) = self.language_model.str_to_token(piece, AddBos::Never) {
+            if let [token] = tokens.as_slice() {
+                if self.id_to_token(token.0).as_deref() == Some(piece) {
+                    return Some(token.0);
+                }
+            }
+        }
+        (0..self.language_model.n_vocab())
+            .find(|&id| self.id_to_token(id).as_deref() == Some(piece))
+    }
 }
 
 pub fn has_gpu_backend() -> bool {
@@ -303,6 +371,81 @@ impl PoolingType for () {
     }
 }
 
+/// How RoPE positions are rescaled, to run a model beyond the context it was trained with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RopeScaling {
+    /// No scaling.
+    None,
+    /// Linear position interpolation.
+    Linear,
+    /// YaRN, which usually holds up better than linear scaling at large factors.
+    Yarn,
+}
+
+impl FromStr for RopeScaling {
+    type Err = ();
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().as_str() {
+            "none" => Ok(Self::None),
+            "linear" => Ok(Self::Linear),
+            "yarn" => Ok(Self::Yarn),
+            _ => Err(()),
+        }
+    }
+}
+
+impl From<RopeScaling> for RopeScalingType {
+    fn from(scaling: RopeScaling) -> Self {
+        match scaling {
+            RopeScaling::None => RopeScalingType::None,
+            RopeScaling::Linear => RopeScalingType::Linear,
+            RopeScaling::Yarn => RopeScalingType::Yarn,
+        }
+    }
+}
+
+/// Options for creating the llama.cpp context of a worker.
+#[derive(Debug, Clone, Default)]
+pub struct ContextOptions {
+    /// Decode on a single thread, with the whole prompt in one micro-batch.
+    /// This pins the floating-point reduction order, so the same input always yields
+    /// bit-identical outputs, at the cost of much lower throughput on multi-core CPUs.
+    pub deterministic: bool,
+    /// Threads used to generate tokens. Defaults to the available parallelism.
+    /// Only affects inference on the CPU.
+    pub n_threads: Option<u32>,
+    /// Threads used to read prompts, which are processed in batches. Defaults to the
+    /// available parallelism. Only affects inference on the CPU.
+    pub n_threads_batch: Option<u32>,
+    /// Force flash attention on, which uses less memory for long contexts.
+    /// When off, llama.cpp enables it only where the backend is known to support it.
+    pub flash_attention: bool,
+    /// Maximum number of prompt tokens decoded at once. Defaults to the context size.
+    /// Lower values reduce peak memory while reading long prompts. Encoders need their
+    /// whole input in a single batch, so this also caps the length of encoded text.
+    pub n_batch: Option<u32>,
+    /// RoPE scaling method. Defaults to the one in the model file.
+    ///
+    /// Setting any of the RoPE options lets the context grow beyond the length the model
+    /// was trained with. Output quality degrades the further the context is stretched,
+    /// so check the results before relying on it.
+    pub rope_scaling: Option<RopeScaling>,
+    /// RoPE base frequency. Defaults to the one in the model file.
+    pub rope_freq_base: Option<f32>,
+    /// RoPE frequency scaling factor, the inverse of the context extension
+    /// (e.g. `0.25` for 4x the trained context). Defaults to the one in the model file.
+    pub rope_freq_scale: Option<f32>,
+}
+
+impl ContextOptions {
+    fn overrides_rope(&self) -> bool {
+        self.rope_scaling.is_some()
+            || self.rope_freq_base.is_some()
+            || self.rope_freq_scale.is_some()
+    }
+}
+
 pub type WriteOutput =
     crate::stream::StreamOutput<Box<dyn miette::Diagnostic + Send + Sync + 'static>>;
 
@@ -316,16 +459,49 @@ where
         n_ctx: u32,
         use_embeddings: bool,
         mtp: Option<crate::chat::MtpConfig>,
+        options: ContextOptions,
+        extra: T,
+    ) -> Result<Worker<'a, T>, InitWorkerError> {
+        Self::new_with_sequences(model, n_ctx, 1, use_embeddings, mtp, options, extra)
+    }
+
+    /// Like [`new_with_type`](Self::new_with_type), but the context holds `n_seqs`
+    /// independent sequences of `n_ctx` tokens each. The engine uses sequence 0; engines for
+    /// the other sequences come from [`InferenceEngine::share_sequence`].
+    pub(crate) fn new_with_sequences(
+        model: &'a Model,
+        n_ctx: u32,
+        n_seqs: u32,
+        use_embeddings: bool,
+        mtp: Option<crate::chat::MtpConfig>,
+        options: ContextOptions,
         extra: T,
     ) -> Result<Worker<'a, T>, InitWorkerError> {
-        info!("Initializing worker");
+        info!(n_seqs, "Initializing worker");
 
         let projection_model = model.projection_model.as_ref();
 
-        // Set up context parameters using available parallelism
-        let n_threads = std::thread::available_parallelism()?.get() as i32;
+        // Set up context parameters using available parallelism, unless configured
+        let available_threads = std::thread::available_parallelism()?.get() as i32;
+        let mut n_threads = options.n_threads.map_or(available_threads, |n| n as i32);
+        let mut n_threads_batch = options
+            .n_threads_batch
+            .map_or(available_threads, |n| n as i32);
+        let n_ctx_train = model.language_model.n_ctx_train();
+        let n_ctx = if options.overrides_rope() {
+            if n_ctx > n_ctx_train {
+                warn!(
+                    n_ctx,
+                    n_ctx_train,
+                    "Context exceeds the trained context length, output quality may degrade"
+                );
+            }
+            n_ctx
+        } else {
+            std::cmp::min(n_ctx, n_ctx_train)
+        };
         let ctx_plan = memory::plan_context(
-            std::cmp::min(n_ctx, model.language_model.n_ctx_train()),
+            n_ctx * n_seqs,
             projection_model.is_some(),
             memory::ModelArchitecture {
                 n_layers: model.language_model.n_layer(),
@@ -335,26 +511,56 @@ where
             },
         )?;
         let planned_n_ctx = ctx_plan.n_ctx;
-        let n_ubatch = ctx_plan.n_ubatch;
+        // each sequence gets an equal share of the context
+        let n_ctx_per_seq = planned_n_ctx / n_seqs;
+        let n_batch = options
+            .n_batch
+            .map_or(n_ctx_per_seq, |n| n.clamp(1, n_ctx_per_seq));
+        let mut n_ubatch = ctx_plan.n_ubatch.min(n_batch);
+        if options.deterministic {
+            debug!("Using deterministic context: single thread, single micro-batch");
+            n_threads = 1;
+            n_threads_batch = 1;
+            n_ubatch = n_batch;
+        }
+        debug!(
+            n_threads,
+            n_threads_batch, n_batch, n_ubatch, "Context parameters"
+        );
         for w in &ctx_plan.warnings {
             warn!("{}", w);
         }
 
-        let ctx_params = LlamaContextParams::default()
+        let mut ctx_params = LlamaContextParams::default()
             .with_n_ctx(std::num::NonZero::new(planned_n_ctx))
-            .with_n_batch(planned_n_ctx) // n_batch sets the max size of a batch (i.e. max prompt size)
+            .with_n_batch(n_batch) // n_batch sets the max number of tokens in a single decode
             .with_n_ubatch(n_ubatch)
             .with_n_threads(n_threads)
-            .with_n_threads_batch(n_threads)
+            .with_n_threads_batch(n_threads_batch)
             .with_embeddings(use_embeddings)
             .with_pooling_type(extra.pooling_type());
+        if let Some(scaling) = options.rope_scaling {
+            ctx_params = ctx_params.with_rope_scaling_type(scaling.into());
+        }
+        if let Some(freq_base) = options.rope_freq_base {
+            ctx_params = ctx_params.with_rope_freq_base(freq_base);
+        }
+        if let Some(freq_scale) = options.rope_freq_scale {
+            ctx_params = ctx_params.with_rope_freq_scale(freq_scale);
+        }
+        if n_seqs > 1 {
+            ctx_params = ctx_params.with_n_seq_max(n_seqs);
+        }
+        if options.flash_attention {
+            debug!("Enabling flash attention");
+            ctx_params = ctx_params.with_flash_attention_policy(FLASH_ATTN_TYPE_ENABLED);
+        }
 
         let ctx = model
             .language_model
             .new_context(&LLAMA_BACKEND, ctx_params)?;
-        let n_batch = planned_n_ctx as usize;
 
-        let big_batch = LlamaBatch::new(ctx.n_ctx() as usize, 1);
+        let big_batch = LlamaBatch::new((ctx.n_ctx() / n_seqs) as usize, 1);
         let small_batch = LlamaBatch::new(1, 1);
 
         let engine_ctx = if let Some(mtp_config) = mtp {
@@ -362,14 +568,18 @@ where
                 Some(draft_model) => {
                     info!("Initializing MTP speculative draft context");
                     let draft_batch_cap: u32 = 32;
-                    let draft_params = LlamaContextParams::default()
+                    let mut draft_params = LlamaContextParams::default()
                         .with_n_ctx(std::num::NonZero::new(planned_n_ctx))
                         .with_n_batch(draft_batch_cap)
                         .with_n_ubatch(draft_batch_cap)
                         .with_n_threads(n_threads)
-                        .with_n_threads_batch(n_threads)
+                        .with_n_threads_batch(n_threads_batch)
                         .with_context_type(LlamaContextType::Mtp)
                         .with_n_rs_seq(0);
+                    if options.flash_attention {
+                        draft_params =
+                            draft_params.with_flash_attention_policy(FLASH_ATTN_TYPE_ENABLED);
+                    }
                     let draft_ctx = draft_model.new_context_with_ctx_other(
                         &LLAMA_BACKEND,
                         draft_params,
@@ -401,10 +611,12 @@ where
             big_batch,
             small_batch,
             projection_model,
-            n_batch,
+            n_ctx_per_seq as usize,
+            n_batch as usize,
             tokenizer,
             use_embeddings,
-        );
+        )
+        .with_n_seqs(n_seqs);
         Ok(Worker { engine, extra })
     }
 
@@ -419,7 +631,8 @@ where
     pub fn read_string(&mut self, text: String) -> Result<&mut Self, ReadError> {
         let inference_lock_token = acquire_inference_lock();
         let chunks = self.engine.tokenize(text, vec![])?;
-        self.engine.read_chunks(chunks, &inference_lock_token)?;
+        self.engine
+            .read_chunks(chunks, None, &inference_lock_token)?;
         Ok(self)
     }
 }
@@ -453,6 +666,13 @@ impl<T> WorkerGuard<T> {
         self.msg_tx.as_ref().is_some_and(|tx| tx.send(msg).is_ok())
     }
 
+    /// Whether the worker thread is still running. It exits early if it crashes.
+    pub(crate) fn is_alive(&self) -> bool {
+        self.join_handle
+            .as_ref()
+            .is_some_and(|handle| !handle.is_finished())
+    }
+
     /// Signal the worker to stop mid-generation (no-op if no stop flag).
     pub(crate) fn stop(&self) {
         if let Some(ref flag) = self.should_stop {
@@ -480,6 +700,59 @@ mod tests {
     use super::*;
     use std::sync::atomic::{AtomicUsize, Ordering};
 
+    #[test]
+    fn test_worker_guard_is_alive() {
+        let (msg_tx, msg_rx) = std::sync::mpsc::channel::<()>();
+        let guard = WorkerGuard::new(
+            msg_tx,
+            std::thread::spawn(move || {
+                msg_rx.recv().unwrap();
+                panic!("worker crashed");
+            }),
+            None,
+        );
+        assert!(guard.is_alive());
+
+        assert!(guard.send(()));
+        while guard.is_alive() {
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        assert!(!guard.send(()));
+    }
+
+    #[test]
+    fn test_tokenize_roundtrip() -> Result<(), Box<dyn std::error::Error>> {
+        let model = crate::test_utils::load_test_model();
+
+        let text = "The capital of Denmark is Copenhagen.";
+        let tokens = model.tokenize(text)?;
+        assert!(!tokens.is_empty());
+        assert_eq!(model.count_tokens(text)?, tokens.len());
+        assert_eq!(model.detokenize(&tokens)?, text);
+        assert_eq!(model.count_tokens("")?, 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_vocab_lookup() {
+        let model = crate::test_utils::load_test_model();
+
+        let id = model
+            .token_to_id("<|im_end|>")
+            .expect("im_end is in the vocabulary");
+        assert_eq!(model.id_to_token(id).as_deref(), Some("<|im_end|>"));
+
+        let id = model.tokenize("Hello").unwrap()[0];
+        let piece = model.id_to_token(id).unwrap();
+        assert_eq!(model.token_to_id(&piece), Some(id));
+
+        assert!(model.n_vocab() > 1000);
+        assert_eq!(model.id_to_token(model.n_vocab() as i32), None);
+        assert_eq!(model.id_to_token(-1), None);
+        assert_eq!(model.token_to_id("not a single token at all"), None);
+    }
+
     #[test]
     fn rejects_projection_model_with_auto_selection() {
         let result = get_model("auto", true, Some("projection.gguf"), None, None);
@@ -0,0 +1,218 @@
+//! Loaded models shared between handles by name.
+//!
+//! A server hosting several models (say a chat model, an embedding model and a reranker)
+//! registers each of them once, and every handle gets its model from the registry. Handles
+//! only hold an `Arc` to the model, so a model used by several handles is in memory once.
+//!
+//! ```no_run
+//! use nobodywho::chat::ChatBuilder;
+//! use nobodywho::encoder::Encoder;
+//! use nobodywho::model_registry::ModelRegistry;
+//!
+//! # fn example() -> Result<(), Box<dyn std::error::Error>> {
+//! let registry = ModelRegistry::new();
+//! registry.load("chat-model", "qwen3-4b.gguf", true)?;
+//! registry.load("embeddings", "embeddings.gguf", true)?;
+//!
+//! let chat = ChatBuilder::from_registry(&registry, "chat-model")?.build()?;
+//! let encoder = Encoder::new(registry.get("embeddings").unwrap(), 1024);
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::errors::{LoadModelError, ModelRegistryError};
+use crate::llm::{get_model, Model};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+struct Entry {
+    model: Arc<Model>,
+    /// Path and GPU setting the model was loaded with, if the registry loaded it
+    source: Option<(String, bool)>,
+}
+
+/// Models by name, shared between the handles created from them.
+///
+/// The registry holds one reference to each model and every handle using it holds another.
+/// A model is freed once it is removed from the registry and the last handle using it is
+/// dropped.
+#[derive(Default)]
+pub struct ModelRegistry {
+    models: Mutex<HashMap<String, Entry>>,
+    /// One lock per path and GPU setting being loaded, so concurrent loads of the same
+    /// model wait for the first one instead of loading it again
+    loading: Mutex<HashMap<(String, bool), Arc<Mutex<()>>>>,
+}
+
+impl ModelRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn models(&self) -> MutexGuard<'_, HashMap<String, Entry>> {
+        // the map is left consistent even if a thread panicked while holding the lock
+        self.models.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Load the model at `model_path` and register it as `name`, replacing any model
+    /// registered as `name` before.
+    ///
+    /// If the registry already loaded the same path with the same GPU setting, under any
+    /// name, that model is shared instead of loading it again. A load of a path that is
+    /// still being loaded by another thread waits for it and shares its model.
+    pub fn load(
+        &self,
+        name: impl Into<String>,
+        model_path: &str,
+        use_gpu_if_available: bool,
+    ) -> Result<Arc<Model>, LoadModelError> {
+        let source = (model_path.to_string(), use_gpu_if_available);
+        let slot = self
+            .loading
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .entry(source.clone())
+            .or_default()
+            .clone();
+        let result = {
+            let _loading = slot.lock().unwrap_or_else(|e| e.into_inner());
+            self.load_source(name.into(), source.clone())
+        };
+
+        // the load has resolved: threads already waiting on this lock find the model
+        // registered (or load it themselves if it failed), and later loads take a new lock
+        let mut loading = self.loading.lock().unwrap_or_else(|e| e.into_inner());
+        if loading
+            .get(&source)
+            .is_some_and(|current| Arc::ptr_eq(current, &slot))
+        {
+            loading.remove(&source);
+        }
+        result
+    }
+
+    /// Load `source` unless it is registered already, and register it as `name`.
+    /// Called with the lock of `source` in [`Self::loading`] held.
+    fn load_source(
+        &self,
+        name: String,
+        source: (String, bool),
+    ) -> Result<Arc<Model>, LoadModelError> {
+        let loaded = self
+            .models()
+            .values()
+            .find(|entry| entry.source.as_ref() == Some(&source))
+            .map(|entry| entry.model.clone());
+        // loading takes a while, so it happens without holding the lock of the map
+        let model = match loaded {
+            Some(model) => model,
+            None => Arc::new(get_model(&source.0, source.1, None, None, None)?),
+        };
+        self.models().insert(
+            name,
+            Entry {
+                model: model.clone(),
+                source: Some(source),
+            },
+        );
+        Ok(model)
+    }
+
+    /// Register an already loaded model as `name`. Returns the model previously registered
+    /// under that name, if any.
+    pub fn insert(&self, name: impl Into<String>, model: Arc<Model>) -> Option<Arc<Model>> {
+        self.models()
+            .insert(
+                name.into(),
+                Entry {
+                    model,
+                    source: None,
+                },
+            )
+            .map(|entry| entry.model)
+    }
+
+    /// The model registered as `name`.
+    pub fn get(&self, name: &str) -> Option<Arc<Model>> {
+        self.models().get(name).map(|entry| entry.model.clone())
+    }
+
+    /// Like [`get`](Self::get), failing with [`ModelRegistryError::UnknownModel`] for an
+    /// unknown name.
+    pub fn require(&self, name: &str) -> Result<Arc<Model>, ModelRegistryError> {
+        self.get(name)
+            .ok_or_else(|| ModelRegistryError::UnknownModel {
+                name: name.to_string(),
+                registered: self.names(),
+            })
+    }
+
+    /// Unregister `name`. The model stays loaded as long as handles use it.
+    pub fn remove(&self, name: &str) -> Option<Arc<Model>> {
+        self.models().remove(name).map(|entry| entry.model)
+    }
+
+    /// The names of all registered models, sorted.
+    pub fn names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.models().keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    /// How many handles and other references outside the registry use the model registered
+    /// as `name`.
+    pub fn users(&self, name: &str) -> Option<usize> {
+        let models = self.models();
+        let entry = models.get(name)?;
+        // every name the model is registered under holds one reference
+        let registered = models
+            .values()
+            .filter(|other| Arc::ptr_eq(&other.model, &entry.model))
+            .count();
+        Some(Arc::strong_count(&entry.model) - registered)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chat::ChatBuilder;
+    use crate::test_utils;
+
+    #[test]
+    fn test_registry_shares_models() -> Result<(), Box<dyn std::error::Error>> {
+        let registry = ModelRegistry::new();
+        let path = test_utils::test_model_path();
+        let chat_model = registry.load("chat-model", &path, true)?;
+        let other = registry.load("other", &path, true)?;
+        assert!(Arc::ptr_eq(&chat_model, &other));
+        assert_eq!(registry.names(), vec!["chat-model", "other"]);
+        drop((chat_model, other));
+        assert_eq!(registry.users("chat-model"), Some(0));
+
+        let chat = ChatBuilder::from_registry(&registry, "chat-model")?.build()?;
+        assert_eq!(registry.users("chat-model"), Some(1));
+        drop(chat);
+        assert_eq!(registry.users("chat-model"), Some(0));
+
+        assert!(matches!(
+            ChatBuilder::from_registry(&registry, "reranker"),
+            Err(ModelRegistryError::UnknownModel { .. })
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_registry_concurrent_loads_share_model() -> Result<(), Box<dyn std::error::Error>> {
+        let registry = ModelRegistry::new();
+        let path = test_utils::test_model_path();
+        let (a, b) = std::thread::scope(|s| {
+            let a = s.spawn(|| registry.load("a", &path, true));
+            let b = s.spawn(|| registry.load("b", &path, true));
+            (a.join().unwrap(), b.join().unwrap())
+        });
+        assert!(Arc::ptr_eq(&a?, &b?));
+        assert!(registry.loading.lock().unwrap().is_empty());
+        Ok(())
+    }
+}
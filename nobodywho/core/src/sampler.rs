@@ -49,18 +49,53 @@ impl SamplerPresets {
                 base: 1.75,
                 allowed_length: 2,
                 penalty_last_n: -1,
-                seq_breakers: vec![
-                    "\n".to_string(),
-                    ":".to_string(),
-                    "\"".to_string(),
-                    "*".to_string(),
-                ],
+                seq_breakers: default_dry_seq_breakers(),
             }],
             SampleStep::Dist,
             default_seed(),
         )
     }
 
+    /// Sampler chain for long-form creative writing: repetition penalties and DRY to
+    /// discourage loops, min_p to prune the long tail, and a fairly high temperature.
+    pub fn creative() -> SamplerConfig {
+        SamplerConfig::new(
+            vec![
+                ShiftStep::Penalties {
+                    penalty_last_n: 256,
+                    penalty_repeat: 1.05,
+                    penalty_freq: 0.0,
+                    penalty_present: 0.0,
+                },
+                ShiftStep::dry_default(),
+                ShiftStep::MinP {
+                    min_keep: 1,
+                    min_p: 0.05,
+                },
+                ShiftStep::Temperature { temperature: 1.0 },
+            ],
+            SampleStep::Dist,
+            default_seed(),
+        )
+    }
+
+    /// Sampler chain for factual tasks where determinism matters more than variety.
+    /// Keeps only a handful of candidates and samples at a very low temperature.
+    pub fn precise() -> SamplerConfig {
+        SamplerConfig::new(
+            vec![
+                ShiftStep::TopK { top_k: 10 },
+                ShiftStep::TopP {
+                    min_keep: 1,
+                    top_p: 0.9,
+                },
+                ShiftStep::Temperature { temperature: 0.1 },
+            ],
+            SampleStep::Dist,
+            default_seed(),
+        )
+    }
+
     /// Constrain output to a JSON schema using llguidance.
     pub fn constrain_with_json_schema(schema: String) -> SamplerConfig {
         SamplerConfig::new(
@@ -98,6 +133,19 @@ impl SamplerPresets {
         SamplerConfig::new(steps, SampleStep::Dist, default_seed())
     }
 
+    /// Constrain output to exactly one of `options`, with nothing before or after it.
+    /// See [`gbnf::builder::choice`].
+    pub fn choice(options: &[&str]) -> SamplerConfig {
+        let grammar = gbnf::builder::choice(options);
+        let mut steps = SamplerConfig::default().steps;
+        steps.push(ShiftStep::Grammar {
+            trigger_on: None,
+            root: grammar.root_name.clone(),
+            grammar: grammar.as_str().into(),
+        });
+        SamplerConfig::new(steps, SampleStep::Dist, default_seed())
+    }
+
     #[deprecated(note = "Use SamplerPresets::constrain_with_grammar() instead")]
     pub fn grammar(grammar: String) -> SamplerConfig {
         let mut steps = SamplerConfig::default().steps;
@@ -123,17 +171,77 @@ pub struct SamplerConfig {
     pub seed: u32,
 }
 
+/// The sequence breakers llama.cpp uses for DRY by default: newlines, colons, quotes and
+/// asterisks. A repeated sequence is never extended across one of these.
+pub fn default_dry_seq_breakers() -> Vec<String> {
+    ["\n", ":", "\"", "*"].map(String::from).to_vec()
+}
+
 pub fn default_seed() -> u32 {
     1234
 }
 
 impl SamplerConfig {
+    /// Logs a warning for every [`SamplerWarning`] of the chain.
     pub fn new(shift_steps: Vec<ShiftStep>, sample_step: SampleStep, seed: u32) -> Self {
-        Self {
+        let config = Self {
             steps: shift_steps,
             sample_step,
             seed,
+        };
+        for warning in config.warnings() {
+            warn!("Sampler misconfiguration: {warning}");
         }
+        config
+    }
+
+    /// Find the steps of the chain that have no effect or don't act the way they probably
+    /// should, because of the order of the steps. See [`SamplerBuilder`] for the canonical
+    /// order.
+    pub fn warnings(&self) -> Vec<SamplerWarning> {
+        let mut warnings = Vec::new();
+        for (index, step) in self.steps.iter().enumerate() {
+            let earlier = &self.steps[..index];
+            if earlier.iter().any(|s| s.name() == step.name()) {
+                warnings.push(SamplerWarning::Duplicate { step: step.name() });
+            }
+            if step.is_penalty() {
+                if let Some(truncation) = earlier.iter().find(|s| s.is_truncation()) {
+                    warnings.push(SamplerWarning::PenaltyAfterTruncation {
+                        penalty: step.name(),
+                        truncation: truncation.name(),
+                    });
+                }
+            }
+            let top_k = earlier.iter().find_map(|s| match s {
+                ShiftStep::TopK { top_k } if *top_k > 0 => Some(*top_k as u32),
+                _ => None,
+            });
+            if let (Some(min_keep), Some(top_k)) = (step.min_keep(), top_k) {
+                if min_keep > top_k {
+                    warnings.push(SamplerWarning::MinKeepAboveTopK {
+                        step: step.name(),
+                        min_keep,
+                        top_k,
+                    });
+                }
+            }
+            match self.sample_step {
+                SampleStep::Greedy
+                    if (step.is_truncation() && !matches!(step, ShiftStep::XTC { .. }))
+                        || matches!(step, ShiftStep::Temperature { .. }) =>
+                {
+                    warnings.push(SamplerWarning::NoEffectBeforeGreedy { step: step.name() });
+                }
+                SampleStep::MirostatV1 { .. } | SampleStep::MirostatV2 { .. }
+                    if step.is_truncation() =>
+                {
+                    warnings.push(SamplerWarning::TruncationBeforeMirostat { step: step.name() });
+                }
+                _ => {}
+            }
+        }
+        warnings
     }
 
     pub fn to_stateful(&self, model: &LlamaModel) -> Result<LlamaSampler, SamplerError> {
@@ -205,7 +313,11 @@ impl SamplerConfig {
                 base,
                 allowed_length,
                 penalty_last_n,
-                seq_breakers,
+                if seq_breakers.is_empty() {
+                    default_dry_seq_breakers()
+                } else {
+                    seq_breakers
+                },
             )),
             ShiftStep::Penalties {
                 penalty_last_n,
@@ -252,9 +364,10 @@ impl SamplerConfig {
             }
         };
 
+        let grammar = resolve_token_refs(model, grammar)?;
         Ok(LlamaSampler::grammar_lazy(
             model,
-            grammar,
+            &grammar,
             root,
             Vec::<&str>::new(),
             &[token],
@@ -267,7 +380,72 @@ impl SamplerConfig {
         grammar: &str,
         root: &str,
     ) -> Result<LlamaSampler, SamplerError> {
-        Ok(LlamaSampler::grammar(model, grammar, root)?)
+        let grammar = resolve_token_refs(model, grammar)?;
+        Ok(LlamaSampler::grammar(model, &grammar, root)?)
+    }
+
+    /// Build the step chain the flat `params` describe, in llama.cpp's order: penalties,
+    /// top-k, typical-p, top-p, min-p and temperature, then sampling from the distribution.
+    /// Steps with neutral settings are left out.
+    ///
+    /// With Mirostat, only the penalties and temperature are kept before it, since Mirostat
+    /// does its own truncation.
+    pub fn from_legacy(params: LegacySamplerParams) -> Self {
+        let mirostat = match params.mirostat {
+            1 => Some(SampleStep::MirostatV1 {
+                tau: params.mirostat_tau,
+                eta: params.mirostat_eta,
+                m: 100,
+            }),
+            2 => Some(SampleStep::MirostatV2 {
+                tau: params.mirostat_tau,
+                eta: params.mirostat_eta,
+            }),
+            _ => None,
+        };
+
+        let mut builder = SamplerBuilder::new().seed(params.seed);
+        if params.penalty_repeat != 1.0
+            || params.penalty_freq != 0.0
+            || params.penalty_present != 0.0
+        {
+            builder = builder.shift(ShiftStep::Penalties {
+                penalty_last_n: params.penalty_last_n,
+                penalty_repeat: params.penalty_repeat,
+                penalty_freq: params.penalty_freq,
+                penalty_present: params.penalty_present,
+            });
+        }
+        if mirostat.is_none() {
+            if params.top_k > 0 {
+                builder = builder.shift(ShiftStep::TopK {
+                    top_k: params.top_k,
+                });
+            }
+            if params.typical_p < 1.0 {
+                builder = builder.shift(ShiftStep::TypicalP {
+                    typ_p: params.typical_p,
+                    min_keep: 1,
+                });
+            }
+            if params.top_p < 1.0 {
+                builder = builder.shift(ShiftStep::TopP {
+                    top_p: params.top_p,
+                    min_keep: 1,
+                });
+            }
+            if params.min_p > 0.0 {
+                builder = builder.shift(ShiftStep::MinP {
+                    min_p: params.min_p,
+                    min_keep: 1,
+                });
+            }
+        }
+        builder
+            .shift(ShiftStep::Temperature {
+                temperature: params.temperature,
+            })
+            .sample(mirostat.unwrap_or(SampleStep::Dist))
     }
 }
 
@@ -288,6 +466,123 @@ impl Default for SamplerConfig {
     }
 }
 
+/// Flat sampler settings, the way older versions exposed them (e.g. in the Godot editor).
+/// Turn them into a step chain with [`SamplerConfig::from_legacy`].
+///
+/// The defaults are neutral, except for `temperature`, which is 0.8 like in llama.cpp.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LegacySamplerParams {
+    pub seed: u32,
+    pub temperature: f32,
+    /// `0` or below disables top-k.
+    pub top_k: i32,
+    /// `1.0` disables top-p.
+    pub top_p: f32,
+    /// `0.0` disables min-p.
+    pub min_p: f32,
+    /// `1.0` disables typical-p.
+    pub typical_p: f32,
+    pub penalty_last_n: i32,
+    /// `1.0` together with zero frequency and presence penalties disables penalties.
+    pub penalty_repeat: f32,
+    pub penalty_freq: f32,
+    pub penalty_present: f32,
+    /// `0` samples from the distribution, `1` uses Mirostat, `2` uses Mirostat 2.0.
+    pub mirostat: i32,
+    pub mirostat_tau: f32,
+    pub mirostat_eta: f32,
+}
+
+impl Default for LegacySamplerParams {
+    fn default() -> Self {
+        Self {
+            seed: default_seed(),
+            temperature: 0.8,
+            top_k: 0,
+            top_p: 1.0,
+            min_p: 0.0,
+            typical_p: 1.0,
+            penalty_last_n: 64,
+            penalty_repeat: 1.0,
+            penalty_freq: 0.0,
+            penalty_present: 0.0,
+            mirostat: 0,
+            mirostat_tau: 5.0,
+            mirostat_eta: 0.1,
+        }
+    }
+}
+
+/// A step of a [`SamplerConfig`] that has no effect or probably doesn't do what was
+/// intended, because of where it is in the chain. Found by [`SamplerConfig::warnings`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SamplerWarning {
+    /// The same kind of step appears more than once.
+    Duplicate { step: &'static str },
+    /// A penalty step comes after a truncation step, so it only reorders the candidates the
+    /// truncation kept instead of steering which ones are kept.
+    PenaltyAfterTruncation {
+        penalty: &'static str,
+        truncation: &'static str,
+    },
+    /// A step can't keep `min_keep` candidates, since an earlier `top_k` leaves fewer.
+    MinKeepAboveTopK {
+        step: &'static str,
+        min_keep: u32,
+        top_k: u32,
+    },
+    /// Greedy sampling always picks the most likely token, which truncation and temperature
+    /// don't change.
+    NoEffectBeforeGreedy { step: &'static str },
+    /// Mirostat picks its own cutoff, so truncating the candidates first fights it.
+    TruncationBeforeMirostat { step: &'static str },
+}
+
+impl std::fmt::Display for SamplerWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SamplerWarning::Duplicate { step } => {
+                write!(f, "the {step} step appears more than once")
+            }
+            SamplerWarning::PenaltyAfterTruncation {
+                penalty,
+                truncation,
+            } => write!(
+                f,
+                "the {penalty} step comes after {truncation}, put penalties first so they affect which tokens are kept"
+            ),
+            SamplerWarning::MinKeepAboveTopK {
+                step,
+                min_keep,
+                top_k,
+            } => write!(
+                f,
+                "the {step} step keeps at least {min_keep} tokens, but the top_k before it only leaves {top_k}"
+            ),
+            SamplerWarning::NoEffectBeforeGreedy { step } => write!(
+                f,
+                "the {step} step has no effect, since greedy sampling always picks the most likely token"
+            ),
+            SamplerWarning::TruncationBeforeMirostat { step } => write!(
+                f,
+                "the {step} step truncates the candidates before mirostat, which picks its own cutoff"
+            ),
+        }
+    }
+}
+
+/// Builds a [`SamplerConfig`] from shift steps, applied in the order they are added, and a
+/// final sample step.
+///
+/// The canonical order, which is also what llama.cpp uses, is:
+/// 1. penalties: `penalties`, `dry`
+/// 2. constraints: `grammar`, `json_schema`, `regex`, `lark`
+/// 3. truncation: `top_k`, `typical_p`, `top_p`, `min_p`, `xtc`
+/// 4. `temperature`
+/// 5. the sample step: `dist`, `greedy` or `mirostat`
+///
+/// Chains that break it in ways that matter are logged as warnings when built, see
+/// [`SamplerConfig::warnings`].
 #[derive(Clone)]
 pub struct SamplerBuilder {
     steps: Vec<ShiftStep>,
@@ -322,11 +617,7 @@ impl SamplerBuilder {
     }
 
     pub fn sample(self, step: SampleStep) -> SamplerConfig {
-        SamplerConfig {
-            steps: self.steps,
-            sample_step: step,
-            seed: self.seed,
-        }
+        SamplerConfig::new(self.steps, step, self.seed)
     }
 }
 
@@ -395,12 +686,20 @@ pub enum ShiftStep {
     Regex(String),
     /// Constrain output using a Lark context-free grammar via llguidance.
     Lark(String),
+    /// DRY ("Don't Repeat Yourself"): penalizes tokens that would extend a sequence that
+    /// already occurred earlier in the context.
     #[serde(rename = "dry")]
     DRY {
+        /// Penalty strength. `0.0` disables DRY, `0.8` is the recommended value.
         multiplier: f32,
+        /// How fast the penalty grows with the length of the repeated sequence.
         base: f32,
+        /// Repeated sequences up to this many tokens are not penalized.
         allowed_length: i32,
+        /// How many recent tokens to search for repetitions. `-1` means the whole context.
         penalty_last_n: i32,
+        /// Strings that end a repeated sequence, so matching never crosses them.
+        /// When empty, [`default_dry_seq_breakers`] is used.
         seq_breakers: Vec<String>,
     },
     Penalties {
@@ -413,6 +712,66 @@ pub enum ShiftStep {
         temperature: f32,
     },
 }
+
+impl ShiftStep {
+    /// The name of the step, as it is serialized.
+    pub fn name(&self) -> &'static str {
+        match self {
+            ShiftStep::TopK { .. } => "top_k",
+            ShiftStep::TopP { .. } => "top_p",
+            ShiftStep::MinP { .. } => "min_p",
+            ShiftStep::XTC { .. } => "xtc",
+            ShiftStep::TypicalP { .. } => "typical_p",
+            ShiftStep::Grammar { .. } => "grammar",
+            ShiftStep::JsonSchema(_) => "json_schema",
+            ShiftStep::Regex(_) => "regex",
+            ShiftStep::Lark(_) => "lark",
+            ShiftStep::DRY { .. } => "dry",
+            ShiftStep::Penalties { .. } => "penalties",
+            ShiftStep::Temperature { .. } => "temperature",
+        }
+    }
+
+    /// Whether the step removes unlikely candidates.
+    fn is_truncation(&self) -> bool {
+        matches!(
+            self,
+            ShiftStep::TopK { .. }
+                | ShiftStep::TopP { .. }
+                | ShiftStep::MinP { .. }
+                | ShiftStep::XTC { .. }
+                | ShiftStep::TypicalP { .. }
+        )
+    }
+
+    /// Whether the step penalizes repetition.
+    fn is_penalty(&self) -> bool {
+        matches!(self, ShiftStep::DRY { .. } | ShiftStep::Penalties { .. })
+    }
+
+    fn min_keep(&self) -> Option<u32> {
+        match self {
+            ShiftStep::TopP { min_keep, .. }
+            | ShiftStep::MinP { min_keep, .. }
+            | ShiftStep::XTC { min_keep, .. }
+            | ShiftStep::TypicalP { min_keep, .. } => Some(*min_keep),
+            _ => None,
+        }
+    }
+
+    /// DRY with the settings llama.cpp recommends: multiplier `0.8`, base `1.75`, allowed
+    /// length `2`, the whole context, and [`default_dry_seq_breakers`].
+    pub fn dry_default() -> Self {
+        ShiftStep::DRY {
+            multiplier: 0.8,
+            base: 1.75,
+            allowed_length: 2,
+            penalty_last_n: -1,
+            seq_breakers: default_dry_seq_breakers(),
+        }
+    }
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(tag = "type", content = "value", rename_all = "snake_case")]
 pub enum SampleStep {
@@ -563,6 +922,19 @@ pub(crate) fn read_sampler_from_metadata(model: &LlamaModel) -> Option<SamplerCo
     ))
 }
 
+/// Resolve the named token references in a GBNF `grammar` (`<think>`, `!<think>`) to IDs
+/// in `model`'s vocabulary, so a grammar written with token names works for any model that
+/// has those tokens, and a missing one is reported by name.
+fn resolve_token_refs(model: &LlamaModel, grammar: &str) -> Result<String, SamplerError> {
+    Ok(gbnf::token_refs::resolve_token_refs(
+        grammar,
+        |token| match model.str_to_token(token, llama_cpp_2::model::AddBos::Never) {
+            Ok(tokens) if tokens.len() == 1 => Some(tokens[0].0 as usize),
+            _ => None,
+        },
+    )?)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -580,6 +952,153 @@ mod tests {
         assert!(matches!(config.steps[1], ShiftStep::Temperature { .. }));
     }
 
+    #[test]
+    fn test_sampler_warnings() {
+        for preset in [
+            SamplerConfig::default(),
+            SamplerPresets::creative(),
+            SamplerPresets::precise(),
+            SamplerConfig::from_legacy(LegacySamplerParams::default()),
+        ] {
+            assert_eq!(preset.warnings(), vec![], "{preset:?}");
+        }
+
+        let config = SamplerBuilder::new()
+            .shift(ShiftStep::TopK { top_k: 5 })
+            .shift(ShiftStep::TopP {
+                top_p: 0.9,
+                min_keep: 10,
+            })
+            .shift(ShiftStep::dry_default())
+            .shift(ShiftStep::Temperature { temperature: 0.7 })
+            .shift(ShiftStep::Temperature { temperature: 0.7 })
+            .sample(SampleStep::Greedy);
+        assert_eq!(
+            config.warnings(),
+            vec![
+                SamplerWarning::NoEffectBeforeGreedy { step: "top_k" },
+                SamplerWarning::MinKeepAboveTopK {
+                    step: "top_p",
+                    min_keep: 10,
+                    top_k: 5
+                },
+                SamplerWarning::NoEffectBeforeGreedy { step: "top_p" },
+                SamplerWarning::PenaltyAfterTruncation {
+                    penalty: "dry",
+                    truncation: "top_k"
+                },
+                SamplerWarning::NoEffectBeforeGreedy {
+                    step: "temperature"
+                },
+                SamplerWarning::Duplicate {
+                    step: "temperature"
+                },
+                SamplerWarning::NoEffectBeforeGreedy {
+                    step: "temperature"
+                },
+            ]
+        );
+
+        let mirostat = SamplerBuilder::new()
+            .shift(ShiftStep::MinP {
+                min_p: 0.05,
+                min_keep: 1,
+            })
+            .sample(SampleStep::MirostatV2 { tau: 5.0, eta: 0.1 });
+        assert_eq!(
+            mirostat.warnings(),
+            vec![SamplerWarning::TruncationBeforeMirostat { step: "min_p" }]
+        );
+    }
+
+    #[test]
+    fn test_dry_default() {
+        let ShiftStep::DRY {
+            multiplier,
+            seq_breakers,
+            ..
+        } = ShiftStep::dry_default()
+        else {
+            panic!("dry_default should be a DRY step");
+        };
+        assert!(multiplier > 0.0, "DRY is disabled at multiplier 0");
+        assert!(seq_breakers.contains(&"\n".to_string()));
+    }
+
+    #[test]
+    fn test_creative_and_precise_presets() {
+        let creative = SamplerPresets::creative();
+        assert!(matches!(creative.sample_step, SampleStep::Dist));
+        assert!(creative
+            .steps
+            .iter()
+            .any(|s| matches!(s, ShiftStep::DRY { .. })));
+        assert!(matches!(
+            creative.steps.last(),
+            Some(ShiftStep::Temperature { temperature }) if *temperature >= 1.0
+        ));
+
+        let precise = SamplerPresets::precise();
+        assert!(matches!(
+            precise.steps.last(),
+            Some(ShiftStep::Temperature { temperature }) if *temperature < 0.5
+        ));
+    }
+
+    #[test]
+    fn test_grammar_token_refs_resolved_by_name() {
+        let model = crate::test_utils::load_test_model();
+        let grammar_config = |grammar: &str| {
+            SamplerBuilder::new()
+                .shift(ShiftStep::Grammar {
+                    trigger_on: None,
+                    root: "root".into(),
+                    grammar: grammar.into(),
+                })
+                .sample(SampleStep::Dist)
+        };
+        let config = grammar_config(r#"root ::= "ok" <|im_end|>"#);
+        assert!(config.to_stateful(&model.language_model).is_ok());
+
+        let config = grammar_config(r#"root ::= "ok" !<|no_such_token|>"#);
+        let Err(SamplerError::UnknownGrammarToken(error)) =
+            config.to_stateful(&model.language_model)
+        else {
+            panic!("expected an unknown token error");
+        };
+        assert_eq!(error.token, "<|no_such_token|>");
+    }
+
+    #[test]
+    fn test_from_legacy() {
+        let config = SamplerConfig::from_legacy(LegacySamplerParams::default());
+        assert_eq!(config.steps.len(), 1);
+        assert!(matches!(config.steps[0], ShiftStep::Temperature { .. }));
+        assert!(matches!(config.sample_step, SampleStep::Dist));
+
+        let config = SamplerConfig::from_legacy(LegacySamplerParams {
+            top_k: 40,
+            min_p: 0.05,
+            penalty_repeat: 1.1,
+            seed: 42,
+            ..Default::default()
+        });
+        assert!(matches!(config.steps[0], ShiftStep::Penalties { .. }));
+        assert!(matches!(config.steps[1], ShiftStep::TopK { top_k: 40 }));
+        assert!(matches!(config.steps[2], ShiftStep::MinP { .. }));
+        assert!(matches!(config.steps[3], ShiftStep::Temperature { .. }));
+        assert_eq!(config.seed, 42);
+
+        // mirostat does its own truncation
+        let config = SamplerConfig::from_legacy(LegacySamplerParams {
+            top_k: 40,
+            mirostat: 2,
+            ..Default::default()
+        });
+        assert_eq!(config.steps.len(), 1);
+        assert!(matches!(config.sample_step, SampleStep::MirostatV2 { .. }));
+    }
+
     #[test]
     fn test_serialize_deserialize_round_trip() {
         let config = SamplerConfig::default();
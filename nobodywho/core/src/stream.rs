@@ -1,5 +1,6 @@
 //! Generic token streaming types shared by the LLM and STT modules.
 
+use std::collections::VecDeque;
 use tokio::sync::mpsc::UnboundedReceiver;
 
 /// A single item on a token stream.
@@ -14,6 +15,21 @@ pub enum StreamOutput<E> {
 
 /// Blocking token stream. Call [`next_token`](Self::next_token) to drive
 /// token-by-token, or [`completed`](Self::completed) to collect the full text.
+///
+/// It is also an [`Iterator`] over the token pieces, for use in a plain `for` loop:
+///
+/// ```
+/// # use nobodywho::chat::ChatHandle;
+/// # fn example(chat: &ChatHandle) -> Result<(), nobodywho::errors::CompletionError> {
+/// for token in chat.ask("Tell me a story") {
+///     print!("{}", token?);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+///
+/// Everything here blocks the calling thread, so don't use it inside an async runtime;
+/// use [`TokenStreamAsync`] there instead.
 pub struct TokenStream<E> {
     pub(crate) rx: UnboundedReceiver<StreamOutput<E>>,
     pub(crate) done: Option<String>,
@@ -51,6 +67,15 @@ impl<E> TokenStream<E> {
     }
 }
 
+impl<E> Iterator for TokenStream<E> {
+    type Item = Result<String, E>;
+
+    /// Blocks until the next token piece arrives. Ends when generation is finished.
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_token().transpose()
+    }
+}
+
 /// Async token stream.
 pub struct TokenStreamAsync<E> {
     pub(crate) rx: UnboundedReceiver<StreamOutput<E>>,
@@ -85,4 +110,214 @@ impl<E> TokenStreamAsync<E> {
             }
         }
     }
+
+    /// Parse the stream as a JSON object, yielding each top-level field as soon as its value
+    /// is complete. Meant for output constrained to a JSON schema, see [`JsonFieldStreamAsync`].
+    pub fn json_fields(self) -> JsonFieldStreamAsync<E> {
+        JsonFieldStreamAsync {
+            tokens: self,
+            parser: JsonFieldParser::default(),
+            pending: VecDeque::new(),
+        }
+    }
+}
+
+/// Async stream of the top-level `(key, value)` pairs of a JSON object being generated.
+/// Created with [`TokenStreamAsync::json_fields`].
+///
+/// ```
+/// # use nobodywho::chat::ChatHandleAsync;
+/// # async fn example(chat: &ChatHandleAsync, grammar: nobodywho::gbnf::GbnfGrammar) -> Result<(), nobodywho::errors::CompletionError> {
+/// let mut fields = chat.ask_with_grammar("Describe a fantasy sword", grammar).json_fields();
+/// while let Some((key, value)) = fields.next_field().await? {
+///     println!("{key} = {value}");
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub struct JsonFieldStreamAsync<E> {
+    tokens: TokenStreamAsync<E>,
+    parser: JsonFieldParser,
+    pending: VecDeque<(String, serde_json::Value)>,
+}
+
+impl<E> JsonFieldStreamAsync<E> {
+    /// Return the next completed field, or `None` when generation is finished.
+    pub async fn next_field(&mut self) -> Result<Option<(String, serde_json::Value)>, E> {
+        loop {
+            if let Some(field) = self.pending.pop_front() {
+                return Ok(Some(field));
+            }
+            match self.tokens.next_token().await? {
+                Some(token) => self.pending.extend(self.parser.push(&token)),
+                None => return Ok(None),
+            }
+        }
+    }
+
+    /// Drain the remaining output and return the full generated text.
+    pub async fn completed(&mut self) -> Result<String, E> {
+        self.tokens.completed().await
+    }
+}
+
+/// Incremental parser for a JSON object that arrives in pieces.
+///
+/// Partial JSON can't be handed to a regular parser, so this one only tracks enough state
+/// (nesting depth and strings) to know where each top-level value ends, and parses that
+/// value on its own once it does. Anything before the opening `{` is skipped, as is
+/// anything after the closing `}`. Values that fail to parse are dropped.
+#[derive(Debug, Default)]
+pub struct JsonFieldParser {
+    state: FieldParserState,
+    buf: String,
+    key: String,
+    depth: usize,
+    in_string: bool,
+    escaped: bool,
+}
+
+#[derive(Debug, Default, PartialEq)]
+enum FieldParserState {
+    #[default]
+    Start,
+    BeforeKey,
+    InKey,
+    BeforeValue,
+    InValue,
+    Done,
+}
+
+impl JsonFieldParser {
+    /// Feed the next piece of text. Returns the fields that it completed, in order.
+    pub fn push(&mut self, text: &str) -> Vec<(String, serde_json::Value)> {
+        let mut fields = vec![];
+        for c in text.chars() {
+            match self.state {
+                FieldParserState::Start => {
+                    if c == '{' {
+                        self.state = FieldParserState::BeforeKey;
+                    }
+                }
+                FieldParserState::BeforeKey => match c {
+                    '"' => {
+                        self.buf = String::from('"');
+                        self.escaped = false;
+                        self.state = FieldParserState::InKey;
+                    }
+                    '}' => self.state = FieldParserState::Done,
+                    _ => (),
+                },
+                FieldParserState::InKey => {
+                    self.buf.push(c);
+                    if self.ends_string(c) {
+                        self.key = serde_json::from_str(&self.buf).unwrap_or_default();
+                        self.state = FieldParserState::BeforeValue;
+                    }
+                }
+                FieldParserState::BeforeValue => {
+                    if c == ':' {
+                        self.buf.clear();
+                        self.depth = 0;
+                        self.in_string = false;
+                        self.state = FieldParserState::InValue;
+                    }
+                }
+                FieldParserState::InValue => {
+                    if self.in_string {
+                        self.buf.push(c);
+                        self.in_string = !self.ends_string(c);
+                        continue;
+                    }
+                    match c {
+                        ',' | '}' if self.depth == 0 => {
+                            if let Ok(value) = serde_json::from_str(self.buf.trim()) {
+                                fields.push((std::mem::take(&mut self.key), value));
+                            }
+                            self.state = if c == ',' {
+                                FieldParserState::BeforeKey
+                            } else {
+                                FieldParserState::Done
+                            };
+                        }
+                        '{' | '[' => {
+                            self.depth += 1;
+                            self.buf.push(c);
+                        }
+                        '}' | ']' => {
+                            self.depth = self.depth.saturating_sub(1);
+                            self.buf.push(c);
+                        }
+                        '"' => {
+                            self.in_string = true;
+                            self.escaped = false;
+                            self.buf.push(c);
+                        }
+                        _ => self.buf.push(c),
+                    }
+                }
+                FieldParserState::Done => break,
+            }
+        }
+        fields
+    }
+
+    // whether `c`, already pushed to `buf`, closes the current string
+    fn ends_string(&mut self, c: char) -> bool {
+        if self.escaped {
+            self.escaped = false;
+            false
+        } else if c == '\\' {
+            self.escaped = true;
+            false
+        } else {
+            c == '"'
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn parse_in_pieces(text: &str, piece_len: usize) -> Vec<(String, serde_json::Value)> {
+        let chars: Vec<char> = text.chars().collect();
+        let mut parser = JsonFieldParser::default();
+        chars
+            .chunks(piece_len)
+            .flat_map(|piece| parser.push(&piece.iter().collect::<String>()))
+            .collect()
+    }
+
+    #[test]
+    fn test_json_fields_complete_as_they_arrive() {
+        let mut parser = JsonFieldParser::default();
+        assert_eq!(parser.push(r#"{"name": "Ex"#), vec![]);
+        assert_eq!(
+            parser.push(r#"calibur", "damage": 4"#),
+            vec![("name".to_string(), json!("Excalibur"))]
+        );
+        assert_eq!(parser.push("2}"), vec![("damage".to_string(), json!(42))]);
+        assert_eq!(parser.push(r#", "ignored": 1}"#), vec![]);
+    }
+
+    #[test]
+    fn test_json_fields_nested_values_and_escapes() {
+        let text = r#"Sure! {"a\"b": "x, } \" y", "tags": ["1", "]"], "stats": {"hp": {"max": 10}}, "ok": true}"#;
+        let expected = vec![
+            ("a\"b".to_string(), json!("x, } \" y")),
+            ("tags".to_string(), json!(["1", "]"])),
+            ("stats".to_string(), json!({"hp": {"max": 10}})),
+            ("ok".to_string(), json!(true)),
+        ];
+        for piece_len in [1, 2, 3, 7, text.len()] {
+            assert_eq!(parse_in_pieces(text, piece_len), expected);
+        }
+    }
+
+    #[test]
+    fn test_json_fields_empty_object() {
+        assert_eq!(parse_in_pieces("{ }", 1), vec![]);
+    }
 }
@@ -67,6 +67,13 @@ impl RenderedChat {
     }
 }
 
+/// Generic ChatML template, used for models that don't ship a chat template of their own.
+const CHATML_TEMPLATE: &str = "{%- for message in messages %}\
+{{- '<|im_start|>' + message['role'] + '\\n' + message['content'] + '<|im_end|>\\n' }}\
+{%- endfor %}\
+{%- if add_generation_prompt %}{{- '<|im_start|>assistant\\n' }}{%- endif %}";
+
+#[derive(Clone)]
 pub struct ChatTemplate {
     template: String,
     bos_token: String,
@@ -88,6 +95,16 @@ impl ChatTemplate {
         })
     }
 
+    /// A generic ChatML template. This is the fallback for models without an embedded chat
+    /// template, e.g. older LLaMA2-based GGUFs.
+    pub fn chatml() -> Self {
+        Self {
+            template: CHATML_TEMPLATE.to_string(),
+            bos_token: String::new(),
+            eos_token: String::new(),
+        }
+    }
+
     fn get_template(&self) -> Result<Template<'_, '_>, minijinja::Error> {
         MINIJINJA_ENV.template_from_str(&self.template)
     }
@@ -234,7 +251,14 @@ pub fn select_template(
     model: &llama_cpp_2::model::LlamaModel,
     with_tools: bool,
 ) -> Result<ChatTemplate, SelectTemplateError> {
-    let default_template = model.chat_template(None)?.to_string()?;
+    let default_template = match model.chat_template(None) {
+        Ok(template) => template.to_string()?,
+        Err(e) => {
+            // base models, and a lot of older GGUFs, have no chat template at all
+            warn!(error = %e, "Model has no usable chat template, falling back to ChatML");
+            CHATML_TEMPLATE.to_string()
+        }
+    };
     let tool_template = model.chat_template(Some("tool_use"));
     let bos = model.token_to_piece(
         model.token_bos(),
@@ -490,6 +514,23 @@ mod tests {
         assert_eq!(rendered5, expected5);
     }
 
+    #[test]
+    fn test_render_chatml_fallback_template() {
+        let ctx = ChatTemplateContext::new(HashMap::new(), None);
+        let messages = vec![
+            Message::new_system("You are a pirate.".into()),
+            Message::new_user("Hello!".into()),
+        ];
+
+        let rendered = ChatTemplate::chatml().render(&messages, &ctx).unwrap();
+        assert_eq!(
+            rendered,
+            "<|im_start|>system\nYou are a pirate.<|im_end|>\n\
+             <|im_start|>user\nHello!<|im_end|>\n\
+             <|im_start|>assistant\n"
+        );
+    }
+
     #[test]
     fn test_rewrite_generation_tags() {
         // Tags become no-op if/endif; whitespace-control markers preserved.
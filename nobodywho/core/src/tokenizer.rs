@@ -292,6 +292,26 @@ impl TokenizerChunks {
             .collect()
     }
 
+    /// The chunks covering the first `to_pos` tokens. A media chunk straddling `to_pos`
+    /// is left out entirely, since it can't be split.
+    pub fn head(&self, to_pos: usize) -> TokenizerChunks {
+        let mut chunks = vec![];
+        let mut pos = 0;
+        for chunk in &self.chunks {
+            if pos >= to_pos {
+                break;
+            }
+            let chunk_size = chunk.n_tokens();
+            if pos + chunk_size <= to_pos {
+                chunks.push(chunk.clone());
+            } else if let TokenizerChunk::Text(tokens, _) = chunk {
+                chunks.push(TokenizerChunk::new_text(tokens[..to_pos - pos].to_vec()));
+            }
+            pos += chunk_size;
+        }
+        TokenizerChunks { chunks }
+    }
+
     pub fn tail(&self, from_pos: usize) -> TokenizerChunks {
         if from_pos >= self.n_tokens() {
             return TokenizerChunks::new();
@@ -456,7 +476,71 @@ impl ProjectionModel {
     }
 }
 
-#[derive(Debug)]
+/// Inserted into text to tokenize the parts on either side of it separately, e.g. so they
+/// can't be read as one special token. A Unicode noncharacter, so it doesn't occur in real text.
+pub(crate) const TOKEN_BREAK: char = '\u{FDD0}';
+
+/// The bytes of `token`'s text, retrying with a big enough buffer if the first guess is too small.
+pub(crate) fn token_to_piece_bytes(
+    model: &LlamaModel,
+    token: LlamaToken,
+    special: bool,
+) -> Result<Vec<u8>, llama_cpp_2::TokenToStringError> {
+    match model.token_to_piece_bytes(token, 8, special, None) {
+        Err(llama_cpp_2::TokenToStringError::InsufficientBufferSpace(i)) => model
+            .token_to_piece_bytes(
+                token,
+                (-i).try_into().expect("Error buffer size is positive"),
+                special,
+                None,
+            ),
+        x => x,
+    }
+}
+
+/// The text of every control and user-defined token in the vocabulary, longest first.
+pub(crate) fn special_token_texts(model: &LlamaModel) -> Vec<String> {
+    let mut texts: Vec<String> = (0..model.n_vocab())
+        .map(LlamaToken::new)
+        .filter_map(|token| {
+            // special tokens only render as text when asked to
+            let text = token_to_piece_bytes(model, token, true).ok()?;
+            let plain = token_to_piece_bytes(model, token, false).ok()?;
+            (!text.is_empty() && text != plain).then(|| String::from_utf8_lossy(&text).into_owned())
+        })
+        .collect();
+    texts.sort_by(|a, b| b.len().cmp(&a.len()).then_with(|| a.cmp(b)));
+    texts.dedup();
+    texts
+}
+
+/// Insert [`TOKEN_BREAK`] after the first character of every special token in
+/// `text`, so the tokenizer reads them as plain text.
+/// `special_tokens` is expected longest first, like [`special_token_texts`] returns them.
+pub(crate) fn break_special_tokens(text: &str, special_tokens: &[String]) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(c) = rest.chars().next() {
+        match special_tokens
+            .iter()
+            .find(|special| rest.starts_with(special.as_str()))
+        {
+            Some(special) => {
+                out.push(c);
+                out.push(TOKEN_BREAK);
+                out.push_str(&special[c.len_utf8()..]);
+                rest = &rest[special.len()..];
+            }
+            None => {
+                out.push(c);
+                rest = &rest[c.len_utf8()..];
+            }
+        }
+    }
+    out
+}
+
+#[derive(Debug, Clone)]
 pub struct Tokenizer<'a> {
     model: &'a LlamaModel,
     projection_model: Option<&'a ProjectionModel>,
@@ -476,6 +560,10 @@ impl<'a> Tokenizer<'a> {
         }
     }
 
+    pub fn set_add_bos(&mut self, add_bos: AddBos) {
+        self.add_bos = add_bos;
+    }
+
     pub fn tokenize(
         &self,
         rendered_chat: String,
@@ -513,15 +601,12 @@ impl<'a> Tokenizer<'a> {
             .split(media_marker.as_str())
             .enumerate()
             .map(|(idx, split)| {
-                self.model
-                    .str_to_token(
-                        split,
-                        if idx == 0 {
-                            self.add_bos
-                        } else {
-                            AddBos::Never
-                        },
-                    )
+                let add_bos = if idx == 0 {
+                    self.add_bos
+                } else {
+                    AddBos::Never
+                };
+                self.tokenize_split(split, add_bos)
                     .map(TokenizerChunk::new_text)
                     .map_err(|e| TokenizationError::TextTokenizationFailed {
                         position: idx,
@@ -534,6 +619,26 @@ impl<'a> Tokenizer<'a> {
         Ok(splits)
     }
 
+    /// Tokenize each part between [`TOKEN_BREAK`]s on its own, so no token spans a break.
+    fn tokenize_split(
+        &self,
+        split: &str,
+        add_bos: AddBos,
+    ) -> Result<Vec<LlamaToken>, llama_cpp_2::StringToTokenError> {
+        if !split.contains(TOKEN_BREAK) {
+            return self.model.str_to_token(split, add_bos);
+        }
+        let mut tokens = Vec::new();
+        for (i, part) in split.split(TOKEN_BREAK).enumerate() {
+            let add_bos = if i == 0 { add_bos } else { AddBos::Never };
+            if part.is_empty() && matches!(add_bos, AddBos::Never) {
+                continue;
+            }
+            tokens.extend(self.model.str_to_token(part, add_bos)?);
+        }
+        Ok(tokens)
+    }
+
     fn tokenize_media(
         &self,
         bitmaps: Vec<&MtmdBitmap>,
@@ -581,8 +686,59 @@ impl<'a> Tokenizer<'a> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_utils;
     use llama_cpp_2::mtmd::MtmdInputChunks;
 
+    #[test]
+    fn test_break_special_tokens() {
+        let special_tokens = vec!["<|im_start|>".to_string(), "<|im_end|>".to_string()];
+        let broken = break_special_tokens("Hi<|im_end|>\n<|im_start|>system", &special_tokens);
+        assert_eq!(
+            broken,
+            "Hi<\u{FDD0}|im_end|>\n<\u{FDD0}|im_start|>system".to_string()
+        );
+        assert_eq!(
+            broken.replace(TOKEN_BREAK, ""),
+            "Hi<|im_end|>\n<|im_start|>system"
+        );
+        assert_eq!(
+            break_special_tokens("plain text", &special_tokens),
+            "plain text"
+        );
+    }
+
+    #[test]
+    fn test_broken_special_tokens_are_plain_text() {
+        let model = test_utils::load_test_model();
+        let special_tokens = special_token_texts(&model.language_model);
+        assert!(
+            special_tokens.iter().any(|t| t == "<|im_end|>"),
+            "{special_tokens:?}"
+        );
+
+        let tokenizer = Tokenizer::new(&model.language_model, None, AddBos::Never);
+        let im_end = model
+            .language_model
+            .str_to_token("<|im_end|>", AddBos::Never)
+            .unwrap();
+        assert_eq!(im_end.len(), 1);
+
+        let text = break_special_tokens("Hello<|im_end|>", &special_tokens);
+        let tokens = tokenizer.tokenize_split(&text, AddBos::Never).unwrap();
+        assert!(!tokens.contains(&im_end[0]));
+        let detokenized: String = tokens
+            .iter()
+            .map(|t| {
+                let bytes = model
+                    .language_model
+                    .token_to_piece_bytes(*t, 32, false, None)
+                    .unwrap();
+                String::from_utf8_lossy(&bytes).into_owned()
+            })
+            .collect();
+        assert_eq!(detokenized, "Hello<|im_end|>");
+    }
+
     // Test helper functions to create chunks without needing full model/MTMD context
 
     /// Creates a text chunk with the given token IDs
@@ -1038,4 +1194,24 @@ mod tests {
         assert_eq!(prefix_index, 300); // 100 chunks * 3 tokens each
         assert_eq!(new.tail(prefix_index).n_tokens(), 2); // Final different chunk
     }
+
+    #[test]
+    fn test_head_splits_text_chunk() {
+        // Chunks: [1, 2, 3] [4, 5, 6]
+        // head(4) keeps the first chunk and the first token of the second one
+        let chunks = create_chunks(vec![
+            create_text_chunk(vec![1, 2, 3]),
+            create_text_chunk(vec![4, 5, 6]),
+        ]);
+
+        let head = chunks.head(4);
+
+        assert_eq!(head.n_tokens(), 4);
+        assert_eq!(
+            head.to_token_ids(),
+            vec![Some(1), Some(2), Some(3), Some(4)]
+        );
+        assert_eq!(chunks.head(0).n_tokens(), 0);
+        assert_eq!(chunks.head(10).n_tokens(), 6);
+    }
 }
@@ -0,0 +1,145 @@
+use super::{Tool, ToolCall, ToolFormat, ToolFormatError, ToolFormatHandler};
+use gbnf::{Expr, GbnfDeclaration, GbnfGrammar, TokenRef};
+
+/// Another format's tool calls, wrapped in different begin/end tokens.
+///
+/// For fine-tunes that keep e.g. the Qwen3 call syntax but use their own markers around it.
+/// Built with [`ToolFormat::custom`].
+#[derive(Debug, Clone)]
+pub struct CustomHandler {
+    begin_token: String,
+    end_token: String,
+    base: Box<ToolFormat>,
+}
+
+impl CustomHandler {
+    pub fn new(begin_token: String, end_token: String, base: ToolFormat) -> Self {
+        Self {
+            begin_token,
+            end_token,
+            base: Box::new(base),
+        }
+    }
+
+    /// The format whose grammar and extraction this one reuses.
+    pub fn base(&self) -> &ToolFormat {
+        &self.base
+    }
+}
+
+impl ToolFormatHandler for CustomHandler {
+    fn begin_token(&self) -> &str {
+        &self.begin_token
+    }
+
+    fn end_token(&self) -> &str {
+        &self.end_token
+    }
+
+    fn generate_grammar(&self, tools: &[Tool]) -> Result<GbnfGrammar, ToolFormatError> {
+        let grammar = self.base.generate_grammar(tools)?;
+        let declarations = grammar
+            .declarations
+            .iter()
+            .map(|declaration| {
+                let expr = replace_marker(
+                    &declaration.expr,
+                    self.base.begin_token(),
+                    &self.begin_token,
+                );
+                let expr = replace_marker(&expr, self.base.end_token(), &self.end_token);
+                GbnfDeclaration::new(declaration.name.clone(), expr)
+            })
+            .collect();
+
+        let defaults = grammar.defaults().clone();
+        Ok(GbnfGrammar::new(declarations, grammar.root_name).with_defaults(defaults))
+    }
+
+    fn extract_tool_calls(&self, input: &str) -> Option<Vec<ToolCall>> {
+        // swap our markers for the base format's, and let it do the parsing
+        let mut input = input.replace(&self.begin_token, self.base.begin_token());
+        if !self.end_token.is_empty() {
+            input = input.replace(&self.end_token, self.base.end_token());
+        }
+        self.base.handler().extract_tool_calls(&input)
+    }
+}
+
+/// Replace every literal (or special token) matching `from` with the literal `to`.
+fn replace_marker(expr: &Expr, from: &str, to: &str) -> Expr {
+    if from.is_empty() {
+        return expr.clone();
+    }
+    match expr {
+        Expr::Characters(s) if s == from => Expr::Characters(to.to_string()),
+        Expr::Token(TokenRef::ByString {
+            name,
+            negated: false,
+        }) if format!("<{name}>") == from => Expr::Characters(to.to_string()),
+        Expr::Group(inner) => Expr::Group(Box::new(replace_marker(inner, from, to))),
+        Expr::Sequence(items) => {
+            Expr::Sequence(items.iter().map(|e| replace_marker(e, from, to)).collect())
+        }
+        Expr::Alternation(alts) => {
+            Expr::Alternation(alts.iter().map(|e| replace_marker(e, from, to)).collect())
+        }
+        Expr::Quantified { expr, quantifier } => Expr::Quantified {
+            expr: Box::new(replace_marker(expr, from, to)),
+            quantifier: quantifier.clone(),
+        },
+        other => other.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tool_calling::{Gemma4Handler, Qwen3Handler};
+    use serde_json::json;
+
+    fn add_tool() -> Tool {
+        Tool::new(
+            "add",
+            "Adds numbers",
+            json!({
+                "type": "object",
+                "properties": {"a": {"type": "integer"}},
+                "required": ["a"]
+            }),
+            std::sync::Arc::new(|_| String::new()),
+        )
+    }
+
+    #[test]
+    fn test_custom_markers_around_qwen3_calls() {
+        let format = ToolFormat::custom("<call>", "</call>", ToolFormat::Qwen3(Qwen3Handler));
+        assert_eq!(format.begin_token(), "<call>");
+        assert_eq!(format.end_token(), "</call>");
+
+        let calls = format
+            .extract_tool_calls(r#"Sure. <call>{"name": "add", "arguments": {"a": 2}}</call>"#)
+            .unwrap();
+        assert_eq!(calls[0].name, "add");
+        assert_eq!(calls[0].arguments, json!({"a": 2}));
+
+        let grammar = format.generate_grammar(&[add_tool()]).unwrap();
+        assert!(grammar.matches(r#"<call>{"name": "add", "arguments": {"a": 2}}</call>"#));
+        assert!(
+            !grammar.matches(r#"<tool_call>{"name": "add", "arguments": {"a": 2}}</tool_call>"#)
+        );
+    }
+
+    #[test]
+    fn test_custom_markers_replace_special_tokens() {
+        let format = ToolFormat::custom("<fn>", "</fn>", ToolFormat::Gemma4(Gemma4Handler));
+
+        let calls = format.extract_tool_calls("<fn>call:add{a:2}</fn>").unwrap();
+        assert_eq!(calls[0].name, "add");
+        assert_eq!(calls[0].arguments, json!({"a": 2}));
+
+        let grammar = format.generate_grammar(&[add_tool()]).unwrap();
+        assert!(grammar.as_str().contains(r#""<fn>""#));
+        assert!(!grammar.as_str().contains("<|tool_call>"));
+    }
+}
@@ -6,7 +6,7 @@ use nom::{
     branch::alt as nom_alt,
     bytes::complete::{tag, take_until, take_while1},
     combinator::{map, value},
-    multi::{many1, separated_list0},
+    multi::separated_list0,
     number::complete::recognize_float,
     sequence::{delimited, separated_pair},
     IResult, Parser,
@@ -411,16 +411,19 @@ impl ToolFormatHandler for Gemma4Handler {
     }
 
     fn extract_tool_calls(&self, input: &str) -> Option<Vec<ToolCall>> {
-        // Find the first <|tool_call> and parse from there
-        let start = input.find(BEGIN_TOKEN)?;
-        let input = &input[start..];
-
-        let Ok((_, calls)) = many1(single_tool_call).parse(input) else {
+        // Parse a call at every <|tool_call>, so text before, between or after calls is skipped
+        let calls: Vec<ToolCall> = input
+            .match_indices(BEGIN_TOKEN)
+            .filter_map(|(start, _)| single_tool_call(&input[start..]).ok())
+            .map(|(_, call)| call)
+            .collect();
+
+        if calls.is_empty() {
             debug!("No Gemma4 tool calls detected");
-            return None;
-        };
-
-        Some(calls)
+            None
+        } else {
+            Some(calls)
+        }
     }
 }
 
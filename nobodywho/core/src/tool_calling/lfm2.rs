@@ -68,23 +68,27 @@ impl ToolFormatHandler for Lfm2Handler {
     }
 
     fn extract_tool_calls(&self, input: &str) -> Option<Vec<ToolCall>> {
-        // Locate the call block. The end token may be absent if the model was
-        // cut off, in which case we parse to the end of the input.
-        let start = input.find(BEGIN_TOKEN)?;
-        let after = &input[start + BEGIN_TOKEN.len()..];
-        let body = match after.find(END_TOKEN) {
-            Some(end) => &after[..end],
-            None => after,
-        };
-
-        // Strip the surrounding `[ ... ]`, tolerating their absence.
-        let body = body.trim();
-        let body = body.strip_prefix('[').unwrap_or(body);
-        let body = body.strip_suffix(']').unwrap_or(body);
-
-        let calls: Vec<ToolCall> = split_top_level(body, ',')
-            .into_iter()
-            .filter_map(|call| parse_one_call(call.trim()))
+        // Every call block starts with the begin token, text around the blocks is skipped.
+        // The end token may be absent if the model was cut off, in which case the block
+        // runs to the next begin token or the end of the input.
+        let calls: Vec<ToolCall> = input
+            .split(BEGIN_TOKEN)
+            .skip(1)
+            .flat_map(|after| {
+                let body = match after.find(END_TOKEN) {
+                    Some(end) => &after[..end],
+                    None => after,
+                };
+
+                // Strip the surrounding `[ ... ]`, tolerating their absence.
+                let body = body.trim();
+                let body = body.strip_prefix('[').unwrap_or(body);
+                let body = body.strip_suffix(']').unwrap_or(body);
+
+                split_top_level(body, ',')
+                    .into_iter()
+                    .filter_map(|call| parse_one_call(call.trim()))
+            })
             .collect();
 
         (!calls.is_empty()).then_some(calls)
@@ -410,7 +414,7 @@ mod tests {
             name: "get_weather".to_string(),
             description: "Get weather".to_string(),
             json_schema: schema,
-            function: std::sync::Arc::new(|_| String::new()),
+            function: std::sync::Arc::new(|_| Ok(String::new())),
         };
 
         let g = h
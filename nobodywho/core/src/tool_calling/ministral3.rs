@@ -3,10 +3,8 @@ use gbnf::builder::{alt, nt, nt_plus, GrammarBuilder};
 use gbnf::json::json_schema_to_grammar;
 use gbnf_macro::gbnf;
 use nom::{
-    bytes::complete::{tag, take_till, take_until},
-    combinator::rest,
-    multi::many1,
-    sequence::{preceded, separated_pair},
+    bytes::complete::{tag, take_till},
+    sequence::terminated,
     IResult, Parser,
 };
 use tracing::debug;
@@ -14,17 +12,9 @@ use tracing::debug;
 #[derive(Debug, Clone, Copy)]
 pub struct Ministral3Handler;
 
-/// Parse a single tool call: [TOOL_CALLS]name[ARGS]json_arguments
-fn tool_call(input: &str) -> IResult<&str, (&str, &str)> {
-    preceded(
-        tag("[TOOL_CALLS]"),
-        separated_pair(
-            take_till(|c| c == '['),
-            tag("[ARGS]"),
-            nom::branch::alt((take_until("[TOOL_CALLS]"), rest)),
-        ),
-    )
-    .parse(input)
+/// Parse the name of a tool call that follows a [TOOL_CALLS] token: name[ARGS]
+fn tool_call_name(input: &str) -> IResult<&str, &str> {
+    terminated(take_till(|c| c == '['), tag("[ARGS]")).parse(input)
 }
 
 impl ToolFormatHandler for Ministral3Handler {
@@ -68,31 +58,34 @@ impl ToolFormatHandler for Ministral3Handler {
     }
 
     fn extract_tool_calls(&self, input: &str) -> Option<Vec<ToolCall>> {
-        let Ok((_, parsed)) = many1(tool_call).parse(input) else {
-            debug!("No Ministral3 tool calls detected");
-            return None;
-        };
-
-        let calls: Vec<ToolCall> = parsed
-            .into_iter()
-            .filter_map(
-                |(name, args_str)| match serde_json::from_str(args_str.trim()) {
-                    Ok(arguments) => {
+        // every call starts with the begin token, text before the first one is skipped
+        let calls: Vec<ToolCall> = input
+            .split(self.begin_token())
+            .skip(1)
+            .filter_map(|part| {
+                let (args_str, name) = tool_call_name(part).ok()?;
+                // the arguments end where the JSON does, anything after them is ignored
+                let mut arguments = serde_json::Deserializer::from_str(args_str.trim_start())
+                    .into_iter::<serde_json::Value>();
+                match arguments.next() {
+                    Some(Ok(arguments)) => {
                         debug!(tool_name = %name.trim(), "Parsed tool call");
                         Some(ToolCall {
                             name: name.trim().to_string(),
                             arguments,
                         })
                     }
-                    Err(e) => {
+                    Some(Err(e)) => {
                         debug!(error = %e, "Failed to parse tool call arguments");
                         None
                     }
-                },
-            )
+                    None => None,
+                }
+            })
             .collect();
 
         if calls.is_empty() {
+            debug!("No Ministral3 tool calls detected");
             None
         } else {
             Some(calls)
@@ -0,0 +1,167 @@
+use super::{Tool, ToolCall, ToolFormatError, ToolFormatHandler};
+use gbnf::builder::{nt, nt_star, seq, t, GrammarBuilder};
+use gbnf::json::json_schema_to_grammar;
+use gbnf::GbnfGrammar;
+use serde_json::json;
+use tracing::debug;
+
+/// Tool calls as emitted by Mistral-Nemo, Mistral-7B-v0.3 and Ministral-8B: a single
+/// `[TOOL_CALLS]` token followed by a JSON array of calls.
+#[derive(Debug, Clone, Copy)]
+pub struct MistralHandler;
+
+impl ToolFormatHandler for MistralHandler {
+    fn begin_token(&self) -> &str {
+        "[TOOL_CALLS]"
+    }
+
+    fn end_token(&self) -> &str {
+        ""
+    }
+
+    fn generate_grammar(&self, tools: &[Tool]) -> Result<GbnfGrammar, ToolFormatError> {
+        let tool_call_schemas: serde_json::Value = tools
+            .iter()
+            .map(|tool| {
+                json!(
+                    {
+                        "type": "object",
+                        "properties": {
+                            "name": { "const": tool.name, },
+                            "arguments": tool.json_schema
+                        },
+                        "required": ["name", "arguments"]
+                    }
+                )
+            })
+            .collect();
+
+        let tool_call_schema = json!(
+            { "oneOf": tool_call_schemas }
+        );
+
+        // Generate JSON grammar for a single call, then wrap it in a non-empty array
+        let json_grammar = json_schema_to_grammar(tool_call_schema, "root")?;
+
+        let grammar = GrammarBuilder::from_existing(json_grammar)
+            .rule("nextcall", seq(&[nt("ws"), t(","), nt("ws"), nt("root")]))
+            .rule(
+                "toolcalls",
+                seq(&[
+                    t(self.begin_token()),
+                    t("["),
+                    nt("ws"),
+                    nt("root"),
+                    nt_star("nextcall"),
+                    nt("ws"),
+                    t("]"),
+                ]),
+            )
+            .root("toolcalls")
+            .build();
+
+        Ok(grammar)
+    }
+
+    fn extract_tool_calls(&self, input: &str) -> Option<Vec<ToolCall>> {
+        let tool_calls: Vec<ToolCall> = input
+            .split(self.begin_token())
+            .skip(1)
+            .filter_map(|part| {
+                // the array ends where the JSON does, anything after it is ignored
+                let mut calls = serde_json::Deserializer::from_str(part.trim_start())
+                    .into_iter::<Vec<ToolCall>>();
+                match calls.next() {
+                    Some(Ok(calls)) => {
+                        debug!(n_calls = calls.len(), "Parsed tool call array");
+                        Some(calls)
+                    }
+                    Some(Err(e)) => {
+                        debug!(error = %e, json = part, "Failed to parse tool call array");
+                        None
+                    }
+                    None => None,
+                }
+            })
+            .flatten()
+            .collect();
+
+        if tool_calls.is_empty() {
+            debug!("No Mistral tool calls detected");
+            None
+        } else {
+            Some(tool_calls)
+        }
+    }
+}
+
+// Tool call format looks like this:
+//
+// [TOOL_CALLS][{"name": "get_weather", "arguments": {"location": "Paris"}}]
+//
+// Jinja template (Mistral-Nemo-Instruct-2407):
+//         {{- "[TOOL_CALLS][" }}
+//         {%- for tool_call in message.tool_calls %}
+//             {%- set arguments = tool_call.function.arguments %}
+//             {{- '{"name": "' + tool_call.function.name + '", "arguments": ' + arguments|tojson + ', "id": "' + tool_call.id + '"}' }}
+//             ...
+//         {{- "]" + eos_token }}
+//
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_single_tool_call() {
+        let handler = MistralHandler;
+        let input = r#"[TOOL_CALLS][{"name": "get_weather", "arguments": {"location": "Paris"}}]"#;
+
+        let tool_calls = handler.extract_tool_calls(input).unwrap();
+        assert_eq!(tool_calls.len(), 1);
+        assert_eq!(tool_calls[0].name, "get_weather");
+        assert_eq!(tool_calls[0].arguments, json!({"location": "Paris"}));
+    }
+
+    #[test]
+    fn test_multiple_tool_calls() {
+        let handler = MistralHandler;
+        let input = r#"[TOOL_CALLS] [{"name": "tool1", "arguments": {"a": 1}}, {"name": "tool2", "arguments": {"b": [2, 3]}, "id": "abc123xyz"}]</s>"#;
+
+        let tool_calls = handler.extract_tool_calls(input).unwrap();
+        assert_eq!(tool_calls.len(), 2);
+        assert_eq!(tool_calls[0].name, "tool1");
+        assert_eq!(tool_calls[0].arguments, json!({"a": 1}));
+        assert_eq!(tool_calls[1].name, "tool2");
+        assert_eq!(tool_calls[1].arguments, json!({"b": [2, 3]}));
+    }
+
+    #[test]
+    fn test_no_tool_calls() {
+        let handler = MistralHandler;
+        assert!(handler
+            .extract_tool_calls("This is just regular text without any tool calls.")
+            .is_none());
+        assert!(handler.extract_tool_calls("[TOOL_CALLS]not json").is_none());
+    }
+
+    #[test]
+    fn test_grammar_matches_tool_call() {
+        let tool = Tool::new(
+            "get_weather",
+            "Gets the weather",
+            json!({
+                "type": "object",
+                "properties": {"location": {"type": "string"}},
+                "required": ["location"]
+            }),
+            std::sync::Arc::new(|_| String::new()),
+        );
+        let grammar = MistralHandler.generate_grammar(&[tool]).unwrap();
+        assert!(grammar.matches(
+            r#"[TOOL_CALLS][{"name": "get_weather", "arguments": {"location": "Paris"}}]"#
+        ));
+        assert!(!grammar.matches("[TOOL_CALLS][]"));
+    }
+}
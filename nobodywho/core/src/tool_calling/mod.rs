@@ -6,13 +6,18 @@
 //! - Qwen3.5/3.6: `<tool_call><function=name><parameter=k>v</parameter>...</function></tool_call>`
 //! - FunctionGemma: `<start_function_call>call:name{param:<escape>val<escape>}<end_function_call>`
 //! - Gemma4: `<|tool_call>call:name{key:<|"|>val<|"|>}<tool_call|>`
-//! - Ministral3: `[TOOL_CALLS][{"name": "...", "arguments": {...}}]`
+//! - Ministral3: `[TOOL_CALLS]name[ARGS]{...}`
+//! - Mistral (Nemo, 7B v0.3): `[TOOL_CALLS][{"name": "...", "arguments": {...}}]`
 //! - LFM2: `<|tool_call_start|>[name(key=value, ...)]<|tool_call_end|>`
+//!
+//! Any of these can be used with different begin/end tokens, see [`ToolFormat::custom`].
 
+mod custom;
 mod functiongemma;
 mod gemma4;
 mod lfm2;
 mod ministral3;
+mod mistral;
 mod qwen3;
 mod qwen35_36;
 
@@ -23,24 +28,54 @@ use serde::{ser::Serializer, Deserialize, Serialize};
 use std::{sync::Arc, time::Duration};
 use tracing::debug;
 
+pub use custom::CustomHandler;
 pub use functiongemma::FunctionGemmaHandler;
 pub use gemma4::Gemma4Handler;
 pub use lfm2::Lfm2Handler;
 pub use ministral3::Ministral3Handler;
+pub use mistral::MistralHandler;
 pub use qwen3::Qwen3Handler;
 pub use qwen35_36::Qwen35_36Handler;
+pub use tool_macro::nobodywho_tool;
 
 // ============================================================================
 // Core Types
 // ============================================================================
 
+/// The function behind a [`Tool`], called with the arguments the model supplied.
+pub type ToolFunction = Arc<dyn Fn(serde_json::Value) -> Result<String, ToolError> + Send + Sync>;
+
 /// A tool that can be called by the LLM.
 #[derive(Clone)]
 pub struct Tool {
     pub name: String,
     pub description: String,
     pub json_schema: serde_json::Value,
-    pub function: Arc<dyn Fn(serde_json::Value) -> String + Send + Sync>,
+    pub function: ToolFunction,
+}
+
+/// A failed tool call, returned by the function of a tool made with [`Tool::new_fallible`].
+///
+/// The model gets the message as the tool response, while the application sees the error in
+/// [`ChatEvent::ToolCallFinished`](crate::chat::ChatEvent::ToolCallFinished) and
+/// [`TurnTrace::tool_errors`](crate::chat::TurnTrace::tool_errors).
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("{message}")]
+pub struct ToolError {
+    pub message: String,
+}
+
+impl ToolError {
+    pub fn new(message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+        }
+    }
+
+    /// The tool response the model is shown for this error.
+    pub fn response(&self) -> String {
+        format!("ERROR: {}", self.message)
+    }
 }
 
 impl std::fmt::Debug for Tool {
@@ -60,6 +95,22 @@ impl Tool {
         description: S,
         json_schema: serde_json::Value,
         function: Arc<dyn Fn(serde_json::Value) -> String + Send + Sync>,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            description: description.into(),
+            json_schema,
+            function: Arc::new(move |args| Ok(function(args))),
+        }
+    }
+
+    /// Like [`Tool::new`], for a function that can fail. An `Err` is shown to the model as
+    /// the tool response, and reported to the application as a failed tool call.
+    pub fn new_fallible<S: Into<String>>(
+        name: S,
+        description: S,
+        json_schema: serde_json::Value,
+        function: ToolFunction,
     ) -> Self {
         Self {
             name: name.into(),
@@ -204,6 +255,102 @@ impl Serialize for Tool {
     }
 }
 
+/// A type that can be a parameter of a function made into a tool with [`nobodywho_tool`].
+///
+/// Implement it for your own types to use them as tool parameters.
+pub trait ToolParameter: serde::de::DeserializeOwned {
+    /// Whether the model has to supply the parameter.
+    const REQUIRED: bool = true;
+
+    /// The JSON schema of the values the model may supply.
+    fn json_schema() -> serde_json::Value;
+}
+
+macro_rules! impl_tool_parameter {
+    ($json_type:literal: $($t:ty),*) => {
+        $(
+            impl ToolParameter for $t {
+                fn json_schema() -> serde_json::Value {
+                    serde_json::json!({ "type": $json_type })
+                }
+            }
+        )*
+    };
+}
+
+impl_tool_parameter!("string": String);
+impl_tool_parameter!("boolean": bool);
+impl_tool_parameter!("integer": i8, i16, i32, i64, isize, u8, u16, u32, u64, usize);
+impl_tool_parameter!("number": f32, f64);
+
+impl<T: ToolParameter> ToolParameter for Vec<T> {
+    fn json_schema() -> serde_json::Value {
+        serde_json::json!({ "type": "array", "items": T::json_schema() })
+    }
+}
+
+impl<T: ToolParameter> ToolParameter for Option<T> {
+    const REQUIRED: bool = false;
+
+    fn json_schema() -> serde_json::Value {
+        T::json_schema()
+    }
+}
+
+impl ToolParameter for serde_json::Value {
+    fn json_schema() -> serde_json::Value {
+        serde_json::json!({})
+    }
+}
+
+/// Used by the code [`nobodywho_tool`] generates. Not public API.
+#[doc(hidden)]
+pub mod macro_support {
+    pub use super::ToolParameter;
+    use super::{ToolError, ToolFunction};
+    use std::sync::Arc;
+
+    /// The schema of the arguments object, from `(name, schema, required, description)`
+    pub fn object_schema(
+        params: Vec<(&str, serde_json::Value, bool, Option<&str>)>,
+    ) -> serde_json::Value {
+        let mut properties = serde_json::Map::new();
+        let mut required = Vec::new();
+        for (name, mut schema, is_required, description) in params {
+            if let (Some(description), serde_json::Value::Object(obj)) = (description, &mut schema)
+            {
+                obj.insert("description".to_string(), description.into());
+            }
+            properties.insert(name.to_string(), schema);
+            if is_required {
+                required.push(name);
+            }
+        }
+        serde_json::json!({
+            "type": "object",
+            "properties": properties,
+            "required": required
+        })
+    }
+
+    /// Deserialize the argument `name`, a missing one counting as `null`
+    pub fn argument<T: ToolParameter>(
+        args: &serde_json::Value,
+        name: &str,
+    ) -> Result<T, ToolError> {
+        let value = args.get(name).cloned().unwrap_or_default();
+        serde_json::from_value(value)
+            .map_err(|e| ToolError::new(format!("Failed to convert argument '{name}': {e}")))
+    }
+
+    /// Wrap a function taking the arguments by reference into a tool function
+    pub fn tool_function(
+        function: impl Fn(&serde_json::Value) -> Result<String, ToolError> + Send + Sync + 'static,
+    ) -> ToolFunction {
+        Arc::new(move |args| function(&args))
+    }
+}
+
 /// A tool call extracted from LLM output.
 #[derive(Debug, Deserialize, Clone, PartialEq)]
 pub struct ToolCall {
@@ -211,6 +358,20 @@ pub struct ToolCall {
     pub arguments: serde_json::Value,
 }
 
+impl ToolCall {
+    /// Some models emit the arguments as a JSON-encoded string (`"arguments": "{\"a\": 2}"`)
+    /// rather than as an object. Decode those, and leave any other arguments as they are.
+    fn decode_stringified_arguments(mut self) -> Self {
+        if let serde_json::Value::String(encoded) = &self.arguments {
+            if let Ok(decoded @ serde_json::Value::Object(_)) = serde_json::from_str(encoded) {
+                debug!(tool_name = %self.name, "Decoded stringified tool call arguments");
+                self.arguments = decoded;
+            }
+        }
+        self
+    }
+}
+
 // Serialize tools according to https://huggingface.co/blog/unified-tool-use
 impl Serialize for ToolCall {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
@@ -274,7 +435,9 @@ pub enum ToolFormat {
     FunctionGemma(FunctionGemmaHandler),
     Gemma4(Gemma4Handler),
     Ministral3(Ministral3Handler),
+    Mistral(MistralHandler),
     Lfm2(Lfm2Handler),
+    Custom(CustomHandler),
 }
 
 impl ToolFormat {
@@ -285,10 +448,39 @@ impl ToolFormat {
             ToolFormat::FunctionGemma(h) => h,
             ToolFormat::Gemma4(h) => h,
             ToolFormat::Ministral3(h) => h,
+            ToolFormat::Mistral(h) => h,
             ToolFormat::Lfm2(h) => h,
+            ToolFormat::Custom(h) => h,
         }
     }
 
+    /// A format that writes its calls like `base` does, but between `begin_token` and
+    /// `end_token` instead of `base`'s own markers.
+    ///
+    /// Meant for fine-tunes that only changed the tool call markers. If `base` has no end
+    /// token (like Mistral's `[TOOL_CALLS]`), `end_token` is not written either.
+    ///
+    /// ```
+    /// use nobodywho::tool_calling::{Qwen3Handler, ToolFormat};
+    ///
+    /// let format = ToolFormat::custom("<call>", "</call>", ToolFormat::Qwen3(Qwen3Handler));
+    /// let calls = format
+    ///     .extract_tool_calls(r#"<call>{"name": "add", "arguments": {"a": 2}}</call>"#)
+    ///     .unwrap();
+    /// assert_eq!(calls[0].name, "add");
+    /// ```
+    pub fn custom(
+        begin_token: impl Into<String>,
+        end_token: impl Into<String>,
+        base: ToolFormat,
+    ) -> Self {
+        ToolFormat::Custom(CustomHandler::new(
+            begin_token.into(),
+            end_token.into(),
+            base,
+        ))
+    }
+
     pub fn begin_token(&self) -> &str {
         self.handler().begin_token()
     }
@@ -301,11 +493,64 @@ impl ToolFormat {
         self.handler().generate_grammar(tools)
     }
 
+    /// Extracts tool calls from the given text. Arguments that the model wrote as a
+    /// JSON-encoded string are decoded, so tools always get the object.
     pub fn extract_tool_calls(&self, input: &str) -> Option<Vec<ToolCall>> {
-        self.handler().extract_tool_calls(input)
+        let tool_calls = self.handler().extract_tool_calls(input)?;
+        Some(
+            tool_calls
+                .into_iter()
+                .map(ToolCall::decode_stringified_arguments)
+                .collect(),
+        )
+    }
+
+    /// The name of this format, as listed by [`supported_tool_formats`].
+    /// Formats made with [`ToolFormat::custom`] are called "custom".
+    pub fn name(&self) -> &'static str {
+        match self {
+            ToolFormat::Qwen3(_) => "qwen3",
+            ToolFormat::Qwen35_36(_) => "qwen3.5",
+            ToolFormat::FunctionGemma(_) => "functiongemma",
+            ToolFormat::Gemma4(_) => "gemma4",
+            ToolFormat::Ministral3(_) => "ministral3",
+            ToolFormat::Mistral(_) => "mistral",
+            ToolFormat::Lfm2(_) => "lfm2",
+            ToolFormat::Custom(_) => "custom",
+        }
+    }
+
+    /// Look up a format by one of the names from [`supported_tool_formats`].
+    pub fn from_name(name: &str) -> Result<Self, ToolFormatError> {
+        match name {
+            "qwen3" => Ok(ToolFormat::Qwen3(Qwen3Handler)),
+            "qwen3.5" => Ok(ToolFormat::Qwen35_36(Qwen35_36Handler)),
+            "functiongemma" => Ok(ToolFormat::FunctionGemma(FunctionGemmaHandler)),
+            "gemma4" => Ok(ToolFormat::Gemma4(Gemma4Handler)),
+            "ministral3" => Ok(ToolFormat::Ministral3(Ministral3Handler)),
+            "mistral" => Ok(ToolFormat::Mistral(MistralHandler)),
+            "lfm2" => Ok(ToolFormat::Lfm2(Lfm2Handler)),
+            _ => Err(ToolFormatError::UnsupportedFormat(name.to_string())),
+        }
     }
 }
 
+/// Names of all tool calling formats, for use with [`ToolFormat::from_name`].
+///
+/// Useful when [`detect_tool_format`] picks the wrong format, e.g. for a fine-tuned model
+/// with stripped metadata: the format can then be set explicitly instead.
+pub fn supported_tool_formats() -> &'static [&'static str] {
+    &[
+        "qwen3",
+        "qwen3.5",
+        "functiongemma",
+        "gemma4",
+        "ministral3",
+        "mistral",
+        "lfm2",
+    ]
+}
+
 fn is_qwen35_36_architecture(arch: &str) -> bool {
     let arch = arch.to_lowercase();
     arch.starts_with("qwen35")
@@ -323,6 +568,21 @@ fn is_qwen35_36_name(name: &str) -> bool {
     .any(|needle| name.contains(needle))
 }
 
+fn is_ministral3_name(name: &str) -> bool {
+    let name = name.to_lowercase();
+    ["ministral3", "ministral 3", "ministral-3"]
+        .iter()
+        .any(|needle| name.contains(needle))
+}
+
+fn is_mistral_name(name: &str) -> bool {
+    let name = name.to_lowercase();
+    // NVIDIA's Nemotron models have a tool calling format of their own
+    name.contains("mistral")
+        || name.contains("ministral")
+        || (name.contains("nemo") && !name.contains("nemotron"))
+}
+
 fn is_qwen3_name(name: &str) -> bool {
     let name = name.to_lowercase();
     name.contains("qwen3") || name.contains("qwen 3") || name.contains("qwen-3")
@@ -366,10 +626,15 @@ pub fn detect_tool_format(model: &LlamaModel) -> Result<ToolFormat, ToolFormatEr
         return Ok(ToolFormat::Qwen3(Qwen3Handler));
     }
 
-    // Check for Ministral3 markers
+    // Check for Mistral markers. Ministral3 puts each call's arguments after an [ARGS] tag,
+    // older Mistral models (Nemo, 7B v0.3) emit a JSON array of calls instead.
     if template_str.contains("[TOOL_CALLS]") {
-        debug!("Detected Ministral3 format from template markers");
-        return Ok(ToolFormat::Ministral3(Ministral3Handler));
+        if template_str.contains("[ARGS]") {
+            debug!("Detected Ministral3 format from template markers");
+            return Ok(ToolFormat::Ministral3(Ministral3Handler));
+        }
+        debug!("Detected Mistral format from template markers");
+        return Ok(ToolFormat::Mistral(MistralHandler));
     }
 
     // Check for LFM2 markers
@@ -427,6 +692,16 @@ pub fn detect_tool_format(model: &LlamaModel) -> Result<ToolFormat, ToolFormatEr
             debug!("Detected Qwen3 format from model name");
             return Ok(ToolFormat::Qwen3(Qwen3Handler));
         }
+
+        if is_ministral3_name(&name_lower) {
+            debug!("Detected Ministral3 format from model name");
+            return Ok(ToolFormat::Ministral3(Ministral3Handler));
+        }
+
+        if is_mistral_name(&name_lower) {
+            debug!("Detected Mistral format from model name");
+            return Ok(ToolFormat::Mistral(MistralHandler));
+        }
     }
 
     Err(ToolFormatError::UnsupportedFormat(
@@ -454,13 +729,25 @@ mod tests {
         assert_eq!(format.end_token(), "<end_function_call>");
     }
 
+    #[test]
+    fn test_tool_format_names() {
+        for name in supported_tool_formats() {
+            let format = ToolFormat::from_name(name).expect("listed format should exist");
+            assert_eq!(format.name(), *name);
+        }
+        assert!(matches!(
+            ToolFormat::from_name("hermes"),
+            Err(ToolFormatError::UnsupportedFormat(_))
+        ));
+    }
+
     #[test]
     fn test_tool_serialization() {
         let tool = Tool {
             name: "test_tool".to_string(),
             description: "A test tool".to_string(),
             json_schema: json!({"type": "object"}),
-            function: Arc::new(|_| "result".to_string()),
+            function: Arc::new(|_| Ok("result".to_string())),
         };
 
         let serialized = match serde_json::to_value(&tool) {
@@ -527,11 +814,55 @@ mod tests {
             ToolFormat::FunctionGemma(_) => "FunctionGemma",
             ToolFormat::Gemma4(_) => "Gemma4",
             ToolFormat::Ministral3(_) => "Ministral3",
+            ToolFormat::Mistral(_) => "Mistral",
             ToolFormat::Lfm2(_) => "Lfm2",
+            ToolFormat::Custom(_) => "Custom",
         };
         eprintln!("detected handler     = {variant}");
     }
 
+    /// Get the current weather for a city
+    ///
+    /// Only knows about sunny days.
+    #[nobodywho_tool(params(city = "The city name"))]
+    fn get_weather(city: String, days: Option<u32>) -> String {
+        format!("Weather in {city} for {} days: sunny", days.unwrap_or(1))
+    }
+
+    #[test]
+    fn test_nobodywho_tool_macro() {
+        let tool = get_weather_tool();
+        assert_eq!(tool.name, "get_weather");
+        assert_eq!(
+            tool.description,
+            "Get the current weather for a city\n\nOnly knows about sunny days."
+        );
+        assert_eq!(
+            tool.json_schema,
+            json!({
+                "type": "object",
+                "properties": {
+                    "city": {"type": "string", "description": "The city name"},
+                    "days": {"type": "integer"}
+                },
+                "required": ["city"]
+            })
+        );
+
+        assert_eq!(
+            (tool.function)(json!({"city": "Copenhagen", "days": 3})).unwrap(),
+            "Weather in Copenhagen for 3 days: sunny"
+        );
+        assert_eq!(
+            (tool.function)(json!({"city": "Copenhagen"})).unwrap(),
+            "Weather in Copenhagen for 1 days: sunny"
+        );
+        let error = (tool.function)(json!({"days": 3})).unwrap_err();
+        assert!(error
+            .message
+            .starts_with("Failed to convert argument 'city'"));
+    }
+
     #[test]
     fn test_tool_call_serialization() {
         use serde_json::json;
@@ -571,6 +902,135 @@ mod tests {
         assert!(is_qwen3_name("Qwen3-8B-Instruct"));
     }
 
+    #[test]
+    fn test_stringified_arguments_are_decoded() {
+        let cases = [
+            (
+                ToolFormat::Qwen3(Qwen3Handler),
+                r#"<tool_call>{"name": "add", "arguments": {"a": 2}}</tool_call>"#,
+                r#"<tool_call>{"name": "add", "arguments": "{\"a\": 2}"}</tool_call>"#,
+            ),
+            (
+                ToolFormat::Mistral(MistralHandler),
+                r#"[TOOL_CALLS][{"name": "add", "arguments": {"a": 2}}]"#,
+                r#"[TOOL_CALLS][{"name": "add", "arguments": "{\"a\": 2}"}]"#,
+            ),
+            (
+                ToolFormat::Ministral3(Ministral3Handler),
+                r#"[TOOL_CALLS]add[ARGS]{"a": 2}"#,
+                r#"[TOOL_CALLS]add[ARGS]"{\"a\": 2}""#,
+            ),
+        ];
+
+        for (format, object_form, string_form) in cases {
+            for input in [object_form, string_form] {
+                let calls = format
+                    .extract_tool_calls(input)
+                    .unwrap_or_else(|| panic!("{} should parse {input}", format.name()));
+                assert_eq!(calls[0].name, "add");
+                assert_eq!(calls[0].arguments, json!({"a": 2}), "{}", format.name());
+            }
+        }
+    }
+
+    #[test]
+    fn test_extraction_skips_surrounding_prose() {
+        // a call to `add` and a call to `sub` in each format, both with arguments {"a": 2}
+        let cases = [
+            (
+                ToolFormat::Qwen3(Qwen3Handler),
+                r#"<tool_call>{"name": "add", "arguments": {"a": 2}}</tool_call>"#,
+                r#"<tool_call>{"name": "sub", "arguments": {"a": 2}}</tool_call>"#,
+            ),
+            (
+                ToolFormat::Qwen35_36(Qwen35_36Handler),
+                "<tool_call>\n<function=add>\n<parameter=a>\n2\n</parameter>\n</function>\n</tool_call>",
+                "<tool_call>\n<function=sub>\n<parameter=a>\n2\n</parameter>\n</function>\n</tool_call>",
+            ),
+            (
+                ToolFormat::FunctionGemma(FunctionGemmaHandler),
+                "<start_function_call>call:add{a:<escape>2<escape>}<end_function_call>",
+                "<start_function_call>call:sub{a:<escape>2<escape>}<end_function_call>",
+            ),
+            (
+                ToolFormat::Gemma4(Gemma4Handler),
+                "<|tool_call>call:add{a:2}<tool_call|>",
+                "<|tool_call>call:sub{a:2}<tool_call|>",
+            ),
+            (
+                ToolFormat::Ministral3(Ministral3Handler),
+                r#"[TOOL_CALLS]add[ARGS]{"a": 2}"#,
+                r#"[TOOL_CALLS]sub[ARGS]{"a": 2}"#,
+            ),
+            (
+                ToolFormat::Mistral(MistralHandler),
+                r#"[TOOL_CALLS][{"name": "add", "arguments": {"a": 2}}]"#,
+                r#"[TOOL_CALLS][{"name": "sub", "arguments": {"a": 2}}]"#,
+            ),
+            (
+                ToolFormat::Lfm2(Lfm2Handler),
+                "<|tool_call_start|>[add(a=2)]<|tool_call_end|>",
+                "<|tool_call_start|>[sub(a=2)]<|tool_call_end|>",
+            ),
+        ];
+
+        for (format, add, sub) in cases {
+            let inputs = [
+                (
+                    format!("I'll call the tool: {add} to help you."),
+                    vec!["add"],
+                ),
+                (
+                    format!("First {add} and then {sub}, that should do it."),
+                    vec!["add", "sub"],
+                ),
+            ];
+            for (input, expected_names) in inputs {
+                let calls = format
+                    .extract_tool_calls(&input)
+                    .unwrap_or_else(|| panic!("{} should parse {input}", format.name()));
+                let names: Vec<&str> = calls.iter().map(|call| call.name.as_str()).collect();
+                assert_eq!(names, expected_names, "{}: {input}", format.name());
+                for call in &calls {
+                    assert_eq!(
+                        call.arguments,
+                        json!({"a": 2}),
+                        "{}: {input}",
+                        format.name()
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_string_arguments_that_are_not_objects_are_kept() {
+        let call = ToolCall {
+            name: "echo".into(),
+            arguments: json!("[1, 2]"),
+        };
+        assert_eq!(
+            call.decode_stringified_arguments().arguments,
+            json!("[1, 2]")
+        );
+    }
+
+    #[test]
+    fn test_mistral_name_detection() {
+        for name in [
+            "Mistral-Nemo-Instruct-2407",
+            "Mistral-7B-Instruct-v0.3",
+            "Ministral-8B-Instruct-2410",
+        ] {
+            assert!(is_mistral_name(name), "{name} should map to Mistral");
+            assert!(!is_ministral3_name(name), "{name} is not Ministral3");
+        }
+
+        assert!(is_ministral3_name("Ministral-3-8B-Instruct-2512"));
+        assert!(!is_mistral_name("Qwen3-8B-Instruct"));
+        assert!(!is_mistral_name("Nemotron-Mini-4B-Instruct"));
+    }
+
     #[test]
     fn test_qwen35_36_architecture_detection_beats_generic_qwen3() {
         for arch in ["qwen35", "qwen35moe", "qwen36", "qwen3.5", "qwen3.6"] {
@@ -130,6 +130,25 @@ mod tests {
         assert_eq!(tool_calls[1].name, "tool2");
     }
 
+    #[test]
+    fn test_qwen3_grammar_allows_line_breaks_around_json() {
+        let tool = Tool::new(
+            "get_weather",
+            "Gets the weather",
+            json!({
+                "type": "object",
+                "properties": {"location": {"type": "string"}},
+                "required": ["location"]
+            }),
+            std::sync::Arc::new(|_| String::new()),
+        );
+        let grammar = Qwen3Handler.generate_grammar(&[tool]).unwrap();
+        // the layout Qwen3's chat template uses for tool calls
+        assert!(grammar.matches(
+            "<tool_call>\n{\"name\": \"get_weather\", \"arguments\": {\"location\": \"Paris\"}}\n</tool_call>\n<tool_call>\n{\"name\": \"get_weather\", \"arguments\": {\"location\": \"Oslo\"}}\n</tool_call>"
+        ));
+    }
+
     #[test]
     fn test_qwen3_extract_no_tool_calls() {
         let handler = Qwen3Handler;
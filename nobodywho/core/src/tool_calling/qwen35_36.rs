@@ -341,7 +341,7 @@ mod tests {
                 },
                 "required": ["city"]
             }),
-            function: std::sync::Arc::new(|_| "".to_string()),
+            function: std::sync::Arc::new(|_| Ok(String::new())),
         };
         let gram = h.generate_grammar(&[tool]).expect("grammar should build");
         let s = gram.as_str();
@@ -365,7 +365,7 @@ mod tests {
                     "z": {"type": "null"}
                 }
             }),
-            function: std::sync::Arc::new(|_| "".to_string()),
+            function: std::sync::Arc::new(|_| Ok(String::new())),
         };
 
         let grammar = h.generate_grammar(&[tool]).expect("grammar should build");
@@ -37,11 +37,16 @@ impl From<nobodywho::chat::Message> for Message {
                 content: content.to_string(),
                 assets,
             },
+            // the thinking has no field of its own here, so it goes back into the content
             nobodywho::chat::Message::Assistant {
                 content,
+                reasoning_content,
                 tool_calls,
             } => Message::Assistant {
-                content,
+                content: match reasoning_content {
+                    Some(thinking) => nobodywho::chat::join_thinking(&thinking, &content),
+                    None => content,
+                },
                 tool_calls,
             },
             nobodywho::chat::Message::System { content } => Message::System { content },
@@ -60,10 +65,14 @@ impl From<Message> for nobodywho::chat::Message {
             Message::Assistant {
                 content,
                 tool_calls,
-            } => nobodywho::chat::Message::Assistant {
-                content,
-                tool_calls,
-            },
+            } => {
+                let (reasoning_content, content) = nobodywho::chat::split_thinking(&content);
+                nobodywho::chat::Message::Assistant {
+                    content: content.to_string(),
+                    reasoning_content: reasoning_content.map(str::to_string),
+                    tool_calls,
+                }
+            }
             Message::System { content } => nobodywho::chat::Message::System { content },
             Message::Tool { name, content } => nobodywho::chat::Message::Tool { name, content },
         }
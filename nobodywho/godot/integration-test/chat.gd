@@ -11,6 +11,8 @@ func run_test():
 	assert(await test_tool_call_underscores())
 	assert(await test_tool_remove())
 	assert(await test_sampler_builder())
+	assert(await test_ask_with_schema())
+	assert(await test_ask_with_options())
 	assert(await test_stats())
 	return true
 
@@ -25,7 +27,7 @@ func test_say():
 
 func test_chat_history():
 	# Reset to clean state
-	reset_context()
+	await reset_context()
 	self.allow_thinking = false
 	
 	# Set up a simple chat history
@@ -64,37 +66,50 @@ func test_tool_call():
 	self.system_prompt = "You're a helpful tool-calling assistant. Remember to keep proper tool calling syntax."
 	self.reset_context()
 	self.allow_thinking = false
+	var called = []
+	var returned = []
+	var on_called = func(name, arguments): called.append([name, arguments])
+	var on_returned = func(name, result): returned.append([name, result])
+	tool_called.connect(on_called)
+	tool_returned.connect(on_returned)
 	# 12.3 is on pupose to test correct type inputs for the tool call
 	ask("I'd like to know the current temperature in Copenhagen. with zipcode 12.3 and in denmark is true")
 	var response = await response_finished
+	tool_called.disconnect(on_called)
+	tool_returned.disconnect(on_returned)
 	print(response)
 	assert("12.34" in response)
+	assert(called.size() > 0, "tool_called should be emitted")
+	assert(called[0][0] == "current_temperature")
+	assert(called[0][1]["location"].to_lower() == "copenhagen")
+	assert(returned.size() == called.size(), "every tool call should return")
+	assert(returned[0][1] == "12.34")
 	return true
 
 
 func toggle_flag() -> String:
-	tool_called = true
+	flag_toggled = true
 	return "flag set"
 
-var tool_called = false
+var flag_toggled = false
 
 func test_tool_remove():
 	self.set_log_level("debug")
-	tool_called = false
+	flag_toggled = false
 	self.add_tool(toggle_flag, "A simple tool that toggles a flag")
 	self.system_prompt = "You're a helpful tool-calling assistant. You may call functions when asked."
 	self.reset_context()
 	self.allow_thinking = false
 	ask("Call the function named 'toggle_flag' now.")
 	var response = await response_finished
-	assert(tool_called, "Tool should be called when registered")
+	assert(flag_toggled, "Tool should be called when registered")
 
-	tool_called = false
+	flag_toggled = false
 	remove_tool(toggle_flag)
 	self.allow_thinking = false
 	ask("I disabled the flag, can you set it again by calling the function named 'toggle_flag' now.")
 	response = await response_finished
-	assert(not tool_called, "Tool should not be called after removal")
+	assert(not flag_toggled, "Tool should not be called after removal")
 	return true
 
 func guess_password(text_1: String, text__1: String, __text__: String) -> String:
@@ -133,6 +148,46 @@ func test_sampler_builder():
 	return true
 
 
+func test_ask_with_schema():
+	print("✨ Testing ask_with_schema")
+	reset_context()
+	self.allow_thinking = false
+	var schema = JSON.stringify({
+		"type": "object",
+		"properties": {
+			"emotion": {"type": "string"},
+			"line": {"type": "string"},
+		},
+		"required": ["emotion", "line"],
+	})
+	ask_with_schema("Greet the player as a grumpy blacksmith.", schema)
+	var response = await response_finished
+	print("✨ Got response: " + response)
+	var parsed = JSON.parse_string(response)
+	assert(parsed is Dictionary)
+	assert(parsed.has("emotion") and parsed.has("line"))
+	return true
+
+
+func test_ask_with_options():
+	print("✨ Testing ask_with_options")
+	reset_context()
+	self.allow_thinking = false
+	ask_with_options("What is the capital of Denmark? Answer in a full sentence.", PackedStringArray(["Copenhagen"]), 0)
+	var response = await response_finished
+	print("✨ Got response: " + response)
+	assert(not "Copenhagen" in response)
+
+	var tokens = []
+	var on_token = func(token): tokens.append(token)
+	response_updated.connect(on_token)
+	ask_with_options("Tell me a long story about Copenhagen.", PackedStringArray(), 5)
+	response = await response_finished
+	response_updated.disconnect(on_token)
+	assert(tokens.size() <= 5)
+	return true
+
+
 func test_stats():
 	reset_context()
 	self.allow_thinking = false
@@ -0,0 +1,18 @@
+extends NobodyWhoCompletion
+
+func run_test():
+	# configure node
+	self.model_node = get_node("../ChatModel")
+	self.stop_words = PackedStringArray([".", "\n"])
+	self.set_sampler_config(NobodyWhoSamplerBuilder.new().greedy())
+
+	# plain continuation, no chat template involved
+	complete("The capital of Denmark is the city of")
+	var response = await response_finished
+	print("✨ Got completion: " + response)
+
+	assert("Copenhagen" in response)
+	# generation stops before the stop word, which is left out
+	assert(not "." in response)
+	print("✨ completion completed")
+	return true
@@ -17,8 +17,10 @@ func _ready() -> void:
 	_fail_on_worker_failure($NobodyWhoEncoder, "NobodyWhoEncoder")
 	_fail_on_worker_failure($CrossEncoder, "NobodyWhoCrossEncoder")
 	_fail_on_worker_failure($Grammar/Chat, "Grammar.Chat")
+	_fail_on_worker_failure($Completion, "NobodyWhoCompletion")
 	assert(await $NobodyWhoEncoder.run_test())
 	assert(await $NobodyWhoChat.run_test())
+	assert(await $Completion.run_test())
 	# Grammar test is disabled: llama.cpp's grammar sampler currently aborts
 	# with a foreign C++ exception on the first sampled token for any grammar
 	# against our test model. The previous test appeared to pass on main only
@@ -1,6 +1,6 @@
 use godot::classes::{INode, ProjectSettings};
 use godot::prelude::*;
-use nobodywho::chat::{ChatConfig, Message};
+use nobodywho::chat::{ChatConfig, ChatEvent, Message};
 use nobodywho::sampler::{
     SampleStep, SamplerConfig as CoreSamplerConfig, SamplerPresets, ShiftStep,
 };
@@ -47,6 +47,34 @@ fn resolve_godot_path(path: &GString) -> String {
     }
 }
 
+/// Logs an error and returns false unless `a` and `b` are non-empty and the same length.
+fn check_encodings(caller: &str, a: &[f32], b: &[f32]) -> bool {
+    if a.is_empty() || b.is_empty() {
+        godot_error!("{caller}: got an empty encoding. Pass the result of encode().");
+        return false;
+    }
+    if a.len() != b.len() {
+        godot_error!(
+            "{caller}: encodings have different lengths ({} and {}). Both encodings must come from the same model.",
+            a.len(),
+            b.len()
+        );
+        return false;
+    }
+    true
+}
+
+fn parse_rope_scaling(
+    rope_scaling: &GString,
+) -> Result<Option<nobodywho::llm::RopeScaling>, GString> {
+    if rope_scaling.is_empty() {
+        return Ok(None);
+    }
+    rope_scaling.to_string().parse().map(Some).map_err(|()| {
+        GString::from("rope_scaling must be empty or one of 'none', 'linear', or 'yarn'")
+    })
+}
+
 fn parse_tts_architecture(
     architecture: String,
 ) -> Result<Option<nobodywho::tts::TtsArchitecture>, GString> {
@@ -651,7 +679,10 @@ struct NobodyWhoChat {
     allow_thinking: bool,
 
     #[export]
-    /// This is the maximum number of tokens that can be stored in the chat history. It will delete information from the chat history if it exceeds this limit.
+    /// This is the maximum number of tokens that can be stored in the chat history.
+    /// Once the chat takes up more than 80% of it when a turn starts, the model first summarizes everything before the last two user turns, and the summary replaces those turns. That turn takes noticeably longer, since a whole extra response is generated before it.
+    /// If the chat still outgrows it, the oldest turns (a user message and the responses to it) are removed as whole messages until the chat takes up half of it. The system prompt, the first turn and the last two turns are never removed.
+    /// The core library's opt-in `Shift` (only remove old turns) and `Error` (never change the history) context policies are not available on this node.
     /// Higher values use more VRAM, but allow for longer "short term memory" for the LLM.
     context_length: u32,
 
@@ -671,6 +702,31 @@ struct NobodyWhoChat {
     /// `p_min`). Only used when `mtp` is enabled.
     mtp_p_min: f32,
 
+    #[export]
+    /// Number of threads used to generate tokens. 0 uses all available CPU cores.
+    /// Only affects inference on the CPU.
+    n_threads: u32,
+
+    #[export]
+    /// Number of threads used to read prompts. 0 uses all available CPU cores.
+    /// Only affects inference on the CPU.
+    n_threads_batch: u32,
+
+    #[export]
+    /// RoPE scaling method: `none`, `linear` or `yarn`. Empty uses the one in the model file.
+    /// Setting any RoPE option allows `context_length` to exceed the context the model was
+    /// trained with, but output quality degrades the further it is stretched.
+    rope_scaling: GString,
+
+    #[export]
+    /// RoPE base frequency. 0 uses the one in the model file.
+    rope_freq_base: f32,
+
+    #[export]
+    /// RoPE frequency scaling factor, the inverse of the context extension
+    /// (e.g. 0.25 for 4x the trained context). 0 uses the one in the model file.
+    rope_freq_scale: f32,
+
     // internal state
     chat_handle: Option<nobodywho::chat::ChatHandleAsync>,
     tools: Vec<nobodywho::tool_calling::Tool>,
@@ -696,6 +752,11 @@ impl INode for NobodyWhoChat {
             mtp: default_config.mtp.is_some(),
             mtp_k_max: mtp_defaults.k_max,
             mtp_p_min: mtp_defaults.p_min,
+            n_threads: 0,
+            n_threads_batch: 0,
+            rope_scaling: GString::new(),
+            rope_freq_base: 0.0,
+            rope_freq_scale: 0.0,
 
             // config
             model_node: None,
@@ -721,6 +782,7 @@ impl NobodyWhoChat {
         n_ctx: u32,
         allow_thinking: bool,
         mtp: Option<nobodywho::chat::MtpConfig>,
+        context_options: nobodywho::llm::ContextOptions,
     ) -> Result<nobodywho::chat::ChatHandleAsync, GString> {
         tokio::task::yield_now().await;
 
@@ -739,6 +801,8 @@ impl NobodyWhoChat {
                 template_variables,
                 sampler_config: None,
                 mtp,
+                context_options,
+                ..Default::default()
             },
         )
         .map_err(|e| GString::from(e.to_string().as_str()))?;
@@ -766,6 +830,7 @@ impl NobodyWhoChat {
             u32,
             bool,
             Option<nobodywho::chat::MtpConfig>,
+            nobodywho::llm::ContextOptions,
         ),
         GString,
     > {
@@ -778,6 +843,14 @@ impl NobodyWhoChat {
             k_max: self.mtp_k_max,
             p_min: self.mtp_p_min,
         });
+        let context_options = nobodywho::llm::ContextOptions {
+            n_threads: (self.n_threads > 0).then_some(self.n_threads),
+            n_threads_batch: (self.n_threads_batch > 0).then_some(self.n_threads_batch),
+            rope_scaling: parse_rope_scaling(&self.rope_scaling)?,
+            rope_freq_base: (self.rope_freq_base > 0.0).then_some(self.rope_freq_base),
+            rope_freq_scale: (self.rope_freq_scale > 0.0).then_some(self.rope_freq_scale),
+            ..Default::default()
+        };
         Ok((
             model_node,
             self.system_prompt.to_string(),
@@ -785,6 +858,7 @@ impl NobodyWhoChat {
             self.context_length,
             self.allow_thinking,
             mtp,
+            context_options,
         ))
     }
 
@@ -797,12 +871,14 @@ impl NobodyWhoChat {
     /// `ask()` before the worker is ready are queued and dispatched once loading
     /// completes.
     fn start_worker(&mut self) {
-        if self.chat_handle.is_some() {
+        if self.chat_handle.as_ref().is_some_and(|h| h.is_alive()) {
             self.signals().worker_started().emit();
             return;
         }
+        // a crashed worker is replaced with a fresh one
+        self.chat_handle = None;
 
-        let (model_node, system_prompt, tools, n_ctx, allow_thinking, mtp) =
+        let (model_node, system_prompt, tools, n_ctx, allow_thinking, mtp, context_options) =
             match self.snapshot_worker_config() {
                 Ok(c) => c,
                 Err(e) => {
@@ -823,6 +899,7 @@ impl NobodyWhoChat {
                 n_ctx,
                 allow_thinking,
                 mtp,
+                context_options,
             )
             .await
             {
@@ -849,6 +926,117 @@ impl NobodyWhoChat {
     /// prompt until loading completes. The generation itself happens on a background
     /// task — emissions arrive via the `response_updated` / `response_finished` signals.
     fn ask(&mut self, message: Variant) {
+        self.ask_impl(message, nobodywho::chat::AskOptions::default())
+    }
+
+    #[func]
+    /// Sends a message like `ask`, with limits that only apply to this reply.
+    ///
+    /// Generation stops right before the first occurrence of any of `stop_words`, which are
+    /// not part of the reply. `max_tokens` caps the length of the reply; `0` or less means
+    /// no limit.
+    ///
+    /// ```
+    /// ask_with_options("Name a fruit.", PackedStringArray(["\n", "."]), 16)
+    /// var fruit = await response_finished
+    /// ```
+    fn ask_with_options(
+        &mut self,
+        message: Variant,
+        stop_words: PackedStringArray,
+        max_tokens: i64,
+    ) {
+        let stop_words = stop_words
+            .as_slice()
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let max_tokens = (max_tokens > 0).then(|| max_tokens.min(u32::MAX.into()) as u32);
+        self.ask_impl(
+            message,
+            nobodywho::chat::AskOptions {
+                stop_words,
+                max_tokens,
+                ..Default::default()
+            },
+        )
+    }
+
+    #[func]
+    /// Sends a message like `ask`, but constrains the reply to JSON matching `json_schema`.
+    /// The constraint only applies to this reply; the chat's sampler is left untouched.
+    /// The response arrives through the usual `response_updated` / `response_finished` signals.
+    ///
+    /// An invalid schema is reported as an error and no reply is generated.
+    ///
+    /// ```
+    /// var schema = '{"type": "object", "properties": {"emotion": {"type": "string"}, "line": {"type": "string"}}, "required": ["emotion", "line"]}'
+    /// ask_with_schema("Greet the player.", schema)
+    /// var reply = JSON.parse_string(await response_finished)
+    /// ```
+    fn ask_with_schema(&mut self, message: Variant, json_schema: String) {
+        let grammar = match nobodywho::gbnf::json::json_schema_to_grammar(json_schema, "root") {
+            Ok(grammar) => grammar,
+            Err(e) => {
+                godot_error!("ask_with_schema() got an invalid JSON schema: {}", e);
+                return;
+            }
+        };
+        self.ask_impl(
+            message,
+            nobodywho::chat::AskOptions {
+                grammar: Some(grammar),
+                ..Default::default()
+            },
+        )
+    }
+
+    #[func]
+    /// Converts a JSON schema to the GBNF grammar `ask_with_schema` would constrain the reply with,
+    /// without running the model. Useful for inspecting or hand-editing the grammar before
+    /// passing it to `set_sampler_preset_constrain_with_grammar`.
+    ///
+    /// An invalid schema is reported as an error and an empty string is returned.
+    ///
+    /// ```
+    /// var schema = '{"type": "object", "properties": {"line": {"type": "string"}}, "required": ["line"]}'
+    /// print(NobodyWhoChat.schema_to_grammar(schema))
+    /// ```
+    fn schema_to_grammar(json_schema: String) -> GString {
+        match nobodywho::gbnf::json::json_schema_to_grammar(json_schema, "root") {
+            Ok(grammar) => grammar.as_str().into(),
+            Err(e) => {
+                godot_error!("schema_to_grammar() got an invalid JSON schema: {}", e);
+                GString::new()
+            }
+        }
+    }
+
+    #[func]
+    /// Sends a message like `ask`, but the reply must be exactly one of `options`, with no
+    /// whitespace around it. The constraint only applies to this reply.
+    ///
+    /// ```
+    /// ask_with_choices("Is the player friend or foe?", PackedStringArray(["friend", "foe"]))
+    /// var verdict = await response_finished
+    /// ```
+    fn ask_with_choices(&mut self, message: Variant, options: PackedStringArray) {
+        if options.is_empty() {
+            godot_error!("ask_with_choices() needs at least one option");
+            return;
+        }
+        let options: Vec<String> = options.as_slice().iter().map(|s| s.to_string()).collect();
+        let options: Vec<&str> = options.iter().map(String::as_str).collect();
+        self.ask_impl(
+            message,
+            nobodywho::chat::AskOptions {
+                grammar: Some(nobodywho::gbnf::builder::choice(&options)),
+                ..Default::default()
+            },
+        )
+    }
+
+    fn ask_impl(&mut self, message: Variant, options: nobodywho::chat::AskOptions) {
         let prompt: tokenizer::Prompt = if let Ok(text) = message.try_to::<GString>() {
             text.to_string().to_prompt()
         } else if let Ok(prompt_node) = message.try_to::<Gd<NobodyWhoPrompt>>() {
@@ -882,8 +1070,15 @@ impl NobodyWhoChat {
             let chat_handle = match existing_handle {
                 Some(h) => h,
                 None => {
-                    let (model_node, system_prompt, tools, n_ctx, allow_thinking, mtp) =
-                        load_config.expect("load_config set when no existing handle");
+                    let (
+                        model_node,
+                        system_prompt,
+                        tools,
+                        n_ctx,
+                        allow_thinking,
+                        mtp,
+                        context_options,
+                    ) = load_config.expect("load_config set when no existing handle");
                     match Self::load_and_store_worker(
                         me,
                         model_node,
@@ -892,6 +1087,7 @@ impl NobodyWhoChat {
                         n_ctx,
                         allow_thinking,
                         mtp,
+                        context_options,
                     )
                     .await
                     {
@@ -904,7 +1100,88 @@ impl NobodyWhoChat {
                     }
                 }
             };
-            let mut generation_channel = chat_handle.ask_channel(prompt);
+            let mut generation_channel = chat_handle.ask_events(prompt, options);
+            while let Some(event) = generation_channel.recv().await {
+                match event {
+                    ChatEvent::Token(tok) => emit_node
+                        .signals()
+                        .response_updated()
+                        .emit(&GString::from(tok.as_str())),
+                    ChatEvent::ToolCallStarted(tool_call) => {
+                        let arguments = json_to_godot(&tool_call.arguments)
+                            .try_to::<VarDictionary>()
+                            .unwrap_or_default();
+                        emit_node
+                            .signals()
+                            .tool_called()
+                            .emit(&GString::from(tool_call.name.as_str()), &arguments)
+                    }
+                    ChatEvent::ToolCallFinished {
+                        tool_call,
+                        response,
+                        ..
+                    } => emit_node.signals().tool_returned().emit(
+                        &GString::from(tool_call.name.as_str()),
+                        &GString::from(response.as_str()),
+                    ),
+                    ChatEvent::Done(resp) => emit_node
+                        .signals()
+                        .response_finished()
+                        .emit(&GString::from(resp.as_str())),
+                    ChatEvent::Error(e) => {
+                        Self::report_generation_error(&emit_node, &e);
+                        return;
+                    }
+                }
+            }
+        });
+    }
+
+    /// Hand the current tools to the running worker, if there is one. Otherwise they are
+    /// picked up when the worker starts.
+    fn apply_tools(&self) {
+        let Some(chat_handle) = self.chat_handle.clone() else {
+            return;
+        };
+        let new_tools = self.tools.clone();
+        godot::task::spawn(async move {
+            if let Err(err) = chat_handle.set_tools(new_tools).await {
+                godot_error!("Error: {}", err.to_string());
+            }
+        });
+    }
+
+    fn report_generation_error(emit_node: &Gd<Self>, e: &nobodywho::errors::CompletionError) {
+        let errmsg = nobodywho::render_miette(e);
+        if e.diagnostic_code().as_deref() == Some("nobodywho::context_overflow") {
+            godot_error!(
+                "The message is too long for the chat's context. Shorten the message or the system prompt, or increase `context_length`.\n{}",
+                errmsg
+            );
+        } else {
+            godot_error!("Error during generation: {}", errmsg);
+        }
+        emit_node.signals().worker_failed().emit(&errmsg);
+    }
+
+    #[func]
+    /// Generates a new reply to the last user message, replacing the previous reply and any
+    /// tool calls it made. The new reply arrives through the `response_updated` /
+    /// `response_finished` signals, just like with `ask`.
+    ///
+    /// ```
+    /// regenerate()
+    /// var another_reply = await response_finished
+    /// ```
+    fn regenerate(&mut self) {
+        let Some(chat_handle) = self.chat_handle.clone() else {
+            godot_error!("Attempted to regenerate, but no worker is running. Doing nothing.");
+            return;
+        };
+
+        let emit_node = self.to_gd();
+        godot::task::spawn(async move {
+            let mut generation_channel = chat_handle.regenerate_channel(None);
             while let Some(out) = generation_channel.recv().await {
                 match out {
                     nobodywho::llm::WriteOutput::Token(tok) => emit_node
@@ -916,9 +1193,52 @@ impl NobodyWhoChat {
                         .response_finished()
                         .emit(&GString::from(resp.as_str())),
                     nobodywho::llm::WriteOutput::Error(e) => {
-                        let errmsg = nobodywho::render_miette(e.as_ref());
-                        godot_error!("Error during generation: {}", errmsg);
-                        emit_node.signals().worker_failed().emit(&errmsg);
+                        let e = nobodywho::errors::CompletionError::WorkerError(e);
+                        Self::report_generation_error(&emit_node, &e);
+                        return;
+                    }
+                }
+            }
+        });
+    }
+
+    #[func]
+    /// Continues the last reply where it stopped after reaching the `max_tokens` limit of
+    /// `ask_with_options`. Only the new text arrives through the `response_updated` /
+    /// `response_finished` signals; the chat history keeps the reply as one message.
+    /// `max_tokens` caps the length of the new text; `0` or less means no limit.
+    ///
+    /// ```
+    /// ask_with_options("Tell me a story.", PackedStringArray(), 64)
+    /// var story = await response_finished
+    /// continue_generation(64)
+    /// story += await response_finished
+    /// ```
+    fn continue_generation(&mut self, max_tokens: i64) {
+        let Some(chat_handle) = self.chat_handle.clone() else {
+            godot_error!(
+                "Attempted to continue generation, but no worker is running. Doing nothing."
+            );
+            return;
+        };
+
+        let max_tokens = (max_tokens > 0).then(|| max_tokens.min(u32::MAX.into()) as u32);
+        let emit_node = self.to_gd();
+        godot::task::spawn(async move {
+            let mut generation_channel = chat_handle.continue_generation_channel(max_tokens);
+            while let Some(out) = generation_channel.recv().await {
+                match out {
+                    nobodywho::llm::WriteOutput::Token(tok) => emit_node
+                        .signals()
+                        .response_updated()
+                        .emit(&GString::from(tok.as_str())),
+                    nobodywho::llm::WriteOutput::Done(resp) => emit_node
+                        .signals()
+                        .response_finished()
+                        .emit(&GString::from(resp.as_str())),
+                    nobodywho::llm::WriteOutput::Error(e) => {
+                        let e = nobodywho::errors::CompletionError::WorkerError(e);
+                        Self::report_generation_error(&emit_node, &e);
                         return;
                     }
                 }
@@ -936,7 +1256,131 @@ impl NobodyWhoChat {
     }
 
     #[func]
-    fn reset_context(&mut self) {
+    /// Stop the current generation and remove the stopped turn from the chat history, so
+    /// neither the message nor the partial response is remembered. `response_finished` is
+    /// still emitted with the partial response.
+    fn cancel_generation(&mut self) {
+        if let Some(chat_handle) = &self.chat_handle {
+            chat_handle.cancel_generation();
+        } else {
+            godot_warn!("Attempted to cancel generation, but no worker is running. Doing nothing.");
+        }
+    }
+
+    #[func]
+    /// Returns whether the chat's worker is running. It is `false` before the worker has been
+    /// started and after it crashed. A crashed worker reports an error for every following
+    /// `ask`; calling `start_worker` again replaces it with a fresh one, with an empty history.
+    ///
+    /// ```
+    /// if not is_alive():
+    ///     start_worker()
+    /// ```
+    fn is_alive(&self) -> bool {
+        self.chat_handle
+            .as_ref()
+            .is_some_and(|chat_handle| chat_handle.is_alive())
+    }
+
+    #[func]
+    /// Reads the system prompt into the context ahead of time, so the first `ask()` only has
+    /// to read the player's message. Starts the worker first if needed.
+    /// Returns a signal that is emitted when it's done, so a loading screen can wait for it:
+    ///
+    /// ```
+    /// await warmup()
+    /// ```
+    fn warmup(&mut self) -> Signal {
+        // decide on a unique name for the response signal, so an earlier warmup can't
+        // fire it before this one is awaited
+        let signal_name = format!(
+            "warmup_{}",
+            self.signal_counter.fetch_add(1, Ordering::Relaxed)
+        );
+        self.base_mut().add_user_signal(&signal_name);
+        let signal = Signal::from_object_signal(&self.base_mut(), &signal_name);
+
+        let existing_handle = self.chat_handle.clone();
+        let load_config = if existing_handle.is_none() {
+            match self.snapshot_worker_config() {
+                Ok(c) => Some(c),
+                Err(e) => {
+                    godot_error!("warmup() dropped: {}", e);
+                    self.signals().worker_failed().emit(&e);
+                    return signal;
+                }
+            }
+        } else {
+            None
+        };
+
+        let me = self.to_gd();
+        let mut emit_node = me.clone();
+        godot::task::spawn(async move {
+            // warming up without awaiting it is fine, so nothing connecting isn't an error
+            let _ = wait_for_chat_signal_connect(&emit_node, &signal_name).await;
+            let chat_handle = match existing_handle {
+                Some(h) => h,
+                None => {
+                    let (
+                        model_node,
+                        system_prompt,
+                        tools,
+                        n_ctx,
+                        allow_thinking,
+                        mtp,
+                        context_options,
+                    ) = load_config.expect("load_config set when no existing handle");
+                    match Self::load_and_store_worker(
+                        me,
+                        model_node,
+                        system_prompt,
+                        tools,
+                        n_ctx,
+                        allow_thinking,
+                        mtp,
+                        context_options,
+                    )
+                    .await
+                    {
+                        Ok(h) => h,
+                        Err(e) => {
+                            godot_error!("warmup() dropped: {}", e);
+                            emit_node.signals().worker_failed().emit(&e);
+                            return;
+                        }
+                    }
+                }
+            };
+            if let Err(e) = chat_handle.warmup().await {
+                godot_error!("Error during warmup: {}", e);
+            }
+            emit_node.emit_signal(&signal_name, &[]);
+            emit_node.signals().warmup_finished().emit();
+        });
+
+        signal
+    }
+
+    #[func]
+    /// Clears the chat history, keeping the system prompt and tools.
+    /// Returns a signal that fires once the worker has reset, so a following `ask()` is
+    /// guaranteed to start from a clean history:
+    ///
+    /// ```
+    /// await reset_context()
+    /// ```
+    fn reset_context(&mut self) -> Signal {
+        // decide on a unique name for the response signal, so an earlier reset can't fire
+        // it before this one is awaited
+        let signal_name = format!(
+            "reset_context_{}",
+            self.signal_counter.fetch_add(1, Ordering::Relaxed)
+        );
+        self.base_mut().add_user_signal(&signal_name);
+        let signal = Signal::from_object_signal(&self.base_mut(), &signal_name);
+        let mut emit_node = self.to_gd();
+
         // Clone the handle so we don't hold a reference to self
         let chat_handle = match self.chat_handle.as_ref() {
             Some(handle) => handle.clone(),
@@ -944,7 +1388,13 @@ impl NobodyWhoChat {
                 godot_error!(
                     "Attempted to reset context, but no worker is running. Doing nothing."
                 );
-                return;
+                // still fire the signal, so `await reset_context()` doesn't hang
+                godot::task::spawn(async move {
+                    let _ = wait_for_chat_signal_connect(&emit_node, &signal_name).await;
+                    emit_node.emit_signal(&signal_name, &[]);
+                    emit_node.signals().context_reset().emit();
+                });
+                return signal;
             }
         };
 
@@ -952,53 +1402,140 @@ impl NobodyWhoChat {
         let tools = self.tools.clone();
 
         godot::task::spawn(async move {
+            // reset before anything asked after this call, whether or not it is awaited
             match chat_handle.reset_chat(Some(system_prompt), tools).await {
                 Ok(()) => (),
                 Err(errmsg) => {
                     godot_error!("Error: {}", errmsg.to_string());
                 }
             }
+            // resetting without awaiting it is fine, so nothing connecting isn't an error
+            let _ = wait_for_chat_signal_connect(&emit_node, &signal_name).await;
+            emit_node.emit_signal(&signal_name, &[]);
+            emit_node.signals().context_reset().emit();
         });
-    }
-
-    #[func]
-    fn get_allow_thinking(&mut self) -> bool {
-        self.allow_thinking
-    }
-
-    #[func]
-    fn set_allow_thinking(&mut self, allow_thinking: bool) {
-        // always mutate local state
-        self.allow_thinking = allow_thinking;
 
-        // if worker is running, also inform that
-        if let Some(chat_handle) = self.chat_handle.as_ref() {
-            let handle_clone = chat_handle.clone();
-            godot::task::spawn(async move {
-                let result = handle_clone
-                    .set_template_variable("enable_thinking".to_string(), allow_thinking)
-                    .await;
-                if let Err(msg) = result {
-                    godot_warn!("Error setting allow_thinking: {}", msg);
-                }
-            });
-        }
+        signal
     }
 
     #[func]
-    fn get_chat_history(&mut self) -> Variant {
+    /// Gets the last reply in the chat history, or an empty string if there is none yet.
+    /// If a reply is being generated, it waits for it to finish. Handy for picking up a reply
+    /// whose signal you missed, e.g. after a scene change mid-generation.
+    ///
+    /// ```
+    /// var reply = await get_last_response()
+    /// ```
+    fn get_last_response(&mut self) -> Variant {
         // Clone the handle so we don't hold a reference to self
         let chat_handle = match self.chat_handle.as_ref() {
             Some(handle) => handle.clone(),
             None => {
-                godot_error!("Attempted to get chat history, but no worker is running. Doing nothing and returning nil.");
+                godot_error!("Attempted to get the last response, but no worker is running. Doing nothing and returning nil.");
                 return Variant::nil();
             }
         };
 
         // decide on a unique name for the response signal
         let signal_name = format!(
-            "get_chat_history_{}",
+            "get_last_response_{}",
+            self.signal_counter.fetch_add(1, Ordering::Relaxed)
+        );
+        self.base_mut().add_user_signal(&signal_name);
+
+        let mut emit_node = self.to_gd();
+        let signal_name_copy = signal_name.clone();
+        godot::task::spawn(async move {
+            let Ok(last_response) = chat_handle.last_response().await else {
+                error!("Chat worker died while waiting for get_last_response.");
+                emit_node.emit_signal(&signal_name_copy, &[]);
+                return;
+            };
+            let last_response = GString::from(last_response.unwrap_or_default().as_str());
+
+            // this potentially waits for 10 frames before giving up
+            if let Err(e) = wait_for_chat_signal_connect(&emit_node, &signal_name_copy).await {
+                godot_error!("Failed getting the last response: {}", e);
+                return;
+            }
+
+            emit_node.emit_signal(&signal_name_copy, &[Variant::from(last_response)]);
+        });
+
+        // returns signal, so that you can `var reply = await get_last_response()`
+        Variant::from(godot::builtin::Signal::from_object_signal(
+            &self.base_mut(),
+            &signal_name,
+        ))
+    }
+
+    #[func]
+    fn get_allow_thinking(&mut self) -> bool {
+        self.allow_thinking
+    }
+
+    #[func]
+    fn set_allow_thinking(&mut self, allow_thinking: bool) {
+        // always mutate local state
+        self.allow_thinking = allow_thinking;
+
+        // if worker is running, also inform that
+        if let Some(chat_handle) = self.chat_handle.as_ref() {
+            let handle_clone = chat_handle.clone();
+            godot::task::spawn(async move {
+                let result = handle_clone
+                    .set_template_variable("enable_thinking".to_string(), allow_thinking)
+                    .await;
+                if let Err(msg) = result {
+                    godot_warn!("Error setting allow_thinking: {}", msg);
+                }
+            });
+        }
+    }
+
+    #[func]
+    fn get_chat_history(&mut self) -> Variant {
+        self.chat_history_signal(None)
+    }
+
+    #[func]
+    /// Gets the chat history for showing to the player, e.g. as a transcript.
+    /// Tool calls and tool responses are left out, or with `collapse_tool_calls`, each round
+    /// of them becomes one assistant message with a line like
+    /// `Called get_weather({"city":"Paris"}): 18 degrees` per call.
+    /// Use `get_chat_history()` for a history to pass back to `set_chat_history()`.
+    ///
+    /// ```
+    /// var transcript = await get_display_history()
+    /// ```
+    fn get_display_history(
+        &mut self,
+        #[opt(default = false)] collapse_tool_calls: bool,
+    ) -> Variant {
+        self.chat_history_signal(Some(if collapse_tool_calls {
+            nobodywho::chat::ToolCallDisplay::Collapse
+        } else {
+            nobodywho::chat::ToolCallDisplay::Omit
+        }))
+    }
+
+    /// Signal for `get_chat_history`, or `get_display_history` when `display` is given.
+    fn chat_history_signal(
+        &mut self,
+        display: Option<nobodywho::chat::ToolCallDisplay>,
+    ) -> Variant {
+        // Clone the handle so we don't hold a reference to self
+        let chat_handle = match self.chat_handle.as_ref() {
+            Some(handle) => handle.clone(),
+            None => {
+                godot_error!("Attempted to get chat history, but no worker is running. Doing nothing and returning nil.");
+                return Variant::nil();
+            }
+        };
+
+        // decide on a unique name for the response signal
+        let signal_name = format!(
+            "get_chat_history_{}",
             self.signal_counter.fetch_add(1, Ordering::Relaxed)
         );
         self.base_mut().add_user_signal(&signal_name);
@@ -1012,6 +1549,10 @@ impl NobodyWhoChat {
                 emit_node.emit_signal(&signal_name_copy, &[]);
                 return;
             };
+            let chat_history = match display {
+                Some(tool_calls) => nobodywho::chat::display_history(&chat_history, tool_calls),
+                None => chat_history,
+            };
             let godot_dict_msgs: Array<VarDictionary> = messages_to_dictionaries(&chat_history);
             let godot_variant_array: Array<Variant> =
                 godot_dict_msgs.iter_shared().map(Variant::from).collect();
@@ -1204,6 +1745,66 @@ impl NobodyWhoChat {
     }
 
     #[func]
+    /// Render the chat history with the chat template, without generating anything.
+    /// Returns a Signal that resolves to the exact text the model reads.
+    /// Usage: `print(await render_prompt())`
+    fn render_prompt(&mut self) -> Variant {
+        let chat_handle = match self.chat_handle.as_ref() {
+            Some(handle) => handle.clone(),
+            None => {
+                godot_error!(
+                    "Attempted to render prompt, but no worker is running. Returning nil."
+                );
+                return Variant::nil();
+            }
+        };
+
+        let signal_name = format!(
+            "render_prompt_{}",
+            self.signal_counter.fetch_add(1, Ordering::Relaxed)
+        );
+        self.base_mut().add_user_signal(&signal_name);
+
+        let mut emit_node = self.to_gd();
+        let signal_name_copy = signal_name.clone();
+        godot::task::spawn(async move {
+            let prompt = match chat_handle.render_current_prompt().await {
+                Ok(prompt) => prompt,
+                Err(e) => {
+                    godot_error!("render_prompt() failed: {}", e);
+                    emit_node.emit_signal(&signal_name_copy, &[]);
+                    return;
+                }
+            };
+
+            match wait_for_chat_signal_connect(&emit_node, &signal_name_copy).await {
+                Ok(()) => (),
+                Err(e) => {
+                    godot_error!("render_prompt() signal connect failed: {}", e);
+                    return;
+                }
+            }
+
+            emit_node.emit_signal(
+                &signal_name_copy,
+                &[Variant::from(GString::from(prompt.as_str()))],
+            );
+        });
+
+        Variant::from(godot::builtin::Signal::from_object_signal(
+            &self.base_mut(),
+            &signal_name,
+        ))
+    }
+
+    #[func]
+    /// Replaces the chat history with `messages`.
+    /// Returns a signal that fires once the worker has applied the new history. Await it
+    /// before calling `ask()`, so the reply is generated from the new history:
+    ///
+    /// ```
+    /// await set_chat_history(messages)
+    /// ```
     fn set_chat_history(&mut self, messages: Array<Variant>) -> Variant {
         let chat_handle = match self.chat_handle.as_ref() {
             Some(handle) => handle.clone(),
@@ -1261,10 +1862,13 @@ impl NobodyWhoChat {
     ///
     /// The tool is a fully typed callable function on a godot object.
     /// The function should return a string.
-    /// All parameters should have type hints, and only primitive types are supported.
+    /// All parameters should have type hints. Supported types are `bool`, `int`, `float`, `String`,
+    /// `Array` (typed arrays like `Array[int]` constrain the elements too), `Dictionary`,
+    /// `Vector2` and `Vector3`.
     /// NobodyWho will use the type hints to constrain the generation, such that the function will
     /// only ever be called with the correct types.
-    /// Fancier types like lists, dictionaries, and classes are not (yet) supported.
+    /// Classes and other types are not (yet) supported.
+    /// Parameters with a default value are optional, the model may leave them out.
     ///
     /// If you need to specify more parameter constraints, see `add_tool_with_schema`.
     ///
@@ -1284,10 +1888,6 @@ impl NobodyWhoChat {
     ///     ask("What is two plus two?")
     /// ```
     fn add_tool(&mut self, callable: Callable, description: String) {
-        if self.chat_handle.is_some() {
-            godot_warn!("Worker already running. Tools won't be available until restart or reset");
-        }
-
         let json_schema = match json_schema_from_callable(&callable) {
             Ok(js) => js,
             Err(e) => {
@@ -1320,10 +1920,6 @@ impl NobodyWhoChat {
         #[opt(default = 0i64)] max_memory_bytes: i64,
         #[opt(default = 0i64)] max_recursion_depth: i64,
     ) {
-        if self.chat_handle.is_some() {
-            godot_warn!("Worker already running. Tools won't be available until restart or reset");
-        }
-
         let duration = if max_duration_secs <= 0 {
             None
         } else {
@@ -1342,6 +1938,7 @@ impl NobodyWhoChat {
 
         let tool = nobodywho::tool_calling::Tool::python(duration, memory, recursion);
         self.tools.push(tool);
+        self.apply_tools();
     }
 
     #[func]
@@ -1395,11 +1992,15 @@ impl NobodyWhoChat {
         description: String,
         json_schema: serde_json::Map<String, serde_json::Value>,
     ) {
-        // list of property names, preserving order of arguments from Callable
+        // list of property names and default values, preserving order of arguments from Callable
         let Some(properties) = json_schema
             .get("properties")
             .and_then(|v| v.as_object())
-            .map(|obj| obj.keys().cloned().collect::<Vec<String>>())
+            .map(|obj| {
+                obj.iter()
+                    .map(|(name, schema)| (name.clone(), schema.get("default").cloned()))
+                    .collect::<Vec<(String, Option<serde_json::Value>)>>()
+            })
         else {
             godot_error!("JSON Schema was malformed");
             return;
@@ -1410,6 +2011,18 @@ impl NobodyWhoChat {
             return;
         };
 
+        // godot types of the arguments, so vectors and typed arrays can be rebuilt from json
+        let arg_types: Vec<ToolArgType> = callable_method_info(&callable)
+            .map(|method_info| {
+                method_info
+                    .at("args")
+                    .to::<Array<VarDictionary>>()
+                    .iter_shared()
+                    .map(|arg| ToolArgType::from_arg_info(&arg))
+                    .collect()
+            })
+            .unwrap_or_default();
+
         // Wrap the callable to make it Send (we ensure thread-safe access via Mutex)
         use std::sync::{Arc, Mutex};
         let callable = Arc::new(Mutex::new(SendCallable(callable)));
@@ -1423,12 +2036,13 @@ impl NobodyWhoChat {
             };
 
             let mut args: Vec<Variant> = vec![];
-            for prop in properties.iter() {
-                let Some(val) = obj.get(prop.as_str()) else {
+            for (i, (prop, default)) in properties.iter().enumerate() {
+                let Some(val) = obj.get(prop.as_str()).or(default.as_ref()) else {
                     warn!("LLM passed bad arguments to tool. Missing argument {prop}");
                     return format!("Error: Missing argument {prop}");
                 };
-                args.push(json_to_godot(val));
+                let arg_type = arg_types.get(i).copied().unwrap_or(ToolArgType::Other);
+                args.push(arg_type.json_to_godot(val));
             }
 
             // Lock the callable for the duration of the call
@@ -1458,6 +2072,7 @@ impl NobodyWhoChat {
             std::sync::Arc::new(func),
         );
         self.tools.push(new_tool);
+        self.apply_tools();
     }
 
     #[func]
@@ -1479,23 +2094,15 @@ impl NobodyWhoChat {
 
         // remove from lsit
         self.tools.retain(|tool| tool.name != method_name);
+        self.apply_tools();
+    }
 
-        // Clone the handle so we don't hold a reference to self
-        let chat_handle = match self.chat_handle.as_ref() {
-            Some(handle) => handle.clone(),
-            None => {
-                godot_error!("Attempted remove tool, but no worker is running. Doing nothing and returning nil.");
-                return;
-            }
-        };
-
-        let new_tools = self.tools.clone();
-
-        godot::task::spawn(async move {
-            if let Err(err) = chat_handle.set_tools(new_tools).await {
-                godot_error!("Error: {}", err.to_string());
-            }
-        });
+    #[func]
+    /// Remove all tools, including the built-in Python and bash tools.
+    /// Like adding or removing a tool, this applies to a running chat right away.
+    fn clear_tools(&mut self) {
+        self.tools.clear();
+        self.apply_tools();
     }
 
     #[func]
@@ -1510,21 +2117,7 @@ impl NobodyWhoChat {
         }
 
         self.tools.retain(|tool| tool.name != tool_name);
-
-        let chat_handle = match self.chat_handle.as_ref() {
-            Some(handle) => handle.clone(),
-            None => {
-                godot_error!("Attempted remove_python_tool, but no worker is running.");
-                return;
-            }
-        };
-
-        let new_tools = self.tools.clone();
-        godot::task::spawn(async move {
-            if let Err(err) = chat_handle.set_tools(new_tools).await {
-                godot_error!("Error: {}", err.to_string());
-            }
-        });
+        self.apply_tools();
     }
 
     #[func]
@@ -1541,10 +2134,6 @@ impl NobodyWhoChat {
     ///     add_bash_tool(1000)   # 1000-command limit
     /// ```
     fn add_bash_tool(&mut self, #[opt(default = 0i64)] max_commands: i64) {
-        if self.chat_handle.is_some() {
-            godot_warn!("Worker already running. Tools won't be available until restart or reset");
-        }
-
         let max_commands = if max_commands <= 0 {
             None
         } else {
@@ -1553,6 +2142,7 @@ impl NobodyWhoChat {
 
         let tool = nobodywho::tool_calling::Tool::bash(max_commands);
         self.tools.push(tool);
+        self.apply_tools();
     }
 
     #[func]
@@ -1567,21 +2157,7 @@ impl NobodyWhoChat {
         }
 
         self.tools.retain(|tool| tool.name != tool_name);
-
-        let chat_handle = match self.chat_handle.as_ref() {
-            Some(handle) => handle.clone(),
-            None => {
-                godot_error!("Attempted remove_bash_tool, but no worker is running.");
-                return;
-            }
-        };
-
-        let new_tools = self.tools.clone();
-        godot::task::spawn(async move {
-            if let Err(err) = chat_handle.set_tools(new_tools).await {
-                godot_error!("Error: {}", err.to_string());
-            }
-        });
+        self.apply_tools();
     }
 
     #[signal]
@@ -1594,6 +2170,25 @@ impl NobodyWhoChat {
     /// Triggered when the LLM has finished generating the response. Returns the full response as a string.
     fn response_finished(response: GString);
 
+    #[signal]
+    /// Triggered when the LLM calls one of the chat's tools, right before the tool runs.
+    /// Returns the tool name and the arguments the model passed to it.
+    /// Useful for e.g. playing a "thinking" animation while the tool does its work.
+    fn tool_called(name: GString, arguments: VarDictionary);
+
+    #[signal]
+    /// Triggered when a tool called by the LLM has finished. Returns the tool name and the
+    /// result that is handed back to the LLM.
+    fn tool_returned(name: GString, result: GString);
+
+    #[signal]
+    /// Triggered when `warmup()` has finished reading the system prompt into the context.
+    fn warmup_finished();
+
+    #[signal]
+    /// Triggered when `reset_context()` has cleared the chat history.
+    fn context_reset();
+
     #[signal]
     /// Emitted once the worker has finished loading (including any model download) and is
     /// ready to accept `ask()` calls. Connect before calling `start_worker()` if you want
@@ -1627,113 +2222,589 @@ impl NobodyWhoChat {
         });
     }
 
-    /// Sets the sampler to use default sampling parameters.
-    /// This provides a balanced configuration suitable for most use cases.
-    #[func]
-    fn set_sampler_preset_default(&mut self) {
-        self.set_sampler_preset_impl(CoreSamplerConfig::default());
+    /// Sets the sampler to use default sampling parameters.
+    /// This provides a balanced configuration suitable for most use cases.
+    #[func]
+    fn set_sampler_preset_default(&mut self) {
+        self.set_sampler_preset_impl(CoreSamplerConfig::default());
+    }
+
+    /// Sets the sampler to use greedy sampling.
+    /// Always selects the most likely token at each step, resulting in deterministic output.
+    /// Use this for predictable, focused responses.
+    #[func]
+    fn set_sampler_preset_greedy(&mut self) {
+        self.set_sampler_preset_impl(SamplerPresets::greedy());
+    }
+
+    /// Sets the sampler to use top-k sampling.
+    /// Only considers the k most likely tokens at each step.
+    /// Lower values (e.g., 10-40) make output more focused, higher values more diverse.
+    #[func]
+    fn set_sampler_preset_top_k(&mut self, k: i32) {
+        self.set_sampler_preset_impl(SamplerPresets::top_k(k));
+    }
+
+    /// Sets the sampler to use top-p (nucleus) sampling.
+    /// Considers tokens until their cumulative probability reaches p.
+    /// Values like 0.9-0.95 provide a good balance between coherence and creativity.
+    #[func]
+    fn set_sampler_preset_top_p(&mut self, p: f32) {
+        self.set_sampler_preset_impl(SamplerPresets::top_p(p));
+    }
+
+    /// Sets the sampler to use temperature-based sampling.
+    /// Higher values (e.g., 0.8-1.2) increase randomness and creativity.
+    /// Lower values (e.g., 0.2-0.5) make output more focused and deterministic.
+    #[func]
+    fn set_sampler_preset_temperature(&mut self, temperature: f32) {
+        self.set_sampler_preset_impl(SamplerPresets::temperature(temperature));
+    }
+
+    /// Sets the sampler to use DRY (Don't Repeat Yourself) sampling.
+    /// Helps reduce repetitive text by penalizing recently generated tokens.
+    /// Useful for longer text generation where repetition is undesirable.
+    #[func]
+    fn set_sampler_preset_dry(&mut self) {
+        self.set_sampler_preset_impl(SamplerPresets::dry());
+    }
+
+    /// Constrains the model output to a JSON schema via llguidance.
+    ///
+    /// Prefer this over set_sampler_preset_json for new code.
+    #[func]
+    fn set_sampler_preset_constrain_with_json_schema(&mut self, schema: String) {
+        self.set_sampler_preset_impl(
+            nobodywho::sampler::SamplerPresets::constrain_with_json_schema(schema),
+        );
+    }
+
+    /// Constrains the model output to a regular expression via llguidance.
+    #[func]
+    fn set_sampler_preset_constrain_with_regex(&mut self, pattern: String) {
+        self.set_sampler_preset_impl(nobodywho::sampler::SamplerPresets::constrain_with_regex(
+            pattern,
+        ));
+    }
+
+    /// Constrains the model output using a Lark context-free grammar via llguidance.
+    #[func]
+    fn set_sampler_preset_constrain_with_grammar(&mut self, grammar: String) {
+        self.set_sampler_preset_impl(nobodywho::sampler::SamplerPresets::constrain_with_grammar(
+            grammar,
+        ));
+    }
+
+    /// Constrain output to valid JSON (any structure) using GBNF.
+    ///
+    /// For schema-validated JSON, use set_sampler_preset_constrain_with_json_schema() instead.
+    #[func]
+    #[allow(deprecated)]
+    fn set_sampler_preset_json(&mut self) {
+        self.set_sampler_preset_impl(SamplerPresets::json());
+    }
+
+    /// Deprecated: Use set_sampler_preset_constrain_with_grammar() instead.
+    #[func]
+    #[deprecated(note = "Use set_sampler_preset_constrain_with_grammar() instead")]
+    fn set_sampler_preset_grammar(&mut self, grammar: String) {
+        self.set_sampler_preset_impl(SamplerPresets::grammar(grammar));
+    }
+
+    /// Sets a custom sampler configuration built with `NobodyWhoSamplerBuilder`.
+    ///
+    /// Use this when the `set_sampler_preset_*` methods don't cover your
+    /// use case (e.g. you want to chain multiple shift steps, set a seed,
+    /// or use Mirostat). See `NobodyWhoSamplerBuilder` for the available steps.
+    ///
+    /// ```gdscript
+    /// var cfg = NobodyWhoSamplerBuilder.new() \
+    ///     .top_k(40) \
+    ///     .temperature(0.8) \
+    ///     .seed(42) \
+    ///     .dist()
+    /// chat.set_sampler_config(cfg)
+    /// ```
+    #[func]
+    fn set_sampler_config(&mut self, config: Gd<NobodyWhoSamplerConfig>) {
+        let inner = config.bind().inner.clone();
+        self.set_sampler_preset_impl(inner);
+    }
+}
+
+#[derive(GodotClass)]
+#[class(base=Node)]
+/// NobodyWhoCompletion continues a piece of text, without applying any chat template.
+///
+/// Use it with base models, which often don't have a chat template, or whenever you want plain
+/// text continuation rather than a conversation. Every call to `complete()` is independent:
+/// nothing is remembered between calls.
+///
+/// Example:
+///
+/// ```
+/// extends NobodyWhoCompletion
+///
+/// func _ready():
+///     self.model_node = get_node("../BaseModel")
+///     self.stop_words = PackedStringArray(["\n"])
+///
+///     complete("The tavern keeper looked up and said:")
+///     var continuation = await response_finished
+///     print(continuation)
+/// ```
+///
+struct NobodyWhoCompletion {
+    #[export]
+    /// The model node for the completion.
+    model_node: Option<Gd<NobodyWhoModel>>,
+
+    #[export]
+    /// The maximum number of tokens of the prompt and the completion together.
+    context_length: u32,
+
+    #[export]
+    /// Generation stops right before the first occurrence of any of these strings.
+    /// The stop word itself is not included in the response.
+    stop_words: PackedStringArray,
+
+    // internal state
+    completion_handle: Option<nobodywho::completion::CompletionHandleAsync>,
+    sampler: CoreSamplerConfig,
+    base: Base<Node>,
+}
+
+#[godot_api]
+impl INode for NobodyWhoCompletion {
+    fn init(base: Base<Node>) -> Self {
+        Self {
+            model_node: None,
+            context_length: 4096,
+            stop_words: PackedStringArray::new(),
+            completion_handle: None,
+            sampler: CoreSamplerConfig::default(),
+            base,
+        }
+    }
+}
+
+#[godot_api]
+impl NobodyWhoCompletion {
+    #[signal]
+    /// Triggered when a new token is generated. Returns the new token as a string.
+    fn response_updated(new_token: GString);
+
+    #[signal]
+    /// Triggered when the completion has finished. Returns the full generated text as a string.
+    fn response_finished(response: GString);
+
+    #[signal]
+    /// Emitted once the worker has finished loading (including any model download) and is
+    /// ready to accept `complete()` calls.
+    fn worker_started();
+
+    #[signal]
+    /// Emitted if loading the model (or setting up the worker) failed, or if a completion
+    /// failed. The payload is a human-readable error message.
+    fn worker_failed(error: GString);
+
+    /// Load the model and create the completion worker. `yield_now()` ensures the
+    /// outer `start_worker(&mut self)` borrow is released before `me.bind_mut()` runs;
+    /// see the NobodyWhoChat::load_and_store_worker docstring for the full rationale.
+    async fn load_and_store_worker(
+        mut me: Gd<Self>,
+        model_node: Gd<NobodyWhoModel>,
+        n_ctx: u32,
+    ) -> Result<nobodywho::completion::CompletionHandleAsync, GString> {
+        tokio::task::yield_now().await;
+
+        let model = NobodyWhoModel::load_model_detached(model_node)
+            .await
+            .map_err(|e| GString::from(nobodywho::render_miette(&e).as_str()))?;
+
+        let handle = nobodywho::completion::CompletionHandleAsync::new(model, n_ctx)
+            .map_err(|e| GString::from(nobodywho::render_miette(&e).as_str()))?;
+
+        let mut b = me.bind_mut();
+        if let Some(existing) = &b.completion_handle {
+            Ok(existing.clone())
+        } else {
+            b.completion_handle = Some(handle.clone());
+            Ok(handle)
+        }
+    }
+
+    #[func]
+    /// Starts the completion worker asynchronously: loads (or downloads) the model on a
+    /// background thread, then creates the worker.
+    ///
+    /// **Returns immediately.** Connect to `worker_started` to know when the worker is
+    /// ready, or `worker_failed(error)` for load errors. Calls to `complete()` before the
+    /// worker is ready are queued and dispatched once loading completes.
+    fn start_worker(&mut self) {
+        if self.completion_handle.is_some() {
+            self.signals().worker_started().emit();
+            return;
+        }
+
+        let Some(model_node) = self.model_node.clone() else {
+            let err = GString::from("Model node was not set");
+            godot_error!("Error starting worker: {}", err);
+            self.signals().worker_failed().emit(&err);
+            return;
+        };
+        let n_ctx = self.context_length;
+
+        let me = self.to_gd();
+        godot::task::spawn(async move {
+            let me_emit = me.clone();
+            match Self::load_and_store_worker(me, model_node, n_ctx).await {
+                Ok(_) => me_emit.signals().worker_started().emit(),
+                Err(e) => {
+                    godot_error!("Error running model: {}", e);
+                    me_emit.signals().worker_failed().emit(&e);
+                }
+            }
+        });
+    }
+
+    #[func]
+    /// Continues `prompt`, which is passed to the model as-is.
+    /// Listen on the `response_updated` and `response_finished` signals to get the generated text.
+    ///
+    /// If the worker has not been started yet, `complete` will auto-start it and queue the
+    /// prompt until loading completes.
+    fn complete(&mut self, prompt: String) {
+        let existing_handle = self.completion_handle.clone();
+        let model_node = if existing_handle.is_none() {
+            godot_warn!("Worker was not started yet, starting now... You may want to call `start_worker()` ahead of time to avoid waiting.");
+            match self.model_node.clone() {
+                Some(n) => Some(n),
+                None => {
+                    let err = GString::from("Model node was not set");
+                    godot_error!("complete() dropped: {}", err);
+                    self.signals().worker_failed().emit(&err);
+                    return;
+                }
+            }
+        } else {
+            None
+        };
+        let n_ctx = self.context_length;
+        let sampler = self.sampler.clone();
+        let stop_words: Vec<String> = self
+            .stop_words
+            .as_slice()
+            .iter()
+            .map(|w| w.to_string())
+            .collect();
+
+        let me = self.to_gd();
+        let emit_node = me.clone();
+        godot::task::spawn(async move {
+            let completion_handle = match existing_handle {
+                Some(h) => h,
+                None => {
+                    let model_node = model_node.expect("model_node set when no existing handle");
+                    match Self::load_and_store_worker(me, model_node, n_ctx).await {
+                        Ok(h) => h,
+                        Err(e) => {
+                            godot_error!("complete() dropped: {}", e);
+                            emit_node.signals().worker_failed().emit(&e);
+                            return;
+                        }
+                    }
+                }
+            };
+            let mut generation_channel =
+                completion_handle.complete_channel(prompt, sampler, stop_words);
+            while let Some(out) = generation_channel.recv().await {
+                match out {
+                    nobodywho::llm::WriteOutput::Token(tok) => emit_node
+                        .signals()
+                        .response_updated()
+                        .emit(&GString::from(tok.as_str())),
+                    nobodywho::llm::WriteOutput::Done(resp) => emit_node
+                        .signals()
+                        .response_finished()
+                        .emit(&GString::from(resp.as_str())),
+                    nobodywho::llm::WriteOutput::Error(e) => {
+                        let errmsg = nobodywho::render_miette(e.as_ref());
+                        godot_error!("Error during completion: {}", errmsg);
+                        emit_node.signals().worker_failed().emit(&errmsg);
+                        return;
+                    }
+                }
+            }
+        });
+    }
+
+    #[func]
+    fn stop_generation(&mut self) {
+        if let Some(completion_handle) = &self.completion_handle {
+            completion_handle.stop_generation();
+        } else {
+            godot_warn!("Attempted to stop generation, but no worker is running. Doing nothing.");
+        }
+    }
+
+    #[func]
+    /// Sets the sampler configuration used for the following completions,
+    /// built with `NobodyWhoSamplerBuilder`.
+    fn set_sampler_config(&mut self, config: Gd<NobodyWhoSamplerConfig>) {
+        self.sampler = config.bind().inner.clone();
+    }
+
+    #[func]
+    /// Sets the (global) log level of NobodyWho.
+    /// Valid arguments are "TRACE", "DEBUG", "INFO", "WARN", and "ERROR".
+    fn set_log_level(level: String) {
+        set_log_level(&level);
+    }
+}
+
+#[derive(GodotClass)]
+#[class(base=Node)]
+/// NobodyWhoStructured generates data that matches a JSON schema, e.g. quests or item stats.
+///
+/// Unlike NobodyWhoChat there is no conversation: every call to `generate()` starts from a
+/// clean history, and the result arrives already parsed, as a Dictionary.
+///
+/// Example:
+///
+/// ```
+/// extends NobodyWhoStructured
+///
+/// func _ready():
+///     self.model_node = get_node("../ChatModel")
+///     self.system_prompt = "You design quests for a fantasy RPG."
+///
+///     var schema = '{"type": "object", "properties": {"title": {"type": "string"}, "reward_gold": {"type": "integer"}}, "required": ["title", "reward_gold"]}'
+///     var quest = await generate("Make a quest about a lost cat.", schema)
+///     print(quest["title"], " pays ", quest["reward_gold"], " gold")
+/// ```
+///
+struct NobodyWhoStructured {
+    #[export]
+    /// The model node used for generation.
+    model_node: Option<Gd<NobodyWhoModel>>,
+
+    #[export]
+    /// Instructions that apply to every generation. Leave empty for none.
+    system_prompt: GString,
+
+    #[export]
+    /// The maximum number of tokens of the prompt and the generated data together.
+    context_length: u32,
+
+    // internal state
+    chat_handle: Option<nobodywho::chat::ChatHandleAsync>,
+    /// Held for a whole generation, so queued generations never see each other's turns.
+    generation_lock: Arc<tokio::sync::Mutex<()>>,
+    base: Base<Node>,
+}
+
+#[godot_api]
+impl INode for NobodyWhoStructured {
+    fn init(base: Base<Node>) -> Self {
+        Self {
+            model_node: None,
+            system_prompt: GString::new(),
+            context_length: 4096,
+            chat_handle: None,
+            generation_lock: Arc::new(tokio::sync::Mutex::new(())),
+            base,
+        }
+    }
+}
+
+#[godot_api]
+impl NobodyWhoStructured {
+    #[signal]
+    /// Triggered when a generation has finished. Returns the generated data, parsed into a Dictionary.
+    fn generated(result: VarDictionary);
+
+    #[signal]
+    /// Emitted once the worker has finished loading (including any model download) and is
+    /// ready to accept `generate()` calls.
+    fn worker_started();
+
+    #[signal]
+    /// Emitted if loading the model (or setting up the worker) failed, or if a generation
+    /// failed. The payload is a human-readable error message.
+    fn worker_failed(error: GString);
+
+    /// Load the model and create the chat worker. `yield_now()` ensures the
+    /// outer `start_worker(&mut self)` borrow is released before `me.bind_mut()` runs;
+    /// see the NobodyWhoChat::load_and_store_worker docstring for the full rationale.
+    async fn load_and_store_worker(
+        mut me: Gd<Self>,
+        model_node: Gd<NobodyWhoModel>,
+        system_prompt: String,
+        n_ctx: u32,
+    ) -> Result<nobodywho::chat::ChatHandleAsync, GString> {
+        tokio::task::yield_now().await;
+
+        let model = NobodyWhoModel::load_model_detached(model_node)
+            .await
+            .map_err(|e| GString::from(nobodywho::render_miette(&e).as_str()))?;
+
+        // thinking would have to fit the schema too, so it is turned off
+        let mut template_variables = HashMap::new();
+        template_variables.insert("enable_thinking".to_string(), false);
+        let handle = nobodywho::chat::ChatHandleAsync::new(
+            model,
+            ChatConfig {
+                system_prompt: (!system_prompt.is_empty()).then_some(system_prompt),
+                n_ctx,
+                template_variables,
+                ..Default::default()
+            },
+        )
+        .map_err(|e| GString::from(e.to_string().as_str()))?;
+
+        let mut b = me.bind_mut();
+        if let Some(existing) = &b.chat_handle {
+            Ok(existing.clone())
+        } else {
+            b.chat_handle = Some(handle.clone());
+            Ok(handle)
+        }
     }
 
-    /// Sets the sampler to use greedy sampling.
-    /// Always selects the most likely token at each step, resulting in deterministic output.
-    /// Use this for predictable, focused responses.
     #[func]
-    fn set_sampler_preset_greedy(&mut self) {
-        self.set_sampler_preset_impl(SamplerPresets::greedy());
-    }
+    /// Starts the worker asynchronously: loads (or downloads) the model on a
+    /// background thread, then creates the worker.
+    ///
+    /// **Returns immediately.** Connect to `worker_started` to know when the worker is
+    /// ready, or `worker_failed(error)` for load errors. Calls to `generate()` before the
+    /// worker is ready are queued and dispatched once loading completes.
+    fn start_worker(&mut self) {
+        if self.chat_handle.is_some() {
+            self.signals().worker_started().emit();
+            return;
+        }
 
-    /// Sets the sampler to use top-k sampling.
-    /// Only considers the k most likely tokens at each step.
-    /// Lower values (e.g., 10-40) make output more focused, higher values more diverse.
-    #[func]
-    fn set_sampler_preset_top_k(&mut self, k: i32) {
-        self.set_sampler_preset_impl(SamplerPresets::top_k(k));
-    }
+        let Some(model_node) = self.model_node.clone() else {
+            let err = GString::from("Model node was not set");
+            godot_error!("Error starting worker: {}", err);
+            self.signals().worker_failed().emit(&err);
+            return;
+        };
+        let system_prompt = self.system_prompt.to_string();
+        let n_ctx = self.context_length;
 
-    /// Sets the sampler to use top-p (nucleus) sampling.
-    /// Considers tokens until their cumulative probability reaches p.
-    /// Values like 0.9-0.95 provide a good balance between coherence and creativity.
-    #[func]
-    fn set_sampler_preset_top_p(&mut self, p: f32) {
-        self.set_sampler_preset_impl(SamplerPresets::top_p(p));
+        let me = self.to_gd();
+        godot::task::spawn(async move {
+            let me_emit = me.clone();
+            match Self::load_and_store_worker(me, model_node, system_prompt, n_ctx).await {
+                Ok(_) => me_emit.signals().worker_started().emit(),
+                Err(e) => {
+                    godot_error!("Error running model: {}", e);
+                    me_emit.signals().worker_failed().emit(&e);
+                }
+            }
+        });
     }
 
-    /// Sets the sampler to use temperature-based sampling.
-    /// Higher values (e.g., 0.8-1.2) increase randomness and creativity.
-    /// Lower values (e.g., 0.2-0.5) make output more focused and deterministic.
     #[func]
-    fn set_sampler_preset_temperature(&mut self, temperature: f32) {
-        self.set_sampler_preset_impl(SamplerPresets::temperature(temperature));
-    }
+    /// Generates data matching `json_schema` from `prompt`. This will return a signal that you
+    /// can use to wait for the result, a Dictionary with the parsed JSON.
+    /// The schema's root must be an object.
+    ///
+    /// Every generation starts from a clean history. Calls made while a generation is running
+    /// are queued. An invalid schema is reported as an error and nothing is generated.
+    fn generate(&mut self, prompt: String, json_schema: String) -> Signal {
+        let signal = Signal::from_object_signal(&self.base_mut(), "generated");
 
-    /// Sets the sampler to use DRY (Don't Repeat Yourself) sampling.
-    /// Helps reduce repetitive text by penalizing recently generated tokens.
-    /// Useful for longer text generation where repetition is undesirable.
-    #[func]
-    fn set_sampler_preset_dry(&mut self) {
-        self.set_sampler_preset_impl(SamplerPresets::dry());
-    }
+        let grammar = match nobodywho::gbnf::json::json_schema_to_grammar(json_schema, "root") {
+            Ok(grammar) => grammar,
+            Err(e) => {
+                godot_error!("generate() got an invalid JSON schema: {}", e);
+                return signal;
+            }
+        };
 
-    /// Constrains the model output to a JSON schema via llguidance.
-    ///
-    /// Prefer this over set_sampler_preset_json for new code.
-    #[func]
-    fn set_sampler_preset_constrain_with_json_schema(&mut self, schema: String) {
-        self.set_sampler_preset_impl(
-            nobodywho::sampler::SamplerPresets::constrain_with_json_schema(schema),
-        );
-    }
+        let existing_handle = self.chat_handle.clone();
+        let model_node = if existing_handle.is_none() {
+            godot_warn!("Worker was not started yet, starting now... You may want to call `start_worker()` ahead of time to avoid waiting.");
+            match self.model_node.clone() {
+                Some(n) => Some(n),
+                None => {
+                    let err = GString::from("Model node was not set");
+                    godot_error!("generate() dropped: {}", err);
+                    self.signals().worker_failed().emit(&err);
+                    return signal;
+                }
+            }
+        } else {
+            None
+        };
+        let system_prompt = self.system_prompt.to_string();
+        let n_ctx = self.context_length;
+        let generation_lock = self.generation_lock.clone();
 
-    /// Constrains the model output to a regular expression via llguidance.
-    #[func]
-    fn set_sampler_preset_constrain_with_regex(&mut self, pattern: String) {
-        self.set_sampler_preset_impl(nobodywho::sampler::SamplerPresets::constrain_with_regex(
-            pattern,
-        ));
-    }
+        let me = self.to_gd();
+        let emit_node = me.clone();
+        godot::task::spawn(async move {
+            let chat_handle = match existing_handle {
+                Some(h) => h,
+                None => {
+                    let model_node = model_node.expect("model_node set when no existing handle");
+                    match Self::load_and_store_worker(me, model_node, system_prompt, n_ctx).await {
+                        Ok(h) => h,
+                        Err(e) => {
+                            godot_error!("generate() dropped: {}", e);
+                            emit_node.signals().worker_failed().emit(&e);
+                            return;
+                        }
+                    }
+                }
+            };
 
-    /// Constrains the model output using a Lark context-free grammar via llguidance.
-    #[func]
-    fn set_sampler_preset_constrain_with_grammar(&mut self, grammar: String) {
-        self.set_sampler_preset_impl(nobodywho::sampler::SamplerPresets::constrain_with_grammar(
-            grammar,
-        ));
-    }
+            let _guard = generation_lock.lock().await;
+            let result = async {
+                chat_handle
+                    .reset_history()
+                    .await
+                    .map_err(|e| e.to_string())?;
+                let response = chat_handle
+                    .ask_with_grammar(prompt, grammar)
+                    .completed()
+                    .await
+                    .map_err(|e| nobodywho::render_miette(&e))?;
+                match serde_json::from_str(&response) {
+                    Ok(value @ serde_json::Value::Object(_)) => {
+                        Ok(json_to_godot(&value).to::<VarDictionary>())
+                    }
+                    Ok(_) => Err("The schema's root must be an object".to_string()),
+                    Err(e) => Err(format!("Generated data is not valid JSON: {e}")),
+                }
+            }
+            .await;
 
-    /// Constrain output to valid JSON (any structure) using GBNF.
-    ///
-    /// For schema-validated JSON, use set_sampler_preset_constrain_with_json_schema() instead.
-    #[func]
-    #[allow(deprecated)]
-    fn set_sampler_preset_json(&mut self) {
-        self.set_sampler_preset_impl(SamplerPresets::json());
-    }
+            match result {
+                Ok(dict) => emit_node.signals().generated().emit(&dict),
+                Err(e) => {
+                    godot_error!("Error during generation: {}", e);
+                    emit_node
+                        .signals()
+                        .worker_failed()
+                        .emit(&GString::from(e.as_str()));
+                }
+            }
+        });
 
-    /// Deprecated: Use set_sampler_preset_constrain_with_grammar() instead.
-    #[func]
-    #[deprecated(note = "Use set_sampler_preset_constrain_with_grammar() instead")]
-    fn set_sampler_preset_grammar(&mut self, grammar: String) {
-        self.set_sampler_preset_impl(SamplerPresets::grammar(grammar));
+        signal
     }
 
-    /// Sets a custom sampler configuration built with `NobodyWhoSamplerBuilder`.
-    ///
-    /// Use this when the `set_sampler_preset_*` methods don't cover your
-    /// use case (e.g. you want to chain multiple shift steps, set a seed,
-    /// or use Mirostat). See `NobodyWhoSamplerBuilder` for the available steps.
-    ///
-    /// ```gdscript
-    /// var cfg = NobodyWhoSamplerBuilder.new() \
-    ///     .top_k(40) \
-    ///     .temperature(0.8) \
-    ///     .seed(42) \
-    ///     .dist()
-    /// chat.set_sampler_config(cfg)
-    /// ```
     #[func]
-    fn set_sampler_config(&mut self, config: Gd<NobodyWhoSamplerConfig>) {
-        let inner = config.bind().inner.clone();
-        self.set_sampler_preset_impl(inner);
+    fn stop_generation(&mut self) {
+        if let Some(chat_handle) = &self.chat_handle {
+            chat_handle.stop_generation();
+        } else {
+            godot_warn!("Attempted to stop generation, but no worker is running. Doing nothing.");
+        }
     }
 }
 
@@ -1857,6 +2928,10 @@ impl NobodyWhoSamplerBuilder {
     }
 
     /// DRY (Don't Repeat Yourself) penalty to reduce repetitive output.
+    /// `multiplier` sets the strength (0.0 disables, 0.8 is recommended), `base` how fast the
+    /// penalty grows with the length of a repeat, `allowed_length` how many repeated tokens
+    /// go unpenalized, and `penalty_last_n` how far back to look (-1 = whole context).
+    /// Repeats never span one of the `seq_breakers`; an empty array uses newline, `:`, `"` and `*`.
     #[func]
     fn dry(
         &mut self,
@@ -1881,6 +2956,13 @@ impl NobodyWhoSamplerBuilder {
         self.to_gd()
     }
 
+    /// DRY with llama.cpp's recommended settings, same as `dry(0.8, 1.75, 2, -1, [])`.
+    #[func]
+    fn dry_default(&mut self) -> Gd<NobodyWhoSamplerBuilder> {
+        self.inner = self.inner.clone().shift(ShiftStep::dry_default());
+        self.to_gd()
+    }
+
     /// Repetition penalties. `penalty_repeat` = 1.0 disables; >1.0 penalizes
     /// recently seen tokens. `penalty_freq` scales with token occurrence count.
     /// `penalty_present` is a flat penalty for any token that appeared before.
@@ -1902,6 +2984,26 @@ impl NobodyWhoSamplerBuilder {
         self.to_gd()
     }
 
+    /// Constrain output with a GBNF grammar, starting from the rule named `root`.
+    /// If `trigger_on` is non-empty the grammar only kicks in once the model generates that
+    /// text, so it can write freely first (e.g. reason, then give a structured answer).
+    /// The grammar must then match the trigger text too. Pass `""` to constrain from the start.
+    #[func]
+    fn grammar(
+        &mut self,
+        grammar: String,
+        trigger_on: String,
+        root: String,
+    ) -> Gd<NobodyWhoSamplerBuilder> {
+        let trigger_on = Some(trigger_on).filter(|t| !t.is_empty());
+        self.inner = self.inner.clone().shift(ShiftStep::Grammar {
+            grammar,
+            trigger_on,
+            root,
+        });
+        self.to_gd()
+    }
+
     /// Set the RNG seed used by random samplers (`dist`, `mirostat_v1`,
     /// `mirostat_v2`, and the `xtc` shift step). `greedy` ignores it.
     /// If unset, a default seed is used.
@@ -2111,10 +3213,110 @@ fn godot_to_json(value: &Variant) -> serde_json::Value {
     }
 }
 
-fn json_schema_from_callable(
-    callable: &Callable,
-) -> Result<serde_json::Map<String, serde_json::Value>, String> {
-    // find method metadata
+/// The godot type of a tool argument, as far as it matters for converting json back.
+#[derive(Debug, Clone, Copy)]
+enum ToolArgType {
+    Vector2,
+    Vector3,
+    /// `Array[T]`, holding the element type
+    TypedArray(VariantType),
+    Other,
+}
+
+impl ToolArgType {
+    fn from_arg_info(arg: &VarDictionary) -> Self {
+        match arg.at("type").to::<VariantType>() {
+            VariantType::VECTOR2 => Self::Vector2,
+            VariantType::VECTOR3 => Self::Vector3,
+            VariantType::ARRAY => array_element_type(arg)
+                .map(Self::TypedArray)
+                .unwrap_or(Self::Other),
+            _ => Self::Other,
+        }
+    }
+
+    /// Like `json_to_godot`, but builds vectors and typed arrays where the function expects them.
+    /// Falls back to `json_to_godot` if the value doesn't fit.
+    fn json_to_godot(self, value: &serde_json::Value) -> Variant {
+        let converted = value.as_array().and_then(|arr| match self {
+            Self::Vector2 => match arr.as_slice() {
+                [x, y] => Some(Variant::from(Vector2::new(
+                    x.as_f64()? as f32,
+                    y.as_f64()? as f32,
+                ))),
+                _ => None,
+            },
+            Self::Vector3 => match arr.as_slice() {
+                [x, y, z] => Some(Variant::from(Vector3::new(
+                    x.as_f64()? as f32,
+                    y.as_f64()? as f32,
+                    z.as_f64()? as f32,
+                ))),
+                _ => None,
+            },
+            Self::TypedArray(VariantType::BOOL) => arr
+                .iter()
+                .map(|v| v.as_bool())
+                .collect::<Option<Array<bool>>>()
+                .map(Variant::from),
+            Self::TypedArray(VariantType::INT) => arr
+                .iter()
+                .map(|v| v.as_i64())
+                .collect::<Option<Array<i64>>>()
+                .map(Variant::from),
+            Self::TypedArray(VariantType::FLOAT) => arr
+                .iter()
+                .map(|v| v.as_f64())
+                .collect::<Option<Array<f64>>>()
+                .map(Variant::from),
+            Self::TypedArray(VariantType::STRING) => arr
+                .iter()
+                .map(|v| v.as_str().map(GString::from))
+                .collect::<Option<Array<GString>>>()
+                .map(Variant::from),
+            _ => None,
+        });
+        converted.unwrap_or_else(|| json_to_godot(value))
+    }
+}
+
+/// Element type of an `Array[T]` argument, if it is typed with a type we can map to json.
+fn array_element_type(arg: &VarDictionary) -> Option<VariantType> {
+    let hint: i64 = arg.get("hint")?.to();
+    if hint != godot::global::PropertyHint::ARRAY_TYPE.ord() as i64 {
+        return None;
+    }
+    match arg.get("hint_string")?.to::<String>().as_str() {
+        "bool" => Some(VariantType::BOOL),
+        "int" => Some(VariantType::INT),
+        "float" => Some(VariantType::FLOAT),
+        "String" => Some(VariantType::STRING),
+        _ => None,
+    }
+}
+
+/// Json schema type name for the primitive godot types.
+fn json_schema_type_name(variant_type: VariantType) -> Option<&'static str> {
+    match variant_type {
+        VariantType::BOOL => Some("boolean"),
+        VariantType::INT => Some("integer"),
+        VariantType::FLOAT => Some("number"),
+        VariantType::STRING => Some("string"),
+        _ => None,
+    }
+}
+
+/// Fixed-length array of numbers, used for the vector types.
+fn number_tuple_schema(len: usize) -> serde_json::Value {
+    serde_json::json!({
+        "type": "array",
+        "prefixItems": vec![serde_json::json!({ "type": "number" }); len],
+        "items": false,
+    })
+}
+
+/// Method metadata (name, args, default_args, ...) of the function behind `callable`.
+fn callable_method_info(callable: &Callable) -> Result<VarDictionary, String> {
     let method_name = callable.method_name().ok_or("Error adding tool: Could not get method name for callable. Did you pass in an anonymous function?".to_string())?;
     let method_obj = callable.object().ok_or("Could not find object for callable. Anonymous functions and static methods are not supported.".to_string())?;
     let method_info = method_obj
@@ -2122,35 +3324,51 @@ fn json_schema_from_callable(
         .iter_shared()
         // XXX: I expect that this bit is pretty slow. But it works for now...
         .find(|dict| dict.at("name").to::<String>() == method_name.to_string());
-    let method_info = method_info.ok_or("Could not find method on this object. Is the method you passed defined on the NobodyWhoChat script?".to_string())?;
+    method_info.ok_or("Could not find method on this object. Is the method you passed defined on the NobodyWhoChat script?".to_string())
+}
+
+fn json_schema_from_callable(
+    callable: &Callable,
+) -> Result<serde_json::Map<String, serde_json::Value>, String> {
+    let method_name = callable.method_name().ok_or("Error adding tool: Could not get method name for callable. Did you pass in an anonymous function?".to_string())?;
+    let method_info = callable_method_info(callable)?;
     let method_args: Array<VarDictionary> = method_info.at("args").to();
+    let default_args: Array<Variant> = method_info
+        .get("default_args")
+        .map(|defaults| defaults.to())
+        .unwrap_or_default();
+    // default values belong to the last arguments
+    let first_optional = method_args.len().saturating_sub(default_args.len());
 
     // start building json schema
     let mut properties = serde_json::Map::new();
     let mut required = vec![];
 
-    for arg in method_args.iter_shared() {
+    for (i, arg) in method_args.iter_shared().enumerate() {
         let arg_name: String = arg.at("name").to();
         let arg_type: VariantType = arg.at("type").to();
-        let arg_type_json_schema_name: &str = match arg_type {
+        let mut property = match arg_type {
             VariantType::NIL => return Err(format!("Error adding tool {method_name}: arguments must all have type hints. Argument '{arg_name}' does not have a type hint.")),
-            VariantType::BOOL => "boolean",
-            VariantType::INT => "integer",
-            VariantType::FLOAT => "number",
-            VariantType::STRING => "string",
-            VariantType::ARRAY => "array",
-            // TODO: more types. E.g. Object, Vector types, Array types, Dictionary
-            _ => {
-                return Err(format!("Error adding tool {method_name} - Unsupported type for argument '{arg_name}': {arg_type:?}"));
-            }
+            VariantType::ARRAY => match array_element_type(&arg).and_then(json_schema_type_name) {
+                Some(item_type) => serde_json::json!({ "type": "array", "items": { "type": item_type } }),
+                None => serde_json::json!({ "type": "array" }),
+            },
+            VariantType::DICTIONARY => serde_json::json!({ "type": "object" }),
+            VariantType::VECTOR2 => number_tuple_schema(2),
+            VariantType::VECTOR3 => number_tuple_schema(3),
+            // TODO: more types. E.g. Object, integer vectors
+            _ => match json_schema_type_name(arg_type) {
+                Some(type_name) => serde_json::json!({ "type": type_name }),
+                None => return Err(format!("Error adding tool {method_name} - Unsupported type for argument '{arg_name}': {arg_type:?}")),
+            },
         };
-
-        properties.insert(
-            arg_name.clone(),
-            serde_json::json!({ "type": arg_type_json_schema_name }),
-        );
-        // TODO: can we make arguments with default values not required?
-        required.push(serde_json::Value::String(arg_name));
+        if i >= first_optional {
+            // arguments with a default value may be left out by the model
+            property["default"] = godot_to_json(&default_args.at(i - first_optional));
+        } else {
+            required.push(serde_json::Value::String(arg_name.clone()));
+        }
+        properties.insert(arg_name, property);
     }
 
     let mut result = serde_json::Map::new();
@@ -2444,6 +3662,17 @@ struct NobodyWhoEncoder {
     #[export]
     /// The model node for the encoder.
     model_node: Option<Gd<NobodyWhoModel>>,
+
+    #[export]
+    /// Number of threads used for encoding. 0 uses all available CPU cores.
+    /// Only affects inference on the CPU.
+    n_threads: u32,
+
+    #[export]
+    /// Number of threads used to read text in batches. 0 uses all available CPU cores.
+    /// Only affects inference on the CPU.
+    n_threads_batch: u32,
+
     encoder_handle: Option<nobodywho::encoder::EncoderAsync>,
     base: Base<Node>,
 }
@@ -2453,6 +3682,8 @@ impl INode for NobodyWhoEncoder {
     fn init(base: Base<Node>) -> Self {
         Self {
             model_node: None,
+            n_threads: 0,
+            n_threads_batch: 0,
             encoder_handle: None,
             base,
         }
@@ -2474,12 +3705,21 @@ impl NobodyWhoEncoder {
     /// Emitted if loading the model (or setting up the encoder worker) failed.
     fn worker_failed(error: GString);
 
+    fn encoder_config(&self) -> nobodywho::encoder::EncoderConfig {
+        nobodywho::encoder::EncoderConfig {
+            n_threads: (self.n_threads > 0).then_some(self.n_threads),
+            n_threads_batch: (self.n_threads_batch > 0).then_some(self.n_threads_batch),
+            ..Default::default()
+        }
+    }
+
     /// Load the model and create the encoder worker. `yield_now()` ensures the
     /// outer `start_worker(&mut self)` borrow is released before `me.bind_mut()` runs;
     /// see the NobodyWhoChat::load_and_store_worker docstring for the full rationale.
     async fn load_and_store_worker(
         mut me: Gd<Self>,
         model_node: Gd<NobodyWhoModel>,
+        config: nobodywho::encoder::EncoderConfig,
     ) -> Result<nobodywho::encoder::EncoderAsync, GString> {
         tokio::task::yield_now().await;
 
@@ -2488,7 +3728,7 @@ impl NobodyWhoEncoder {
             .map_err(|e| GString::from(nobodywho::render_miette(&e).as_str()))?;
 
         // TODO: configurable n_ctx
-        let handle = nobodywho::encoder::EncoderAsync::new(model, 4096);
+        let handle = nobodywho::encoder::EncoderAsync::with_config(model, 4096, config);
 
         let mut b = me.bind_mut();
         if let Some(existing) = &b.encoder_handle {
@@ -2519,10 +3759,11 @@ impl NobodyWhoEncoder {
             return;
         };
 
+        let config = self.encoder_config();
         let me = self.to_gd();
         godot::task::spawn(async move {
             let me_emit = me.clone();
-            match Self::load_and_store_worker(me, model_node).await {
+            match Self::load_and_store_worker(me, model_node, config).await {
                 Ok(_) => me_emit.signals().worker_started().emit(),
                 Err(e) => {
                     godot_error!("Error running model: {}", e);
@@ -2555,6 +3796,7 @@ impl NobodyWhoEncoder {
             None
         };
 
+        let config = self.encoder_config();
         let me = self.to_gd();
         let emit_node = me.clone();
         godot::task::spawn(async move {
@@ -2562,7 +3804,7 @@ impl NobodyWhoEncoder {
                 Some(h) => h,
                 None => {
                     let model_node = model_node.expect("model_node set when no existing handle");
-                    match Self::load_and_store_worker(me, model_node).await {
+                    match Self::load_and_store_worker(me, model_node, config).await {
                         Ok(h) => h,
                         Err(e) => {
                             godot_error!("encode() dropped: {}", e);
@@ -2587,13 +3829,65 @@ impl NobodyWhoEncoder {
         godot::builtin::Signal::from_object_signal(&self.base_mut(), "encoding_finished")
     }
 
+    #[func]
+    /// Returns the length of the encodings this encoder produces, e.g. to size your storage up front.
+    /// Returns 0 until the worker has started, so call this after `worker_started`.
+    fn get_embedding_dim(&self) -> i64 {
+        self.encoder_handle
+            .as_ref()
+            .map_or(0, |handle| handle.embedding_dim() as i64)
+    }
+
     #[func]
     /// Calculates the similarity between two encoding vectors.
     /// Returns a value between 0 and 1, where 1 is the highest similarity.
+    ///
+    /// Both encodings must come from the same model. Returns NAN (and logs an error)
+    /// if either is empty or their lengths differ.
     fn cosine_similarity(a: PackedFloat32Array, b: PackedFloat32Array) -> f32 {
+        if !check_encodings("cosine_similarity", a.as_slice(), b.as_slice()) {
+            return f32::NAN;
+        }
         nobodywho::encoder::cosine_similarity(a.as_slice(), b.as_slice())
     }
 
+    #[func]
+    /// Returns true if the similarity between two encoding vectors is above `threshold`,
+    /// e.g. to check whether the player said something like a known phrase.
+    /// Returns false (and logs an error) if the encodings don't come from the same model.
+    fn is_similar(a: PackedFloat32Array, b: PackedFloat32Array, threshold: f32) -> bool {
+        check_encodings("is_similar", a.as_slice(), b.as_slice())
+            && nobodywho::encoder::is_similar(a.as_slice(), b.as_slice(), threshold)
+    }
+
+    #[func]
+    /// Finds the candidate encoding most similar to `query`.
+    /// Returns a dictionary with the `index` of that candidate and its `similarity`.
+    /// If no candidate is more similar than `threshold`, `index` is -1.
+    fn best_match(
+        query: PackedFloat32Array,
+        candidates: Array<PackedFloat32Array>,
+        threshold: f32,
+    ) -> VarDictionary {
+        let mut dict = VarDictionary::new();
+        dict.set("index", -1);
+        dict.set("similarity", f32::NAN);
+        let candidates: Vec<Vec<f32>> = candidates.iter_shared().map(|c| c.to_vec()).collect();
+        if !candidates
+            .iter()
+            .all(|candidate| check_encodings("best_match", query.as_slice(), candidate))
+        {
+            return dict;
+        }
+        if let Some((index, similarity)) =
+            nobodywho::encoder::best_match(query.as_slice(), &candidates, threshold)
+        {
+            dict.set("index", index as i64);
+            dict.set("similarity", similarity);
+        }
+        dict
+    }
+
     #[func]
     /// Sets the (global) log level of NobodyWho.
     /// Valid arguments are "TRACE", "DEBUG", "INFO", "WARN", and "ERROR".
@@ -2653,6 +3947,12 @@ impl NobodyWhoCrossEncoder {
     /// Triggered when the ranking has finished. Returns the ranked documents as a PackedStringArray.
     fn ranking_finished(ranked_documents: PackedStringArray);
 
+    #[signal]
+    /// Emitted by `rank()` as soon as each document has been scored, before `ranking_finished`.
+    /// `index` is the position of the document in the array passed to `rank()`.
+    /// Useful for driving a progress bar when ranking many documents.
+    fn ranking_progress(index: i64, score: f32);
+
     #[signal]
     /// Emitted once the crossencoder worker has finished loading (including any model
     /// download) and is ready to accept `rank()` calls.
@@ -2724,7 +4024,8 @@ impl NobodyWhoCrossEncoder {
     #[func]
     /// Ranks documents based on their relevance to the query.
     /// Returns a signal that you can use to wait for the ranking.
-    /// The signal will return a PackedStringArray of ranked documents.
+    /// The signal will return a PackedStringArray of ranked documents, most relevant first.
+    /// Documents with equal scores keep their input order.
     ///
     /// Parameters:
     /// - query: The question or query to rank documents against
@@ -2773,13 +4074,26 @@ impl NobodyWhoCrossEncoder {
                     }
                 }
             };
-            match crossencoder_handle.rank(query, docs_vec.clone()).await {
-                Ok(scores) => {
-                    let result = Self::_to_sorted_string_array(docs_vec, scores, limit);
-                    emit_node.signals().ranking_finished().emit(&result);
-                }
-                Err(err) => godot_error!("Failed generating ranking: {err}"),
+            let mut scores_rx = crossencoder_handle.rank_stream(query, docs_vec.clone());
+            let mut scores = vec![f32::NAN; docs_vec.len()];
+            let mut n_scored = 0;
+            while let Some((index, score)) = scores_rx.recv().await {
+                scores[index] = score;
+                n_scored += 1;
+                emit_node
+                    .signals()
+                    .ranking_progress()
+                    .emit(index as i64, score);
+            }
+            if n_scored < docs_vec.len() {
+                godot_error!(
+                    "Failed generating ranking: only {n_scored} of {} documents were scored",
+                    docs_vec.len()
+                );
+                return;
             }
+            let result = Self::_to_sorted_string_array(docs_vec, scores, limit);
+            emit_node.signals().ranking_finished().emit(&result);
         });
 
         godot::builtin::Signal::from_object_signal(&self.base_mut(), "ranking_finished")
@@ -2846,10 +4160,7 @@ impl NobodyWhoCrossEncoder {
         scores: Vec<f32>,
         limit: i32,
     ) -> PackedStringArray {
-        let mut docs_with_scores: Vec<(String, f32)> = documents.into_iter().zip(scores).collect();
-        docs_with_scores.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
-
-        let ranked_docs: Vec<String> = docs_with_scores
+        let ranked_docs: Vec<String> = nobodywho::crossencoder::sort_by_score(documents, scores)
             .into_iter()
             .map(|(doc, _)| doc)
             .take(if limit > 0 {
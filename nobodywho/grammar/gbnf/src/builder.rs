@@ -192,6 +192,31 @@ pub fn not_chars(chars: &[char]) -> Expr {
     }
 }
 
+/// Build a grammar that matches exactly one of `options` and nothing else.
+///
+/// The options are literal text, so quotes, backslashes and newlines in them are escaped.
+/// No whitespace is allowed around the chosen option unless the option itself contains it.
+///
+/// ```
+/// use gbnf::builder::choice;
+///
+/// let grammar = choice(&["yes", "no", "maybe"]);
+/// assert!(grammar.matches("maybe"));
+/// assert!(!grammar.matches(" yes"));
+/// ```
+///
+/// # Panics
+///
+/// If `options` is empty, as no text could match.
+pub fn choice(options: &[&str]) -> GbnfGrammar {
+    assert!(!options.is_empty(), "choice() needs at least one option");
+    let options: Vec<Expr> = options.iter().map(|option| t(option)).collect();
+    GrammarBuilder::new()
+        .rule("root", alt(&options))
+        .root("root")
+        .build()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -263,6 +288,29 @@ mod tests {
         assert!(output.contains("\"world\""));
     }
 
+    #[test]
+    fn test_choice() {
+        let grammar = choice(&["red", "green", "say \"blue\"", "a\\b\nc"]);
+
+        assert!(grammar.matches("red"));
+        assert!(grammar.matches("say \"blue\""));
+        assert!(grammar.matches("a\\b\nc"));
+        assert!(!grammar.matches("re"));
+        assert!(!grammar.matches("red "));
+        assert!(!grammar.matches("redgreen"));
+
+        let output = grammar.as_str();
+        assert!(output.contains(r#""say \"blue\"""#), "{output}");
+        assert!(output.contains(r#""a\\b\nc""#), "{output}");
+    }
+
+    #[test]
+    fn test_single_choice() {
+        let grammar = choice(&["only"]);
+        assert!(grammar.matches("only"));
+        assert!(!grammar.matches(""));
+    }
+
     #[test]
     fn test_root_name_is_set() {
         let grammar = GrammarBuilder::new()
@@ -1,7 +1,7 @@
 //! Grammar composition utilities.
 //!
-//! Provides functions for safely composing GBNF grammars by uniquifying
-//! nonterminal identifiers to avoid naming collisions.
+//! Provides functions for safely composing GBNF grammars by uniquifying or
+//! namespacing nonterminal identifiers to avoid naming collisions.
 
 use std::collections::HashMap;
 use std::sync::atomic::{AtomicU64, Ordering};
@@ -50,11 +50,28 @@ pub fn rename_nonterminals(expr: &Expr, map: &HashMap<String, String>) -> Expr {
 /// Panics if the grammar's `root_name` does not match any declaration name.
 pub fn uniquify(grammar: &GbnfGrammar) -> GbnfGrammar {
     let suffix = next_suffix();
+    rename_rules(grammar, |name| format!("{}{}", name, suffix))
+}
 
+/// Return a new grammar with every rule name prefixed with `prefix` (e.g., `root`
+/// becomes `weapon-root`), along with every `NonTerminal` reference to those rules.
+///
+/// Unlike [`uniquify`], the new names are predictable, so the rules can still be
+/// referred to by name after merging two grammars whose rule names overlap.
+///
+/// # Panics
+///
+/// Panics if the grammar's `root_name` does not match any declaration name.
+pub fn namespace(grammar: &GbnfGrammar, prefix: &str) -> GbnfGrammar {
+    rename_rules(grammar, |name| format!("{}-{}", prefix, name))
+}
+
+/// Rename every declaration, the references to it, and the root with `rename`.
+fn rename_rules(grammar: &GbnfGrammar, rename: impl Fn(&str) -> String) -> GbnfGrammar {
     let rename_map: HashMap<String, String> = grammar
         .declarations
         .iter()
-        .map(|d| (d.name.clone(), format!("{}{}", d.name, suffix)))
+        .map(|d| (d.name.clone(), rename(&d.name)))
         .collect();
 
     let new_declarations = grammar
@@ -78,7 +95,7 @@ pub fn uniquify(grammar: &GbnfGrammar) -> GbnfGrammar {
         })
         .clone();
 
-    GbnfGrammar::new(new_declarations, new_root)
+    GbnfGrammar::new(new_declarations, new_root).with_defaults(grammar.defaults().clone())
 }
 
 /// Reset the grammar counter (for testing only).
@@ -202,4 +219,35 @@ mod tests {
         assert_eq!(u1.declarations[0].name, "root-g0");
         assert_eq!(u2.declarations[0].name, "root-g1");
     }
+
+    #[test]
+    fn test_namespace() {
+        let grammar = GbnfGrammar::new(
+            vec![
+                GbnfDeclaration::new(
+                    "root".to_string(),
+                    Expr::Sequence(vec![
+                        Expr::NonTerminal("value".to_string()),
+                        Expr::NonTerminal("ws".to_string()),
+                    ]),
+                ),
+                GbnfDeclaration::new("value".to_string(), Expr::Characters("hello".to_string())),
+            ],
+            "root".to_string(),
+        );
+
+        let namespaced = namespace(&grammar, "greeting");
+
+        assert_eq!(namespaced.root_name, "greeting-root");
+        assert_eq!(namespaced.declarations[0].name, "greeting-root");
+        assert_eq!(namespaced.declarations[1].name, "greeting-value");
+        // references to rules outside the grammar are left alone
+        assert_eq!(
+            namespaced.declarations[0].expr,
+            Expr::Sequence(vec![
+                Expr::NonTerminal("greeting-value".to_string()),
+                Expr::NonTerminal("ws".to_string()),
+            ])
+        );
+    }
 }
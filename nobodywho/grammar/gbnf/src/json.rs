@@ -18,6 +18,8 @@ pub enum JsonSchemaError {
     UnresolvedRef(String),
     /// Invalid JSON input
     InvalidJson(String),
+    /// The generated grammar rejects an instance that satisfies the schema
+    VerificationFailed(String),
 }
 
 impl std::fmt::Display for JsonSchemaError {
@@ -27,12 +29,33 @@ impl std::fmt::Display for JsonSchemaError {
             JsonSchemaError::UnsupportedFeature(msg) => write!(f, "Unsupported feature: {}", msg),
             JsonSchemaError::UnresolvedRef(msg) => write!(f, "Unresolved reference: {}", msg),
             JsonSchemaError::InvalidJson(msg) => write!(f, "Invalid JSON: {}", msg),
+            JsonSchemaError::VerificationFailed(instance) => {
+                write!(
+                    f,
+                    "Generated grammar rejects a valid instance: {}",
+                    instance
+                )
+            }
         }
     }
 }
 
 impl std::error::Error for JsonSchemaError {}
 
+/// Whitespace the generated grammar allows between JSON tokens, see
+/// [`JsonSchemaConverter::with_whitespace`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum JsonWhitespace {
+    /// No whitespace at all, like `{"a":1,"b":[2,3]}`. Takes the fewest tokens.
+    Compact,
+    /// Any run of spaces, tabs and line breaks. Tool call grammars rely on this for the
+    /// line breaks their formats put around the JSON.
+    #[default]
+    Minimal,
+    /// A single space, or a line break followed by indentation
+    Pretty,
+}
+
 /// Converter from JSON Schema to GBNF Grammar
 pub struct JsonSchemaConverter {
     /// Generated declarations
@@ -43,6 +66,16 @@ pub struct JsonSchemaConverter {
     definitions: HashMap<String, Value>,
     /// Track which definitions have been converted to avoid duplicates
     converted_refs: HashMap<String, String>,
+    /// How often a definition may nest inside itself, see [`Self::with_max_depth`]
+    max_depth: Option<usize>,
+    /// Definitions currently being converted, innermost last
+    ref_stack: Vec<String>,
+    /// Whether string `enum` values match in any case, see [`Self::with_case_insensitive_enums`]
+    case_insensitive_enums: bool,
+    /// What the `ws` rule matches, see [`Self::with_whitespace`]
+    whitespace: JsonWhitespace,
+    /// Whether nullable properties may be left out, see [`Self::with_omittable_nulls`]
+    omittable_nulls: bool,
 }
 
 impl JsonSchemaConverter {
@@ -53,15 +86,128 @@ impl JsonSchemaConverter {
             rule_counter: 0,
             definitions: HashMap::new(),
             converted_refs: HashMap::new(),
+            max_depth: None,
+            ref_stack: Vec::new(),
+            case_insensitive_enums: false,
+            whitespace: JsonWhitespace::default(),
+            omittable_nulls: false,
         }
     }
 
+    /// Limit how deeply a recursive definition may nest inside itself
+    ///
+    /// Once a `$ref` would expand a definition for the `max_depth + 1`th time, the recursion
+    /// is cut off: arrays of it must stay empty, optional properties of it are left out and
+    /// `oneOf`/`anyOf` branches pointing at it are dropped. If the schema requires it there,
+    /// conversion fails. Without a limit, recursive definitions may nest without bound.
+    ///
+    /// ```
+    /// use gbnf::json::JsonSchemaConverter;
+    ///
+    /// let schema = serde_json::json!({
+    ///     "$defs": {
+    ///         "Node": {
+    ///             "type": "object",
+    ///             "properties": {"children": {"type": "array", "items": {"$ref": "#/$defs/Node"}}},
+    ///             "required": ["children"]
+    ///         }
+    ///     },
+    ///     "$ref": "#/$defs/Node"
+    /// });
+    /// let grammar = JsonSchemaConverter::new()
+    ///     .with_max_depth(2)
+    ///     .convert(&schema, "root")
+    ///     .unwrap();
+    /// assert!(grammar.matches(r#"{"children": [{"children": []}]}"#));
+    /// assert!(!grammar.matches(r#"{"children": [{"children": [{"children": []}]}]}"#));
+    /// ```
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = Some(max_depth);
+        self
+    }
+
+    /// Let the letters of string `enum` values be generated in any case
+    ///
+    /// Each letter with an upper and lower case form matches either, everything else only
+    /// matches itself, so the grammar still only accepts the enum's values, up to case.
+    /// `const` values keep their exact case.
+    ///
+    /// ```
+    /// use gbnf::json::JsonSchemaConverter;
+    ///
+    /// let schema = serde_json::json!({"enum": ["red", "dark-blue"]});
+    /// let grammar = JsonSchemaConverter::new()
+    ///     .with_case_insensitive_enums()
+    ///     .convert(&schema, "root")
+    ///     .unwrap();
+    /// assert!(grammar.matches(r#""RED""#));
+    /// assert!(grammar.matches(r#""Dark-Blue""#));
+    /// assert!(!grammar.matches(r#""dark_blue""#));
+    /// ```
+    pub fn with_case_insensitive_enums(mut self) -> Self {
+        self.case_insensitive_enums = true;
+        self
+    }
+
+    /// Choose the whitespace allowed between JSON tokens
+    ///
+    /// Whitespace costs tokens without changing what the JSON means, so the less of it the
+    /// grammar allows, the shorter the output. Defaults to [`JsonWhitespace::Minimal`].
+    ///
+    /// ```
+    /// use gbnf::json::{JsonSchemaConverter, JsonWhitespace};
+    ///
+    /// let schema = serde_json::json!({"type": "array", "items": {"type": "integer"}});
+    /// let grammar = JsonSchemaConverter::new()
+    ///     .with_whitespace(JsonWhitespace::Compact)
+    ///     .convert(&schema, "root")
+    ///     .unwrap();
+    /// assert!(grammar.matches("[1,2]"));
+    /// assert!(!grammar.matches("[1, 2]"));
+    /// ```
+    pub fn with_whitespace(mut self, whitespace: JsonWhitespace) -> Self {
+        self.whitespace = whitespace;
+        self
+    }
+
+    /// Let nullable properties be left out instead of written as `null`
+    ///
+    /// A property whose schema is an `anyOf`/`oneOf` of some schema and `{"type": "null"}`,
+    /// which is how e.g. Pydantic describes an `Optional[T]` field, becomes an optional
+    /// property holding the non-null schema, even if it is listed in `required`. This saves
+    /// the tokens of spelling out `null`, but the output no longer validates against the
+    /// schema when a required property is left out, so only use it when the reader fills in
+    /// missing properties itself. By default, nullable properties follow `required` and
+    /// accept `null`.
+    ///
+    /// ```
+    /// use gbnf::json::JsonSchemaConverter;
+    ///
+    /// let schema = serde_json::json!({
+    ///     "type": "object",
+    ///     "properties": {"nickname": {"anyOf": [{"type": "string"}, {"type": "null"}]}},
+    ///     "required": ["nickname"]
+    /// });
+    /// let grammar = JsonSchemaConverter::new()
+    ///     .with_omittable_nulls()
+    ///     .convert(&schema, "root")
+    ///     .unwrap();
+    /// assert!(grammar.matches("{}"));
+    /// assert!(grammar.matches(r#"{"nickname": "Ada"}"#));
+    /// assert!(!grammar.matches(r#"{"nickname": null}"#));
+    /// ```
+    pub fn with_omittable_nulls(mut self) -> Self {
+        self.omittable_nulls = true;
+        self
+    }
+
     /// Convert a JSON Schema value to a GBNF Grammar
     pub fn convert(&mut self, schema: &Value, root: &str) -> Result<GbnfGrammar, JsonSchemaError> {
         // Reset state
         self.declarations.clear();
         self.rule_counter = 0;
         self.converted_refs.clear();
+        self.ref_stack.clear();
 
         // Extract definitions if present
         self.extract_definitions(schema);
@@ -74,10 +220,113 @@ impl JsonSchemaConverter {
         self.declarations
             .insert(0, GbnfDeclaration::new(root.to_string(), root_expr));
 
-        Ok(GbnfGrammar::new(
-            std::mem::take(&mut self.declarations),
-            root.to_string(),
-        ))
+        let mut grammar =
+            GbnfGrammar::new(std::mem::take(&mut self.declarations), root.to_string());
+        self.collect_defaults(schema, "", 0, &mut grammar.defaults);
+        Ok(grammar)
+    }
+
+    /// Convert a JSON Schema value to a GBNF Grammar, then check the grammar against it
+    ///
+    /// A few instances that satisfy the schema are generated: a minimal one with only the
+    /// required parts, and a fuller one with optional properties, array items and additional
+    /// properties filled in. If the grammar rejects any of them, conversion fails with
+    /// [`JsonSchemaError::VerificationFailed`] holding the rejected instance.
+    ///
+    /// ```
+    /// use gbnf::json::JsonSchemaConverter;
+    ///
+    /// let schema = serde_json::json!({
+    ///     "type": "object",
+    ///     "properties": {"name": {"type": "string"}, "tags": {"type": "array"}},
+    ///     "required": ["name"]
+    /// });
+    /// let grammar = JsonSchemaConverter::new()
+    ///     .convert_and_verify(&schema, "root")
+    ///     .unwrap();
+    /// assert!(grammar.matches(r#"{"name": "Ada"}"#));
+    /// ```
+    pub fn convert_and_verify(
+        &mut self,
+        schema: &Value,
+        root: &str,
+    ) -> Result<GbnfGrammar, JsonSchemaError> {
+        let grammar = self.convert(schema, root)?;
+
+        for mode in [SampleMode::Minimal, SampleMode::Full] {
+            // schemas that only recurse forever have no finite instance to check
+            let Some(instance) = self.sample_instance(schema, mode, 0, &mut Vec::new()) else {
+                continue;
+            };
+            if !grammar.matches(&instance) {
+                return Err(JsonSchemaError::VerificationFailed(instance));
+            }
+        }
+
+        Ok(grammar)
+    }
+
+    /// Record the `default` of every optional property reachable from `schema`
+    ///
+    /// `pointer` is the JSON Pointer of the value `schema` describes. This walks the schema
+    /// rather than the generated rules, since a `$ref` is only converted once but can be
+    /// used under several properties.
+    fn collect_defaults(
+        &self,
+        schema: &Value,
+        pointer: &str,
+        depth: usize,
+        defaults: &mut HashMap<String, Value>,
+    ) {
+        let Some(obj) = schema.as_object() else {
+            return;
+        };
+        if depth > MAX_SAMPLE_DEPTH {
+            return;
+        }
+
+        if let Some(def) = obj
+            .get("$ref")
+            .and_then(|r| r.as_str())
+            .and_then(|r| self.definitions.get(r))
+        {
+            self.collect_defaults(def, pointer, depth + 1, defaults);
+        }
+        if let Some(all_of) = obj.get("allOf").and_then(|a| a.as_array()) {
+            self.collect_defaults(&merge_all_of(all_of), pointer, depth + 1, defaults);
+        }
+        for key in ["oneOf", "anyOf"] {
+            for branch in obj
+                .get(key)
+                .and_then(|b| b.as_array())
+                .into_iter()
+                .flatten()
+            {
+                self.collect_defaults(branch, pointer, depth + 1, defaults);
+            }
+        }
+
+        let Some(properties) = obj.get("properties").and_then(|p| p.as_object()) else {
+            return;
+        };
+        let required = obj.get("required").and_then(|r| r.as_array());
+        for (prop_name, prop_schema) in properties {
+            // JSON Pointer escaping, see RFC 6901
+            let prop_pointer = format!(
+                "{}/{}",
+                pointer,
+                prop_name.replace('~', "~0").replace('/', "~1")
+            );
+            let is_required = self.omittable_null(prop_schema).is_none()
+                && required
+                    .is_some_and(|r| r.iter().any(|v| v.as_str() == Some(prop_name.as_str())));
+            if !is_required && let Some(default) = prop_schema.get("default") {
+                defaults
+                    .entry(prop_pointer.clone())
+                    .or_insert_with(|| default.clone());
+            }
+            self.collect_defaults(prop_schema, &prop_pointer, depth + 1, defaults);
+        }
     }
 
     /// Extract $defs or definitions from the schema
@@ -102,11 +351,25 @@ impl JsonSchemaConverter {
 
     /// Add common JSON primitive rules
     fn add_json_primitives(&mut self) {
-        // ws ::= [' ' '\t' '\n' '\r']*
-        self.declarations.push(GbnfDeclaration::new(
-            "ws".to_string(),
-            star(cset(&[' ', '\t', '\n', '\r'])),
-        ));
+        let ws = match self.whitespace {
+            // ws ::= ""
+            JsonWhitespace::Compact => t(""),
+            // ws ::= [ \t\n\r]*
+            JsonWhitespace::Minimal => star(cset(&[' ', '\t', '\n', '\r'])),
+            // ws ::= (" " | "\n" [ \t]{0,20})?
+            JsonWhitespace::Pretty => opt(alt(&[
+                t(" "),
+                seq(&[
+                    t("\n"),
+                    Expr::Quantified {
+                        expr: Box::new(cset(&[' ', '\t'])),
+                        quantifier: Quantifier::Range(0, 20),
+                    },
+                ]),
+            ])),
+        };
+        self.declarations
+            .push(GbnfDeclaration::new("ws".to_string(), ws));
 
         // json-number ::= "-"? json-int json-frac? json-exp?
         self.declarations.push(GbnfDeclaration::new(
@@ -223,6 +486,61 @@ impl JsonSchemaConverter {
                 Expr::Characters("\"".to_string()),
             ]),
         ));
+
+        // json-value ::= json-object | json-array | json-string | json-number | json-boolean | json-null
+        self.declarations.push(GbnfDeclaration::new(
+            "json-value".to_string(),
+            alt(&[
+                nt("json-object"),
+                nt("json-array"),
+                nt("json-string"),
+                nt("json-number"),
+                nt("json-boolean"),
+                nt("json-null"),
+            ]),
+        ));
+
+        // json-member ::= json-string ws ":" ws json-value
+        self.declarations.push(GbnfDeclaration::new(
+            "json-member".to_string(),
+            seq(&[
+                nt("json-string"),
+                nt("ws"),
+                t(":"),
+                nt("ws"),
+                nt("json-value"),
+            ]),
+        ));
+
+        // json-object ::= "{" ws (json-member (ws "," ws json-member)*)? ws "}"
+        self.declarations.push(GbnfDeclaration::new(
+            "json-object".to_string(),
+            seq(&[
+                t("{"),
+                nt("ws"),
+                opt(seq(&[
+                    nt("json-member"),
+                    star(seq(&[nt("ws"), t(","), nt("ws"), nt("json-member")])),
+                ])),
+                nt("ws"),
+                t("}"),
+            ]),
+        ));
+
+        // json-array ::= "[" ws (json-value (ws "," ws json-value)*)? ws "]"
+        self.declarations.push(GbnfDeclaration::new(
+            "json-array".to_string(),
+            seq(&[
+                t("["),
+                nt("ws"),
+                opt(seq(&[
+                    nt("json-value"),
+                    star(seq(&[nt("ws"), t(","), nt("ws"), nt("json-value")])),
+                ])),
+                nt("ws"),
+                t("]"),
+            ]),
+        ));
     }
 
     /// Generate a unique rule name
@@ -232,6 +550,40 @@ impl JsonSchemaConverter {
         name
     }
 
+    /// Whether `schema` is a `$ref` that can't be expanded inside `ref_stack` any more,
+    /// because the definition already nests [`Self::with_max_depth`] levels deep there
+    fn exceeds_max_depth(&self, ref_stack: &[String], schema: &Value) -> bool {
+        let Some(max_depth) = self.max_depth else {
+            return false;
+        };
+        schema
+            .get("$ref")
+            .and_then(|r| r.as_str())
+            .is_some_and(|ref_str| ref_stack.iter().filter(|r| *r == ref_str).count() >= max_depth)
+    }
+
+    /// The non-null schema of a nullable property that may be left out instead, see
+    /// [`Self::with_omittable_nulls`]
+    fn omittable_null<'s>(&self, schema: &'s Value) -> Option<&'s Value> {
+        if !self.omittable_nulls {
+            return None;
+        }
+        nullable_inner(schema)
+    }
+
+    /// Key under which the rule for `ref_str` is cached
+    ///
+    /// With a depth limit, how far a definition may still expand depends on how often each
+    /// definition already encloses it, so every combination gets its own rule.
+    fn ref_cache_key(&self, ref_str: &str) -> String {
+        if self.max_depth.is_none() {
+            return ref_str.to_string();
+        }
+        let mut enclosing: Vec<&str> = self.ref_stack.iter().map(String::as_str).collect();
+        enclosing.sort_unstable();
+        format!("{}@{}", ref_str, enclosing.join(","))
+    }
+
     /// Convert a JSON Schema to an expression
     fn convert_schema(&mut self, schema: &Value) -> Result<Expr, JsonSchemaError> {
         // Handle boolean schemas
@@ -251,6 +603,13 @@ impl JsonSchemaConverter {
 
         // Handle $ref
         if let Some(ref_value) = obj.get("$ref") {
+            if self.exceeds_max_depth(&self.ref_stack, schema) {
+                return Err(JsonSchemaError::InvalidSchema(format!(
+                    "{} is required to nest deeper than the max depth of {}",
+                    ref_value,
+                    self.max_depth.unwrap_or_default()
+                )));
+            }
             return self.convert_ref(ref_value);
         }
 
@@ -264,6 +623,13 @@ impl JsonSchemaConverter {
             return self.convert_const(const_value);
         }
 
+        // Handle oneOf/anyOf tagged with an OpenAPI discriminator
+        if let Some(discriminator) = obj.get("discriminator")
+            && let Some(branches) = obj.get("oneOf").or_else(|| obj.get("anyOf"))
+        {
+            return self.convert_discriminated(branches, discriminator);
+        }
+
         // Handle oneOf
         if let Some(one_of) = obj.get("oneOf") {
             return self.convert_one_of(one_of);
@@ -295,7 +661,8 @@ impl JsonSchemaConverter {
             .ok_or_else(|| JsonSchemaError::InvalidSchema("$ref must be a string".to_string()))?;
 
         // Check if already converted
-        if let Some(rule_name) = self.converted_refs.get(ref_str) {
+        let cache_key = self.ref_cache_key(ref_str);
+        if let Some(rule_name) = self.converted_refs.get(&cache_key) {
             return Ok(Expr::NonTerminal(rule_name.clone()));
         }
 
@@ -316,11 +683,13 @@ impl JsonSchemaConverter {
         let rule_name = self.next_rule_name(&rule_name);
 
         // Mark as converted before recursing to handle circular refs
-        self.converted_refs
-            .insert(ref_str.to_string(), rule_name.clone());
+        self.converted_refs.insert(cache_key, rule_name.clone());
 
         // Convert the definition
-        let expr = self.convert_schema(&def)?;
+        self.ref_stack.push(ref_str.to_string());
+        let expr = self.convert_schema(&def);
+        self.ref_stack.pop();
+        let expr = expr?;
         self.declarations
             .push(GbnfDeclaration::new(rule_name.clone(), expr));
 
@@ -339,8 +708,13 @@ impl JsonSchemaConverter {
             ));
         }
 
-        let alternatives: Result<Vec<Expr>, _> =
-            arr.iter().map(|v| self.convert_const(v)).collect();
+        let alternatives: Result<Vec<Expr>, _> = arr
+            .iter()
+            .map(|v| match v {
+                Value::String(s) if self.case_insensitive_enums => Ok(case_insensitive_string(s)),
+                v => self.convert_const(v),
+            })
+            .collect();
 
         let alternatives = alternatives?;
         if alternatives.len() == 1 {
@@ -352,24 +726,7 @@ impl JsonSchemaConverter {
 
     /// Convert a const value
     fn convert_const(&mut self, value: &Value) -> Result<Expr, JsonSchemaError> {
-        match value {
-            Value::Null => Ok(Expr::Characters("null".to_string())),
-            Value::Bool(b) => Ok(Expr::Characters(
-                if *b { "true" } else { "false" }.to_string(),
-            )),
-            Value::Number(n) => Ok(Expr::Characters(n.to_string())),
-            Value::String(s) => {
-                // Need to emit as a JSON string with quotes
-                let escaped = escape_json_string(s);
-                Ok(Expr::Characters(format!("\"{}\"", escaped)))
-            }
-            Value::Array(_) | Value::Object(_) => {
-                // For complex values, emit the JSON directly
-                let json_str = serde_json::to_string(value)
-                    .map_err(|e| JsonSchemaError::InvalidJson(e.to_string()))?;
-                Ok(Expr::Characters(json_str))
-            }
-        }
+        Ok(Expr::Characters(json_literal(value)?))
     }
 
     /// Convert oneOf
@@ -378,8 +735,22 @@ impl JsonSchemaConverter {
             .as_array()
             .ok_or_else(|| JsonSchemaError::InvalidSchema("oneOf must be an array".to_string()))?;
 
-        let alternatives: Result<Vec<Expr>, _> =
-            arr.iter().map(|s| self.convert_schema(s)).collect();
+        // branches cut off by the max depth are dropped
+        let branches: Vec<&Value> = arr
+            .iter()
+            .filter(|s| !self.exceeds_max_depth(&self.ref_stack, s))
+            .collect();
+        if branches.is_empty() && !arr.is_empty() {
+            return Err(JsonSchemaError::InvalidSchema(format!(
+                "every branch of {} nests deeper than the max depth",
+                one_of
+            )));
+        }
+
+        let alternatives: Result<Vec<Expr>, _> = branches
+            .into_iter()
+            .map(|s| self.convert_schema(s))
+            .collect();
 
         let alternatives = alternatives?;
         if alternatives.len() == 1 {
@@ -394,43 +765,116 @@ impl JsonSchemaConverter {
         self.convert_one_of(any_of)
     }
 
+    /// Convert a oneOf/anyOf with a discriminator
+    ///
+    /// Each alternative fixes the discriminator property to its own tag values, so the tag
+    /// can't disagree with the shape of the branch.
+    fn convert_discriminated(
+        &mut self,
+        branches: &Value,
+        discriminator: &Value,
+    ) -> Result<Expr, JsonSchemaError> {
+        let branches = branches.as_array().ok_or_else(|| {
+            JsonSchemaError::InvalidSchema("oneOf/anyOf must be an array".to_string())
+        })?;
+        let branches = self.discriminated_branches(branches, discriminator)?;
+        self.convert_one_of(&Value::Array(branches))
+    }
+
+    /// Rewrite each branch of a tagged union so the discriminator property is required and
+    /// only accepts the tags that map to that branch
+    ///
+    /// Tags come from the discriminator's `mapping` when it lists the branch. Otherwise they
+    /// come from a `const` or `enum` on the branch's discriminator property, or failing that
+    /// from the name of the referenced definition, as in OpenAPI.
+    fn discriminated_branches(
+        &self,
+        branches: &[Value],
+        discriminator: &Value,
+    ) -> Result<Vec<Value>, JsonSchemaError> {
+        let property_name = discriminator
+            .get("propertyName")
+            .and_then(|p| p.as_str())
+            .ok_or_else(|| {
+                JsonSchemaError::InvalidSchema(
+                    "discriminator must have a propertyName string".to_string(),
+                )
+            })?;
+        let mapping = discriminator.get("mapping").and_then(|m| m.as_object());
+
+        branches
+            .iter()
+            .map(|branch| {
+                let ref_str = branch.get("$ref").and_then(|r| r.as_str());
+                let mut resolved = match ref_str {
+                    Some(ref_str) => self
+                        .definitions
+                        .get(ref_str)
+                        .cloned()
+                        .ok_or_else(|| JsonSchemaError::UnresolvedRef(ref_str.to_string()))?,
+                    None => branch.clone(),
+                };
+
+                let mapped_tags: Vec<Value> = mapping
+                    .into_iter()
+                    .flatten()
+                    .filter(|(_, target)| ref_str.is_some() && target.as_str() == ref_str)
+                    .map(|(tag, _)| Value::String(tag.clone()))
+                    .collect();
+                let property = resolved
+                    .get("properties")
+                    .and_then(|p| p.get(property_name));
+                let tags = if !mapped_tags.is_empty() {
+                    mapped_tags
+                } else if let Some(value) = property.and_then(|p| p.get("const")) {
+                    vec![value.clone()]
+                } else if let Some(values) = property.and_then(|p| p.get("enum")?.as_array()) {
+                    values.clone()
+                } else if let Some(name) = ref_str.and_then(|r| r.rsplit('/').next()) {
+                    vec![Value::String(name.to_string())]
+                } else {
+                    return Err(JsonSchemaError::InvalidSchema(format!(
+                        "cannot tell the {} value of discriminated branch {}",
+                        property_name, branch
+                    )));
+                };
+
+                let obj = resolved.as_object_mut().ok_or_else(|| {
+                    JsonSchemaError::InvalidSchema(
+                        "discriminated branches must be object schemas".to_string(),
+                    )
+                })?;
+                let properties = obj
+                    .entry("properties")
+                    .or_insert_with(|| Value::Object(serde_json::Map::new()));
+                if let Some(properties) = properties.as_object_mut() {
+                    properties.insert(
+                        property_name.to_string(),
+                        serde_json::json!({ "enum": tags }),
+                    );
+                }
+                let required = obj
+                    .entry("required")
+                    .or_insert_with(|| Value::Array(Vec::new()));
+                if let Some(required) = required.as_array_mut()
+                    && !required.iter().any(|r| r.as_str() == Some(property_name))
+                {
+                    required.push(Value::String(property_name.to_string()));
+                }
+                obj.entry("type")
+                    .or_insert_with(|| Value::String("object".to_string()));
+                Ok(resolved)
+            })
+            .collect()
+    }
+
     /// Convert allOf
     fn convert_all_of(&mut self, all_of: &Value) -> Result<Expr, JsonSchemaError> {
         let arr = all_of
             .as_array()
             .ok_or_else(|| JsonSchemaError::InvalidSchema("allOf must be an array".to_string()))?;
 
-        // For allOf, we need to merge the schemas
-        // This is a simplified implementation that only handles object merging
-        let mut merged_properties: HashMap<String, Value> = HashMap::new();
-        let mut merged_required: Vec<String> = Vec::new();
-
-        for schema in arr {
-            if let Some(obj) = schema.as_object() {
-                if let Some(props) = obj.get("properties").and_then(|p| p.as_object()) {
-                    for (name, prop) in props {
-                        merged_properties.insert(name.clone(), prop.clone());
-                    }
-                }
-                if let Some(req) = obj.get("required").and_then(|r| r.as_array()) {
-                    for r in req {
-                        if let Some(s) = r.as_str()
-                            && !merged_required.contains(&s.to_string())
-                        {
-                            merged_required.push(s.to_string());
-                        }
-                    }
-                }
-            }
-        }
-
-        // Build a merged schema
-        let merged = serde_json::json!({
-            "type": "object",
-            "properties": merged_properties,
-            "required": merged_required
-        });
-
+        let merged = merge_all_of(arr);
         self.convert_schema(&merged)
     }
 
@@ -592,6 +1036,7 @@ impl JsonSchemaConverter {
     /// - `items`: schema for all array elements (homogeneous array)
     /// - `prefixItems`: schemas for positional elements (tuple-like)
     /// - Both: prefixItems first, then items for additional elements
+    /// - `contains` with a `const`/`enum` subschema on a homogeneous array
     fn convert_array_type(
         &mut self,
         schema: &serde_json::Map<String, Value>,
@@ -599,6 +1044,10 @@ impl JsonSchemaConverter {
         let prefix_items = schema.get("prefixItems").and_then(|p| p.as_array());
         let items_schema = schema.get("items");
 
+        if let Some(contains) = schema.get("contains") {
+            return self.convert_contains_array(schema, items_schema, contains);
+        }
+
         match (prefix_items, items_schema) {
             // Only prefixItems: tuple with fixed elements
             (Some(prefix), None) => self.convert_tuple_array(prefix, None),
@@ -616,6 +1065,15 @@ impl JsonSchemaConverter {
         &mut self,
         items_schema: Option<&Value>,
     ) -> Result<Expr, JsonSchemaError> {
+        if items_schema.is_some_and(|items| self.exceeds_max_depth(&self.ref_stack, items)) {
+            // the recursion is cut off here, only the empty array is left
+            return Ok(Expr::Sequence(vec![
+                Expr::Characters("[".to_string()),
+                Expr::NonTerminal("ws".to_string()),
+                Expr::Characters("]".to_string()),
+            ]));
+        }
+
         let items_expr = if let Some(items) = items_schema {
             self.convert_schema(items)?
         } else {
@@ -654,6 +1112,77 @@ impl JsonSchemaConverter {
         Ok(Expr::NonTerminal(rule_name))
     }
 
+    /// Convert a homogeneous array that must contain at least one element matching `contains`
+    ///
+    /// "At least one matching element" can't be expressed exactly in a CFG in general, but when
+    /// the contained element is a `const` or `enum` we can place it between two runs of
+    /// arbitrary items: `"[" (item ",")* contained ("," item)* "]"`.
+    fn convert_contains_array(
+        &mut self,
+        schema: &serde_json::Map<String, Value>,
+        items_schema: Option<&Value>,
+        contains: &Value,
+    ) -> Result<Expr, JsonSchemaError> {
+        let is_literal = contains
+            .as_object()
+            .is_some_and(|c| c.contains_key("const") || c.contains_key("enum"));
+        if !is_literal
+            || schema.contains_key("prefixItems")
+            || schema.contains_key("minContains")
+            || schema.contains_key("maxContains")
+        {
+            return Err(JsonSchemaError::UnsupportedFeature(
+                "contains is only supported with a const or enum subschema on a homogeneous array"
+                    .to_string(),
+            ));
+        }
+
+        let items_expr = if let Some(items) = items_schema {
+            self.convert_schema(items)?
+        } else {
+            Expr::NonTerminal("json-value".to_string())
+        };
+        let item_rule = self.next_rule_name("item");
+        self.declarations
+            .push(GbnfDeclaration::new(item_rule.clone(), items_expr));
+
+        let contains_expr = self.convert_schema(contains)?;
+        let contains_rule = self.next_rule_name("contains");
+        self.declarations
+            .push(GbnfDeclaration::new(contains_rule.clone(), contains_expr));
+
+        let rule_name = self.next_rule_name("array");
+        let expr = Expr::Sequence(vec![
+            Expr::Characters("[".to_string()),
+            Expr::NonTerminal("ws".to_string()),
+            Expr::Quantified {
+                expr: Box::new(Expr::Sequence(vec![
+                    Expr::NonTerminal(item_rule.clone()),
+                    Expr::NonTerminal("ws".to_string()),
+                    Expr::Characters(",".to_string()),
+                    Expr::NonTerminal("ws".to_string()),
+                ])),
+                quantifier: Quantifier::ZeroOrMore,
+            },
+            Expr::NonTerminal(contains_rule),
+            Expr::Quantified {
+                expr: Box::new(Expr::Sequence(vec![
+                    Expr::NonTerminal("ws".to_string()),
+                    Expr::Characters(",".to_string()),
+                    Expr::NonTerminal("ws".to_string()),
+                    Expr::NonTerminal(item_rule),
+                ])),
+                quantifier: Quantifier::ZeroOrMore,
+            },
+            Expr::NonTerminal("ws".to_string()),
+            Expr::Characters("]".to_string()),
+        ]);
+
+        self.declarations
+            .push(GbnfDeclaration::new(rule_name.clone(), expr));
+        Ok(Expr::NonTerminal(rule_name))
+    }
+
     /// Convert a tuple array (prefixItems with optional trailing items)
     fn convert_tuple_array(
         &mut self,
@@ -693,7 +1222,10 @@ impl JsonSchemaConverter {
         }
 
         // Add additional items if specified (but not if items: false)
-        // items: false means "no additional items allowed" which is the default behavior
+        // items: false means "no additional items allowed" which is the default behavior,
+        // and so are items that would nest deeper than the max depth
+        let additional_items =
+            additional_items.filter(|items| !self.exceeds_max_depth(&self.ref_stack, items));
         if let Some(items_schema) = additional_items {
             // items: false means no additional items - same as None
             if items_schema.as_bool() != Some(false) {
@@ -731,6 +1263,12 @@ impl JsonSchemaConverter {
     /// additionalProperties handling:
     /// - `false` or absent: no additional properties allowed
     /// - `{schema}`: additional properties with constrained values (fixed ordering: defined props first)
+    ///
+    /// minProperties/maxProperties bound the number of additional properties, see
+    /// [`Self::additional_property_bounds`].
+    ///
+    /// With [`Self::with_omittable_nulls`], nullable properties (`anyOf: [T, {"type": "null"}]`)
+    /// are optional and hold a `T` when present.
     fn convert_object_type(
         &mut self,
         schema: &serde_json::Map<String, Value>,
@@ -744,13 +1282,15 @@ impl JsonSchemaConverter {
             .unwrap_or_default();
 
         // additionalProperties: only support false or a schema object
-        let additional_schema = schema.get("additionalProperties").filter(|v| v.is_object());
+        let additional_schema = schema
+            .get("additionalProperties")
+            .filter(|v| v.is_object() && !self.exceeds_max_depth(&self.ref_stack, v));
 
         // Handle case with no defined properties
         let properties = match properties {
             Some(p) if !p.is_empty() => p,
             _ => {
-                return self.convert_object_only_additional(additional_schema);
+                return self.convert_object_only_additional(schema, additional_schema);
             }
         };
 
@@ -758,15 +1298,18 @@ impl JsonSchemaConverter {
         let mut prop_rules: Vec<(String, String, bool)> = Vec::new();
 
         for (prop_name, prop_schema) in properties {
+            let nullable = self.omittable_null(prop_schema);
+            let is_required = nullable.is_none() && required.contains(&prop_name.as_str());
+            let prop_schema = nullable.unwrap_or(prop_schema);
+            if !is_required && self.exceeds_max_depth(&self.ref_stack, prop_schema) {
+                // the recursion is cut off here, so the property is left out
+                continue;
+            }
             let prop_expr = self.convert_schema(prop_schema)?;
             let rule_name = self.next_rule_name(&format!("prop-{}", prop_name.replace('_', "-")));
             self.declarations
                 .push(GbnfDeclaration::new(rule_name.clone(), prop_expr));
-            prop_rules.push((
-                prop_name.clone(),
-                rule_name,
-                required.contains(&prop_name.as_str()),
-            ));
+            prop_rules.push((prop_name.clone(), rule_name, is_required));
         }
 
         let required_props: Vec<_> = prop_rules.iter().filter(|(_, _, r)| *r).collect();
@@ -789,43 +1332,78 @@ impl JsonSchemaConverter {
             obj_parts.extend(Self::property_kv(prop_name, prop_rule_name));
         }
 
-        // Optional defined properties
+        // Optional defined properties, each with a leading comma
+        let mut opt_rule_names: Vec<String> = Vec::new();
         for (prop_name, prop_rule_name, _) in &optional_props {
             let opt_rule_name =
                 self.next_rule_name(&format!("opt-{}", prop_name.replace('_', "-")));
 
-            let mut opt_parts = if has_content {
-                Self::comma_separator()
-            } else {
-                vec![]
-            };
+            let mut opt_parts = Self::comma_separator();
             opt_parts.extend(Self::property_kv(prop_name, prop_rule_name));
 
             self.declarations.push(GbnfDeclaration::new(
                 opt_rule_name.clone(),
                 Expr::Sequence(opt_parts),
             ));
-
-            obj_parts.push(Expr::Quantified {
-                expr: Box::new(Expr::NonTerminal(opt_rule_name)),
-                quantifier: Quantifier::Optional,
-            });
+            opt_rule_names.push(opt_rule_name);
         }
 
         // Additional properties
-        if let Some(add_schema) = additional_schema {
-            let add_prop_rule = self.create_additional_prop_rule(add_schema)?;
+        let (min_additional, max_additional) = Self::additional_property_bounds(
+            schema,
+            required_props.len(),
+            optional_props.len(),
+            additional_schema.is_some(),
+        )?;
+        let add_prop_rule = match additional_schema {
+            Some(add_schema) if max_additional != Some(0) => {
+                Some(self.create_additional_prop_rule(add_schema)?)
+            }
+            _ => None,
+        };
 
-            // (ws "," ws additional-prop)*
-            obj_parts.push(Expr::Quantified {
-                expr: Box::new(Expr::Sequence(vec![
-                    Expr::NonTerminal("ws".to_string()),
-                    Expr::Characters(",".to_string()),
-                    Expr::NonTerminal("ws".to_string()),
-                    Expr::NonTerminal(add_prop_rule),
-                ])),
-                quantifier: Quantifier::ZeroOrMore,
-            });
+        // Everything that may follow the optional property at `index`
+        let tail = |index: usize| -> Vec<Expr> {
+            let mut parts: Vec<Expr> = opt_rule_names[index..]
+                .iter()
+                .map(|opt_rule_name| opt(nt(opt_rule_name)))
+                .collect();
+            if let Some(add_prop_rule) = &add_prop_rule {
+                // (ws "," ws additional-prop){min,max}
+                parts.extend(Self::more_additional_props(
+                    add_prop_rule,
+                    min_additional,
+                    max_additional,
+                ));
+            }
+            parts
+        };
+
+        if has_content {
+            obj_parts.extend(tail(0));
+        } else {
+            // Without a required property, whichever member comes first has no leading comma
+            let mut first_members: Vec<Expr> = Vec::new();
+            for (index, (prop_name, prop_rule_name, _)) in optional_props.iter().enumerate() {
+                let mut parts = Self::property_kv(prop_name, prop_rule_name);
+                parts.extend(tail(index + 1));
+                first_members.push(Expr::Sequence(parts));
+            }
+            if let Some(add_prop_rule) = &add_prop_rule {
+                let mut parts = vec![nt(add_prop_rule)];
+                parts.extend(Self::more_additional_props(
+                    add_prop_rule,
+                    min_additional.saturating_sub(1),
+                    max_additional.map(|max| max - 1),
+                ));
+                first_members.push(Expr::Sequence(parts));
+            }
+            let members = Expr::Group(Box::new(alt(&first_members)));
+            if min_additional > 0 && add_prop_rule.is_some() {
+                obj_parts.push(members);
+            } else {
+                obj_parts.push(opt(members));
+            }
         }
 
         obj_parts.push(Expr::NonTerminal("ws".to_string()));
@@ -867,31 +1445,31 @@ impl JsonSchemaConverter {
     /// Convert object with no defined properties, only additionalProperties
     fn convert_object_only_additional(
         &mut self,
+        schema: &serde_json::Map<String, Value>,
         additional_schema: Option<&Value>,
     ) -> Result<Expr, JsonSchemaError> {
+        let (min_additional, max_additional) =
+            Self::additional_property_bounds(schema, 0, 0, additional_schema.is_some())?;
         match additional_schema {
-            Some(add_schema) => {
+            Some(add_schema) if max_additional != Some(0) => {
                 let add_prop_rule = self.create_additional_prop_rule(add_schema)?;
                 let rule_name = self.next_rule_name("object");
 
-                // Pattern: { ws (add-prop (ws "," ws add-prop)*)? ws }
+                // Pattern: { ws (add-prop (ws "," ws add-prop){min-1,max-1})? ws }
+                let mut members = vec![Expr::NonTerminal(add_prop_rule.clone())];
+                members.extend(Self::more_additional_props(
+                    &add_prop_rule,
+                    min_additional.saturating_sub(1),
+                    max_additional.map(|max| max - 1),
+                ));
+                let members = Expr::Sequence(members);
                 let obj_expr = Expr::Sequence(vec![
                     Expr::Characters("{".to_string()),
                     Expr::NonTerminal("ws".to_string()),
-                    Expr::Quantified {
-                        expr: Box::new(Expr::Sequence(vec![
-                            Expr::NonTerminal(add_prop_rule.clone()),
-                            Expr::Quantified {
-                                expr: Box::new(Expr::Sequence(vec![
-                                    Expr::NonTerminal("ws".to_string()),
-                                    Expr::Characters(",".to_string()),
-                                    Expr::NonTerminal("ws".to_string()),
-                                    Expr::NonTerminal(add_prop_rule),
-                                ])),
-                                quantifier: Quantifier::ZeroOrMore,
-                            },
-                        ])),
-                        quantifier: Quantifier::Optional,
+                    if min_additional > 0 {
+                        members
+                    } else {
+                        opt(members)
                     },
                     Expr::NonTerminal("ws".to_string()),
                     Expr::Characters("}".to_string()),
@@ -901,7 +1479,7 @@ impl JsonSchemaConverter {
                     .push(GbnfDeclaration::new(rule_name.clone(), obj_expr));
                 Ok(Expr::NonTerminal(rule_name))
             }
-            None => {
+            _ => {
                 // Empty object only: { ws }
                 Ok(Expr::Sequence(vec![
                     Expr::Characters("{".to_string()),
@@ -912,6 +1490,300 @@ impl JsonSchemaConverter {
         }
     }
 
+    /// Serialize an instance of `schema`, with keys in the order the grammar expects them
+    ///
+    /// The instance has no whitespace between tokens, which every [`JsonWhitespace`] allows.
+    ///
+    /// Returns `None` if no instance can be built within [`MAX_SAMPLE_DEPTH`] levels of nesting,
+    /// or within the max depth of recursive definitions. `ref_stack` holds the definitions
+    /// the instance is nested in, like in conversion.
+    fn sample_instance(
+        &self,
+        schema: &Value,
+        mode: SampleMode,
+        depth: usize,
+        ref_stack: &mut Vec<String>,
+    ) -> Option<String> {
+        if depth > MAX_SAMPLE_DEPTH {
+            return None;
+        }
+        let full = mode == SampleMode::Full;
+
+        if let Some(b) = schema.as_bool() {
+            return b.then(|| Self::sample_any_value(mode));
+        }
+        let obj = schema.as_object()?;
+
+        if let Some(ref_str) = obj.get("$ref").and_then(|r| r.as_str()) {
+            if self.exceeds_max_depth(ref_stack, schema) {
+                return None;
+            }
+            let def = self.definitions.get(ref_str)?;
+            ref_stack.push(ref_str.to_string());
+            let instance = self.sample_instance(def, mode, depth + 1, ref_stack);
+            ref_stack.pop();
+            return instance;
+        }
+        if let Some(values) = obj.get("enum").and_then(|e| e.as_array()) {
+            let value = if full { values.last() } else { values.first() }?;
+            return json_literal(value).ok();
+        }
+        if let Some(value) = obj.get("const") {
+            return json_literal(value).ok();
+        }
+        if let Some(branches) = obj
+            .get("oneOf")
+            .or_else(|| obj.get("anyOf"))
+            .and_then(|b| b.as_array())
+        {
+            let branches = match obj.get("discriminator") {
+                Some(discriminator) => self.discriminated_branches(branches, discriminator).ok()?,
+                None => branches.clone(),
+            };
+            let branches: Vec<Value> = branches
+                .into_iter()
+                .filter(|branch| !self.exceeds_max_depth(ref_stack, branch))
+                .collect();
+            let branch = if full {
+                branches.last()
+            } else {
+                branches.first()
+            }?;
+            return self.sample_instance(branch, mode, depth + 1, ref_stack);
+        }
+        if let Some(all_of) = obj.get("allOf").and_then(|a| a.as_array()) {
+            return self.sample_instance(&merge_all_of(all_of), mode, depth + 1, ref_stack);
+        }
+
+        let type_str = match obj.get("type") {
+            None => return Some(Self::sample_any_value(mode)),
+            Some(Value::Array(types)) => {
+                let mut types = types.iter().filter_map(|t| t.as_str());
+                if full { types.last() } else { types.next() }?
+            }
+            Some(type_value) => type_value.as_str()?,
+        };
+
+        match type_str {
+            "string" => Some(Self::sample_string(obj, mode)),
+            "number" => Some(if full { "-1.5e3" } else { "0" }.to_string()),
+            "integer" => Some(if full { "-42" } else { "0" }.to_string()),
+            "boolean" => Some(full.to_string()),
+            "null" => Some("null".to_string()),
+            "array" => self.sample_array(obj, mode, depth, ref_stack),
+            "object" => self.sample_object(obj, mode, depth, ref_stack),
+            _ => None,
+        }
+    }
+
+    /// A value for schemas that accept anything
+    fn sample_any_value(mode: SampleMode) -> String {
+        match mode {
+            SampleMode::Minimal => "null".to_string(),
+            SampleMode::Full => r#"{"key":[1,"two",true,null]}"#.to_string(),
+        }
+    }
+
+    fn sample_string(schema: &serde_json::Map<String, Value>, mode: SampleMode) -> String {
+        let full = mode == SampleMode::Full;
+        match schema.get("format").and_then(|f| f.as_str()) {
+            Some("date") => r#""2024-01-31""#.to_string(),
+            Some("time") => r#""12:30:00""#.to_string(),
+            Some("date-time") if full => r#""2024-01-31T12:30:00+02:00""#.to_string(),
+            Some("date-time") => r#""2024-01-31T12:30:00""#.to_string(),
+            _ if full => r#""say \"hi\"\n\u00e9""#.to_string(),
+            _ => r#""""#.to_string(),
+        }
+    }
+
+    fn sample_array(
+        &self,
+        schema: &serde_json::Map<String, Value>,
+        mode: SampleMode,
+        depth: usize,
+        ref_stack: &mut Vec<String>,
+    ) -> Option<String> {
+        let full = mode == SampleMode::Full;
+        let prefix_items = schema
+            .get("prefixItems")
+            .and_then(|p| p.as_array())
+            .filter(|p| !p.is_empty());
+        let items_schema = schema.get("items");
+
+        let mut items = Vec::new();
+        if let Some(prefix) = prefix_items {
+            for item_schema in prefix {
+                items.push(self.sample_instance(item_schema, mode, depth + 1, ref_stack)?);
+            }
+            if full
+                && let Some(rest) = items_schema.filter(|i| i.as_bool() != Some(false))
+                && let Some(item) = self.sample_instance(rest, mode, depth + 1, ref_stack)
+            {
+                items.push(item);
+            }
+        } else if full {
+            let item_schema = items_schema.cloned().unwrap_or(Value::Bool(true));
+            if let Some(item) = self.sample_instance(&item_schema, mode, depth + 1, ref_stack) {
+                items.push(item.clone());
+                items.push(item);
+            }
+        }
+
+        Some(format!("[{}]", items.join(",")))
+    }
+
+    fn sample_object(
+        &self,
+        schema: &serde_json::Map<String, Value>,
+        mode: SampleMode,
+        depth: usize,
+        ref_stack: &mut Vec<String>,
+    ) -> Option<String> {
+        let full = mode == SampleMode::Full;
+        let required: Vec<&str> = schema
+            .get("required")
+            .and_then(|r| r.as_array())
+            .map(|arr| arr.iter().filter_map(|v| v.as_str()).collect())
+            .unwrap_or_default();
+        let additional_schema = schema.get("additionalProperties").filter(|v| v.is_object());
+
+        // same order as the grammar: required properties, then optional ones, then extras
+        let mut members = Vec::new();
+        if let Some(properties) = schema.get("properties").and_then(|p| p.as_object()) {
+            for (name, prop_schema) in properties {
+                if self.omittable_null(prop_schema).is_none() && required.contains(&name.as_str()) {
+                    let value = self.sample_instance(prop_schema, mode, depth + 1, ref_stack)?;
+                    members.push(format!("\"{}\":{}", escape_json_string(name), value));
+                }
+            }
+            for (name, prop_schema) in properties {
+                let nullable = self.omittable_null(prop_schema);
+                if full
+                    && (nullable.is_some() || !required.contains(&name.as_str()))
+                    && let Some(value) = self.sample_instance(
+                        nullable.unwrap_or(prop_schema),
+                        mode,
+                        depth + 1,
+                        ref_stack,
+                    )
+                {
+                    members.push(format!("\"{}\":{}", escape_json_string(name), value));
+                }
+            }
+        }
+        if let Some(add_schema) = additional_schema {
+            // counted the same way as in convert_object_type
+            let (n_required, n_optional) = schema
+                .get("properties")
+                .and_then(|p| p.as_object())
+                .map_or((0, 0), |p| {
+                    let n_required = p
+                        .iter()
+                        .filter(|(k, v)| {
+                            self.omittable_null(v).is_none() && required.contains(&k.as_str())
+                        })
+                        .count();
+                    (n_required, p.len() - n_required)
+                });
+            let (min_additional, max_additional) =
+                Self::additional_property_bounds(schema, n_required, n_optional, true).ok()?;
+            let n_additional = match mode {
+                SampleMode::Minimal => min_additional,
+                SampleMode::Full => max_additional
+                    .map_or(min_additional.max(1), |max| max.min(min_additional.max(1))),
+            };
+            for index in 0..n_additional {
+                let value = self.sample_instance(add_schema, mode, depth + 1, ref_stack)?;
+                members.push(format!("\"extra-{}\":{}", index, value));
+            }
+        }
+
+        Some(format!("{{{}}}", members.join(",")))
+    }
+
+    /// How many additional properties an object may have, given minProperties/maxProperties
+    ///
+    /// Required properties count toward both bounds. Optional defined properties are counted
+    /// as absent for the minimum and as present for the maximum, so the object stays within
+    /// bounds whichever of them the model writes. Bounds that only hold for some choices of
+    /// optional properties can't be expressed in the grammar and are rejected.
+    fn additional_property_bounds(
+        schema: &serde_json::Map<String, Value>,
+        n_required: usize,
+        n_optional: usize,
+        allows_additional: bool,
+    ) -> Result<(usize, Option<usize>), JsonSchemaError> {
+        let min_properties = schema
+            .get("minProperties")
+            .and_then(|m| m.as_u64())
+            .unwrap_or(0) as usize;
+        let max_properties = schema
+            .get("maxProperties")
+            .and_then(|m| m.as_u64())
+            .map(|m| m as usize);
+
+        if let Some(max_properties) = max_properties
+            && (max_properties < min_properties || max_properties < n_required)
+        {
+            return Err(JsonSchemaError::InvalidSchema(format!(
+                "maxProperties {} is less than minProperties {} or the {} required properties",
+                max_properties, min_properties, n_required
+            )));
+        }
+
+        if max_properties.is_some_and(|max| max < n_required + n_optional) {
+            return Err(JsonSchemaError::UnsupportedFeature(
+                "maxProperties that the optional properties could exceed".to_string(),
+            ));
+        }
+
+        if !allows_additional {
+            if min_properties > n_required + n_optional {
+                return Err(JsonSchemaError::InvalidSchema(format!(
+                    "minProperties {} is more than the {} defined properties",
+                    min_properties,
+                    n_required + n_optional
+                )));
+            }
+            if min_properties > n_required {
+                return Err(JsonSchemaError::UnsupportedFeature(
+                    "minProperties that depend on which optional properties are present"
+                        .to_string(),
+                ));
+            }
+            return Ok((0, Some(0)));
+        }
+
+        let min_additional = min_properties.saturating_sub(n_required);
+        let max_additional = max_properties.map(|max| max - n_required - n_optional);
+        if max_additional.is_some_and(|max| max < min_additional) {
+            return Err(JsonSchemaError::UnsupportedFeature(
+                "minProperties/maxProperties that depend on which optional properties are present"
+                    .to_string(),
+            ));
+        }
+        Ok((min_additional, max_additional))
+    }
+
+    /// Helper: `(ws "," ws additional-prop)` repeated between `min` and `max` times
+    fn more_additional_props(add_prop_rule: &str, min: usize, max: Option<usize>) -> Option<Expr> {
+        let quantifier = match (min, max) {
+            (_, Some(0)) => return None,
+            (0, None) => Quantifier::ZeroOrMore,
+            (1, None) => Quantifier::OneOrMore,
+            (min, None) => Quantifier::AtLeast(min),
+            (0, Some(1)) => Quantifier::Optional,
+            (min, Some(max)) if min == max => Quantifier::Exact(min),
+            (min, Some(max)) => Quantifier::Range(min, max),
+        };
+        let mut repeated = Self::comma_separator();
+        repeated.push(nt(add_prop_rule));
+        Some(Expr::Quantified {
+            expr: Box::new(Expr::Sequence(repeated)),
+            quantifier,
+        })
+    }
+
     /// Helper: comma separator sequence
     fn comma_separator() -> Vec<Expr> {
         vec![
@@ -958,6 +1830,109 @@ fn escape_json_string(s: &str) -> String {
     result
 }
 
+/// A JSON string literal of `s` where every letter with an upper and lower case form matches
+/// either, as in [`JsonSchemaConverter::with_case_insensitive_enums`]
+fn case_insensitive_string(s: &str) -> Expr {
+    let mut parts = Vec::new();
+    let mut literal = String::from("\"");
+    for c in s.chars() {
+        let mut lower = c.to_lowercase();
+        let mut upper = c.to_uppercase();
+        match (lower.next(), lower.next(), upper.next(), upper.next()) {
+            // only letters that map to a single, different character in the other case
+            (Some(l), None, Some(u), None) if l != u => {
+                if !literal.is_empty() {
+                    parts.push(Expr::Characters(std::mem::take(&mut literal)));
+                }
+                let mut cases = vec![l, u];
+                if !cases.contains(&c) {
+                    // titlecase letters like `ǅ`
+                    cases.push(c);
+                }
+                parts.push(cset(&cases));
+            }
+            _ => literal.push_str(&escape_json_string(&c.to_string())),
+        }
+    }
+    literal.push('"');
+    parts.push(Expr::Characters(literal));
+    Expr::Sequence(parts)
+}
+
+/// The JSON text of a literal value, as it appears in `enum` and `const` rules
+fn json_literal(value: &Value) -> Result<String, JsonSchemaError> {
+    match value {
+        Value::Null => Ok("null".to_string()),
+        Value::Bool(b) => Ok(b.to_string()),
+        Value::Number(n) => Ok(n.to_string()),
+        // Need to emit as a JSON string with quotes
+        Value::String(s) => Ok(format!("\"{}\"", escape_json_string(s))),
+        // For complex values, emit the JSON directly
+        Value::Array(_) | Value::Object(_) => {
+            serde_json::to_string(value).map_err(|e| JsonSchemaError::InvalidJson(e.to_string()))
+        }
+    }
+}
+
+/// The non-null branch of an `anyOf`/`oneOf` of some schema and `{"type": "null"}`, which
+/// is how e.g. Pydantic describes an `Optional[T]` field
+fn nullable_inner(schema: &Value) -> Option<&Value> {
+    let obj = schema.as_object()?;
+    let branches = obj.get("anyOf").or_else(|| obj.get("oneOf"))?.as_array()?;
+    let is_null = |branch: &Value| branch.get("type").and_then(|t| t.as_str()) == Some("null");
+    match branches.as_slice() {
+        [a, b] if is_null(b) && !is_null(a) => Some(a),
+        [a, b] if is_null(a) && !is_null(b) => Some(b),
+        _ => None,
+    }
+}
+
+/// Merge the object schemas of an allOf into a single object schema
+///
+/// This is a simplified implementation that only handles object merging
+fn merge_all_of(schemas: &[Value]) -> Value {
+    let mut merged_properties = serde_json::Map::new();
+    let mut merged_required: Vec<String> = Vec::new();
+
+    for schema in schemas {
+        if let Some(obj) = schema.as_object() {
+            if let Some(props) = obj.get("properties").and_then(|p| p.as_object()) {
+                for (name, prop) in props {
+                    merged_properties.insert(name.clone(), prop.clone());
+                }
+            }
+            if let Some(req) = obj.get("required").and_then(|r| r.as_array()) {
+                for r in req {
+                    if let Some(s) = r.as_str()
+                        && !merged_required.contains(&s.to_string())
+                    {
+                        merged_required.push(s.to_string());
+                    }
+                }
+            }
+        }
+    }
+
+    serde_json::json!({
+        "type": "object",
+        "properties": merged_properties,
+        "required": merged_required
+    })
+}
+
+/// Which instance of a schema to generate when verifying a grammar
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SampleMode {
+    /// Only required properties, empty arrays, first alternatives
+    Minimal,
+    /// Every property and some array items, last alternatives
+    Full,
+}
+
+/// How deeply nested schemas are followed when generating instances or collecting defaults,
+/// which cuts off recursive schemas
+const MAX_SAMPLE_DEPTH: usize = 12;
+
 // ---------------------------------------------------------------------------
 // Expression-building helpers (used in place of the gbnf! macro)
 // ---------------------------------------------------------------------------
@@ -1101,6 +2076,75 @@ mod tests {
         assert!(gbnf.contains(r#"\"blue\""#));
     }
 
+    #[test]
+    fn test_case_insensitive_enum() {
+        let schema = serde_json::json!({"enum": ["Red", "x-1", "Straße", 3]});
+        let grammar = JsonSchemaConverter::new()
+            .with_case_insensitive_enums()
+            .convert(&schema, "root")
+            .unwrap();
+
+        for accepted in [
+            r#""red""#,
+            r#""RED""#,
+            r#""rEd""#,
+            r#""X-1""#,
+            r#""STRAßE""#,
+            "3",
+        ] {
+            assert!(grammar.matches(accepted), "{accepted}");
+        }
+        for rejected in [r#""reed""#, r#""x_1""#, r#""blue""#, r#""STRASSE""#] {
+            assert!(!grammar.matches(rejected), "{rejected}");
+        }
+
+        // exact case without the flag
+        let grammar = JsonSchemaConverter::new().convert(&schema, "root").unwrap();
+        assert!(grammar.matches(r#""Red""#));
+        assert!(!grammar.matches(r#""red""#));
+    }
+
+    #[test]
+    fn test_whitespace_policy() {
+        let schema = serde_json::json!({
+            "type": "object",
+            "properties": {"a": {"type": "array", "items": {"type": "integer"}}},
+            "required": ["a"]
+        });
+        let convert = |whitespace| {
+            JsonSchemaConverter::new()
+                .with_whitespace(whitespace)
+                .convert_and_verify(&schema, "root")
+                .unwrap()
+        };
+        let compact = r#"{"a":[1,2]}"#;
+        let spaced = r#"{"a": [1, 2]}"#;
+        let pretty = "{\n  \"a\": [\n    1,\n    2\n  ]\n}";
+        let loose = "{  \"a\":\t[1 ,2]}";
+
+        let grammar = convert(JsonWhitespace::Compact);
+        assert!(grammar.matches(compact));
+        for rejected in [spaced, pretty, loose] {
+            assert!(!grammar.matches(rejected), "{rejected}");
+        }
+
+        let grammar = convert(JsonWhitespace::Minimal);
+        for accepted in [compact, spaced, pretty, loose] {
+            assert!(grammar.matches(accepted), "{accepted}");
+        }
+
+        let grammar = convert(JsonWhitespace::Pretty);
+        for accepted in [compact, spaced, pretty] {
+            assert!(grammar.matches(accepted), "{accepted}");
+        }
+        assert!(!grammar.matches(loose));
+
+        assert_eq!(
+            JsonSchemaConverter::new().whitespace,
+            JsonWhitespace::Minimal
+        );
+    }
+
     #[test]
     fn test_object_with_properties() {
         let schema = r#"{
@@ -1171,6 +2215,35 @@ mod tests {
         assert!(gbnf.contains("]"));
     }
 
+    #[test]
+    fn test_array_contains() {
+        let schema = r#"{
+            "type": "array",
+            "items": {"type": "string"},
+            "contains": {"const": "admin"}
+        }"#;
+        let grammar = json_schema_to_grammar(schema, "root").unwrap();
+
+        assert!(grammar.matches(r#"["admin"]"#));
+        assert!(grammar.matches(r#"["user", "admin", "guest"]"#));
+        assert!(!grammar.matches(r#"["user", "guest"]"#));
+        assert!(!grammar.matches("[]"));
+    }
+
+    #[test]
+    fn test_array_contains_unsupported() {
+        let schema = r#"{
+            "type": "array",
+            "items": {"type": "integer"},
+            "contains": {"type": "integer", "minimum": 5}
+        }"#;
+        let result = json_schema_to_grammar(schema, "root");
+        assert!(matches!(
+            result,
+            Err(JsonSchemaError::UnsupportedFeature(_))
+        ));
+    }
+
     #[test]
     fn test_nested_arrays_matrix() {
         let schema = r#"{"type":"object","properties":{"listOfMatrices":{"type":"array","items":{"type":"array","items":{"type":"array","items":{"type":"number"}}}}},"required":["listOfMatrices"],"additionalProperties":false}"#;
@@ -1380,4 +2453,391 @@ mod tests {
         // Should have json-null for nullable types
         assert!(gbnf.contains("json-null"));
     }
+
+    #[test]
+    fn test_nullable_property_is_omittable() {
+        let schema = r##"{
+          "type": "object",
+          "properties": {
+            "name": { "type": "string" },
+            "nickname": {
+              "anyOf": [{ "type": "string" }, { "type": "null" }]
+            }
+          },
+          "required": ["name", "nickname"]
+        }"##;
+
+        // by default, a required nullable property must be written, possibly as null
+        let grammar = json_schema_to_grammar(schema, "root").unwrap();
+        assert!(grammar.matches(r#"{"name": "Ada", "nickname": null}"#));
+        assert!(grammar.matches(r#"{"name": "Ada", "nickname": "Countess"}"#));
+        assert!(!grammar.matches(r#"{"name": "Ada"}"#));
+
+        // with omittable nulls, it is an optional member holding a string, not a null
+        let schema: Value = serde_json::from_str(schema).unwrap();
+        let grammar = JsonSchemaConverter::new()
+            .with_omittable_nulls()
+            .convert_and_verify(&schema, "root")
+            .unwrap();
+        let gbnf = grammar.as_str();
+        eprintln!("Generated grammar:\n{}", gbnf);
+        assert!(gbnf.contains("opt-nickname"));
+        let nickname_rule = gbnf
+            .lines()
+            .find(|line| line.starts_with("prop-nickname"))
+            .unwrap();
+        assert!(!nickname_rule.contains("json-null"));
+        assert!(grammar.matches(r#"{"name": "Ada"}"#));
+        assert!(!grammar.matches(r#"{"name": "Ada", "nickname": null}"#));
+    }
+
+    #[test]
+    fn test_untyped_values_match_any_json() {
+        let schema = r#"{
+            "type": "object",
+            "properties": {"data": {}, "list": {"type": "array"}},
+            "required": ["data", "list"]
+        }"#;
+        let grammar = json_schema_to_grammar(schema, "root").unwrap();
+
+        assert!(grammar.matches(r#"{"data": {"a": [1, null, "x"]}, "list": [true, {}]}"#));
+        assert!(grammar.matches(r#"{"data": -2.5e3, "list": []}"#));
+        assert!(!grammar.matches(r#"{"data": [1,], "list": []}"#));
+    }
+
+    #[test]
+    fn test_convert_and_verify() {
+        let schemas = [
+            serde_json::json!({"type": "string", "format": "date-time"}),
+            serde_json::json!({"enum": ["a\"b", 1.5, null, [1, 2]]}),
+            serde_json::json!({"type": ["integer", "null"]}),
+            serde_json::json!({
+                "type": "array",
+                "prefixItems": [{"type": "string"}, {"type": "number"}],
+                "items": {"type": "boolean"}
+            }),
+            serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "name": {"type": "string"},
+                    "age": {"type": "integer"},
+                    "birthday": {"type": "string", "format": "date"},
+                    "tags": {"type": "array", "items": {"type": "string"}}
+                },
+                "required": ["name", "tags"],
+                "additionalProperties": {"type": "number"}
+            }),
+            serde_json::json!({
+                "allOf": [
+                    {"properties": {"x": {"type": "integer"}}, "required": ["x"]},
+                    {"properties": {"y": {"type": "integer"}}}
+                ]
+            }),
+        ];
+
+        for schema in schemas {
+            let result = JsonSchemaConverter::new().convert_and_verify(&schema, "root");
+            assert!(result.is_ok(), "{schema}: {result:?}");
+        }
+    }
+
+    #[test]
+    fn test_convert_and_verify_recursive_schema() {
+        let schema = serde_json::json!({
+            "$defs": {
+                "Node": {
+                    "type": "object",
+                    "properties": {
+                        "children": {"type": "array", "items": {"$ref": "#/$defs/Node"}},
+                        "value": {"type": "integer"}
+                    },
+                    "required": ["children", "value"]
+                }
+            },
+            "$ref": "#/$defs/Node"
+        });
+
+        let grammar = JsonSchemaConverter::new()
+            .convert_and_verify(&schema, "root")
+            .unwrap();
+        assert!(grammar.matches(r#"{"children": [{"children": [], "value": 2}], "value": 1}"#));
+    }
+
+    #[test]
+    fn test_max_depth_cuts_off_recursive_tree() {
+        let schema = serde_json::json!({
+            "$defs": {
+                "node": {
+                    "type": "object",
+                    "properties": {
+                        "children": {"type": "array", "items": {"$ref": "#/$defs/node"}},
+                        "value": {"type": "integer"}
+                    },
+                    "required": ["children", "value"]
+                }
+            },
+            "$ref": "#/$defs/node"
+        });
+
+        let grammar = JsonSchemaConverter::new()
+            .with_max_depth(3)
+            .convert_and_verify(&schema, "root")
+            .unwrap();
+        assert!(grammar.matches(r#"{"children": [], "value": 1}"#));
+        assert!(grammar.matches(
+            r#"{"children": [{"children": [{"children": [], "value": 3}], "value": 2}, {"children": [], "value": 2}], "value": 1}"#
+        ));
+        assert!(!grammar.matches(
+            r#"{"children": [{"children": [{"children": [{"children": [], "value": 4}], "value": 3}], "value": 2}], "value": 1}"#
+        ));
+    }
+
+    #[test]
+    fn test_max_depth_drops_optional_and_alternative_recursion() {
+        let schema = serde_json::json!({
+            "$defs": {
+                "item": {
+                    "type": "object",
+                    "properties": {
+                        "next": {"$ref": "#/$defs/item"},
+                        "parent": {"anyOf": [{"$ref": "#/$defs/item"}, {"type": "null"}]}
+                    },
+                    "required": ["parent"]
+                }
+            },
+            "$ref": "#/$defs/item"
+        });
+
+        let grammar = JsonSchemaConverter::new()
+            .with_max_depth(2)
+            .convert_and_verify(&schema, "root")
+            .unwrap();
+        assert!(grammar.matches(r#"{"parent": {"parent": null}, "next": {"parent": null}}"#));
+        assert!(!grammar.matches(r#"{"parent": {"parent": {"parent": null}}}"#));
+        assert!(
+            !grammar
+                .matches(r#"{"parent": null, "next": {"parent": null, "next": {"parent": null}}}"#)
+        );
+    }
+
+    #[test]
+    fn test_max_depth_rejects_required_recursion() {
+        let schema = serde_json::json!({
+            "$defs": {
+                "loop": {
+                    "type": "object",
+                    "properties": {"inner": {"$ref": "#/$defs/loop"}},
+                    "required": ["inner"]
+                }
+            },
+            "$ref": "#/$defs/loop"
+        });
+
+        let result = JsonSchemaConverter::new()
+            .with_max_depth(3)
+            .convert(&schema, "root");
+        assert!(matches!(result, Err(JsonSchemaError::InvalidSchema(_))));
+    }
+
+    #[test]
+    fn test_object_without_required_properties() {
+        let schema = serde_json::json!({
+            "type": "object",
+            "properties": {"a": {"type": "integer"}, "b": {"type": "integer"}},
+            "additionalProperties": {"type": "boolean"}
+        });
+        let grammar = JsonSchemaConverter::new()
+            .convert_and_verify(&schema, "root")
+            .unwrap();
+
+        assert!(grammar.matches("{}"));
+        assert!(grammar.matches(r#"{"a": 1, "b": 2, "c": true}"#));
+        assert!(grammar.matches(r#"{"b": 2}"#));
+        assert!(grammar.matches(r#"{"c": true}"#));
+        assert!(!grammar.matches(r#"{, "c": true}"#));
+        assert!(!grammar.matches(r#"{"a": 1 "b": 2}"#));
+    }
+
+    #[test]
+    fn test_discriminator_fixes_tag_per_branch() {
+        let schema = serde_json::json!({
+            "$defs": {
+                "Cat": {
+                    "type": "object",
+                    "properties": {"meows": {"type": "boolean"}, "pet_type": {"type": "string"}},
+                    "required": ["meows"]
+                },
+                "Dog": {
+                    "type": "object",
+                    "properties": {"barks": {"type": "boolean"}, "pet_type": {"type": "string"}},
+                    "required": ["barks"]
+                }
+            },
+            "oneOf": [{"$ref": "#/$defs/Cat"}, {"$ref": "#/$defs/Dog"}],
+            "discriminator": {
+                "propertyName": "pet_type",
+                "mapping": {"cat": "#/$defs/Cat", "kitten": "#/$defs/Cat"}
+            }
+        });
+        let grammar = JsonSchemaConverter::new()
+            .convert_and_verify(&schema, "root")
+            .unwrap();
+
+        assert!(grammar.matches(r#"{"meows": true, "pet_type": "cat"}"#));
+        assert!(grammar.matches(r#"{"meows": true, "pet_type": "kitten"}"#));
+        // Dog isn't in the mapping, so its tag is the definition name
+        assert!(grammar.matches(r#"{"barks": false, "pet_type": "Dog"}"#));
+        assert!(!grammar.matches(r#"{"barks": false, "pet_type": "cat"}"#));
+        assert!(!grammar.matches(r#"{"meows": true}"#));
+    }
+
+    #[test]
+    fn test_discriminator_from_const() {
+        let schema = serde_json::json!({
+            "anyOf": [
+                {"properties": {"kind": {"const": "circle"}, "radius": {"type": "number"}}},
+                {"properties": {"kind": {"const": "square"}, "side": {"type": "number"}}}
+            ],
+            "discriminator": {"propertyName": "kind"}
+        });
+        let grammar = json_schema_to_grammar(&schema, "root").unwrap();
+
+        assert!(grammar.matches(r#"{"kind": "circle", "radius": 1}"#));
+        assert!(grammar.matches(r#"{"kind": "square"}"#));
+        assert!(!grammar.matches(r#"{"kind": "square", "radius": 1}"#));
+    }
+
+    #[test]
+    fn test_defaults_of_optional_properties() {
+        let schema = serde_json::json!({
+            "$defs": {
+                "Size": {
+                    "type": "object",
+                    "properties": {"unit": {"type": "string", "default": "cm"}}
+                }
+            },
+            "type": "object",
+            "properties": {
+                "count": {"type": "integer", "default": 1},
+                "name": {"type": "string", "default": "ignored"},
+                "sizes": {
+                    "type": "object",
+                    "properties": {
+                        "height": {"$ref": "#/$defs/Size"},
+                        "width": {"anyOf": [{"$ref": "#/$defs/Size"}, {"type": "null"}]}
+                    }
+                }
+            },
+            "required": ["name"]
+        });
+        let grammar = json_schema_to_grammar(&schema, "root").unwrap();
+
+        let expected: HashMap<String, Value> = [
+            ("/count", serde_json::json!(1)),
+            ("/sizes/height/unit", serde_json::json!("cm")),
+            ("/sizes/width/unit", serde_json::json!("cm")),
+        ]
+        .into_iter()
+        .map(|(pointer, value)| (pointer.to_string(), value))
+        .collect();
+        assert_eq!(grammar.defaults(), &expected);
+    }
+
+    #[test]
+    fn test_min_max_properties_map() {
+        let schema = serde_json::json!({
+            "type": "object",
+            "additionalProperties": {"type": "integer"},
+            "minProperties": 1,
+            "maxProperties": 3
+        });
+        let grammar = JsonSchemaConverter::new()
+            .convert_and_verify(&schema, "root")
+            .unwrap();
+
+        assert!(!grammar.matches("{}"));
+        assert!(grammar.matches(r#"{"a": 1}"#));
+        assert!(grammar.matches(r#"{"a": 1, "b": 2, "c": 3}"#));
+        assert!(!grammar.matches(r#"{"a": 1, "b": 2, "c": 3, "d": 4}"#));
+    }
+
+    #[test]
+    fn test_min_max_properties_count_required() {
+        let schema = serde_json::json!({
+            "type": "object",
+            "properties": {"id": {"type": "integer"}},
+            "required": ["id"],
+            "additionalProperties": {"type": "string"},
+            "minProperties": 2,
+            "maxProperties": 2
+        });
+        let grammar = JsonSchemaConverter::new()
+            .convert_and_verify(&schema, "root")
+            .unwrap();
+
+        assert!(grammar.matches(r#"{"id": 1, "name": "x"}"#));
+        assert!(!grammar.matches(r#"{"id": 1}"#));
+        assert!(!grammar.matches(r#"{"id": 1, "name": "x", "other": "y"}"#));
+
+        let too_small = serde_json::json!({
+            "type": "object",
+            "properties": {"a": {}, "b": {}},
+            "required": ["a", "b"],
+            "additionalProperties": {},
+            "maxProperties": 1
+        });
+        assert!(matches!(
+            json_schema_to_grammar(&too_small, "root"),
+            Err(JsonSchemaError::InvalidSchema(_))
+        ));
+    }
+
+    #[test]
+    fn test_min_max_properties_with_optional_properties() {
+        // three optional properties could exceed maxProperties 2
+        let optional_over_max = serde_json::json!({
+            "type": "object",
+            "properties": {"a": {}, "b": {}, "c": {}},
+            "additionalProperties": {},
+            "maxProperties": 2
+        });
+        assert!(matches!(
+            json_schema_to_grammar(&optional_over_max, "root"),
+            Err(JsonSchemaError::UnsupportedFeature(_))
+        ));
+
+        // without additionalProperties the bounds still apply to the defined properties
+        let closed = serde_json::json!({
+            "type": "object",
+            "properties": {"a": {}, "b": {}},
+            "required": ["a"],
+            "minProperties": 2
+        });
+        assert!(matches!(
+            json_schema_to_grammar(&closed, "root"),
+            Err(JsonSchemaError::UnsupportedFeature(_))
+        ));
+        let unsatisfiable = serde_json::json!({
+            "type": "object",
+            "properties": {"a": {}},
+            "minProperties": 2
+        });
+        assert!(matches!(
+            json_schema_to_grammar(&unsatisfiable, "root"),
+            Err(JsonSchemaError::InvalidSchema(_))
+        ));
+
+        let within_bounds = serde_json::json!({
+            "type": "object",
+            "properties": {"a": {"type": "integer"}, "b": {"type": "integer"}},
+            "required": ["a"],
+            "minProperties": 1,
+            "maxProperties": 2
+        });
+        let grammar = JsonSchemaConverter::new()
+            .convert_and_verify(&within_bounds, "root")
+            .unwrap();
+        assert!(grammar.matches(r#"{"a": 1}"#));
+        assert!(grammar.matches(r#"{"a": 1, "b": 2}"#));
+    }
 }
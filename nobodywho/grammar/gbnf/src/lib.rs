@@ -10,6 +10,10 @@ pub mod builder;
 pub mod compose;
 pub mod gbnf_to_lark;
 pub mod json;
+mod matcher;
+pub mod token_refs;
+
+use std::collections::HashMap;
 
 /// A complete GBNF grammar containing multiple declarations
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -20,6 +24,11 @@ pub struct GbnfGrammar {
     pub gbnf_string: String,
     /// The name of the root rule (start symbol)
     pub root_name: String,
+    /// Default values of optional object properties, keyed by JSON Pointer (e.g. `/user/age`)
+    ///
+    /// Only filled in by [`json::JsonSchemaConverter`]. The grammar lets the model leave these
+    /// properties out, so callers can use this to fill them in after parsing.
+    defaults: HashMap<String, serde_json::Value>,
 }
 
 impl GbnfGrammar {
@@ -34,6 +43,7 @@ impl GbnfGrammar {
             declarations,
             gbnf_string,
             root_name,
+            defaults: HashMap::new(),
         }
     }
 
@@ -41,6 +51,17 @@ impl GbnfGrammar {
     pub fn as_str(&self) -> &str {
         &self.gbnf_string
     }
+
+    /// Default values of optional object properties, keyed by JSON Pointer (e.g. `/user/age`)
+    pub fn defaults(&self) -> &HashMap<String, serde_json::Value> {
+        &self.defaults
+    }
+
+    /// Replace the property defaults, e.g. to carry them over to a rewritten grammar
+    pub fn with_defaults(mut self, defaults: HashMap<String, serde_json::Value>) -> Self {
+        self.defaults = defaults;
+        self
+    }
 }
 
 /// A single GBNF rule declaration (e.g., `root ::= "hello"`)
@@ -186,6 +207,95 @@ pub enum Expr {
 }
 
 impl Expr {
+    /// A string literal that has to match exactly.
+    ///
+    /// ```
+    /// use gbnf::Expr;
+    ///
+    /// assert_eq!(Expr::literal("hi").to_gbnf(), r#""hi""#);
+    /// ```
+    pub fn literal(s: impl Into<String>) -> Self {
+        Expr::Characters(s.into())
+    }
+
+    /// A reference to the rule called `name`.
+    pub fn rule(name: impl Into<String>) -> Self {
+        Expr::NonTerminal(name.into())
+    }
+
+    /// The given expressions, one after the other.
+    ///
+    /// Alternations are grouped so they stay a single item of the sequence, and a sequence
+    /// of one item is just that item.
+    ///
+    /// ```
+    /// use gbnf::{Expr, GbnfDeclaration, GbnfGrammar};
+    ///
+    /// let greeting = Expr::seq([
+    ///     Expr::alt([Expr::literal("Hello"), Expr::literal("Hi")]),
+    ///     Expr::opt(Expr::literal(",")),
+    ///     Expr::literal(" "),
+    ///     Expr::rule("name"),
+    /// ]);
+    /// let grammar = GbnfGrammar::new(
+    ///     vec![
+    ///         GbnfDeclaration::new("root".into(), greeting),
+    ///         GbnfDeclaration::new("name".into(), Expr::one_of_chars("ABC".chars())),
+    ///     ],
+    ///     "root".into(),
+    /// );
+    /// assert_eq!(
+    ///     grammar.as_str(),
+    ///     "root ::= (\"Hello\" | \"Hi\") \",\"? \" \" name\nname ::= [ABC]"
+    /// );
+    /// ```
+    pub fn seq(items: impl IntoIterator<Item = Expr>) -> Self {
+        let mut items: Vec<Expr> = items
+            .into_iter()
+            .map(|item| match item {
+                Expr::Alternation(_) => Expr::Group(Box::new(item)),
+                item => item,
+            })
+            .collect();
+        if items.len() == 1 {
+            items.remove(0)
+        } else {
+            Expr::Sequence(items)
+        }
+    }
+
+    /// Any one of the given expressions. An alternation of one item is just that item.
+    pub fn alt(items: impl IntoIterator<Item = Expr>) -> Self {
+        let mut items: Vec<Expr> = items.into_iter().collect();
+        if items.len() == 1 {
+            items.remove(0)
+        } else {
+            Expr::Alternation(items)
+        }
+    }
+
+    /// Any single character out of `chars`, like `[abc]`.
+    ///
+    /// ```
+    /// use gbnf::Expr;
+    ///
+    /// assert_eq!(Expr::one_of_chars("+-".chars()).to_gbnf(), "[+-]");
+    /// ```
+    pub fn one_of_chars(chars: impl IntoIterator<Item = char>) -> Self {
+        Expr::CharacterRange(CharacterRange::Set {
+            chars: chars.into_iter().collect(),
+            negated: false,
+        })
+    }
+
+    /// `expr` or nothing, like `expr?`.
+    pub fn opt(expr: Expr) -> Self {
+        Expr::Quantified {
+            expr: Box::new(expr),
+            quantifier: Quantifier::Optional,
+        }
+    }
+
     /// Convert to GBNF string representation
     pub fn to_gbnf(&self) -> String {
         match self {
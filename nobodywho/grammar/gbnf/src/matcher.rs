@@ -0,0 +1,241 @@
+//! Matching text against a GBNF grammar.
+//!
+//! This is a plain recognizer over the grammar AST, meant for checking grammars in tests and
+//! at build time. It is not used for constrained generation, which happens in llama.cpp.
+
+use std::collections::{BTreeSet, HashMap};
+
+use crate::{CharacterRange, Expr, GbnfGrammar, Quantifier};
+
+impl GbnfGrammar {
+    /// Check whether the whole of `input` can be derived from the root rule.
+    ///
+    /// Token references (`<think>`, `<[1000]>`) never match, since they refer to model
+    /// tokens rather than text. References to rules that don't exist never match either.
+    ///
+    /// ```
+    /// use gbnf::{Expr, GbnfDeclaration, GbnfGrammar};
+    ///
+    /// let grammar = GbnfGrammar::new(
+    ///     vec![GbnfDeclaration::new(
+    ///         "root".into(),
+    ///         Expr::alt([Expr::literal("yes"), Expr::literal("no")]),
+    ///     )],
+    ///     "root".into(),
+    /// );
+    /// assert!(grammar.matches("yes"));
+    /// assert!(!grammar.matches("maybe"));
+    /// ```
+    pub fn matches(&self, input: &str) -> bool {
+        let mut matcher = Matcher {
+            grammar: self,
+            input: input.chars().collect(),
+            memo: HashMap::new(),
+        };
+        let input_len = matcher.input.len();
+        matcher.match_rule(&self.root_name, 0).contains(&input_len)
+    }
+}
+
+/// Finds every position a grammar expression can end at, given where it starts.
+struct Matcher<'a> {
+    grammar: &'a GbnfGrammar,
+    input: Vec<char>,
+    /// End positions per (rule, start position).
+    memo: HashMap<(&'a str, usize), BTreeSet<usize>>,
+}
+
+impl<'a> Matcher<'a> {
+    fn match_rule(&mut self, name: &str, start: usize) -> BTreeSet<usize> {
+        let grammar = self.grammar;
+        let Some(declaration) = grammar.declarations.iter().find(|d| d.name == name) else {
+            return BTreeSet::new();
+        };
+        let key = (declaration.name.as_str(), start);
+        if let Some(ends) = self.memo.get(&key) {
+            return ends.clone();
+        }
+        // an empty entry while the rule is being matched cuts off left recursion
+        self.memo.insert(key, BTreeSet::new());
+        let ends = self.match_expr(&declaration.expr, start);
+        self.memo.insert(key, ends.clone());
+        ends
+    }
+
+    fn match_expr(&mut self, expr: &'a Expr, start: usize) -> BTreeSet<usize> {
+        match expr {
+            Expr::Characters(s) => {
+                let len = s.chars().count();
+                let matched = self
+                    .input
+                    .get(start..start + len)
+                    .is_some_and(|slice| slice.iter().copied().eq(s.chars()));
+                matched.then_some(start + len).into_iter().collect()
+            }
+            Expr::CharacterRange(range) => {
+                let matched = self
+                    .input
+                    .get(start)
+                    .is_some_and(|&c| range_contains(range, c));
+                matched.then_some(start + 1).into_iter().collect()
+            }
+            Expr::Token(_) => BTreeSet::new(),
+            Expr::NonTerminal(name) => self.match_rule(name, start),
+            Expr::Group(inner) => self.match_expr(inner, start),
+            Expr::Sequence(items) => {
+                let mut positions = BTreeSet::from([start]);
+                for item in items {
+                    positions = self.step(item, &positions);
+                    if positions.is_empty() {
+                        break;
+                    }
+                }
+                positions
+            }
+            Expr::Alternation(alternatives) => alternatives
+                .iter()
+                .flat_map(|alternative| self.match_expr(alternative, start))
+                .collect(),
+            Expr::Quantified { expr, quantifier } => {
+                let (min, max) = match quantifier {
+                    Quantifier::Optional => (0, Some(1)),
+                    Quantifier::OneOrMore => (1, None),
+                    Quantifier::ZeroOrMore => (0, None),
+                    Quantifier::Exact(n) => (*n, Some(*n)),
+                    Quantifier::AtLeast(n) => (*n, None),
+                    Quantifier::Range(n, m) => (*n, Some(*m)),
+                };
+                self.match_repeated(expr, start, min, max)
+            }
+        }
+    }
+
+    /// End positions of `expr` starting from any of `positions`.
+    fn step(&mut self, expr: &'a Expr, positions: &BTreeSet<usize>) -> BTreeSet<usize> {
+        positions
+            .iter()
+            .flat_map(|&position| self.match_expr(expr, position))
+            .collect()
+    }
+
+    fn match_repeated(
+        &mut self,
+        expr: &'a Expr,
+        start: usize,
+        min: usize,
+        max: Option<usize>,
+    ) -> BTreeSet<usize> {
+        let mut frontier = BTreeSet::from([start]);
+        for _ in 0..min {
+            frontier = self.step(expr, &frontier);
+            if frontier.is_empty() {
+                return frontier;
+            }
+        }
+
+        let mut ends = frontier.clone();
+        let mut repetitions = min;
+        while !frontier.is_empty() && max.is_none_or(|max| repetitions < max) {
+            let next = self.step(expr, &frontier);
+            repetitions += 1;
+            // without an upper bound, positions that were already reached add nothing new
+            frontier = match max {
+                Some(_) => next.clone(),
+                None => next.difference(&ends).copied().collect(),
+            };
+            ends.extend(next);
+        }
+        ends
+    }
+}
+
+fn range_contains(range: &CharacterRange, c: char) -> bool {
+    match range {
+        CharacterRange::Range {
+            begin,
+            end,
+            negated,
+        } => (*begin..=*end).contains(&c) != *negated,
+        CharacterRange::Set { chars, negated } => chars.contains(&c) != *negated,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::builder::{GrammarBuilder, nt, t};
+    use crate::{CharacterRange, Expr, Quantifier};
+
+    #[test]
+    fn test_matches_sequence_and_alternation() {
+        let grammar = GrammarBuilder::new()
+            .rule(
+                "root",
+                Expr::seq([Expr::alt([t("Hello"), t("Hi")]), t(" "), nt("name")]),
+            )
+            .rule("name", Expr::alt([t("World"), t("Friend")]))
+            .root("root")
+            .build();
+
+        assert!(grammar.matches("Hello World"));
+        assert!(grammar.matches("Hi Friend"));
+        assert!(!grammar.matches("Hi"));
+        assert!(!grammar.matches("Hi Friend!"));
+    }
+
+    #[test]
+    fn test_matches_quantifiers() {
+        let digit = Expr::CharacterRange(CharacterRange::Range {
+            begin: '0',
+            end: '9',
+            negated: false,
+        });
+        let grammar = GrammarBuilder::new()
+            .rule(
+                "root",
+                Expr::seq([
+                    Expr::Quantified {
+                        expr: Box::new(digit.clone()),
+                        quantifier: Quantifier::Range(2, 3),
+                    },
+                    Expr::opt(t("!")),
+                    Expr::Quantified {
+                        expr: Box::new(t("ab")),
+                        quantifier: Quantifier::ZeroOrMore,
+                    },
+                ]),
+            )
+            .root("root")
+            .build();
+
+        assert!(grammar.matches("12"));
+        assert!(grammar.matches("123!abab"));
+        assert!(!grammar.matches("1"));
+        assert!(!grammar.matches("1234"));
+        assert!(!grammar.matches("12aba"));
+    }
+
+    #[test]
+    fn test_matches_recursive_rules() {
+        // nested parentheses
+        let grammar = GrammarBuilder::new()
+            .rule(
+                "root",
+                Expr::alt([t("x"), Expr::seq([t("("), nt("root"), t(")")])]),
+            )
+            .root("root")
+            .build();
+
+        assert!(grammar.matches("((x))"));
+        assert!(!grammar.matches("((x)"));
+    }
+
+    #[test]
+    fn test_missing_rule_never_matches() {
+        let grammar = GrammarBuilder::new()
+            .rule("root", nt("missing"))
+            .root("root")
+            .build();
+
+        assert!(!grammar.matches(""));
+    }
+}
@@ -0,0 +1,124 @@
+//! Resolving named token references against a model's vocabulary.
+//!
+//! GBNF can reference tokens by name (`<think>`, `!<think>`) or by ID (`<[1000]>`).
+//! Names only mean something for a specific vocabulary, so a grammar using them is
+//! resolved to IDs once the model is known.
+
+/// A named token reference that isn't a single token in the vocabulary.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownTokenError {
+    /// The token text, including the angle brackets, e.g. `<think>`.
+    pub token: String,
+}
+
+impl std::fmt::Display for UnknownTokenError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "The grammar references the token {}, which is not a single token in this model's vocabulary",
+            self.token
+        )
+    }
+}
+
+impl std::error::Error for UnknownTokenError {}
+
+/// Replace the named token references in the GBNF text `grammar` with references by ID,
+/// e.g. `!<think>` with `!<[151667]>`.
+///
+/// `resolve` gets the token text including the angle brackets (`<think>`) and returns its
+/// ID, or `None` if the vocabulary has no such token. String literals, character classes
+/// and comments are left alone.
+///
+/// ```
+/// use gbnf::token_refs::resolve_token_refs;
+///
+/// let grammar = r#"root ::= <think> "<b>" !<think>* <[2]>"#;
+/// let resolved = resolve_token_refs(grammar, |token| (token == "<think>").then_some(7)).unwrap();
+/// assert_eq!(resolved, r#"root ::= <[7]> "<b>" !<[7]>* <[2]>"#);
+/// ```
+pub fn resolve_token_refs(
+    grammar: &str,
+    mut resolve: impl FnMut(&str) -> Option<usize>,
+) -> Result<String, UnknownTokenError> {
+    let mut resolved = String::with_capacity(grammar.len());
+    let mut chars = grammar.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        match c {
+            // copied up to (and including) the closing character, skipping escaped ones
+            '"' | '[' => {
+                let close = if c == '"' { '"' } else { ']' };
+                let mut end = grammar.len();
+                while let Some((i, c)) = chars.next() {
+                    if c == '\\' {
+                        chars.next();
+                    } else if c == close {
+                        end = i + 1;
+                        break;
+                    }
+                }
+                resolved.push_str(&grammar[start..end]);
+            }
+            '#' => {
+                let end = grammar[start..]
+                    .find('\n')
+                    .map_or(grammar.len(), |i| start + i);
+                resolved.push_str(&grammar[start..end]);
+                while chars.next_if(|&(i, _)| i < end).is_some() {}
+            }
+            '<' => {
+                let Some(len) = grammar[start..].find('>') else {
+                    // not a token reference, leave it to the grammar parser to complain
+                    resolved.push_str(&grammar[start..]);
+                    break;
+                };
+                let token = &grammar[start..=start + len];
+                if token.starts_with("<[") {
+                    resolved.push_str(token);
+                } else {
+                    let id = resolve(token).ok_or_else(|| UnknownTokenError {
+                        token: token.to_string(),
+                    })?;
+                    resolved.push_str(&format!("<[{id}]>"));
+                }
+                while chars.next_if(|&(i, _)| i <= start + len).is_some() {}
+            }
+            c => resolved.push(c),
+        }
+    }
+    Ok(resolved)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vocab(token: &str) -> Option<usize> {
+        match token {
+            "<think>" => Some(10),
+            "</think>" => Some(11),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_resolves_named_and_negated_refs() {
+        let grammar = "root ::= <think> (!</think>)* </think> answer\nanswer ::= [a-z]+";
+        assert_eq!(
+            resolve_token_refs(grammar, vocab).unwrap(),
+            "root ::= <[10]> (!<[11]>)* <[11]> answer\nanswer ::= [a-z]+"
+        );
+    }
+
+    #[test]
+    fn test_leaves_literals_classes_and_comments_alone() {
+        let grammar = "# uses <nope>\nroot ::= \"<nope> \\\" <nope>\" [<>\\]] <[5]>";
+        assert_eq!(resolve_token_refs(grammar, vocab).unwrap(), grammar);
+    }
+
+    #[test]
+    fn test_unknown_token() {
+        let error = resolve_token_refs("root ::= <nope>", vocab).unwrap_err();
+        assert_eq!(error.token, "<nope>");
+    }
+}
@@ -35,6 +35,8 @@ class Chat:
     to use, whether to allow extended thinking, etc.
     See `ChatAsync` for the async version of this class.
     """
+    def __enter__(self, /) -> Chat: ...
+    def __exit__(self, /, exc_type: object, exc_value: object, traceback: object) -> bool: ...
     def __new__(
         cls,
         /,
@@ -46,6 +48,11 @@ class Chat:
         sampler: "SamplerConfig | None" = None,
         allow_thinking: "bool | None" = None,
         mtp: "MtpConfig | None" = None,
+        n_threads: "int | None" = None,
+        n_threads_batch: "int | None" = None,
+        rope_scaling: "typing.Literal['none', 'linear', 'yarn'] | None" = None,
+        rope_freq_base: "float | None" = None,
+        rope_freq_scale: "float | None" = None,
     ) -> "Chat":
         """
         Create a new Chat instance for conversational text generation.
@@ -63,11 +70,22 @@ class Chat:
             mtp: Optional MtpConfig to enable MTP speculative decoding on this chat.
                 Requires the `Model` to have been loaded with a compatible
                 `draft_model_path`. Adds around 5% to VRAM usage. Defaults to None.
+            n_threads: Number of threads used to generate tokens. Only affects CPU inference.
+                Defaults to the number of available CPU cores.
+            n_threads_batch: Number of threads used to read prompts. Only affects CPU inference.
+                Defaults to the number of available CPU cores.
+            rope_scaling: RoPE scaling method: "none", "linear" or "yarn". Defaults to the one in
+                the model file. Setting any RoPE option allows `n_ctx` to exceed the context the
+                model was trained with, but output quality degrades the further it is stretched.
+            rope_freq_base: RoPE base frequency. Defaults to the one in the model file.
+            rope_freq_scale: RoPE frequency scaling factor, the inverse of the context extension
+                (e.g. 0.25 for 4x the trained context). Defaults to the one in the model file.
 
         Returns:
             A Chat instance
 
         Raises:
+            ValueError: If `rope_scaling` is not a valid scaling method
             RuntimeError: If the model cannot be loaded
         """
     def ask(self, /, prompt: "str | Prompt") -> "TokenStream":
@@ -77,6 +95,54 @@ class Chat:
         Args:
             prompt: The user prompt to send (plain text or a multimodal Prompt)
 
+        Returns:
+            A TokenStream that yields tokens as they are generated
+        """
+    def ask_structured(
+        self, /, prompt: "str | Prompt", response_type: "type"
+    ) -> "typing.Any":
+        """
+        Ask for a response matching `response_type` and return it as an instance of that type.
+
+        The JSON schema of `response_type` constrains generation for this turn only, and the
+        generated JSON is validated with pydantic. Works with pydantic models, dataclasses and
+        anything else pydantic's `TypeAdapter` accepts.
+
+        Args:
+            prompt: The user prompt to send (plain text or a multimodal Prompt)
+            response_type: The type to parse the response into, e.g. a pydantic model
+
+        Returns:
+            An instance of `response_type`
+
+        Raises:
+            ImportError: If pydantic is not installed
+            ValueError: If the generated output does not validate against `response_type`
+        """
+    def cancel_generation(self, /) -> None:
+        """
+        Stop the current text generation, and remove the stopped turn from the chat history.
+
+        Unlike `stop_generation()`, neither the user message nor the partial response is kept,
+        so the history is as if the turn never happened. Use this to let the user interrupt
+        and ask again cleanly. Does nothing once the chat is closed.
+        """
+    def close(self, /) -> None:
+        """
+        Stop the background worker and free its context. Any generation in progress is
+        stopped. Using the chat afterwards raises RuntimeError.
+
+        Called when leaving a `with` block. Without it, the worker only stops once the `Chat`
+        is garbage collected, so servers that create many chats should close them.
+        """
+    def edit_last_user_message(self, /, prompt: "str | Prompt") -> "TokenStream":
+        """
+        Replace the last user message with `prompt` and generate a response to it.
+        The old user message and everything after it is removed from the chat history.
+
+        Args:
+            prompt: The edited user prompt (plain text or a multimodal Prompt)
+
         Returns:
             A TokenStream that yields tokens as they are generated
         """
@@ -91,6 +157,27 @@ class Chat:
         Raises:
             RuntimeError: If retrieval fails
         """
+    def get_display_history(
+        self, /, tool_calls: "typing.Literal['omit', 'collapse']" = "omit"
+    ) -> "list[dict]":
+        """
+        Get the chat history for showing to people, e.g. in a chat UI.
+
+        Unlike `get_chat_history()`, tool calls and tool responses are left out or collapsed.
+        Use `get_chat_history()` for a history to pass back to `set_chat_history()`.
+
+        Args:
+            tool_calls: "omit" leaves tool calls and their responses out, "collapse" replaces
+                each round of them with one assistant message, with a line like
+                `Called get_weather({"city":"Paris"}): 18 degrees` per call. Defaults to "omit".
+
+        Returns:
+            List of message dicts, each with 'role' (str) and 'content' (str) keys.
+
+        Raises:
+            ValueError: If `tool_calls` is not a valid value
+            RuntimeError: If retrieval fails
+        """
     def get_sampler_config(self, /) -> SamplerConfig:
         """
         Get the current sampler configuration.
@@ -121,6 +208,15 @@ class Chat:
         Raises:
             RuntimeError: If the variables cannot be retrieved
         """
+    def is_alive(self, /) -> bool:
+        """
+        Whether the chat's worker is still running. A crashed worker makes every following
+        response raise `WorkerDeadError`, so this can be used to replace the chat.
+        A closed chat is not alive.
+
+        Returns:
+            bool
+        """
     def mtp_acceptance_rate(self, /) -> float | None:
         """
         MTP draft acceptance rate for the most recent generation, in [0.0, 1.0].
@@ -130,6 +226,30 @@ class Chat:
         Returns:
             Optional[float]
         """
+    def regenerate(self, /, sampler: "SamplerConfig | None" = None) -> "TokenStream":
+        """
+        Generate a new response to the last user message, replacing the previous response
+        and any tool calls it made.
+
+        Args:
+            sampler: Sampler to use for this response only. Defaults to the chat's sampler.
+
+        Returns:
+            A TokenStream that yields tokens as they are generated
+        """
+    def render_prompt(self, /) -> "str":
+        """
+        Render the current chat history with the chat template, without generating anything.
+
+        This is the exact text the model reads, including role markers, the system prompt and
+        any tool definitions the template injects. Useful for debugging odd output.
+
+        Returns:
+            The templated prompt
+
+        Raises:
+            RuntimeError: If the chat template fails to render
+        """
     def reset(self, /, system_prompt: str | None, tools: Sequence[Tool]) -> None:
         """
         Reset the conversation with a new system prompt and tools. Clears all chat history.
@@ -235,7 +355,7 @@ class Chat:
         Stop the current text generation immediately.
 
         This can be used to cancel an in-progress generation if the response is taking too long
-        or is no longer needed.
+        or is no longer needed. Does nothing once the chat is closed.
         """
     def tokenize(self, /, prompt: "str | Prompt") -> "list[int | None]":
         """
@@ -256,6 +376,31 @@ class Chat:
         Raises:
             RuntimeError: If tokenization fails
         """
+    def warmup(self, /) -> None:
+        """
+        Read the system prompt (and any chat history) into the context ahead of time, so the
+        first `ask` only has to read the new message. This method blocks.
+        Useful during a loading screen, since reading a long system prompt can take a while.
+
+        Raises:
+            RuntimeError: If the prompt can't be read into the context
+        """
+    def would_be_context_tokens(self, /, prompt: "str | Prompt") -> "int":
+        """
+        Count the tokens the whole chat would take up in the context with `prompt` as the next
+        user message, chat template included. The chat itself is left unchanged.
+
+        Compare it to the context size to trim the history before a message would overflow it.
+
+        Args:
+            prompt: The text or multimodal Prompt of the next message
+
+        Returns:
+            The number of tokens
+
+        Raises:
+            RuntimeError: If the chat can't be rendered or tokenized
+        """
 
 @final
 class ChatAsync:
@@ -263,6 +408,12 @@ class ChatAsync:
     This is the async version of the `Chat` class.
     See the docs for the `Chat` class for more information.
     """
+    async def __aenter__(self, /) -> ChatAsync: ...
+    async def __aexit__(
+        self, /, exc_type: object, exc_value: object, traceback: object
+    ) -> None: ...
+    def __enter__(self, /) -> ChatAsync: ...
+    def __exit__(self, /, exc_type: object, exc_value: object, traceback: object) -> bool: ...
     def __new__(
         cls,
         /,
@@ -274,6 +425,11 @@ class ChatAsync:
         sampler: "SamplerConfig | None" = None,
         allow_thinking: "bool | None" = None,
         mtp: "MtpConfig | None" = None,
+        n_threads: "int | None" = None,
+        n_threads_batch: "int | None" = None,
+        rope_scaling: "typing.Literal['none', 'linear', 'yarn'] | None" = None,
+        rope_freq_base: "float | None" = None,
+        rope_freq_scale: "float | None" = None,
     ) -> "ChatAsync":
         """
         Create a new async Chat instance for conversational text generation.
@@ -291,11 +447,22 @@ class ChatAsync:
             mtp: Optional MtpConfig to enable MTP speculative decoding on this chat.
                 Requires the `Model` to have been loaded with a compatible
                 `draft_model_path`. Adds around 5% to VRAM usage. Defaults to None.
+            n_threads: Number of threads used to generate tokens. Only affects CPU inference.
+                Defaults to the number of available CPU cores.
+            n_threads_batch: Number of threads used to read prompts. Only affects CPU inference.
+                Defaults to the number of available CPU cores.
+            rope_scaling: RoPE scaling method: "none", "linear" or "yarn". Defaults to the one in
+                the model file. Setting any RoPE option allows `n_ctx` to exceed the context the
+                model was trained with, but output quality degrades the further it is stretched.
+            rope_freq_base: RoPE base frequency. Defaults to the one in the model file.
+            rope_freq_scale: RoPE frequency scaling factor, the inverse of the context extension
+                (e.g. 0.25 for 4x the trained context). Defaults to the one in the model file.
 
         Returns:
             A ChatAsync instance
 
         Raises:
+            ValueError: If `rope_scaling` is not a valid scaling method
             RuntimeError: If the model cannot be loaded
         """
     def ask(self, /, prompt: "str | Prompt") -> "TokenStreamAsync":
@@ -305,6 +472,82 @@ class ChatAsync:
         Args:
             prompt: The user prompt to send (plain text or a multimodal Prompt)
 
+        Returns:
+            A TokenStreamAsync that yields tokens as they are generated
+        """
+    def ask_structured(
+        self, /, prompt: "str | Prompt", response_type: "type"
+    ) -> "typing.Awaitable[typing.Any]":
+        """
+        Ask for a response matching `response_type` and return it as an instance of that type.
+
+        The JSON schema of `response_type` constrains generation for this turn only, and the
+        generated JSON is validated with pydantic. Works with pydantic models, dataclasses and
+        anything else pydantic's `TypeAdapter` accepts.
+
+        Args:
+            prompt: The user prompt to send (plain text or a multimodal Prompt)
+            response_type: The type to parse the response into, e.g. a pydantic model
+
+        Returns:
+            An instance of `response_type`
+
+        Raises:
+            ImportError: If pydantic is not installed
+            ValueError: If the generated output does not validate against `response_type`
+        """
+    def ask_structured_stream(
+        self, /, prompt: "str | Prompt", response_type: "type"
+    ) -> "JsonFieldStreamAsync":
+        """
+        Ask for a JSON object matching `response_type`, and stream its top-level fields as
+        they are completed. Useful for updating a UI field by field, e.g. showing `name` before
+        the rest of the object is done.
+
+        The JSON schema of `response_type` constrains generation for this turn only. Values are
+        plain JSON values (dicts, lists, strings, numbers, ...); they are not validated, since
+        the object isn't complete yet. Use `await stream.completed()` for the full JSON text.
+
+        Args:
+            prompt: The user prompt to send (plain text or a multimodal Prompt)
+            response_type: The type describing the object, e.g. a pydantic model
+
+        Returns:
+            A JsonFieldStreamAsync that yields `(key, value)` tuples
+
+        Raises:
+            ImportError: If pydantic is not installed
+            ValueError: If the schema of `response_type` uses unsupported features
+
+        Example:
+            async for key, value in chat.ask_structured_stream("Invent a sword", Weapon):
+                print(f"{key}: {value}")
+        """
+    async def cancel_generation(self, /) -> None:
+        """
+        Stop the current text generation, and remove the stopped turn from the chat history.
+
+        Unlike `stop_generation()`, neither the user message nor the partial response is kept,
+        so the history is as if the turn never happened. Use this to let the user interrupt
+        and ask again cleanly. Does nothing once the chat is closed.
+        """
+    async def close(self, /) -> None:
+        """
+        Stop the background worker and free its context. Any generation in progress is
+        stopped. Using the chat afterwards raises RuntimeError.
+
+        Called when leaving an `async with` (or `with`) block. Without it, the worker only
+        stops once the `ChatAsync` is garbage collected, so servers that create many chats
+        should close them.
+        """
+    def edit_last_user_message(self, /, prompt: "str | Prompt") -> "TokenStreamAsync":
+        """
+        Replace the last user message with `prompt` and generate a response to it.
+        The old user message and everything after it is removed from the chat history.
+
+        Args:
+            prompt: The edited user prompt (plain text or a multimodal Prompt)
+
         Returns:
             A TokenStreamAsync that yields tokens as they are generated
         """
@@ -319,6 +562,27 @@ class ChatAsync:
         Raises:
             RuntimeError: If retrieval fails
         """
+    async def get_display_history(
+        self, /, tool_calls: "typing.Literal['omit', 'collapse']" = "omit"
+    ) -> "list[dict]":
+        """
+        Get the chat history for showing to people, e.g. in a chat UI.
+
+        Unlike `get_chat_history()`, tool calls and tool responses are left out or collapsed.
+        Use `get_chat_history()` for a history to pass back to `set_chat_history()`.
+
+        Args:
+            tool_calls: "omit" leaves tool calls and their responses out, "collapse" replaces
+                each round of them with one assistant message, with a line like
+                `Called get_weather({"city":"Paris"}): 18 degrees` per call. Defaults to "omit".
+
+        Returns:
+            List of message dicts, each with 'role' (str) and 'content' (str) keys.
+
+        Raises:
+            ValueError: If `tool_calls` is not a valid value
+            RuntimeError: If retrieval fails
+        """
     async def get_sampler_config(self, /) -> SamplerConfig:
         """
         Get the current sampler configuration.
@@ -349,6 +613,15 @@ class ChatAsync:
         Raises:
             RuntimeError: If the variables cannot be retrieved
         """
+    def is_alive(self, /) -> bool:
+        """
+        Whether the chat's worker is still running. A crashed worker makes every following
+        response raise `WorkerDeadError`, so this can be used to replace the chat.
+        A closed chat is not alive.
+
+        Returns:
+            bool
+        """
     async def mtp_acceptance_rate(self, /) -> float | None:
         """
         MTP draft acceptance rate for the most recent generation, in [0.0, 1.0].
@@ -358,6 +631,32 @@ class ChatAsync:
         Returns:
             Optional[float]
         """
+    def regenerate(
+        self, /, sampler: "SamplerConfig | None" = None
+    ) -> "TokenStreamAsync":
+        """
+        Generate a new response to the last user message, replacing the previous response
+        and any tool calls it made.
+
+        Args:
+            sampler: Sampler to use for this response only. Defaults to the chat's sampler.
+
+        Returns:
+            A TokenStreamAsync that yields tokens as they are generated
+        """
+    async def render_prompt(self, /) -> "str":
+        """
+        Render the current chat history with the chat template, without generating anything.
+
+        This is the exact text the model reads, including role markers, the system prompt and
+        any tool definitions the template injects. Useful for debugging odd output.
+
+        Returns:
+            The templated prompt
+
+        Raises:
+            RuntimeError: If the chat template fails to render
+        """
     async def reset(self, /, system_prompt: str | None, tools: Sequence[Tool]) -> None:
         """
         Reset the conversation with a new system prompt and tools. Clears all chat history.
@@ -463,7 +762,7 @@ class ChatAsync:
         Stop the current text generation immediately.
 
         This can be used to cancel an in-progress generation if the response is taking too long
-        or is no longer needed.
+        or is no longer needed. Does nothing once the chat is closed.
         """
     async def tokenize(self, /, prompt: "str | Prompt") -> "list[int | None]":
         """
@@ -484,6 +783,31 @@ class ChatAsync:
         Raises:
             RuntimeError: If tokenization fails
         """
+    async def warmup(self, /) -> None:
+        """
+        Read the system prompt (and any chat history) into the context ahead of time, so the
+        first `ask` only has to read the new message. Useful during a loading screen, since
+        reading a long system prompt can take a while.
+
+        Raises:
+            RuntimeError: If the prompt can't be read into the context
+        """
+    async def would_be_context_tokens(self, /, prompt: "str | Prompt") -> "int":
+        """
+        Count the tokens the whole chat would take up in the context with `prompt` as the next
+        user message, chat template included. The chat itself is left unchanged.
+
+        Compare it to the context size to trim the history before a message would overflow it.
+
+        Args:
+            prompt: The text or multimodal Prompt of the next message
+
+        Returns:
+            The number of tokens
+
+        Raises:
+            RuntimeError: If the chat can't be rendered or tokenized
+        """
 
 @final
 class ChatStats:
@@ -502,6 +826,106 @@ class ChatStats:
         The number of tokens currently used in the context (KV cache position).
         """
 
+@final
+class Completion:
+    """
+    `Completion` continues a piece of text, without applying any chat template.
+    This is what you want for base models, which often don't have a chat template,
+    or whenever you want plain text continuation rather than a conversation.
+    Every call to `.complete()` is independent: nothing is remembered between calls.
+    See `CompletionAsync` for the async version of this class.
+    """
+    def __new__(
+        cls, /, model: "Model | os.PathLike | str", n_ctx: int = 4096
+    ) -> "Completion":
+        """
+        Create a new Completion instance for plain text continuation.
+
+        Args:
+            model: A text generation model (Model instance, local path, `huggingface:` path, or `https://` URL to a GGUF file)
+            n_ctx: Context size (maximum length of prompt and completion together, in tokens). Defaults to 4096.
+
+        Returns:
+            A Completion instance
+
+        Raises:
+            RuntimeError: If the model cannot be loaded
+        """
+    def complete(
+        self,
+        /,
+        prompt: "str",
+        sampler: "SamplerConfig | None" = None,
+        stop_words: "list[str]" = ...,
+    ) -> "TokenStream":
+        """
+        Continue `prompt` and get a streaming response.
+
+        Args:
+            prompt: The text to continue. It is passed to the model as-is.
+            sampler: SamplerConfig for token selection. Defaults to SamplerConfig.default().
+            stop_words: Generation stops right before the first occurrence of any of these strings.
+                The stop word itself is not included in the output. Defaults to no stop words.
+
+        Returns:
+            A TokenStream that yields tokens as they are generated
+        """
+    def stop_generation(self, /) -> None:
+        """
+        Stop the current completion immediately.
+        """
+
+@final
+class CompletionAsync:
+    """
+    This is the async version of the `Completion` class.
+    See the docs for the `Completion` class for more information.
+    """
+    def __new__(
+        cls, /, model: "Model | os.PathLike | str", n_ctx: int = 4096
+    ) -> "CompletionAsync":
+        """
+        Create a new async Completion instance for plain text continuation.
+
+        Args:
+            model: A text generation model (Model instance, local path, `huggingface:` path, or `https://` URL to a GGUF file)
+            n_ctx: Context size (maximum length of prompt and completion together, in tokens). Defaults to 4096.
+
+        Returns:
+            A CompletionAsync instance
+
+        Raises:
+            RuntimeError: If the model cannot be loaded
+        """
+    def complete(
+        self,
+        /,
+        prompt: "str",
+        sampler: "SamplerConfig | None" = None,
+        stop_words: "list[str]" = ...,
+    ) -> "TokenStreamAsync":
+        """
+        Continue `prompt` and get a streaming response asynchronously.
+
+        Args:
+            prompt: The text to continue. It is passed to the model as-is.
+            sampler: SamplerConfig for token selection. Defaults to SamplerConfig.default().
+            stop_words: Generation stops right before the first occurrence of any of these strings.
+                The stop word itself is not included in the output. Defaults to no stop words.
+
+        Returns:
+            A TokenStreamAsync that yields tokens as they are generated
+        """
+    async def stop_generation(self, /) -> None:
+        """
+        Stop the current completion immediately.
+        """
+
+class ContextOverflowError(RuntimeError):
+    """
+    Raised when the system prompt and the last message together don't fit in the context.
+    """
+
 @final
 class CrossEncoder:
     """
@@ -552,10 +976,23 @@ class CrossEncoder:
 
         Returns:
             List of (document, score) tuples sorted by descending similarity (most similar first).
+            Documents with equal scores keep their input order, and NaN scores go last.
 
         Raises:
             RuntimeError: If ranking fails
         """
+    def rank_stream(self, /, query: str, documents: Sequence[str]) -> RankStream:
+        """
+        Score documents one at a time, yielding results as soon as each is computed.
+        Useful for showing progress or partial results when ranking many documents.
+
+        Args:
+            query: The query text
+            documents: List of documents to compare against the query
+
+        Returns:
+            A RankStream yielding (index, score) tuples, in document order
+        """
 
 @final
 class CrossEncoderAsync:
@@ -605,10 +1042,25 @@ class CrossEncoderAsync:
 
         Returns:
             List of (document, score) tuples sorted by descending similarity (most similar first).
+            Documents with equal scores keep their input order, and NaN scores go last.
 
         Raises:
             RuntimeError: If ranking fails
         """
+    def rank_stream(
+        self, /, query: str, documents: Sequence[str]
+    ) -> RankStreamAsync:
+        """
+        Score documents one at a time, yielding results as soon as each is computed.
+        Use with `async for index, score in crossencoder.rank_stream(query, documents)`.
+
+        Args:
+            query: The query text
+            documents: List of documents to compare against the query
+
+        Returns:
+            A RankStreamAsync yielding (index, score) tuples, in document order
+        """
 
 @final
 class Encoder:
@@ -620,7 +1072,15 @@ class Encoder:
     See `EncoderAsync` for the async version of this class.
     """
     def __new__(
-        cls, /, model: "Model | os.PathLike | str", n_ctx: int = 4096
+        cls,
+        /,
+        model: "Model | os.PathLike | str",
+        n_ctx: int = 4096,
+        deterministic: bool = False,
+        pooling: "typing.Literal['mean', 'cls', 'last'] | None" = None,
+        n_threads: "int | None" = None,
+        n_threads_batch: "int | None" = None,
+        on_overflow: "typing.Literal['truncate', 'error']" = "truncate",
     ) -> "Encoder":
         """
         Create a new Encoder for generating text embeddings.
@@ -628,13 +1088,33 @@ class Encoder:
         Args:
             model: An embedding model (Model instance, local path, `huggingface:` path, or `https://` URL to a GGUF file)
             n_ctx: Context size (maximum sequence length). Defaults to 4096.
+            deterministic: If True, the same text always yields a bit-identical embedding. This
+                decodes on a single thread, so encoding is considerably slower on multi-core CPUs.
+                Defaults to False.
+            pooling: How token embeddings are combined into one vector: "mean", "cls" or "last".
+                Defaults to the pooling recommended in the model file. BGE models need "cls",
+                most sentence-transformers models "mean", and decoder-based embedding models "last".
+            n_threads: Number of threads used for encoding. Only affects CPU inference.
+                Defaults to the number of available CPU cores.
+            n_threads_batch: Number of threads used to read text in batches. Only affects CPU
+                inference. Defaults to the number of available CPU cores.
+            on_overflow: What to do with texts longer than the encoder can read at once: "truncate"
+                embeds only the first tokens that fit and logs a warning, "error" raises. For RAG,
+                prefer "error" and chunk your documents, so no text silently drops out of the index.
+                Defaults to "truncate".
 
         Returns:
             An Encoder instance
 
         Raises:
+            ValueError: If `pooling` or `on_overflow` is not a valid value
             RuntimeError: If the model cannot be loaded
         """
+    @property
+    def dim(self, /) -> int:
+        """
+        The length of the embedding vectors this encoder returns, read from the model metadata.
+        """
     def encode(self, /, text: str) -> list[float]:
         """
         Generate an embedding vector for the given text. This method blocks until complete.
@@ -646,7 +1126,7 @@ class Encoder:
             A list of floats representing the embedding vector
 
         Raises:
-            RuntimeError: If encoding fails
+            RuntimeError: If encoding fails, or if the text is too long and `on_overflow` is "error"
         """
 
 @final
@@ -655,7 +1135,15 @@ class EncoderAsync:
     This is the async version of the `Encoder` class. See the docs on `Encoder` for more detail.
     """
     def __new__(
-        cls, /, model: "Model | os.PathLike | str", n_ctx: int = 4096
+        cls,
+        /,
+        model: "Model | os.PathLike | str",
+        n_ctx: int = 4096,
+        deterministic: bool = False,
+        pooling: "typing.Literal['mean', 'cls', 'last'] | None" = None,
+        n_threads: "int | None" = None,
+        n_threads_batch: "int | None" = None,
+        on_overflow: "typing.Literal['truncate', 'error']" = "truncate",
     ) -> "EncoderAsync":
         """
         Create a new async Encoder for generating text embeddings.
@@ -663,13 +1151,33 @@ class EncoderAsync:
         Args:
             model: An embedding model (Model instance, local path, `huggingface:` path, or `https://` URL to a GGUF file)
             n_ctx: Context size (maximum sequence length). Defaults to 4096.
+            deterministic: If True, the same text always yields a bit-identical embedding. This
+                decodes on a single thread, so encoding is considerably slower on multi-core CPUs.
+                Defaults to False.
+            pooling: How token embeddings are combined into one vector: "mean", "cls" or "last".
+                Defaults to the pooling recommended in the model file. BGE models need "cls",
+                most sentence-transformers models "mean", and decoder-based embedding models "last".
+            n_threads: Number of threads used for encoding. Only affects CPU inference.
+                Defaults to the number of available CPU cores.
+            n_threads_batch: Number of threads used to read text in batches. Only affects CPU
+                inference. Defaults to the number of available CPU cores.
+            on_overflow: What to do with texts longer than the encoder can read at once: "truncate"
+                embeds only the first tokens that fit and logs a warning, "error" raises. For RAG,
+                prefer "error" and chunk your documents, so no text silently drops out of the index.
+                Defaults to "truncate".
 
         Returns:
             An EncoderAsync instance
 
         Raises:
+            ValueError: If `pooling` or `on_overflow` is not a valid value
             RuntimeError: If the model cannot be loaded
         """
+    @property
+    def dim(self, /) -> int:
+        """
+        The length of the embedding vectors this encoder returns, read from the model metadata.
+        """
     async def encode(self, /, text: str) -> list[float]:
         """
         Generate an embedding vector for the given text asynchronously.
@@ -681,7 +1189,7 @@ class EncoderAsync:
             A list of floats representing the embedding vector
 
         Raises:
-            RuntimeError: If encoding fails
+            RuntimeError: If encoding fails, or if the text is too long and `on_overflow` is "error"
         """
 
 @final
@@ -697,6 +1205,20 @@ class Image:
     @property
     def path(self, /) -> str: ...
 
+@final
+class JsonFieldStreamAsync:
+    """
+    `JsonFieldStreamAsync` yields `(key, value)` tuples from `ChatAsync.ask_structured_stream`,
+    one for each top-level field of the generated JSON object, as soon as its value is complete.
+    Supports `async for key, value in stream` and `await stream.completed()`.
+    """
+    def __aiter__(self, /) -> JsonFieldStreamAsync: ...
+    def __anext__(self, /) -> typing.Awaitable[tuple[str, typing.Any]]: ...
+    async def completed(self, /) -> str:
+        """
+        Wait for generation to finish and return the full generated JSON text.
+        """
+
 @final
 class Model:
     """
@@ -730,6 +1252,39 @@ class Model:
         Raises:
             RuntimeError: If the model file cannot be loaded
         """
+    def count_tokens(self, /, text: str) -> int:
+        """
+        Count the tokens in a piece of text.
+
+        No chat template is applied, so this does not include the tokens a `Chat` adds
+        around each message. Use it for budgeting, not as an exact measure of context usage.
+
+        Args:
+            text: The text to count tokens in
+
+        Returns:
+            The number of tokens
+        """
+    def detokenize(self, /, ids: Sequence[int]) -> str:
+        """
+        Convert token ids back to text.
+
+        Args:
+            ids: The token ids to convert
+
+        Returns:
+            The decoded text
+        """
+    def id_to_token(self, /, id: int) -> str | None:
+        """
+        The text of a single token, with special tokens rendered as text.
+
+        Args:
+            id: The token id
+
+        Returns:
+            The token's text, or None if the id is outside the vocabulary
+        """
     @staticmethod
     async def load_model_async(
         model_path: "os.PathLike | str",
@@ -763,6 +1318,35 @@ class Model:
         """
         The maximum context size this model was trained with.
         """
+    @property
+    def n_vocab(self, /) -> int:
+        """
+        The number of tokens in the vocabulary. Valid token ids are `0` to `n_vocab - 1`.
+        """
+    def token_to_id(self, /, piece: str) -> int | None:
+        """
+        Find the token whose text is exactly `piece`, special tokens like `<|im_end|>` included.
+        Useful for token references in grammars and for debugging tokenization.
+
+        Args:
+            piece: The text of the token
+
+        Returns:
+            The token id, or None if no single token has that text
+        """
+    def tokenize(self, /, text: str) -> list[int]:
+        """
+        Convert text to token ids using the model's vocabulary.
+
+        No chat template is applied, so this does not include the tokens a `Chat` adds
+        around each message (role markers, special tokens, etc.).
+
+        Args:
+            text: The text to tokenize
+
+        Returns:
+            A list of token ids
+        """
 
 @final
 class MtpConfig:
@@ -813,6 +1397,23 @@ class Prompt:
     @staticmethod
     def from_json(data: "object") -> "Prompt": ...
 
+@final
+class RankStream:
+    """
+    `RankStream` yields `(index, score)` tuples from `CrossEncoder.rank_stream`, as each
+    document is scored. Iterate over it with a normal `for` loop.
+    """
+    def __iter__(self, /) -> RankStream: ...
+    def __next__(self, /) -> tuple[int, float]: ...
+
+@final
+class RankStreamAsync:
+    """
+    `RankStreamAsync` is the async variant of `RankStream`. Supports `async for index, score in stream`.
+    """
+    def __aiter__(self, /) -> RankStreamAsync: ...
+    def __anext__(self, /) -> typing.Awaitable[tuple[int, float]]: ...
+
 @final
 class STT:
     """
@@ -900,17 +1501,30 @@ class SamplerBuilder:
         base: float,
         allowed_length: int,
         penalty_last_n: int,
-        seq_breakers: Sequence[str],
+        seq_breakers: Sequence[str] = ...,
     ) -> SamplerBuilder:
         """
         DRY (Don't Repeat Yourself) sampler to reduce repetition.
 
+        DRY penalizes tokens that would extend a sequence that already occurred earlier in
+        the context. See `dry_default()` for llama.cpp's recommended settings.
+
         Args:
-            multiplier: Penalty strength multiplier
-            base: Base penalty value
-            allowed_length: Maximum allowed repetition length
-            penalty_last_n: Number of recent tokens to consider
-            seq_breakers: List of strings that break repetition sequences
+            multiplier: Penalty strength. 0.0 disables DRY, 0.8 is the recommended value.
+            base: How fast the penalty grows with the length of the repeated sequence.
+                  Typical: 1.75.
+            allowed_length: Repeated sequences up to this many tokens are not penalized.
+                            Typical: 2.
+            penalty_last_n: How many recent tokens to search for repetitions.
+                            -1 means the whole context.
+            seq_breakers: Strings that end a repeated sequence, so matching never crosses
+                          them. When empty, newline, colon, double quote and asterisk are used.
+        """
+    def dry_default(self, /) -> SamplerBuilder:
+        """
+        DRY (Don't Repeat Yourself) sampler with llama.cpp's recommended settings:
+        multiplier 0.8, base 1.75, allowed_length 2, the whole context, and newline, colon,
+        double quote and asterisk as sequence breakers.
         """
     def grammar(
         self, /, grammar: str, trigger_on: str | None, root: str
@@ -989,6 +1603,10 @@ class SamplerBuilder:
         """
         Set the RNG seed used by random samplers (`dist`, `mirostat_v1`, `mirostat_v2`, `xtc`).
         `greedy` ignores it. If unset, a default seed is used.
+        Two generations with the same seed, prompt and sampler chain yield identical output.
+
+        Args:
+            seed: The RNG seed
         """
     def temperature(self, /, temperature: float) -> SamplerBuilder:
         """
@@ -1077,6 +1695,19 @@ class SamplerPresets:
     E.g. `SamplerPresets.temperature(0.8)` will return a `SamplerConfig` with temperature=0.8.
     """
     @staticmethod
+    def choice(options: Sequence[str]) -> SamplerConfig:
+        """
+        Create a sampler that constrains output to exactly one of the given options.
+
+        The options are matched literally, with no whitespace around them.
+
+        Args:
+            options: The strings the output may be
+
+        Raises:
+            ValueError: If `options` is empty
+        """
+    @staticmethod
     def constrain_with_grammar(grammar: str) -> SamplerConfig:
         """
         Create a sampler that constrains output using a Lark grammar via llguidance.
@@ -1101,6 +1732,12 @@ class SamplerPresets:
             pattern: Regular expression pattern
         """
     @staticmethod
+    def creative() -> SamplerConfig:
+        """
+        Create a sampler suited to long-form creative writing. Combines repetition penalties,
+        DRY, min_p and a high temperature to produce varied text without getting stuck in loops.
+        """
+    @staticmethod
     def default() -> SamplerConfig:
         """
         Get the default sampler configuration.
@@ -1128,6 +1765,12 @@ class SamplerPresets:
         For schema-validated JSON, use `constrain_with_json_schema()` instead.
         """
     @staticmethod
+    def precise() -> SamplerConfig:
+        """
+        Create a sampler suited to factual tasks. Uses a narrow candidate set and a very low
+        temperature, so output is close to deterministic.
+        """
+    @staticmethod
     def temperature(temperature: float) -> SamplerConfig:
         """
         Create a sampler with temperature scaling.
@@ -1165,6 +1808,57 @@ class Text:
     @property
     def text(self, /) -> str: ...
 
+class TokenEvent:
+    """
+    An event on a `TokenStream`, see `TokenStream.events()`.
+    `arguments` holds the tool call arguments as a JSON string.
+    """
+    @final
+    class Text(TokenEvent):
+        """
+        A piece of generated text.
+        """
+        __match_args__ = ("text",)
+        def __new__(cls, /, text: str) -> TokenEvent.Text: ...
+        @property
+        def text(self, /) -> str: ...
+
+    @final
+    class ToolCallFinished(TokenEvent):
+        """
+        A tool finished running and returned `response`.
+        """
+        __match_args__ = ("name", "arguments", "response")
+        def __new__(
+            cls, /, name: str, arguments: str, response: str
+        ) -> TokenEvent.ToolCallFinished: ...
+        @property
+        def arguments(self, /) -> str: ...
+        @property
+        def name(self, /) -> str: ...
+        @property
+        def response(self, /) -> str: ...
+
+    @final
+    class ToolCallStarted(TokenEvent):
+        """
+        The model called a tool, which is about to run.
+        """
+        __match_args__ = ("name", "arguments")
+        def __new__(cls, /, name: str, arguments: str) -> TokenEvent.ToolCallStarted: ...
+        @property
+        def arguments(self, /) -> str: ...
+        @property
+        def name(self, /) -> str: ...
+
+@final
+class TokenEventIterator:
+    """
+    Iterator over the `TokenEvent`s of a `TokenStream`. Returned by `TokenStream.events()`.
+    """
+    def __iter__(self, /) -> TokenEventIterator: ...
+    def __next__(self, /) -> TokenEvent: ...
+
 @final
 class TokenStream:
     """
@@ -1177,6 +1871,23 @@ class TokenStream:
     def __iter__(self, /) -> TokenStream: ...
     def __next__(self, /) -> str: ...
     def completed(self, /) -> str: ...
+    def events(self, /) -> TokenEventIterator:
+        """
+        Iterate over `TokenEvent`s instead of plain token strings.
+
+        Example:
+            for event in chat.ask("What's the weather in Copenhagen?").events():
+                match event:
+                    case TokenEvent.Text(text=text):
+                        print(text, end="")
+                    case TokenEvent.ToolCallStarted(name=name, arguments=arguments):
+                        print(f"calling {name}({arguments})")
+        """
+    def next_event(self, /) -> TokenEvent | None:
+        """
+        Return the next `TokenEvent`, or `None` when generation is finished.
+        Unlike `next_token`, this also reports the tool calls the model makes.
+        """
     def next_token(self, /) -> str | None: ...
 
 @final
@@ -1197,6 +1908,11 @@ class Tool(typing.Generic[T]):
     `Tool`s are constructed using the `@tool` decorator.
     """
     def __call__(self, /, *args, **kwargs) -> "T": ...
+    @property
+    def json_schema(self, /) -> typing.Any:
+        """
+        The JSON schema describing this tool's parameters, as shown to the model.
+        """
 
 @final
 class Tts:
@@ -1244,6 +1960,11 @@ class Tts:
         Synthesize text asynchronously and return WAV bytes.
         """
 
+class WorkerDeadError(RuntimeError):
+    """
+    Raised when the worker behind a chat has crashed. Create a new chat to continue.
+    """
+
 def bash_tool(max_commands: int | None = None) -> Tool:
     """
     Create a bash interpreter tool that the LLM can use to run bash snippets.
@@ -1306,6 +2027,24 @@ def get_cached_models() -> list[tuple[str, int]]:
         RuntimeError: If the cache directory cannot be read
     """
 
+def json_schema_to_grammar(schema: str, root: str = ...) -> str:
+    """
+    Convert a JSON schema to a GBNF grammar, without running any model.
+
+    Useful for inspecting (or hand-editing) the grammar before passing it to
+    `SamplerPresets.constrain_with_grammar()`.
+
+    Args:
+        schema: JSON schema as a string
+        root: Name of the root rule in the generated grammar
+
+    Returns:
+        The grammar in GBNF format
+
+    Raises:
+        ValueError: If the schema is invalid or uses unsupported features
+    """
+
 def python_tool(
     max_duration: int | None = None,
     max_memory: int | None = None,
@@ -7,6 +7,31 @@ use nobodywho::render_miette;
 
 mod parse;
 
+pyo3::create_exception!(
+    nobodywho,
+    ContextOverflowError,
+    pyo3::exceptions::PyRuntimeError,
+    "Raised when the system prompt and the last message together don't fit in the context."
+);
+
+pyo3::create_exception!(
+    nobodywho,
+    WorkerDeadError,
+    pyo3::exceptions::PyRuntimeError,
+    "Raised when the worker behind a chat has crashed. Create a new chat to continue."
+);
+
+/// Turn an error from a chat stream into a Python exception, picking a specific exception
+/// type where there is one.
+fn completion_error_to_py(e: &nobodywho::errors::CompletionError) -> PyErr {
+    let message = render_miette(e);
+    match e.diagnostic_code().as_deref() {
+        Some("nobodywho::context_overflow") => ContextOverflowError::new_err(message),
+        Some("nobodywho::worker_dead") => WorkerDeadError::new_err(message),
+        _ => pyo3::exceptions::PyRuntimeError::new_err(message),
+    }
+}
+
 /// Gate for forwarding tracing events to Python's logging module.
 /// Set to `true` after pyo3_log is installed, set to `false` via an `atexit`
 /// handler before `Py_FinalizeEx` runs. This prevents worker threads from
@@ -203,6 +228,80 @@ impl Model {
     pub fn max_ctx(&self) -> u32 {
         self.model.max_ctx()
     }
+
+    /// Convert text to token ids using the model's vocabulary.
+    ///
+    /// No chat template is applied, so this does not include the tokens a `Chat` adds
+    /// around each message (role markers, special tokens, etc.).
+    ///
+    /// Args:
+    ///     text: The text to tokenize
+    ///
+    /// Returns:
+    ///     A list of token ids
+    pub fn tokenize(&self, text: &str) -> PyResult<Vec<i32>> {
+        self.model
+            .tokenize(text)
+            .map_err(|e| pyo3::exceptions::PyRuntimeError::new_err(e.to_string()))
+    }
+
+    /// Count the tokens in a piece of text.
+    ///
+    /// No chat template is applied, so this does not include the tokens a `Chat` adds
+    /// around each message. Use it for budgeting, not as an exact measure of context usage.
+    ///
+    /// Args:
+    ///     text: The text to count tokens in
+    ///
+    /// Returns:
+    ///     The number of tokens
+    pub fn count_tokens(&self, text: &str) -> PyResult<usize> {
+        self.model
+            .count_tokens(text)
+            .map_err(|e| pyo3::exceptions::PyRuntimeError::new_err(e.to_string()))
+    }
+
+    /// Convert token ids back to text.
+    ///
+    /// Args:
+    ///     ids: The token ids to convert
+    ///
+    /// Returns:
+    ///     The decoded text
+    pub fn detokenize(&self, ids: Vec<i32>) -> PyResult<String> {
+        self.model
+            .detokenize(&ids)
+            .map_err(|e| pyo3::exceptions::PyRuntimeError::new_err(e.to_string()))
+    }
+
+    /// The number of tokens in the vocabulary. Valid token ids are `0` to `n_vocab - 1`.
+    #[getter]
+    pub fn n_vocab(&self) -> usize {
+        self.model.n_vocab()
+    }
+
+    /// The text of a single token, with special tokens rendered as text.
+    ///
+    /// Args:
+    ///     id: The token id
+    ///
+    /// Returns:
+    ///     The token's text, or None if the id is outside the vocabulary
+    pub fn id_to_token(&self, id: i32) -> Option<String> {
+        self.model.id_to_token(id)
+    }
+
+    /// Find the token whose text is exactly `piece`, special tokens like `<|im_end|>` included.
+    /// Useful for token references in grammars and for debugging tokenization.
+    ///
+    /// Args:
+    ///     piece: The text of the token
+    ///
+    /// Returns:
+    ///     The token id, or None if no single token has that text
+    pub fn token_to_id(&self, piece: &str, py: Python) -> Option<i32> {
+        py.detach(|| self.model.token_to_id(piece))
+    }
 }
 
 /// This type represents a `Model | str` from python
@@ -365,23 +464,88 @@ impl STTAsync {
 // Token streams (shared by Chat and STT)
 // ---------------------------------------------------------------------------
 
-// Type-erased inner for sync streams — lets Chat and STT share one pyclass.
+// Type-erased inner for sync streams — lets Chat, Completion and STT share one pyclass.
 enum SyncStreamInner {
-    Chat(nobodywho::chat::TokenStream),
+    Chat {
+        rx: tokio::sync::mpsc::UnboundedReceiver<nobodywho::chat::ChatEvent>,
+        done: Option<String>,
+    },
     Stt(nobodywho::stream::TokenStream<nobodywho::errors::SttError>),
+    Completion(nobodywho::chat::TokenStream),
 }
 
 impl SyncStreamInner {
-    fn next_token(&mut self) -> Result<Option<String>, String> {
+    fn next_event(&mut self) -> PyResult<Option<TokenEvent>> {
+        use nobodywho::chat::ChatEvent;
         match self {
-            Self::Chat(s) => s.next_token().map_err(|e| render_miette(&e)),
-            Self::Stt(s) => s.next_token().map_err(|e| e.to_string()),
+            Self::Chat { rx, done } => {
+                if done.is_some() {
+                    return Ok(None);
+                }
+                match rx.blocking_recv() {
+                    Some(ChatEvent::Token(text)) => Ok(Some(TokenEvent::Text { text })),
+                    Some(ChatEvent::ToolCallStarted(tool_call)) => {
+                        Ok(Some(TokenEvent::ToolCallStarted {
+                            name: tool_call.name,
+                            arguments: tool_call.arguments.to_string(),
+                        }))
+                    }
+                    Some(ChatEvent::ToolCallFinished {
+                        tool_call,
+                        response,
+                        ..
+                    }) => Ok(Some(TokenEvent::ToolCallFinished {
+                        name: tool_call.name,
+                        arguments: tool_call.arguments.to_string(),
+                        response,
+                    })),
+                    Some(ChatEvent::Done(text)) => {
+                        *done = Some(text);
+                        Ok(None)
+                    }
+                    Some(ChatEvent::Error(e)) => Err(completion_error_to_py(&e)),
+                    // the worker dropped the request without finishing it
+                    None => Err(completion_error_to_py(
+                        &nobodywho::errors::CompletionError::WorkerError(Box::new(
+                            nobodywho::errors::SayError::WorkerDead,
+                        )),
+                    )),
+                }
+            }
+            Self::Stt(s) => Ok(s
+                .next_token()
+                .map_err(|e| pyo3::exceptions::PyRuntimeError::new_err(e.to_string()))?
+                .map(|text| TokenEvent::Text { text })),
+            Self::Completion(s) => Ok(s
+                .next_token()
+                .map_err(|e| completion_error_to_py(&e))?
+                .map(|text| TokenEvent::Text { text })),
+        }
+    }
+    fn next_token(&mut self) -> PyResult<Option<String>> {
+        // tool events are skipped in the plain text stream
+        loop {
+            match self.next_event()? {
+                Some(TokenEvent::Text { text }) => return Ok(Some(text)),
+                Some(_) => continue,
+                None => return Ok(None),
+            }
         }
     }
-    fn completed(&mut self) -> Result<String, String> {
+    fn completed(&mut self) -> PyResult<String> {
+        match self {
+            Self::Stt(s) => {
+                return s
+                    .completed()
+                    .map_err(|e| pyo3::exceptions::PyRuntimeError::new_err(e.to_string()))
+            }
+            Self::Completion(s) => return s.completed().map_err(|e| completion_error_to_py(&e)),
+            Self::Chat { .. } => (),
+        }
+        while self.next_event()?.is_some() {}
         match self {
-            Self::Chat(s) => s.completed().map_err(|e| render_miette(&e)),
-            Self::Stt(s) => s.completed().map_err(|e| e.to_string()),
+            Self::Chat { done, .. } => Ok(done.clone().unwrap_or_default()),
+            Self::Stt(_) | Self::Completion(_) => unreachable!(),
         }
     }
 }
@@ -393,16 +557,22 @@ enum AsyncStreamInner {
 }
 
 impl AsyncStreamInner {
-    async fn next_token(&mut self) -> Result<Option<String>, String> {
+    async fn next_token(&mut self) -> PyResult<Option<String>> {
         match self {
-            Self::Chat(s) => s.next_token().await.map_err(|e| render_miette(&e)),
-            Self::Stt(s) => s.next_token().await.map_err(|e| e.to_string()),
+            Self::Chat(s) => s.next_token().await.map_err(|e| completion_error_to_py(&e)),
+            Self::Stt(s) => s
+                .next_token()
+                .await
+                .map_err(|e| pyo3::exceptions::PyRuntimeError::new_err(e.to_string())),
         }
     }
-    async fn completed(&mut self) -> Result<String, String> {
+    async fn completed(&mut self) -> PyResult<String> {
         match self {
-            Self::Chat(s) => s.completed().await.map_err(|e| render_miette(&e)),
-            Self::Stt(s) => s.completed().await.map_err(|e| e.to_string()),
+            Self::Chat(s) => s.completed().await.map_err(|e| completion_error_to_py(&e)),
+            Self::Stt(s) => s
+                .completed()
+                .await
+                .map_err(|e| pyo3::exceptions::PyRuntimeError::new_err(e.to_string())),
         }
     }
 }
@@ -422,6 +592,68 @@ fn parse_tts_device(device: &str) -> PyResult<nobodywho::tts::TtsDevice> {
     }
 }
 
+// builds a pydantic TypeAdapter for `response_type`, along with its json schema
+fn structured_output_adapter(
+    py: Python,
+    response_type: &Bound<'_, PyAny>,
+) -> PyResult<(Py<PyAny>, String)> {
+    let pydantic = PyModule::import(py, "pydantic").map_err(|_| {
+        pyo3::exceptions::PyImportError::new_err(
+            "ask_structured requires pydantic. Install it with `pip install pydantic`",
+        )
+    })?;
+    let adapter = pydantic.getattr("TypeAdapter")?.call1((response_type,))?;
+    let schema = adapter.call_method0("json_schema")?;
+    let schema_str: String = py
+        .import("json")?
+        .call_method1("dumps", (schema,))?
+        .extract()?;
+    Ok((adapter.unbind(), schema_str))
+}
+
+fn validate_structured_output(
+    py: Python,
+    adapter: &Py<PyAny>,
+    output: &str,
+) -> PyResult<Py<PyAny>> {
+    adapter
+        .call_method1(py, "validate_json", (output,))
+        .map_err(|e| {
+            pyo3::exceptions::PyValueError::new_err(format!(
+                "Model output does not match the requested type: {e}\nOutput: {output}"
+            ))
+        })
+}
+
+fn structured_ask_options(schema: String) -> PyResult<nobodywho::chat::AskOptions> {
+    let grammar = nobodywho::gbnf::json::json_schema_to_grammar(schema, "root")
+        .map_err(|e| PyErr::new::<pyo3::exceptions::PyValueError, _>(e.to_string()))?;
+    Ok(nobodywho::chat::AskOptions {
+        grammar: Some(grammar),
+        ..Default::default()
+    })
+}
+
+fn parse_pooling(pooling: &str) -> PyResult<nobodywho::encoder::Pooling> {
+    pooling.parse().map_err(|()| {
+        pyo3::exceptions::PyValueError::new_err("pooling must be one of 'mean', 'cls', or 'last'")
+    })
+}
+
+fn parse_rope_scaling(rope_scaling: &str) -> PyResult<nobodywho::llm::RopeScaling> {
+    rope_scaling.parse().map_err(|()| {
+        pyo3::exceptions::PyValueError::new_err(
+            "rope_scaling must be one of 'none', 'linear', or 'yarn'",
+        )
+    })
+}
+
+fn parse_tool_call_display(tool_calls: &str) -> PyResult<nobodywho::chat::ToolCallDisplay> {
+    tool_calls.parse().map_err(|()| {
+        pyo3::exceptions::PyValueError::new_err("tool_calls must be one of 'omit' or 'collapse'")
+    })
+}
+
 fn parse_tts_architecture(architecture: &str) -> PyResult<nobodywho::tts::TtsArchitecture> {
     architecture.parse().map_err(|()| {
         pyo3::exceptions::PyValueError::new_err(
@@ -603,12 +835,10 @@ pub struct TokenStream {
 impl TokenStream {
     pub fn next_token(&mut self, py: Python) -> PyResult<Option<String>> {
         py.detach(|| self.inner.next_token())
-            .map_err(pyo3::exceptions::PyRuntimeError::new_err)
     }
 
     pub fn completed(&mut self, py: Python) -> PyResult<String> {
         py.detach(|| self.inner.completed())
-            .map_err(pyo3::exceptions::PyRuntimeError::new_err)
     }
 
     pub fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
@@ -617,7 +847,58 @@ impl TokenStream {
 
     pub fn __next__(&mut self, py: Python) -> PyResult<Option<String>> {
         py.detach(|| self.inner.next_token())
-            .map_err(pyo3::exceptions::PyRuntimeError::new_err)
+    }
+
+    /// Return the next `TokenEvent`, or `None` when generation is finished.
+    /// Unlike `next_token`, this also reports the tool calls the model makes.
+    pub fn next_event(&mut self, py: Python) -> PyResult<Option<TokenEvent>> {
+        py.detach(|| self.inner.next_event())
+    }
+
+    /// Iterate over `TokenEvent`s instead of plain token strings.
+    ///
+    /// Example:
+    ///     for event in chat.ask("What's the weather in Copenhagen?").events():
+    ///         match event:
+    ///             case TokenEvent.Text(text=text):
+    ///                 print(text, end="")
+    ///             case TokenEvent.ToolCallStarted(name=name, arguments=arguments):
+    ///                 print(f"calling {name}({arguments})")
+    pub fn events(slf: Py<Self>) -> TokenEventIterator {
+        TokenEventIterator { stream: slf }
+    }
+}
+
+/// An event on a `TokenStream`, see `TokenStream.events()`.
+/// `arguments` holds the tool call arguments as a JSON string.
+#[pyclass(frozen)]
+pub enum TokenEvent {
+    /// A piece of generated text.
+    Text { text: String },
+    /// The model called a tool, which is about to run.
+    ToolCallStarted { name: String, arguments: String },
+    /// A tool finished running and returned `response`.
+    ToolCallFinished {
+        name: String,
+        arguments: String,
+        response: String,
+    },
+}
+
+/// Iterator over the `TokenEvent`s of a `TokenStream`. Returned by `TokenStream.events()`.
+#[pyclass]
+pub struct TokenEventIterator {
+    stream: Py<TokenStream>,
+}
+
+#[pymethods]
+impl TokenEventIterator {
+    pub fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    pub fn __next__(&self, py: Python) -> PyResult<Option<TokenEvent>> {
+        self.stream.borrow_mut(py).next_event(py)
     }
 }
 
@@ -631,21 +912,52 @@ pub struct TokenStreamAsync {
 #[pymethods]
 impl TokenStreamAsync {
     pub async fn next_token(&mut self) -> PyResult<Option<String>> {
-        self.inner
-            .lock()
-            .await
-            .next_token()
-            .await
-            .map_err(pyo3::exceptions::PyRuntimeError::new_err)
+        self.inner.lock().await.next_token().await
+    }
+
+    pub async fn completed(&mut self) -> PyResult<String> {
+        self.inner.lock().await.completed().await
+    }
+
+    pub fn __aiter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
     }
 
+    pub fn __anext__<'py>(&mut self, py: Python<'py>) -> PyResult<Bound<'py, pyo3::types::PyAny>> {
+        let locals = pyo3_async_runtimes::TaskLocals::with_running_loop(py)?.copy_context(py)?;
+        let inner = self.inner.clone();
+        pyo3_async_runtimes::tokio::future_into_py_with_locals(py, locals, async move {
+            match inner.lock().await.next_token().await {
+                Ok(Some(t)) => Ok(t),
+                Ok(None) => Err(pyo3::exceptions::PyStopAsyncIteration::new_err(())),
+                Err(e) => Err(e),
+            }
+        })
+    }
+}
+
+/// `JsonFieldStreamAsync` yields `(key, value)` tuples from `ChatAsync.ask_structured_stream`,
+/// one for each top-level field of the generated JSON object, as soon as its value is complete.
+/// Supports `async for key, value in stream` and `await stream.completed()`.
+#[pyclass]
+pub struct JsonFieldStreamAsync {
+    inner: std::sync::Arc<
+        tokio::sync::Mutex<
+            nobodywho::stream::JsonFieldStreamAsync<nobodywho::errors::CompletionError>,
+        >,
+    >,
+}
+
+#[pymethods]
+impl JsonFieldStreamAsync {
+    /// Wait for generation to finish and return the full generated JSON text.
     pub async fn completed(&mut self) -> PyResult<String> {
         self.inner
             .lock()
             .await
             .completed()
             .await
-            .map_err(pyo3::exceptions::PyRuntimeError::new_err)
+            .map_err(|e| completion_error_to_py(&e))
     }
 
     pub fn __aiter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
@@ -656,10 +968,14 @@ impl TokenStreamAsync {
         let locals = pyo3_async_runtimes::TaskLocals::with_running_loop(py)?.copy_context(py)?;
         let inner = self.inner.clone();
         pyo3_async_runtimes::tokio::future_into_py_with_locals(py, locals, async move {
-            match inner.lock().await.next_token().await {
-                Ok(Some(t)) => Ok(t),
+            match inner.lock().await.next_field().await {
+                Ok(Some((key, value))) => Python::attach(|py| {
+                    let value = pythonize::pythonize(py, &value)
+                        .map_err(|e| pyo3::exceptions::PyValueError::new_err(e.to_string()))?;
+                    Ok((key, value.unbind()))
+                }),
                 Ok(None) => Err(pyo3::exceptions::PyStopAsyncIteration::new_err(())),
-                Err(e) => Err(pyo3::exceptions::PyRuntimeError::new_err(e)),
+                Err(e) => Err(completion_error_to_py(&e)),
             }
         })
     }
@@ -695,23 +1011,63 @@ impl Encoder {
     /// Args:
     ///     model: An embedding model (Model instance, local path, `huggingface:` path, or `https://` URL to a GGUF file)
     ///     n_ctx: Context size (maximum sequence length). Defaults to 4096.
+    ///     deterministic: If True, the same text always yields a bit-identical embedding. This
+    ///         decodes on a single thread, so encoding is considerably slower on multi-core CPUs.
+    ///         Defaults to False.
+    ///     pooling: How token embeddings are combined into one vector: "mean", "cls" or "last".
+    ///         Defaults to the pooling recommended in the model file. BGE models need "cls",
+    ///         most sentence-transformers models "mean", and decoder-based embedding models "last".
+    ///     n_threads: Number of threads used for encoding. Only affects CPU inference.
+    ///         Defaults to the number of available CPU cores.
+    ///     n_threads_batch: Number of threads used to read text in batches. Only affects CPU
+    ///         inference. Defaults to the number of available CPU cores.
+    ///     on_overflow: What to do with texts longer than the encoder can read at once: "truncate"
+    ///         embeds only the first tokens that fit and logs a warning, "error" raises. For RAG,
+    ///         prefer "error" and chunk your documents, so no text silently drops out of the index.
+    ///         Defaults to "truncate".
     ///
     /// Returns:
     ///     An Encoder instance
     ///
     /// Raises:
+    ///     ValueError: If `pooling` or `on_overflow` is not a valid value
     ///     RuntimeError: If the model cannot be loaded
 
     #[new]
-    #[pyo3(signature = (model: "Model | os.PathLike | str", n_ctx = 4096) -> "Encoder")]
-    pub fn new(model: ModelOrPath, n_ctx: u32) -> PyResult<Self> {
+    #[pyo3(signature = (model: "Model | os.PathLike | str", n_ctx = 4096, deterministic = false, pooling: "typing.Literal['mean', 'cls', 'last'] | None" = None, n_threads: "int | None" = None, n_threads_batch: "int | None" = None, on_overflow: "typing.Literal['truncate', 'error']" = "truncate") -> "Encoder")]
+    pub fn new(
+        model: ModelOrPath,
+        n_ctx: u32,
+        deterministic: bool,
+        pooling: Option<&str>,
+        n_threads: Option<u32>,
+        n_threads_batch: Option<u32>,
+        on_overflow: &str,
+    ) -> PyResult<Self> {
+        let config = nobodywho::encoder::EncoderConfig {
+            pooling: pooling.map(parse_pooling).transpose()?,
+            on_overflow: on_overflow.parse().map_err(|()| {
+                pyo3::exceptions::PyValueError::new_err(
+                    "on_overflow must be one of 'truncate' or 'error'",
+                )
+            })?,
+            deterministic,
+            n_threads,
+            n_threads_batch,
+        };
         let nw_model = model.get_inner_model()?;
-        let encoder = nobodywho::encoder::Encoder::new(nw_model, n_ctx);
+        let encoder = nobodywho::encoder::Encoder::with_config(nw_model, n_ctx, config);
         Ok(Self {
             encoder: Some(encoder),
         })
     }
 
+    /// The length of the embedding vectors this encoder returns, read from the model metadata.
+    #[getter]
+    pub fn dim(&self) -> usize {
+        self.inner().embedding_dim()
+    }
+
     /// Generate an embedding vector for the given text. This method blocks until complete.
     ///
     /// Args:
@@ -721,7 +1077,7 @@ impl Encoder {
     ///     A list of floats representing the embedding vector
     ///
     /// Raises:
-    ///     RuntimeError: If encoding fails
+    ///     RuntimeError: If encoding fails, or if the text is too long and `on_overflow` is "error"
     pub fn encode(&self, text: String, py: Python) -> PyResult<Vec<f32>> {
         py.detach(|| {
             self.inner()
@@ -759,23 +1115,63 @@ impl EncoderAsync {
     /// Args:
     ///     model: An embedding model (Model instance, local path, `huggingface:` path, or `https://` URL to a GGUF file)
     ///     n_ctx: Context size (maximum sequence length). Defaults to 4096.
+    ///     deterministic: If True, the same text always yields a bit-identical embedding. This
+    ///         decodes on a single thread, so encoding is considerably slower on multi-core CPUs.
+    ///         Defaults to False.
+    ///     pooling: How token embeddings are combined into one vector: "mean", "cls" or "last".
+    ///         Defaults to the pooling recommended in the model file. BGE models need "cls",
+    ///         most sentence-transformers models "mean", and decoder-based embedding models "last".
+    ///     n_threads: Number of threads used for encoding. Only affects CPU inference.
+    ///         Defaults to the number of available CPU cores.
+    ///     n_threads_batch: Number of threads used to read text in batches. Only affects CPU
+    ///         inference. Defaults to the number of available CPU cores.
+    ///     on_overflow: What to do with texts longer than the encoder can read at once: "truncate"
+    ///         embeds only the first tokens that fit and logs a warning, "error" raises. For RAG,
+    ///         prefer "error" and chunk your documents, so no text silently drops out of the index.
+    ///         Defaults to "truncate".
     ///
     /// Returns:
     ///     An EncoderAsync instance
     ///
     /// Raises:
+    ///     ValueError: If `pooling` or `on_overflow` is not a valid value
     ///     RuntimeError: If the model cannot be loaded
 
     #[new]
-    #[pyo3(signature = (model: "Model | os.PathLike | str", n_ctx = 4096) -> "EncoderAsync")]
-    pub fn new(model: ModelOrPath, n_ctx: u32) -> PyResult<Self> {
+    #[pyo3(signature = (model: "Model | os.PathLike | str", n_ctx = 4096, deterministic = false, pooling: "typing.Literal['mean', 'cls', 'last'] | None" = None, n_threads: "int | None" = None, n_threads_batch: "int | None" = None, on_overflow: "typing.Literal['truncate', 'error']" = "truncate") -> "EncoderAsync")]
+    pub fn new(
+        model: ModelOrPath,
+        n_ctx: u32,
+        deterministic: bool,
+        pooling: Option<&str>,
+        n_threads: Option<u32>,
+        n_threads_batch: Option<u32>,
+        on_overflow: &str,
+    ) -> PyResult<Self> {
+        let config = nobodywho::encoder::EncoderConfig {
+            pooling: pooling.map(parse_pooling).transpose()?,
+            on_overflow: on_overflow.parse().map_err(|()| {
+                pyo3::exceptions::PyValueError::new_err(
+                    "on_overflow must be one of 'truncate' or 'error'",
+                )
+            })?,
+            deterministic,
+            n_threads,
+            n_threads_batch,
+        };
         let nw_model = model.get_inner_model()?;
-        let encoder_handle = nobodywho::encoder::EncoderAsync::new(nw_model, n_ctx);
+        let encoder_handle = nobodywho::encoder::EncoderAsync::with_config(nw_model, n_ctx, config);
         Ok(Self {
             encoder_handle: Some(encoder_handle),
         })
     }
 
+    /// The length of the embedding vectors this encoder returns, read from the model metadata.
+    #[getter]
+    pub fn dim(&self) -> usize {
+        self.inner().embedding_dim()
+    }
+
     /// Generate an embedding vector for the given text asynchronously.
     ///
     /// Args:
@@ -785,7 +1181,7 @@ impl EncoderAsync {
     ///     A list of floats representing the embedding vector
     ///
     /// Raises:
-    ///     RuntimeError: If encoding fails
+    ///     RuntimeError: If encoding fails, or if the text is too long and `on_overflow` is "error"
     async fn encode(&self, text: String) -> PyResult<Vec<f32>> {
         self.inner().encode(text).await.map_err(|e| {
             PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(format!(
@@ -870,6 +1266,7 @@ impl CrossEncoder {
     ///
     /// Returns:
     ///     List of (document, score) tuples sorted by descending similarity (most similar first).
+    ///     Documents with equal scores keep their input order, and NaN scores go last.
     ///
     /// Raises:
     ///     RuntimeError: If ranking fails
@@ -885,6 +1282,21 @@ impl CrossEncoder {
                 .map_err(|e| PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(format!("{}", e)))
         })
     }
+
+    /// Score documents one at a time, yielding results as soon as each is computed.
+    /// Useful for showing progress or partial results when ranking many documents.
+    ///
+    /// Args:
+    ///     query: The query text
+    ///     documents: List of documents to compare against the query
+    ///
+    /// Returns:
+    ///     A RankStream yielding (index, score) tuples, in document order
+    pub fn rank_stream(&self, query: String, documents: Vec<String>) -> RankStream {
+        RankStream {
+            inner: self.inner().rank_stream(query, documents),
+        }
+    }
 }
 
 /// This is the async version of `CrossEncoder`.
@@ -960,6 +1372,7 @@ impl CrossEncoderAsync {
     ///
     /// Returns:
     ///     List of (document, score) tuples sorted by descending similarity (most similar first).
+    ///     Documents with equal scores keep their input order, and NaN scores go last.
     ///
     /// Raises:
     ///     RuntimeError: If ranking fails
@@ -973,6 +1386,65 @@ impl CrossEncoderAsync {
             .await
             .map_err(|e| PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(format!("{}", e)))
     }
+
+    /// Score documents one at a time, yielding results as soon as each is computed.
+    /// Use with `async for index, score in crossencoder.rank_stream(query, documents)`.
+    ///
+    /// Args:
+    ///     query: The query text
+    ///     documents: List of documents to compare against the query
+    ///
+    /// Returns:
+    ///     A RankStreamAsync yielding (index, score) tuples, in document order
+    pub fn rank_stream(&self, query: String, documents: Vec<String>) -> RankStreamAsync {
+        RankStreamAsync {
+            rx: std::sync::Arc::new(tokio::sync::Mutex::new(
+                self.inner().rank_stream(query, documents),
+            )),
+        }
+    }
+}
+
+/// `RankStream` yields `(index, score)` tuples from `CrossEncoder.rank_stream`, as each
+/// document is scored. Iterate over it with a normal `for` loop.
+#[pyclass]
+pub struct RankStream {
+    inner: nobodywho::crossencoder::RankStream,
+}
+
+#[pymethods]
+impl RankStream {
+    pub fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    pub fn __next__(&mut self, py: Python) -> Option<(usize, f32)> {
+        py.detach(|| self.inner.next())
+    }
+}
+
+/// `RankStreamAsync` is the async variant of `RankStream`. Supports `async for index, score in stream`.
+#[pyclass]
+pub struct RankStreamAsync {
+    rx: std::sync::Arc<tokio::sync::Mutex<tokio::sync::mpsc::Receiver<(usize, f32)>>>,
+}
+
+#[pymethods]
+impl RankStreamAsync {
+    pub fn __aiter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    pub fn __anext__<'py>(&mut self, py: Python<'py>) -> PyResult<Bound<'py, pyo3::types::PyAny>> {
+        let locals = pyo3_async_runtimes::TaskLocals::with_running_loop(py)?.copy_context(py)?;
+        let rx = self.rx.clone();
+        pyo3_async_runtimes::tokio::future_into_py_with_locals(py, locals, async move {
+            match rx.lock().await.recv().await {
+                Some(item) => Ok(item),
+                None => Err(pyo3::exceptions::PyStopAsyncIteration::new_err(())),
+            }
+        })
+    }
 }
 
 /// Tuning for MTP speculative decoding. Pass an instance as the `mtp`
@@ -1032,8 +1504,10 @@ pub struct Chat {
 }
 
 impl Chat {
-    fn handle(&self) -> &nobodywho::chat::ChatHandle {
-        self.chat_handle.as_ref().expect("Chat used after drop")
+    fn handle(&self) -> PyResult<&nobodywho::chat::ChatHandle> {
+        self.chat_handle
+            .as_ref()
+            .ok_or_else(|| pyo3::exceptions::PyRuntimeError::new_err("Chat is closed"))
     }
 }
 
@@ -1061,15 +1535,26 @@ impl Chat {
     ///     mtp: Optional MtpConfig to enable MTP speculative decoding on this chat.
     ///         Requires the `Model` to have been loaded with a compatible
     ///         `draft_model_path`. Adds around 5% to VRAM usage. Defaults to None.
+    ///     n_threads: Number of threads used to generate tokens. Only affects CPU inference.
+    ///         Defaults to the number of available CPU cores.
+    ///     n_threads_batch: Number of threads used to read prompts. Only affects CPU inference.
+    ///         Defaults to the number of available CPU cores.
+    ///     rope_scaling: RoPE scaling method: "none", "linear" or "yarn". Defaults to the one in
+    ///         the model file. Setting any RoPE option allows `n_ctx` to exceed the context the
+    ///         model was trained with, but output quality degrades the further it is stretched.
+    ///     rope_freq_base: RoPE base frequency. Defaults to the one in the model file.
+    ///     rope_freq_scale: RoPE frequency scaling factor, the inverse of the context extension
+    ///         (e.g. 0.25 for 4x the trained context). Defaults to the one in the model file.
     ///
     /// Returns:
     ///     A Chat instance
     ///
     /// Raises:
+    ///     ValueError: If `rope_scaling` is not a valid scaling method
     ///     RuntimeError: If the model cannot be loaded
 
     #[new]
-    #[pyo3(signature = (model: "Model | os.PathLike | str", n_ctx = 4096, system_prompt = None, template_variables: "dict[str, bool]" = std::collections::HashMap::<String, bool>::new(), tools: "list[Tool]" = Vec::<Tool>::new(), sampler: "SamplerConfig | None" = None, allow_thinking: "bool | None" = None, mtp: "MtpConfig | None" = None) -> "Chat")]
+    #[pyo3(signature = (model: "Model | os.PathLike | str", n_ctx = 4096, system_prompt = None, template_variables: "dict[str, bool]" = std::collections::HashMap::<String, bool>::new(), tools: "list[Tool]" = Vec::<Tool>::new(), sampler: "SamplerConfig | None" = None, allow_thinking: "bool | None" = None, mtp: "MtpConfig | None" = None, n_threads: "int | None" = None, n_threads_batch: "int | None" = None, rope_scaling: "typing.Literal['none', 'linear', 'yarn'] | None" = None, rope_freq_base: "float | None" = None, rope_freq_scale: "float | None" = None) -> "Chat")]
     pub fn new(
         model: ModelOrPath,
         n_ctx: u32,
@@ -1079,9 +1564,22 @@ impl Chat {
         sampler: Option<SamplerConfig>,
         allow_thinking: Option<bool>,
         mtp: Option<MtpConfig>,
+        n_threads: Option<u32>,
+        n_threads_batch: Option<u32>,
+        rope_scaling: Option<&str>,
+        rope_freq_base: Option<f32>,
+        rope_freq_scale: Option<f32>,
         py: Python<'_>,
     ) -> PyResult<Self> {
         let nw_model = model.get_inner_model()?;
+        let context_options = nobodywho::llm::ContextOptions {
+            n_threads,
+            n_threads_batch,
+            rope_scaling: rope_scaling.map(parse_rope_scaling).transpose()?,
+            rope_freq_base,
+            rope_freq_scale,
+            ..Default::default()
+        };
 
         // Handle deprecated allow_thinking parameter
         let mut template_vars = template_variables;
@@ -1104,7 +1602,8 @@ impl Chat {
                 .with_context_size(n_ctx)
                 .with_tools(tools.into_iter().map(|t| t.tool).collect())
                 .with_template_variables(template_vars)
-                .with_system_prompt(system_prompt);
+                .with_system_prompt(system_prompt)
+                .with_context_options(context_options);
             if let Some(mtp) = mtp {
                 builder = builder.with_mtp(mtp.into());
             }
@@ -1132,17 +1631,91 @@ impl Chat {
     /// Returns:
     ///     A TokenStream that yields tokens as they are generated
     #[pyo3(signature = (prompt: "str | Prompt") -> "TokenStream")]
-    pub fn ask(&self, prompt: PromptOrText) -> TokenStream {
-        let stream = match prompt {
-            PromptOrText::Text(text) => self.handle().ask(text),
-            PromptOrText::PromptObj(prompt_obj) => {
-                self.handle().ask(prompt_obj.borrow().prompt.clone())
-            }
+    pub fn ask(&self, prompt: PromptOrText) -> PyResult<TokenStream> {
+        let options = nobodywho::chat::AskOptions::default();
+        let rx = match prompt {
+            PromptOrText::Text(text) => self.handle()?.ask_events(text, options),
+            PromptOrText::PromptObj(prompt_obj) => self
+                .handle()?
+                .ask_events(prompt_obj.borrow().prompt.clone(), options),
         };
 
-        TokenStream {
-            inner: SyncStreamInner::Chat(stream),
-        }
+        Ok(TokenStream {
+            inner: SyncStreamInner::Chat { rx, done: None },
+        })
+    }
+
+    /// Generate a new response to the last user message, replacing the previous response
+    /// and any tool calls it made.
+    ///
+    /// Args:
+    ///     sampler: Sampler to use for this response only. Defaults to the chat's sampler.
+    ///
+    /// Returns:
+    ///     A TokenStream that yields tokens as they are generated
+    #[pyo3(signature = (sampler: "SamplerConfig | None" = None) -> "TokenStream")]
+    pub fn regenerate(&self, sampler: Option<SamplerConfig>) -> PyResult<TokenStream> {
+        Ok(TokenStream {
+            inner: SyncStreamInner::Completion(
+                self.handle()?.regenerate(sampler.map(|s| s.sampler_config)),
+            ),
+        })
+    }
+
+    /// Replace the last user message with `prompt` and generate a response to it.
+    /// The old user message and everything after it is removed from the chat history.
+    ///
+    /// Args:
+    ///     prompt: The edited user prompt (plain text or a multimodal Prompt)
+    ///
+    /// Returns:
+    ///     A TokenStream that yields tokens as they are generated
+    #[pyo3(signature = (prompt: "str | Prompt") -> "TokenStream")]
+    pub fn edit_last_user_message(&self, prompt: PromptOrText) -> PyResult<TokenStream> {
+        let prompt = match prompt {
+            PromptOrText::Text(text) => nobodywho::tokenizer::Promptable::to_prompt(&text),
+            PromptOrText::PromptObj(prompt_obj) => prompt_obj.borrow().prompt.clone(),
+        };
+        Ok(TokenStream {
+            inner: SyncStreamInner::Completion(self.handle()?.edit_last_user_message(prompt)),
+        })
+    }
+
+    /// Ask for a response matching `response_type` and return it as an instance of that type.
+    ///
+    /// The JSON schema of `response_type` constrains generation for this turn only, and the
+    /// generated JSON is validated with pydantic. Works with pydantic models, dataclasses and
+    /// anything else pydantic's `TypeAdapter` accepts.
+    ///
+    /// Args:
+    ///     prompt: The user prompt to send (plain text or a multimodal Prompt)
+    ///     response_type: The type to parse the response into, e.g. a pydantic model
+    ///
+    /// Returns:
+    ///     An instance of `response_type`
+    ///
+    /// Raises:
+    ///     ImportError: If pydantic is not installed
+    ///     ValueError: If the generated output does not validate against `response_type`
+    #[pyo3(signature = (prompt: "str | Prompt", response_type: "type") -> "typing.Any")]
+    pub fn ask_structured(
+        &self,
+        prompt: PromptOrText,
+        response_type: &Bound<'_, PyAny>,
+        py: Python,
+    ) -> PyResult<Py<PyAny>> {
+        let (adapter, schema) = structured_output_adapter(py, response_type)?;
+        let prompt = match prompt {
+            PromptOrText::Text(text) => nobodywho::tokenizer::Promptable::to_prompt(&text),
+            PromptOrText::PromptObj(prompt_obj) => prompt_obj.borrow().prompt.clone(),
+        };
+        let mut stream = self
+            .handle()?
+            .ask_with_options(prompt, structured_ask_options(schema)?);
+        let output = py
+            .detach(|| stream.completed())
+            .map_err(|e| pyo3::exceptions::PyRuntimeError::new_err(render_miette(&e)))?;
+        validate_structured_output(py, &adapter, &output)
     }
 
     /// Reset the conversation with a new system prompt and tools. Clears all chat history.
@@ -1160,7 +1733,7 @@ impl Chat {
         py: Python,
     ) -> PyResult<()> {
         py.detach(|| {
-            self.handle()
+            self.handle()?
                 .reset_chat(system_prompt, tools.into_iter().map(|t| t.tool).collect())
                 .map_err(|e| pyo3::exceptions::PyRuntimeError::new_err(e.to_string()))
         })
@@ -1172,7 +1745,7 @@ impl Chat {
     ///     RuntimeError: If reset fails
     pub fn reset_history(&self, py: Python) -> PyResult<()> {
         py.detach(|| {
-            self.handle()
+            self.handle()?
                 .reset_history()
                 .map_err(|e| pyo3::exceptions::PyRuntimeError::new_err(e.to_string()))
         })
@@ -1199,7 +1772,7 @@ impl Chat {
             1,
         )?;
         py.detach(|| {
-            self.handle()
+            self.handle()?
                 .set_template_variable("enable_thinking".to_string(), allow_thinking)
                 .map_err(|e| pyo3::exceptions::PyValueError::new_err(e.to_string()))
         })
@@ -1215,7 +1788,7 @@ impl Chat {
     ///     RuntimeError: If the variable cannot be set
     pub fn set_template_variable(&self, name: String, value: bool, py: Python) -> PyResult<()> {
         py.detach(|| {
-            self.handle()
+            self.handle()?
                 .set_template_variable(name, value)
                 .map_err(|e| pyo3::exceptions::PyRuntimeError::new_err(e.to_string()))
         })
@@ -1234,7 +1807,7 @@ impl Chat {
         py: Python,
     ) -> PyResult<()> {
         py.detach(|| {
-            self.handle()
+            self.handle()?
                 .set_template_variables(variables)
                 .map_err(|e| pyo3::exceptions::PyRuntimeError::new_err(e.to_string()))
         })
@@ -1252,7 +1825,7 @@ impl Chat {
         py: Python,
     ) -> PyResult<std::collections::HashMap<String, bool>> {
         py.detach(|| {
-            self.handle()
+            self.handle()?
                 .get_template_variables()
                 .map(|vars| vars.into_iter().collect())
                 .map_err(|e| pyo3::exceptions::PyRuntimeError::new_err(e.to_string()))
@@ -1270,7 +1843,7 @@ impl Chat {
     #[pyo3(signature = () -> "list[dict]")]
     pub fn get_chat_history(&self, py: Python) -> PyResult<Py<PyAny>> {
         let msgs = py.detach(|| {
-            self.handle()
+            self.handle()?
                 .get_chat_history()
                 .map_err(|e| pyo3::exceptions::PyRuntimeError::new_err(e.to_string()))
         })?;
@@ -1280,6 +1853,36 @@ impl Chat {
             .map(|bound| bound.unbind())
     }
 
+    /// Get the chat history for showing to people, e.g. in a chat UI.
+    ///
+    /// Unlike `get_chat_history()`, tool calls and tool responses are left out or collapsed.
+    /// Use `get_chat_history()` for a history to pass back to `set_chat_history()`.
+    ///
+    /// Args:
+    ///     tool_calls: "omit" leaves tool calls and their responses out, "collapse" replaces
+    ///         each round of them with one assistant message, with a line like
+    ///         `Called get_weather({"city":"Paris"}): 18 degrees` per call. Defaults to "omit".
+    ///
+    /// Returns:
+    ///     List of message dicts, each with 'role' (str) and 'content' (str) keys.
+    ///
+    /// Raises:
+    ///     ValueError: If `tool_calls` is not a valid value
+    ///     RuntimeError: If retrieval fails
+    #[pyo3(signature = (tool_calls: "typing.Literal['omit', 'collapse']" = "omit") -> "list[dict]")]
+    pub fn get_display_history(&self, py: Python, tool_calls: &str) -> PyResult<Py<PyAny>> {
+        let tool_calls = parse_tool_call_display(tool_calls)?;
+        let msgs = py.detach(|| {
+            self.handle()?
+                .get_display_history(tool_calls)
+                .map_err(|e| pyo3::exceptions::PyRuntimeError::new_err(e.to_string()))
+        })?;
+
+        pythonize::pythonize(py, &msgs)
+            .map_err(|e| pyo3::exceptions::PyValueError::new_err(e.to_string()))
+            .map(|bound| bound.unbind())
+    }
+
     /// Replace the chat history with a new list of messages.
     ///
     /// Args:
@@ -1295,7 +1898,7 @@ impl Chat {
             .map_err(|e| pyo3::exceptions::PyValueError::new_err(e.to_string()))?;
 
         py.detach(|| {
-            self.handle()
+            self.handle()?
                 .set_chat_history(msgs)
                 .map_err(|e| pyo3::exceptions::PyRuntimeError::new_err(e.to_string()))
         })
@@ -1304,9 +1907,29 @@ impl Chat {
     /// Stop the current text generation immediately.
     ///
     /// This can be used to cancel an in-progress generation if the response is taking too long
-    /// or is no longer needed.
+    /// or is no longer needed. Does nothing once the chat is closed.
     pub fn stop_generation(&self, py: Python) {
-        py.detach(|| self.handle().stop_generation())
+        if let Some(handle) = &self.chat_handle {
+            py.detach(|| handle.stop_generation())
+        }
+    }
+
+    /// Stop the current text generation, and remove the stopped turn from the chat history.
+    ///
+    /// Unlike `stop_generation()`, neither the user message nor the partial response is kept,
+    /// so the history is as if the turn never happened. Use this to let the user interrupt
+    /// and ask again cleanly. Does nothing once the chat is closed.
+    pub fn cancel_generation(&self, py: Python) {
+        if let Some(handle) = &self.chat_handle {
+            py.detach(|| handle.cancel_generation())
+        }
+    }
+
+    /// Whether the chat's worker is still running. A crashed worker makes every following
+    /// response raise `WorkerDeadError`, so this can be used to replace the chat.
+    /// A closed chat is not alive.
+    pub fn is_alive(&self) -> bool {
+        self.chat_handle.as_ref().is_some_and(|h| h.is_alive())
     }
 
     /// Update the list of tools available to the model without resetting chat history.
@@ -1318,7 +1941,7 @@ impl Chat {
     ///     RuntimeError: If updating tools fails
     pub fn set_tools(&self, tools: Vec<Tool>, py: Python) -> PyResult<()> {
         py.detach(|| {
-            self.handle()
+            self.handle()?
                 .set_tools(tools.into_iter().map(|t| t.tool).collect())
                 .map_err(|e| pyo3::exceptions::PyRuntimeError::new_err(e.to_string()))
         })
@@ -1333,7 +1956,7 @@ impl Chat {
     ///     RuntimeError: If the system prompt cannot be changed
     pub fn set_system_prompt(&self, system_prompt: Option<String>, py: Python) -> PyResult<()> {
         py.detach(|| {
-            self.handle()
+            self.handle()?
                 .set_system_prompt(system_prompt)
                 .map_err(|e| pyo3::exceptions::PyRuntimeError::new_err(e.to_string()))
         })
@@ -1348,7 +1971,7 @@ impl Chat {
     ///     RuntimeError: If the sampler config cannot be changed
     pub fn set_sampler_config(&self, sampler: SamplerConfig, py: Python) -> PyResult<()> {
         py.detach(|| {
-            self.handle()
+            self.handle()?
                 .set_sampler_config(sampler.sampler_config)
                 .map_err(|e| pyo3::exceptions::PyRuntimeError::new_err(e.to_string()))
         })
@@ -1363,7 +1986,7 @@ impl Chat {
     ///     RuntimeError: If the sampler config cannot be retrieved
     pub fn get_sampler_config(&self, py: Python) -> PyResult<SamplerConfig> {
         py.detach(|| {
-            self.handle()
+            self.handle()?
                 .get_sampler_config()
                 .map(|sampler_config| SamplerConfig { sampler_config })
                 .map_err(|e| pyo3::exceptions::PyRuntimeError::new_err(e.to_string()))
@@ -1377,7 +2000,7 @@ impl Chat {
     #[pyo3(signature = () -> "ChatStats")]
     pub fn stats(&self, py: Python) -> PyResult<ChatStats> {
         py.detach(|| {
-            self.handle()
+            self.handle()?
                 .get_stats()
                 .map(|s| ChatStats {
                     context_size: s.context_size,
@@ -1395,7 +2018,7 @@ impl Chat {
     ///     Optional[float]
     pub fn mtp_acceptance_rate(&self, py: Python) -> PyResult<Option<f32>> {
         py.detach(|| {
-            self.handle()
+            self.handle()?
                 .mtp_acceptance_rate()
                 .map_err(|e| pyo3::exceptions::PyRuntimeError::new_err(e.to_string()))
         })
@@ -1410,7 +2033,7 @@ impl Chat {
     ///     RuntimeError: If the system prompt cannot be retrieved
     pub fn get_system_prompt(&self, py: Python) -> PyResult<Option<String>> {
         py.detach(|| {
-            self.handle()
+            self.handle()?
                 .get_system_prompt()
                 .map_err(|e| pyo3::exceptions::PyRuntimeError::new_err(e.to_string()))
         })
@@ -1439,11 +2062,95 @@ impl Chat {
             PromptOrText::PromptObj(prompt_obj) => prompt_obj.borrow().prompt.clone(),
         };
         py.detach(|| {
-            self.handle()
+            self.handle()?
                 .tokenize(nw_prompt)
                 .map_err(|e| pyo3::exceptions::PyRuntimeError::new_err(e.to_string()))
         })
     }
+
+    /// Count the tokens the whole chat would take up in the context with `prompt` as the next
+    /// user message, chat template included. The chat itself is left unchanged.
+    ///
+    /// Compare it to the context size to trim the history before a message would overflow it.
+    ///
+    /// Args:
+    ///     prompt: The text or multimodal Prompt of the next message
+    ///
+    /// Returns:
+    ///     The number of tokens
+    ///
+    /// Raises:
+    ///     RuntimeError: If the chat can't be rendered or tokenized
+    #[pyo3(signature = (prompt: "str | Prompt") -> "int")]
+    pub fn would_be_context_tokens(&self, prompt: PromptOrText, py: Python) -> PyResult<usize> {
+        let nw_prompt = match prompt {
+            PromptOrText::Text(text) => nobodywho::tokenizer::Prompt::from(text),
+            PromptOrText::PromptObj(prompt_obj) => prompt_obj.borrow().prompt.clone(),
+        };
+        py.detach(|| {
+            self.handle()?
+                .would_be_context_tokens(nw_prompt)
+                .map_err(|e| pyo3::exceptions::PyRuntimeError::new_err(e.to_string()))
+        })
+    }
+
+    /// Render the current chat history with the chat template, without generating anything.
+    ///
+    /// This is the exact text the model reads, including role markers, the system prompt and
+    /// any tool definitions the template injects. Useful for debugging odd output.
+    ///
+    /// Returns:
+    ///     The templated prompt
+    ///
+    /// Raises:
+    ///     RuntimeError: If the chat template fails to render
+    #[pyo3(signature = () -> "str")]
+    pub fn render_prompt(&self, py: Python) -> PyResult<String> {
+        py.detach(|| {
+            self.handle()?
+                .render_current_prompt()
+                .map_err(|e| pyo3::exceptions::PyRuntimeError::new_err(e.to_string()))
+        })
+    }
+
+    /// Stop the background worker and free its context. Any generation in progress is
+    /// stopped. Using the chat afterwards raises RuntimeError.
+    ///
+    /// Called when leaving a `with` block. Without it, the worker only stops once the `Chat`
+    /// is garbage collected, so servers that create many chats should close them.
+    pub fn close(&mut self, py: Python) {
+        let handle = self.chat_handle.take();
+        py.detach(|| drop(handle));
+    }
+
+    pub fn __enter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    pub fn __exit__(
+        &mut self,
+        py: Python,
+        _exc_type: Py<PyAny>,
+        _exc_value: Py<PyAny>,
+        _traceback: Py<PyAny>,
+    ) -> bool {
+        self.close(py);
+        false
+    }
+
+    /// Read the system prompt (and any chat history) into the context ahead of time, so the
+    /// first `ask` only has to read the new message. This method blocks.
+    /// Useful during a loading screen, since reading a long system prompt can take a while.
+    ///
+    /// Raises:
+    ///     RuntimeError: If the prompt can't be read into the context
+    pub fn warmup(&self, py: Python) -> PyResult<()> {
+        py.detach(|| {
+            self.handle()?
+                .warmup()
+                .map_err(|e| pyo3::exceptions::PyRuntimeError::new_err(e.to_string()))
+        })
+    }
 }
 
 /// This is the async version of the `Chat` class.
@@ -1455,10 +2162,10 @@ pub struct ChatAsync {
 }
 
 impl ChatAsync {
-    fn handle(&self) -> &nobodywho::chat::ChatHandleAsync {
+    fn handle(&self) -> PyResult<&nobodywho::chat::ChatHandleAsync> {
         self.chat_handle
             .as_ref()
-            .expect("ChatAsync used after drop")
+            .ok_or_else(|| pyo3::exceptions::PyRuntimeError::new_err("Chat is closed"))
     }
 }
 
@@ -1486,15 +2193,26 @@ impl ChatAsync {
     ///     mtp: Optional MtpConfig to enable MTP speculative decoding on this chat.
     ///         Requires the `Model` to have been loaded with a compatible
     ///         `draft_model_path`. Adds around 5% to VRAM usage. Defaults to None.
+    ///     n_threads: Number of threads used to generate tokens. Only affects CPU inference.
+    ///         Defaults to the number of available CPU cores.
+    ///     n_threads_batch: Number of threads used to read prompts. Only affects CPU inference.
+    ///         Defaults to the number of available CPU cores.
+    ///     rope_scaling: RoPE scaling method: "none", "linear" or "yarn". Defaults to the one in
+    ///         the model file. Setting any RoPE option allows `n_ctx` to exceed the context the
+    ///         model was trained with, but output quality degrades the further it is stretched.
+    ///     rope_freq_base: RoPE base frequency. Defaults to the one in the model file.
+    ///     rope_freq_scale: RoPE frequency scaling factor, the inverse of the context extension
+    ///         (e.g. 0.25 for 4x the trained context). Defaults to the one in the model file.
     ///
     /// Returns:
     ///     A ChatAsync instance
     ///
     /// Raises:
+    ///     ValueError: If `rope_scaling` is not a valid scaling method
     ///     RuntimeError: If the model cannot be loaded
 
     #[new]
-    #[pyo3(signature = (model: "Model | os.PathLike | str", n_ctx = 4096, system_prompt = None, template_variables: "dict[str, bool]" = std::collections::HashMap::<String, bool>::new(), tools: "list[Tool]" = vec![], sampler: "SamplerConfig | None" = None, allow_thinking: "bool | None" = None, mtp: "MtpConfig | None" = None) -> "ChatAsync")]
+    #[pyo3(signature = (model: "Model | os.PathLike | str", n_ctx = 4096, system_prompt = None, template_variables: "dict[str, bool]" = std::collections::HashMap::<String, bool>::new(), tools: "list[Tool]" = vec![], sampler: "SamplerConfig | None" = None, allow_thinking: "bool | None" = None, mtp: "MtpConfig | None" = None, n_threads: "int | None" = None, n_threads_batch: "int | None" = None, rope_scaling: "typing.Literal['none', 'linear', 'yarn'] | None" = None, rope_freq_base: "float | None" = None, rope_freq_scale: "float | None" = None) -> "ChatAsync")]
     pub fn new(
         model: ModelOrPath,
         n_ctx: u32,
@@ -1504,9 +2222,22 @@ impl ChatAsync {
         sampler: Option<SamplerConfig>,
         allow_thinking: Option<bool>,
         mtp: Option<MtpConfig>,
+        n_threads: Option<u32>,
+        n_threads_batch: Option<u32>,
+        rope_scaling: Option<&str>,
+        rope_freq_base: Option<f32>,
+        rope_freq_scale: Option<f32>,
         py: Python<'_>,
     ) -> PyResult<Self> {
         let nw_model = model.get_inner_model()?;
+        let context_options = nobodywho::llm::ContextOptions {
+            n_threads,
+            n_threads_batch,
+            rope_scaling: rope_scaling.map(parse_rope_scaling).transpose()?,
+            rope_freq_base,
+            rope_freq_scale,
+            ..Default::default()
+        };
 
         // Handle deprecated allow_thinking parameter
         let mut template_vars = template_variables;
@@ -1529,7 +2260,8 @@ impl ChatAsync {
                 .with_context_size(n_ctx)
                 .with_tools(tools.into_iter().map(|t| t.tool).collect())
                 .with_template_variables(template_vars)
-                .with_system_prompt(system_prompt);
+                .with_system_prompt(system_prompt)
+                .with_context_options(context_options);
             if let Some(mtp) = mtp {
                 builder = builder.with_mtp(mtp.into());
             }
@@ -1556,17 +2288,137 @@ impl ChatAsync {
     /// Returns:
     ///     A TokenStreamAsync that yields tokens as they are generated
     #[pyo3(signature = (prompt: "str | Prompt") -> "TokenStreamAsync")]
-    pub fn ask(&self, prompt: PromptOrText) -> TokenStreamAsync {
+    pub fn ask(&self, prompt: PromptOrText) -> PyResult<TokenStreamAsync> {
         let stream = match prompt {
-            PromptOrText::Text(text) => self.handle().ask(text),
+            PromptOrText::Text(text) => self.handle()?.ask(text),
             PromptOrText::PromptObj(prompt_obj) => {
-                self.handle().ask(prompt_obj.borrow().prompt.clone())
+                self.handle()?.ask(prompt_obj.borrow().prompt.clone())
             }
         };
 
-        TokenStreamAsync {
+        Ok(TokenStreamAsync {
             inner: std::sync::Arc::new(tokio::sync::Mutex::new(AsyncStreamInner::Chat(stream))),
-        }
+        })
+    }
+
+    /// Generate a new response to the last user message, replacing the previous response
+    /// and any tool calls it made.
+    ///
+    /// Args:
+    ///     sampler: Sampler to use for this response only. Defaults to the chat's sampler.
+    ///
+    /// Returns:
+    ///     A TokenStreamAsync that yields tokens as they are generated
+    #[pyo3(signature = (sampler: "SamplerConfig | None" = None) -> "TokenStreamAsync")]
+    pub fn regenerate(&self, sampler: Option<SamplerConfig>) -> PyResult<TokenStreamAsync> {
+        let stream = self.handle()?.regenerate(sampler.map(|s| s.sampler_config));
+        Ok(TokenStreamAsync {
+            inner: std::sync::Arc::new(tokio::sync::Mutex::new(AsyncStreamInner::Chat(stream))),
+        })
+    }
+
+    /// Replace the last user message with `prompt` and generate a response to it.
+    /// The old user message and everything after it is removed from the chat history.
+    ///
+    /// Args:
+    ///     prompt: The edited user prompt (plain text or a multimodal Prompt)
+    ///
+    /// Returns:
+    ///     A TokenStreamAsync that yields tokens as they are generated
+    #[pyo3(signature = (prompt: "str | Prompt") -> "TokenStreamAsync")]
+    pub fn edit_last_user_message(&self, prompt: PromptOrText) -> PyResult<TokenStreamAsync> {
+        let prompt = match prompt {
+            PromptOrText::Text(text) => nobodywho::tokenizer::Promptable::to_prompt(&text),
+            PromptOrText::PromptObj(prompt_obj) => prompt_obj.borrow().prompt.clone(),
+        };
+        let stream = self.handle()?.edit_last_user_message(prompt);
+        Ok(TokenStreamAsync {
+            inner: std::sync::Arc::new(tokio::sync::Mutex::new(AsyncStreamInner::Chat(stream))),
+        })
+    }
+
+    /// Ask for a response matching `response_type` and return it as an instance of that type.
+    ///
+    /// The JSON schema of `response_type` constrains generation for this turn only, and the
+    /// generated JSON is validated with pydantic. Works with pydantic models, dataclasses and
+    /// anything else pydantic's `TypeAdapter` accepts.
+    ///
+    /// Args:
+    ///     prompt: The user prompt to send (plain text or a multimodal Prompt)
+    ///     response_type: The type to parse the response into, e.g. a pydantic model
+    ///
+    /// Returns:
+    ///     An instance of `response_type`
+    ///
+    /// Raises:
+    ///     ImportError: If pydantic is not installed
+    ///     ValueError: If the generated output does not validate against `response_type`
+    #[pyo3(signature = (prompt: "str | Prompt", response_type: "type") -> "typing.Awaitable[typing.Any]")]
+    pub fn ask_structured<'py>(
+        &self,
+        prompt: PromptOrText,
+        response_type: &Bound<'_, PyAny>,
+        py: Python<'py>,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let (adapter, schema) = structured_output_adapter(py, response_type)?;
+        let prompt = match prompt {
+            PromptOrText::Text(text) => nobodywho::tokenizer::Promptable::to_prompt(&text),
+            PromptOrText::PromptObj(prompt_obj) => prompt_obj.borrow().prompt.clone(),
+        };
+        let mut stream = self
+            .handle()?
+            .ask_with_options(prompt, structured_ask_options(schema)?);
+        let locals = pyo3_async_runtimes::TaskLocals::with_running_loop(py)?.copy_context(py)?;
+        pyo3_async_runtimes::tokio::future_into_py_with_locals(py, locals, async move {
+            let output = stream
+                .completed()
+                .await
+                .map_err(|e| pyo3::exceptions::PyRuntimeError::new_err(render_miette(&e)))?;
+            Python::attach(|py| validate_structured_output(py, &adapter, &output))
+        })
+    }
+
+    /// Ask for a JSON object matching `response_type`, and stream its top-level fields as
+    /// they are completed. Useful for updating a UI field by field, e.g. showing `name` before
+    /// the rest of the object is done.
+    ///
+    /// The JSON schema of `response_type` constrains generation for this turn only. Values are
+    /// plain JSON values (dicts, lists, strings, numbers, ...); they are not validated, since
+    /// the object isn't complete yet. Use `await stream.completed()` for the full JSON text.
+    ///
+    /// Args:
+    ///     prompt: The user prompt to send (plain text or a multimodal Prompt)
+    ///     response_type: The type describing the object, e.g. a pydantic model
+    ///
+    /// Returns:
+    ///     A JsonFieldStreamAsync that yields `(key, value)` tuples
+    ///
+    /// Raises:
+    ///     ImportError: If pydantic is not installed
+    ///     ValueError: If the schema of `response_type` uses unsupported features
+    ///
+    /// Example:
+    ///     async for key, value in chat.ask_structured_stream("Invent a sword", Weapon):
+    ///         print(f"{key}: {value}")
+    #[pyo3(signature = (prompt: "str | Prompt", response_type: "type") -> "JsonFieldStreamAsync")]
+    pub fn ask_structured_stream(
+        &self,
+        prompt: PromptOrText,
+        response_type: &Bound<'_, PyAny>,
+        py: Python,
+    ) -> PyResult<JsonFieldStreamAsync> {
+        let (_, schema) = structured_output_adapter(py, response_type)?;
+        let prompt = match prompt {
+            PromptOrText::Text(text) => nobodywho::tokenizer::Promptable::to_prompt(&text),
+            PromptOrText::PromptObj(prompt_obj) => prompt_obj.borrow().prompt.clone(),
+        };
+        let fields = self
+            .handle()?
+            .ask_with_options(prompt, structured_ask_options(schema)?)
+            .json_fields();
+        Ok(JsonFieldStreamAsync {
+            inner: std::sync::Arc::new(tokio::sync::Mutex::new(fields)),
+        })
     }
 
     /// Reset the conversation with a new system prompt and tools. Clears all chat history.
@@ -1578,7 +2430,7 @@ impl ChatAsync {
     /// Raises:
     ///     RuntimeError: If reset fails
     pub async fn reset(&self, system_prompt: Option<String>, tools: Vec<Tool>) -> PyResult<()> {
-        self.handle()
+        self.handle()?
             .reset_chat(system_prompt, tools.into_iter().map(|t| t.tool).collect())
             .await
             .map_err(|e| pyo3::exceptions::PyRuntimeError::new_err(e.to_string()))
@@ -1589,7 +2441,7 @@ impl ChatAsync {
     /// Raises:
     ///     RuntimeError: If reset fails
     pub async fn reset_history(&self) -> PyResult<()> {
-        self.handle()
+        self.handle()?
             .reset_history()
             .await
             .map_err(|e| pyo3::exceptions::PyRuntimeError::new_err(e.to_string()))
@@ -1617,7 +2469,7 @@ impl ChatAsync {
                 1,
             )
         })?;
-        self.handle()
+        self.handle()?
             .set_template_variable("enable_thinking".to_string(), allow_thinking)
             .await
             .map_err(|e| pyo3::exceptions::PyValueError::new_err(e.to_string()))
@@ -1632,7 +2484,7 @@ impl ChatAsync {
     /// Raises:
     ///     RuntimeError: If the variable cannot be set
     pub async fn set_template_variable(&self, name: String, value: bool) -> PyResult<()> {
-        self.handle()
+        self.handle()?
             .set_template_variable(name, value)
             .await
             .map_err(|e| pyo3::exceptions::PyRuntimeError::new_err(e.to_string()))
@@ -1649,7 +2501,7 @@ impl ChatAsync {
         &self,
         variables: std::collections::HashMap<String, bool>,
     ) -> PyResult<()> {
-        self.handle()
+        self.handle()?
             .set_template_variables(variables)
             .await
             .map_err(|e| pyo3::exceptions::PyRuntimeError::new_err(e.to_string()))
@@ -1665,26 +2517,58 @@ impl ChatAsync {
     pub async fn get_template_variables(
         &self,
     ) -> PyResult<std::collections::HashMap<String, bool>> {
-        self.handle()
+        self.handle()?
             .get_template_variables()
             .await
             .map(|vars| vars.into_iter().collect())
             .map_err(|e| pyo3::exceptions::PyRuntimeError::new_err(e.to_string()))
     }
 
-    /// Get the current chat history as a list of message dictionaries.
+    /// Get the current chat history as a list of message dictionaries.
+    ///
+    /// Returns:
+    ///     List of message dicts, each with 'role' (str) and 'content' (str) keys.
+    ///     Example: [{"role": "user", "content": "Hello"}, {"role": "assistant", "content": "Hi!"}]
+    ///
+    /// Raises:
+    ///     RuntimeError: If retrieval fails
+    #[pyo3(signature = () -> "list[dict]")]
+    pub async fn get_chat_history(&self) -> PyResult<Py<PyAny>> {
+        let msgs = self
+            .handle()?
+            .get_chat_history()
+            .await
+            .map_err(|e| pyo3::exceptions::PyRuntimeError::new_err(e.to_string()))?;
+
+        Python::attach(|py| {
+            pythonize::pythonize(py, &msgs)
+                .map_err(|e| pyo3::exceptions::PyValueError::new_err(e.to_string()))
+                .map(|bound| bound.unbind())
+        })
+    }
+
+    /// Get the chat history for showing to people, e.g. in a chat UI.
+    ///
+    /// Unlike `get_chat_history()`, tool calls and tool responses are left out or collapsed.
+    /// Use `get_chat_history()` for a history to pass back to `set_chat_history()`.
+    ///
+    /// Args:
+    ///     tool_calls: "omit" leaves tool calls and their responses out, "collapse" replaces
+    ///         each round of them with one assistant message, with a line like
+    ///         `Called get_weather({"city":"Paris"}): 18 degrees` per call. Defaults to "omit".
     ///
     /// Returns:
     ///     List of message dicts, each with 'role' (str) and 'content' (str) keys.
-    ///     Example: [{"role": "user", "content": "Hello"}, {"role": "assistant", "content": "Hi!"}]
     ///
     /// Raises:
+    ///     ValueError: If `tool_calls` is not a valid value
     ///     RuntimeError: If retrieval fails
-    #[pyo3(signature = () -> "list[dict]")]
-    pub async fn get_chat_history(&self) -> PyResult<Py<PyAny>> {
+    #[pyo3(signature = (tool_calls: "typing.Literal['omit', 'collapse']" = "omit") -> "list[dict]")]
+    pub async fn get_display_history(&self, tool_calls: String) -> PyResult<Py<PyAny>> {
+        let tool_calls = parse_tool_call_display(&tool_calls)?;
         let msgs = self
-            .handle()
-            .get_chat_history()
+            .handle()?
+            .get_display_history(tool_calls)
             .await
             .map_err(|e| pyo3::exceptions::PyRuntimeError::new_err(e.to_string()))?;
 
@@ -1712,7 +2596,7 @@ impl ChatAsync {
                 .map_err(|e| pyo3::exceptions::PyValueError::new_err(e.to_string()))
         })?;
 
-        self.handle()
+        self.handle()?
             .set_chat_history(msgs)
             .await
             .map_err(|e| pyo3::exceptions::PyRuntimeError::new_err(e.to_string()))
@@ -1721,9 +2605,29 @@ impl ChatAsync {
     /// Stop the current text generation immediately.
     ///
     /// This can be used to cancel an in-progress generation if the response is taking too long
-    /// or is no longer needed.
+    /// or is no longer needed. Does nothing once the chat is closed.
     pub async fn stop_generation(&self) {
-        self.handle().stop_generation()
+        if let Some(handle) = &self.chat_handle {
+            handle.stop_generation()
+        }
+    }
+
+    /// Stop the current text generation, and remove the stopped turn from the chat history.
+    ///
+    /// Unlike `stop_generation()`, neither the user message nor the partial response is kept,
+    /// so the history is as if the turn never happened. Use this to let the user interrupt
+    /// and ask again cleanly. Does nothing once the chat is closed.
+    pub async fn cancel_generation(&self) {
+        if let Some(handle) = &self.chat_handle {
+            handle.cancel_generation()
+        }
+    }
+
+    /// Whether the chat's worker is still running. A crashed worker makes every following
+    /// response raise `WorkerDeadError`, so this can be used to replace the chat.
+    /// A closed chat is not alive.
+    pub fn is_alive(&self) -> bool {
+        self.chat_handle.as_ref().is_some_and(|h| h.is_alive())
     }
 
     /// Update the list of tools available to the model without resetting chat history.
@@ -1734,7 +2638,7 @@ impl ChatAsync {
     /// Raises:
     ///     RuntimeError: If updating tools fails
     pub async fn set_tools(&self, tools: Vec<Tool>) -> PyResult<()> {
-        self.handle()
+        self.handle()?
             .set_tools(tools.into_iter().map(|t| t.tool).collect())
             .await
             .map_err(|e| pyo3::exceptions::PyRuntimeError::new_err(e.to_string()))
@@ -1748,7 +2652,7 @@ impl ChatAsync {
     /// Raises:
     ///     RuntimeError: If the system prompt cannot be changed
     pub async fn set_system_prompt(&self, system_prompt: Option<String>) -> PyResult<()> {
-        self.handle()
+        self.handle()?
             .set_system_prompt(system_prompt)
             .await
             .map_err(|e| pyo3::exceptions::PyRuntimeError::new_err(e.to_string()))
@@ -1762,7 +2666,7 @@ impl ChatAsync {
     /// Raises:
     ///     RuntimeError: If the sampler config cannot be changed
     pub async fn set_sampler_config(&self, sampler: SamplerConfig) -> PyResult<()> {
-        self.handle()
+        self.handle()?
             .set_sampler_config(sampler.sampler_config)
             .await
             .map_err(|e| pyo3::exceptions::PyRuntimeError::new_err(e.to_string()))
@@ -1776,7 +2680,7 @@ impl ChatAsync {
     /// Raises:
     ///     RuntimeError: If the sampler config cannot be retrieved
     pub async fn get_sampler_config(&self) -> PyResult<SamplerConfig> {
-        self.handle()
+        self.handle()?
             .get_sampler_config()
             .await
             .map(|sampler_config| SamplerConfig { sampler_config })
@@ -1789,7 +2693,7 @@ impl ChatAsync {
     ///     ChatStats with context_size and context_used fields
     #[pyo3(signature = () -> "ChatStats")]
     pub async fn stats(&self) -> PyResult<ChatStats> {
-        self.handle()
+        self.handle()?
             .get_stats()
             .await
             .map(|s| ChatStats {
@@ -1806,7 +2710,7 @@ impl ChatAsync {
     /// Returns:
     ///     Optional[float]
     pub async fn mtp_acceptance_rate(&self) -> PyResult<Option<f32>> {
-        self.handle()
+        self.handle()?
             .mtp_acceptance_rate()
             .await
             .map_err(|e| pyo3::exceptions::PyRuntimeError::new_err(e.to_string()))
@@ -1820,7 +2724,7 @@ impl ChatAsync {
     /// Raises:
     ///     RuntimeError: If the system prompt cannot be retrieved
     pub async fn get_system_prompt(&self) -> PyResult<Option<String>> {
-        self.handle()
+        self.handle()?
             .get_system_prompt()
             .await
             .map_err(|e| pyo3::exceptions::PyRuntimeError::new_err(e.to_string()))
@@ -1856,11 +2760,280 @@ impl ChatAsync {
                 ))
             }
         })?;
-        self.handle()
+        self.handle()?
             .tokenize(nw_prompt)
             .await
             .map_err(|e| pyo3::exceptions::PyRuntimeError::new_err(e.to_string()))
     }
+
+    /// Count the tokens the whole chat would take up in the context with `prompt` as the next
+    /// user message, chat template included. The chat itself is left unchanged.
+    ///
+    /// Compare it to the context size to trim the history before a message would overflow it.
+    ///
+    /// Args:
+    ///     prompt: The text or multimodal Prompt of the next message
+    ///
+    /// Returns:
+    ///     The number of tokens
+    ///
+    /// Raises:
+    ///     RuntimeError: If the chat can't be rendered or tokenized
+    #[pyo3(signature = (prompt: "str | Prompt") -> "int")]
+    pub async fn would_be_context_tokens(&self, prompt: Py<PyAny>) -> PyResult<usize> {
+        let nw_prompt = Python::attach(|py| -> PyResult<nobodywho::tokenizer::Prompt> {
+            let bound = prompt.bind(py);
+            if let Ok(text) = bound.extract::<String>() {
+                Ok(nobodywho::tokenizer::Prompt::from(text))
+            } else if let Ok(p) = bound.cast::<crate::Prompt>() {
+                Ok(p.borrow().prompt.clone())
+            } else {
+                Err(pyo3::exceptions::PyTypeError::new_err(
+                    "prompt must be str or Prompt",
+                ))
+            }
+        })?;
+        self.handle()?
+            .would_be_context_tokens(nw_prompt)
+            .await
+            .map_err(|e| pyo3::exceptions::PyRuntimeError::new_err(e.to_string()))
+    }
+
+    /// Render the current chat history with the chat template, without generating anything.
+    ///
+    /// This is the exact text the model reads, including role markers, the system prompt and
+    /// any tool definitions the template injects. Useful for debugging odd output.
+    ///
+    /// Returns:
+    ///     The templated prompt
+    ///
+    /// Raises:
+    ///     RuntimeError: If the chat template fails to render
+    #[pyo3(signature = () -> "str")]
+    pub async fn render_prompt(&self) -> PyResult<String> {
+        self.handle()?
+            .render_current_prompt()
+            .await
+            .map_err(|e| pyo3::exceptions::PyRuntimeError::new_err(e.to_string()))
+    }
+
+    /// Stop the background worker and free its context. Any generation in progress is
+    /// stopped. Using the chat afterwards raises RuntimeError.
+    ///
+    /// Called when leaving an `async with` (or `with`) block. Without it, the worker only
+    /// stops once the `ChatAsync` is garbage collected, so servers that create many chats
+    /// should close them.
+    pub fn close<'py>(&mut self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        let handle = self.chat_handle.take();
+        pyo3_async_runtimes::tokio::future_into_py(py, async move {
+            // joining the worker blocks, keep it off the event loop
+            tokio::task::spawn_blocking(move || drop(handle))
+                .await
+                .map_err(|e| pyo3::exceptions::PyRuntimeError::new_err(e.to_string()))?;
+            Ok(())
+        })
+    }
+
+    pub fn __aenter__<'py>(slf: Py<Self>, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        pyo3_async_runtimes::tokio::future_into_py(py, async move { Ok(slf) })
+    }
+
+    pub fn __aexit__<'py>(
+        &mut self,
+        py: Python<'py>,
+        _exc_type: Py<PyAny>,
+        _exc_value: Py<PyAny>,
+        _traceback: Py<PyAny>,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        self.close(py)
+    }
+
+    pub fn __enter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    pub fn __exit__(
+        &mut self,
+        py: Python,
+        _exc_type: Py<PyAny>,
+        _exc_value: Py<PyAny>,
+        _traceback: Py<PyAny>,
+    ) -> bool {
+        let handle = self.chat_handle.take();
+        py.detach(|| drop(handle));
+        false
+    }
+
+    /// Read the system prompt (and any chat history) into the context ahead of time, so the
+    /// first `ask` only has to read the new message. Useful during a loading screen, since
+    /// reading a long system prompt can take a while.
+    ///
+    /// Raises:
+    ///     RuntimeError: If the prompt can't be read into the context
+    pub async fn warmup(&self) -> PyResult<()> {
+        self.handle()?
+            .warmup()
+            .await
+            .map_err(|e| pyo3::exceptions::PyRuntimeError::new_err(e.to_string()))
+    }
+}
+
+/// `Completion` continues a piece of text, without applying any chat template.
+/// This is what you want for base models, which often don't have a chat template,
+/// or whenever you want plain text continuation rather than a conversation.
+/// Every call to `.complete()` is independent: nothing is remembered between calls.
+/// See `CompletionAsync` for the async version of this class.
+#[pyclass]
+pub struct Completion {
+    // Option so we can take it in Drop to release it with the GIL temporarily dropped.
+    completion_handle: Option<nobodywho::completion::CompletionHandle>,
+}
+
+impl Completion {
+    fn handle(&self) -> &nobodywho::completion::CompletionHandle {
+        self.completion_handle
+            .as_ref()
+            .expect("Completion used after drop")
+    }
+}
+
+impl Drop for Completion {
+    fn drop(&mut self) {
+        let handle = self.completion_handle.take();
+        Python::attach(|py| py.detach(|| drop(handle)));
+    }
+}
+
+#[pymethods]
+impl Completion {
+    /// Create a new Completion instance for plain text continuation.
+    ///
+    /// Args:
+    ///     model: A text generation model (Model instance, local path, `huggingface:` path, or `https://` URL to a GGUF file)
+    ///     n_ctx: Context size (maximum length of prompt and completion together, in tokens). Defaults to 4096.
+    ///
+    /// Returns:
+    ///     A Completion instance
+    ///
+    /// Raises:
+    ///     RuntimeError: If the model cannot be loaded
+    #[new]
+    #[pyo3(signature = (model: "Model | os.PathLike | str", n_ctx = 4096) -> "Completion")]
+    pub fn new(model: ModelOrPath, n_ctx: u32, py: Python<'_>) -> PyResult<Self> {
+        let nw_model = model.get_inner_model()?;
+        let completion_handle = py
+            .detach(|| nobodywho::completion::CompletionHandle::new(nw_model, n_ctx))
+            .map_err(|e| pyo3::exceptions::PyRuntimeError::new_err(render_miette(&e)))?;
+        Ok(Self {
+            completion_handle: Some(completion_handle),
+        })
+    }
+
+    /// Continue `prompt` and get a streaming response.
+    ///
+    /// Args:
+    ///     prompt: The text to continue. It is passed to the model as-is.
+    ///     sampler: SamplerConfig for token selection. Defaults to SamplerConfig.default().
+    ///     stop_words: Generation stops right before the first occurrence of any of these strings.
+    ///         The stop word itself is not included in the output. Defaults to no stop words.
+    ///
+    /// Returns:
+    ///     A TokenStream that yields tokens as they are generated
+    #[pyo3(signature = (prompt: "str", sampler: "SamplerConfig | None" = None, stop_words: "list[str]" = Vec::<String>::new()) -> "TokenStream")]
+    pub fn complete(
+        &self,
+        prompt: String,
+        sampler: Option<SamplerConfig>,
+        stop_words: Vec<String>,
+    ) -> TokenStream {
+        let sampler = sampler.map(|s| s.sampler_config).unwrap_or_default();
+        TokenStream {
+            inner: SyncStreamInner::Completion(self.handle().complete(prompt, sampler, stop_words)),
+        }
+    }
+
+    /// Stop the current completion immediately.
+    pub fn stop_generation(&self, py: Python) {
+        py.detach(|| self.handle().stop_generation())
+    }
+}
+
+/// This is the async version of the `Completion` class.
+/// See the docs for the `Completion` class for more information.
+#[pyclass]
+pub struct CompletionAsync {
+    // Option so we can take it in Drop to release it with the GIL temporarily dropped.
+    completion_handle: Option<nobodywho::completion::CompletionHandleAsync>,
+}
+
+impl CompletionAsync {
+    fn handle(&self) -> &nobodywho::completion::CompletionHandleAsync {
+        self.completion_handle
+            .as_ref()
+            .expect("CompletionAsync used after drop")
+    }
+}
+
+impl Drop for CompletionAsync {
+    fn drop(&mut self) {
+        let handle = self.completion_handle.take();
+        Python::attach(|py| py.detach(|| drop(handle)));
+    }
+}
+
+#[pymethods]
+impl CompletionAsync {
+    /// Create a new async Completion instance for plain text continuation.
+    ///
+    /// Args:
+    ///     model: A text generation model (Model instance, local path, `huggingface:` path, or `https://` URL to a GGUF file)
+    ///     n_ctx: Context size (maximum length of prompt and completion together, in tokens). Defaults to 4096.
+    ///
+    /// Returns:
+    ///     A CompletionAsync instance
+    ///
+    /// Raises:
+    ///     RuntimeError: If the model cannot be loaded
+    #[new]
+    #[pyo3(signature = (model: "Model | os.PathLike | str", n_ctx = 4096) -> "CompletionAsync")]
+    pub fn new(model: ModelOrPath, n_ctx: u32, py: Python<'_>) -> PyResult<Self> {
+        let nw_model = model.get_inner_model()?;
+        let completion_handle = py
+            .detach(|| nobodywho::completion::CompletionHandleAsync::new(nw_model, n_ctx))
+            .map_err(|e| pyo3::exceptions::PyRuntimeError::new_err(render_miette(&e)))?;
+        Ok(Self {
+            completion_handle: Some(completion_handle),
+        })
+    }
+
+    /// Continue `prompt` and get a streaming response asynchronously.
+    ///
+    /// Args:
+    ///     prompt: The text to continue. It is passed to the model as-is.
+    ///     sampler: SamplerConfig for token selection. Defaults to SamplerConfig.default().
+    ///     stop_words: Generation stops right before the first occurrence of any of these strings.
+    ///         The stop word itself is not included in the output. Defaults to no stop words.
+    ///
+    /// Returns:
+    ///     A TokenStreamAsync that yields tokens as they are generated
+    #[pyo3(signature = (prompt: "str", sampler: "SamplerConfig | None" = None, stop_words: "list[str]" = Vec::<String>::new()) -> "TokenStreamAsync")]
+    pub fn complete(
+        &self,
+        prompt: String,
+        sampler: Option<SamplerConfig>,
+        stop_words: Vec<String>,
+    ) -> TokenStreamAsync {
+        let sampler = sampler.map(|s| s.sampler_config).unwrap_or_default();
+        let stream = self.handle().complete(prompt, sampler, stop_words);
+        TokenStreamAsync {
+            inner: std::sync::Arc::new(tokio::sync::Mutex::new(AsyncStreamInner::Chat(stream))),
+        }
+    }
+
+    /// Stop the current completion immediately.
+    pub async fn stop_generation(&self) {
+        self.handle().stop_generation()
+    }
 }
 
 /// Compute the cosine similarity between two vectors.
@@ -2050,6 +3223,10 @@ impl SamplerBuilder {
 
     /// Set the RNG seed used by random samplers (`dist`, `mirostat_v1`, `mirostat_v2`, `xtc`).
     /// `greedy` ignores it. If unset, a default seed is used.
+    /// Two generations with the same seed, prompt and sampler chain yield identical output.
+    ///
+    /// Args:
+    ///     seed: The RNG seed
     pub fn seed(&self, seed: u32) -> Self {
         SamplerBuilder {
             inner: self.inner.clone().seed(seed),
@@ -2091,12 +3268,20 @@ impl SamplerBuilder {
 
     /// DRY (Don't Repeat Yourself) sampler to reduce repetition.
     ///
+    /// DRY penalizes tokens that would extend a sequence that already occurred earlier in
+    /// the context. See `dry_default()` for llama.cpp's recommended settings.
+    ///
     /// Args:
-    ///     multiplier: Penalty strength multiplier
-    ///     base: Base penalty value
-    ///     allowed_length: Maximum allowed repetition length
-    ///     penalty_last_n: Number of recent tokens to consider
-    ///     seq_breakers: List of strings that break repetition sequences
+    ///     multiplier: Penalty strength. 0.0 disables DRY, 0.8 is the recommended value.
+    ///     base: How fast the penalty grows with the length of the repeated sequence.
+    ///           Typical: 1.75.
+    ///     allowed_length: Repeated sequences up to this many tokens are not penalized.
+    ///                     Typical: 2.
+    ///     penalty_last_n: How many recent tokens to search for repetitions.
+    ///                     -1 means the whole context.
+    ///     seq_breakers: Strings that end a repeated sequence, so matching never crosses
+    ///                   them. When empty, newline, colon, double quote and asterisk are used.
+    #[pyo3(signature = (multiplier, base, allowed_length, penalty_last_n, seq_breakers = Vec::new()))]
     pub fn dry(
         &self,
         multiplier: f32,
@@ -2117,6 +3302,13 @@ impl SamplerBuilder {
         )
     }
 
+    /// DRY (Don't Repeat Yourself) sampler with llama.cpp's recommended settings:
+    /// multiplier 0.8, base 1.75, allowed_length 2, the whole context, and newline, colon,
+    /// double quote and asterisk as sequence breakers.
+    pub fn dry_default(&self) -> Self {
+        shift_step(self.clone(), nobodywho::sampler::ShiftStep::dry_default())
+    }
+
     /// Apply repetition penalties to discourage repeated tokens.
     ///
     /// Args:
@@ -2283,6 +3475,24 @@ impl SamplerPresets {
         }
     }
 
+    /// Create a sampler suited to long-form creative writing. Combines repetition penalties,
+    /// DRY, min_p and a high temperature to produce varied text without getting stuck in loops.
+    #[staticmethod]
+    pub fn creative() -> SamplerConfig {
+        SamplerConfig {
+            sampler_config: nobodywho::sampler::SamplerPresets::creative(),
+        }
+    }
+
+    /// Create a sampler suited to factual tasks. Uses a narrow candidate set and a very low
+    /// temperature, so output is close to deterministic.
+    #[staticmethod]
+    pub fn precise() -> SamplerConfig {
+        SamplerConfig {
+            sampler_config: nobodywho::sampler::SamplerPresets::precise(),
+        }
+    }
+
     /// Create a sampler that constrains output to a JSON schema via llguidance.
     ///
     /// Args:
@@ -2316,6 +3526,28 @@ impl SamplerPresets {
         }
     }
 
+    /// Create a sampler that constrains output to exactly one of the given options.
+    ///
+    /// The options are matched literally, with no whitespace around them.
+    ///
+    /// Args:
+    ///     options: The strings the output may be
+    ///
+    /// Raises:
+    ///     ValueError: If `options` is empty
+    #[staticmethod]
+    pub fn choice(options: Vec<String>) -> PyResult<SamplerConfig> {
+        if options.is_empty() {
+            return Err(pyo3::exceptions::PyValueError::new_err(
+                "choice() needs at least one option",
+            ));
+        }
+        let options: Vec<&str> = options.iter().map(String::as_str).collect();
+        Ok(SamplerConfig {
+            sampler_config: nobodywho::sampler::SamplerPresets::choice(&options),
+        })
+    }
+
     /// Create a sampler that constrains output using a Lark grammar via llguidance.
     ///
     /// Args:
@@ -2367,6 +3599,14 @@ impl Clone for Tool {
 
 #[pymethods]
 impl Tool {
+    /// The JSON schema describing this tool's parameters, as shown to the model.
+    #[getter]
+    pub fn json_schema(&self, py: Python) -> PyResult<Py<PyAny>> {
+        pythonize::pythonize(py, &self.tool.json_schema)
+            .map_err(|e| pyo3::exceptions::PyValueError::new_err(e.to_string()))
+            .map(|bound| bound.unbind())
+    }
+
     #[pyo3(signature = (*args, **kwargs) -> "T")]
     fn __call__(
         &self,
@@ -2632,7 +3872,7 @@ fn tool<'a>(
                 .extract::<bool>()?;
 
             // generate json schema from function type annotations
-            let json_schema = python_func_json_schema(py, &fun, &params)?;
+            let (json_schema, pydantic_models) = python_func_json_schema(py, &fun, &params)?;
             let decode_schema = json_schema.clone();
 
             let fun_clone = fun.clone_ref(py);
@@ -2641,7 +3881,12 @@ fn tool<'a>(
             let wrapped_function = move |json: serde_json::Value| {
                 Python::attach(|py| {
                     // construct kwargs to call the function with
-                    let kwargs = match json_to_kwargs(py, json, decode_schema.to_owned()) {
+                    let kwargs = match json_to_kwargs(
+                        py,
+                        json,
+                        decode_schema.to_owned(),
+                        &pydantic_models,
+                    ) {
                         Ok(kwargs) => kwargs,
                         Err(e) => return format!("ERROR: Failed to convert arguments: {e}"),
                     };
@@ -2744,7 +3989,7 @@ fn python_tool(
                 ));
             };
 
-            Ok(tool_fn(serde_json::json!({ "code": code })))
+            Ok(tool_fn(serde_json::json!({ "code": code })).unwrap_or_else(|e| e.response()))
         },
     )?;
 
@@ -2792,7 +4037,8 @@ fn bash_tool(max_commands: Option<usize>, py: Python) -> PyResult<Tool> {
                 ));
             };
 
-            Ok(tool_fn(serde_json::json!({ "commands": commands })))
+            Ok(tool_fn(serde_json::json!({ "commands": commands }))
+                .unwrap_or_else(|e| e.response()))
         },
     )?;
 
@@ -2803,11 +4049,16 @@ fn bash_tool(max_commands: Option<usize>, py: Python) -> PyResult<Tool> {
 }
 
 // takes a python function (assumes static types), and returns a json schema for that function
+// returns the json schema for the arguments object, along with the pydantic model class of
+// every parameter annotated with one
 fn python_func_json_schema(
     py: Python,
     fun: &Py<PyAny>,
     param_descriptions: &std::collections::HashMap<String, String>,
-) -> PyResult<serde_json::Value> {
+) -> PyResult<(
+    serde_json::Value,
+    std::collections::HashMap<String, Py<PyAny>>,
+)> {
     // import inspect (from stdlib)
     let inspect = PyModule::import(py, "inspect")?;
 
@@ -2820,6 +4071,24 @@ fn python_func_json_schema(
         .extract::<std::collections::HashMap<String, Bound<pyo3::types::PyAny>>>()?;
     let args = argspec.getattr("args")?.extract::<Vec<String>>()?;
 
+    // parameters with a default value are optional for the model to supply.
+    // `defaults` holds the defaults of the last n positional args.
+    let n_defaults = argspec
+        .getattr("defaults")?
+        .extract::<Option<Vec<Bound<pyo3::types::PyAny>>>>()?
+        .map_or(0, |defaults| defaults.len());
+    let mut optional_params: std::collections::HashSet<String> = args
+        [args.len().saturating_sub(n_defaults)..]
+        .iter()
+        .cloned()
+        .collect();
+    if let Some(kwonlydefaults) = argspec
+        .getattr("kwonlydefaults")?
+        .extract::<Option<std::collections::HashMap<String, Bound<pyo3::types::PyAny>>>>()?
+    {
+        optional_params.extend(kwonlydefaults.into_keys());
+    }
+
     // check that all arguments are annotated
     if let Some(missing_arg) = args.iter().find(|arg| !annotations.contains_key(*arg)) {
         return Err(pyo3::exceptions::PyTypeError::new_err(format!(
@@ -2853,12 +4122,27 @@ fn python_func_json_schema(
 
     let mut properties = serde_json::Map::new();
     let mut required = Vec::new();
+    let mut pydantic_models = std::collections::HashMap::new();
 
     for (key, value) in annotations {
         if key == "return" {
             continue;
         }
 
+        if let Some(mut property) = pydantic_model_schema(py, &value)? {
+            if let Some(description) = param_descriptions.get(&key) {
+                if let serde_json::Value::Object(ref mut obj) = property {
+                    obj.insert("description".to_string(), serde_json::json!(description));
+                }
+            }
+            properties.insert(key.clone(), property);
+            pydantic_models.insert(key.clone(), value.unbind());
+            if !optional_params.contains(&key) {
+                required.push(key);
+            }
+            continue;
+        }
+
         let type_name = if value.getattr("__args__").is_ok() {
             // It's a GenericAlias (list[int], dict[str, int], etc.)
             // Use str() to get the full representation
@@ -2890,9 +4174,10 @@ fn python_func_json_schema(
         // add to json schema properties
         properties.insert(key.clone(), property);
 
-        // add to list of required keys for object
-        // TODO: allow optional parameters for params that have a default argument
-        required.push(key);
+        // add to list of required keys for object, unless the param has a default argument
+        if !optional_params.contains(&key) {
+            required.push(key);
+        }
     }
 
     // assemble the complete json schema for an arguments object
@@ -2902,20 +4187,112 @@ fn python_func_json_schema(
         "required": required
     });
 
-    Ok(kwargs_schema)
+    Ok((kwargs_schema, pydantic_models))
 }
 
-// takes a sede_json::value, assumed to be an object, and returns a PyDict
-fn json_to_kwargs(
+// returns the json schema of `annotation` if it is a pydantic model class.
+// returns None for anything else, and when pydantic isn't installed.
+fn pydantic_model_schema(
     py: Python,
+    annotation: &Bound<pyo3::types::PyAny>,
+) -> PyResult<Option<serde_json::Value>> {
+    let Ok(pydantic) = PyModule::import(py, "pydantic") else {
+        return Ok(None);
+    };
+    let base_model = pydantic.getattr("BaseModel")?;
+
+    // issubclass raises for non-class annotations like `list[int]`
+    let inspect = PyModule::import(py, "inspect")?;
+    if !inspect
+        .getattr("isclass")?
+        .call1((annotation,))?
+        .extract::<bool>()?
+    {
+        return Ok(None);
+    }
+    let builtins = PyModule::import(py, "builtins")?;
+    if !builtins
+        .getattr("issubclass")?
+        .call1((annotation, base_model))?
+        .extract::<bool>()?
+    {
+        return Ok(None);
+    }
+
+    let schema = annotation.call_method0("model_json_schema")?;
+    let json_str: String = PyModule::import(py, "json")?
+        .call_method1("dumps", (schema,))?
+        .extract()?;
+    let schema: serde_json::Value = serde_json::from_str(&json_str)
+        .map_err(|e| pyo3::exceptions::PyValueError::new_err(e.to_string()))?;
+
+    // nested models are emitted as `$ref`s into `$defs`. the schema ends up nested inside
+    // the tool call schema, where those refs can't be resolved, so inline them.
+    let defs = schema
+        .get("$defs")
+        .cloned()
+        .unwrap_or_else(|| serde_json::json!({}));
+    inline_schema_refs(&schema, &defs, &mut vec![]).map(Some)
+}
+
+fn inline_schema_refs(
+    schema: &serde_json::Value,
+    defs: &serde_json::Value,
+    seen: &mut Vec<String>,
+) -> PyResult<serde_json::Value> {
+    match schema {
+        serde_json::Value::Object(obj) => {
+            if let Some(serde_json::Value::String(reference)) = obj.get("$ref") {
+                let name = reference.trim_start_matches("#/$defs/").to_string();
+                if seen.contains(&name) {
+                    return Err(pyo3::exceptions::PyTypeError::new_err(format!(
+                        "ERROR: Recursive pydantic models are not supported as tool parameters: {name}"
+                    )));
+                }
+                let Some(definition) = defs.get(&name) else {
+                    return Err(pyo3::exceptions::PyTypeError::new_err(format!(
+                        "ERROR: Could not resolve pydantic schema reference: {reference}"
+                    )));
+                };
+                seen.push(name);
+                let inlined = inline_schema_refs(definition, defs, seen)?;
+                seen.pop();
+                return Ok(inlined);
+            }
+            obj.iter()
+                .filter(|(key, _)| key.as_str() != "$defs")
+                .map(|(key, value)| Ok((key.clone(), inline_schema_refs(value, defs, seen)?)))
+                .collect::<PyResult<serde_json::Map<_, _>>>()
+                .map(serde_json::Value::Object)
+        }
+        serde_json::Value::Array(items) => items
+            .iter()
+            .map(|item| inline_schema_refs(item, defs, seen))
+            .collect::<PyResult<Vec<_>>>()
+            .map(serde_json::Value::Array),
+        other => Ok(other.clone()),
+    }
+}
+
+// takes a sede_json::value, assumed to be an object, and returns a PyDict
+fn json_to_kwargs<'py>(
+    py: Python<'py>,
     json: serde_json::Value,
     json_schema: serde_json::Value,
-) -> PyResult<Bound<pyo3::types::PyDict>> {
+    pydantic_models: &std::collections::HashMap<String, Py<PyAny>>,
+) -> PyResult<Bound<'py, pyo3::types::PyDict>> {
     let py_dict = pyo3::types::PyDict::new(py);
 
     match json {
         serde_json::Value::Object(obj) => {
             for (k, v) in obj {
+                // let pydantic validate and construct its own models
+                if let Some(model) = pydantic_models.get(&k) {
+                    let value_py =
+                        model.call_method1(py, "model_validate_json", (v.to_string(),))?;
+                    py_dict.set_item(k, value_py)?;
+                    continue;
+                }
                 let obj_schema = match json_schema.get("properties") {
                     Some(props) => match props.get(k.clone()) {
                         Some(obj_schema) => obj_schema,
@@ -3064,6 +4441,28 @@ fn get_cached_models() -> PyResult<Vec<(String, usize)>> {
         .collect()
 }
 
+/// Convert a JSON schema to a GBNF grammar, without running any model.
+///
+/// Useful for inspecting (or hand-editing) the grammar before passing it to
+/// `SamplerPresets.constrain_with_grammar()`.
+///
+/// Args:
+///     schema: JSON schema as a string
+///     root: Name of the root rule in the generated grammar
+///
+/// Returns:
+///     The grammar in GBNF format
+///
+/// Raises:
+///     ValueError: If the schema is invalid or uses unsupported features
+#[pyfunction]
+#[pyo3(signature = (schema, root = "root"))]
+fn json_schema_to_grammar(schema: String, root: &str) -> PyResult<String> {
+    nobodywho::gbnf::json::json_schema_to_grammar(schema, root)
+        .map(|grammar| grammar.as_str().to_string())
+        .map_err(|e| PyErr::new::<pyo3::exceptions::PyValueError, _>(e.to_string()))
+}
+
 #[pymodule(name = "nobodywho")]
 pub mod nobodywhopython {
     use pyo3::prelude::*;
@@ -3221,6 +4620,8 @@ pub mod nobodywhopython {
     #[pymodule_export]
     use super::get_cached_models;
     #[pymodule_export]
+    use super::json_schema_to_grammar;
+    #[pymodule_export]
     use super::python_tool;
     #[pymodule_export]
     use super::tool;
@@ -3233,6 +4634,12 @@ pub mod nobodywhopython {
     #[pymodule_export]
     use super::ChatStats;
     #[pymodule_export]
+    use super::Completion;
+    #[pymodule_export]
+    use super::CompletionAsync;
+    #[pymodule_export]
+    use super::ContextOverflowError;
+    #[pymodule_export]
     use super::CrossEncoder;
     #[pymodule_export]
     use super::CrossEncoderAsync;
@@ -3243,12 +4650,18 @@ pub mod nobodywhopython {
     #[pymodule_export]
     use super::Image;
     #[pymodule_export]
+    use super::JsonFieldStreamAsync;
+    #[pymodule_export]
     use super::Model;
     #[pymodule_export]
     use super::MtpConfig;
     #[pymodule_export]
     use super::Prompt;
     #[pymodule_export]
+    use super::RankStream;
+    #[pymodule_export]
+    use super::RankStreamAsync;
+    #[pymodule_export]
     use super::STTAsync;
     #[pymodule_export]
     use super::SamplerBuilder;
@@ -3259,6 +4672,10 @@ pub mod nobodywhopython {
     #[pymodule_export]
     use super::Text;
     #[pymodule_export]
+    use super::TokenEvent;
+    #[pymodule_export]
+    use super::TokenEventIterator;
+    #[pymodule_export]
     use super::TokenStream;
     #[pymodule_export]
     use super::TokenStreamAsync;
@@ -3267,5 +4684,7 @@ pub mod nobodywhopython {
     #[pymodule_export]
     use super::Tts;
     #[pymodule_export]
+    use super::WorkerDeadError;
+    #[pymodule_export]
     use super::STT;
 }
@@ -141,6 +141,11 @@ def test_encoder_sync(encoder):
     )
 
 
+def test_encoder_dim(encoder):
+    """The reported dimension matches the length of the embeddings"""
+    assert encoder.dim == len(encoder.encode("Test text for embedding."))
+
+
 @pytest.mark.asyncio
 async def test_encoder_async(encoder_model):
     """Test that encoder can generate embeddings using async API"""
@@ -155,6 +160,34 @@ async def test_encoder_async(encoder_model):
     )
 
 
+def test_encoder_deterministic(encoder_model):
+    """Deterministic mode yields bit-identical embeddings for the same input"""
+    encoder = nobodywho.Encoder(encoder_model, n_ctx=1024, deterministic=True)
+
+    first = encoder.encode("I don't want to be different")
+    second = encoder.encode("I don't want to be different")
+
+    assert first == second
+
+
+def test_encoder_pooling(encoder_model):
+    """An explicit pooling type still produces sensible similarities"""
+    encoder = nobodywho.Encoder(encoder_model, n_ctx=1024, pooling="cls")
+
+    copenhagen = encoder.encode("Copenhagen is the capital of Denmark.")
+    berlin = encoder.encode("Berlin is the capital of Germany.")
+    insult = encoder.encode("Your mother was a hamster!")
+
+    assert nobodywho.cosine_similarity(copenhagen, insult) < (
+        nobodywho.cosine_similarity(copenhagen, berlin)
+    )
+
+
+def test_encoder_invalid_pooling(encoder_model):
+    with pytest.raises(ValueError):
+        nobodywho.Encoder(encoder_model, pooling="max")
+
+
 def test_cosine_similarity():
     """Test that cosine similarity function works"""
     vec1 = [1.0, 2.0, 3.0]
@@ -177,6 +210,22 @@ def test_cosine_similarity_error():
         nobodywho.cosine_similarity(vec1, vec2)
 
 
+def test_json_schema_to_grammar():
+    schema = json.dumps(
+        {
+            "type": "object",
+            "properties": {"name": {"type": "string"}},
+            "required": ["name"],
+        }
+    )
+    grammar = nobodywho.json_schema_to_grammar(schema, "root")
+    assert "root ::=" in grammar
+    assert "name" in grammar
+
+    with pytest.raises(ValueError):
+        nobodywho.json_schema_to_grammar('{"type": 5}')
+
+
 # CrossEncoder tests
 @pytest.fixture(scope="module")
 def crossencoder_model():
@@ -261,6 +310,38 @@ async def test_crossencoder_rank_and_sort_async(crossencoder_model):
         assert doc in documents, "Document should be from original list"
 
 
+def test_crossencoder_rank_stream_sync(crossencoder):
+    """rank_stream yields (index, score) pairs matching rank()"""
+    query = "What is the capital of France?"
+    documents = [
+        "France is a country in Europe.",
+        "Paris is the capital of France.",
+        "The capital of Germany is Berlin.",
+    ]
+
+    expected = crossencoder.rank(query, documents)
+    streamed = list(crossencoder.rank_stream(query, documents))
+
+    assert [index for index, _ in streamed] == [0, 1, 2]
+    assert [score for _, score in streamed] == expected
+
+
+@pytest.mark.asyncio
+async def test_crossencoder_rank_stream_async(crossencoder_model):
+    """async rank_stream yields a score for every document"""
+    crossencoder_async = nobodywho.CrossEncoderAsync(crossencoder_model)
+    documents = ["Paris is the capital of France.", "France is a country in Europe."]
+
+    streamed = [
+        item
+        async for item in crossencoder_async.rank_stream(
+            "What is the capital of France?", documents
+        )
+    ]
+
+    assert sorted(index for index, _ in streamed) == [0, 1]
+
+
 def test_load_chat_from_path():
     model_path = os.environ.get("TEST_MODEL")
     assert isinstance(model_path, str)
@@ -396,6 +477,34 @@ def test_stop_generation(chat):
     assert len(full_response) < 200, "Generation should have been stopped early"
 
 
+@pytest.mark.asyncio
+async def test_stop_generation_async(chat_async):
+    """Stopping ends the async stream cleanly, and the partial response is kept"""
+    stream = chat_async.ask("Count from 1 to 100 slowly, one number per line.")
+
+    # keep iterating after stopping, the loop must end on its own
+    tokens = []
+    async for token in stream:
+        tokens.append(token)
+        if len(tokens) == 6:
+            await chat_async.stop_generation()
+
+    assert len("".join(tokens)) < 200, "Generation should have been stopped early"
+    assert isinstance(await stream.completed(), str)
+
+
+def test_cancel_generation(chat):
+    """cancel_generation stops the stream and drops the whole turn from the history"""
+    stream = chat.ask("Count from 1 to 100 slowly, one number per line.")
+    for i, _ in enumerate(stream):
+        if i >= 5:
+            chat.cancel_generation()
+            break
+    stream.completed()
+
+    assert chat.get_chat_history() == []
+
+
 # ---- llguidance constraint tests ----
 #
 # These tests deliberately use prompts that would produce long, complex responses
@@ -474,3 +583,167 @@ def test_constrain_with_grammar_gbnf(model):
 
 def test_tokenize(chat):
     assert chat.tokenize("Hey!") == [18665, 0]
+
+
+def test_render_prompt(chat):
+    chat.set_chat_history([{"role": "user", "content": "Hello!"}])
+    prompt = chat.render_prompt()
+    assert "<|im_start|>user\nHello!<|im_end|>" in prompt
+    assert prompt.startswith("<|im_start|>system\nYou are a helpful assistant")
+    assert "<|im_start|>assistant\n" in prompt
+
+
+def test_warmup(model):
+    chat = nobodywho.Chat(
+        model, n_ctx=1024, system_prompt="You are a helpful assistant."
+    )
+    assert chat.stats().context_used == 0
+    chat.warmup()
+    assert chat.stats().context_used > 0
+    assert "Copenhagen" in chat.ask("What is the capital of Denmark?").completed()
+
+
+def test_chat_context_manager(model):
+    with nobodywho.Chat(model, n_ctx=1024) as chat:
+        assert chat.ask("Say hi.").completed()
+    # closing again does nothing
+    chat.close()
+    chat.stop_generation()
+    with pytest.raises(RuntimeError, match="closed"):
+        chat.ask("Say hi again.")
+    with pytest.raises(RuntimeError, match="closed"):
+        chat.get_chat_history()
+
+
+@pytest.mark.asyncio
+async def test_chat_async_context_manager(model):
+    async with nobodywho.ChatAsync(model, n_ctx=1024) as chat:
+        assert await chat.ask("Say hi.").completed()
+    await chat.close()
+    with pytest.raises(RuntimeError, match="closed"):
+        chat.ask("Say hi again.")
+    with pytest.raises(RuntimeError, match="closed"):
+        await chat.get_chat_history()
+
+
+def test_would_be_context_tokens(chat):
+    short = chat.would_be_context_tokens("Hi")
+    long = chat.would_be_context_tokens("Hi, how are you doing on this fine day?")
+    assert long > short
+    assert len(chat.get_chat_history()) == 0
+
+
+def test_sampler_seed_is_reproducible(model):
+    """Two chats sampling with `dist` and the same seed produce identical output."""
+
+    def generate():
+        sampler = nobodywho.SamplerBuilder().seed(42).temperature(1.2).dist()
+        chat = nobodywho.Chat(
+            model,
+            sampler=sampler,
+            template_variables={"enable_thinking": False},
+        )
+        return chat.ask("Write a short poem about the sea.").completed()
+
+    assert generate() == generate()
+
+
+def test_ask_structured(chat):
+    pydantic = pytest.importorskip("pydantic")
+
+    class City(pydantic.BaseModel):
+        name: str
+        country: str
+        population: int
+
+    city = chat.ask_structured("Tell me about Copenhagen.", City)
+    assert isinstance(city, City)
+    assert "Copenhagen" in city.name
+
+    # the constraint only applies to that turn
+    response = chat.ask("Say hello in one word.").completed()
+    with pytest.raises(json.JSONDecodeError):
+        json.loads(response)
+
+
+@pytest.mark.asyncio
+async def test_ask_structured_async(chat_async):
+    pydantic = pytest.importorskip("pydantic")
+
+    class City(pydantic.BaseModel):
+        name: str
+        country: str
+
+    city = await chat_async.ask_structured("Tell me about Copenhagen.", City)
+    assert isinstance(city, City)
+    assert city.country == "Denmark"
+
+
+@pytest.mark.asyncio
+async def test_ask_structured_stream(chat_async):
+    pydantic = pytest.importorskip("pydantic")
+
+    class City(pydantic.BaseModel):
+        name: str
+        country: str
+        population: int
+
+    stream = chat_async.ask_structured_stream("Tell me about Copenhagen.", City)
+    fields = [(key, value) async for key, value in stream]
+    assert [key for key, _ in fields] == ["name", "country", "population"]
+    assert dict(fields) == json.loads(await stream.completed())
+
+
+def test_model_tokenize(model):
+    text = "The capital of Denmark is Copenhagen."
+    ids = model.tokenize(text)
+    assert len(ids) > 0
+    assert model.count_tokens(text) == len(ids)
+    assert model.detokenize(ids) == text
+
+
+def test_model_vocab(model):
+    assert model.n_vocab > 1000
+    im_end = model.token_to_id("<|im_end|>")
+    assert im_end is not None
+    assert model.id_to_token(im_end) == "<|im_end|>"
+    assert model.id_to_token(model.n_vocab) is None
+
+
+def test_completion(model):
+    completion = nobodywho.Completion(model, n_ctx=1024)
+    text = completion.complete(
+        "The capital of Denmark is the city of",
+        sampler=nobodywho.SamplerPresets.greedy(),
+        stop_words=[".", "\n"],
+    ).completed()
+    assert "Copenhagen" in text
+    assert "." not in text and "\n" not in text
+
+
+@pytest.mark.asyncio
+async def test_completion_async(model):
+    completion = nobodywho.CompletionAsync(model, n_ctx=1024)
+    stream = completion.complete(
+        "The capital of Denmark is the city of",
+        sampler=nobodywho.SamplerPresets.greedy(),
+        stop_words=["."],
+    )
+    tokens = [token async for token in stream]
+    assert "Copenhagen" in "".join(tokens)
+
+
+def test_context_overflow(model):
+    chat = nobodywho.Chat(model, n_ctx=256)
+    with pytest.raises(nobodywho.ContextOverflowError):
+        chat.ask("Repeat after me: " * 100).completed()
+    # it's still a RuntimeError, for code that catches those
+    assert issubclass(nobodywho.ContextOverflowError, RuntimeError)
+
+
+def test_is_alive(model):
+    chat = nobodywho.Chat(model, n_ctx=256)
+    assert chat.is_alive()
+    chat.close()
+    assert not chat.is_alive()
+    assert issubclass(nobodywho.WorkerDeadError, RuntimeError)
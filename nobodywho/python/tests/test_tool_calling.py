@@ -1,3 +1,4 @@
+import json
 import os
 
 import nobodywho
@@ -138,6 +139,39 @@ def test_tool_calling(chat):
     assert tool_responses[0]["content"] == "✨JULEMAND✨"
 
 
+def test_tool_calling_events(chat):
+    events = list(
+        chat.ask("Please sparklify this word: 'julemand' and show me the result").events()
+    )
+
+    started = [
+        e for e in events if isinstance(e, nobodywho.TokenEvent.ToolCallStarted)
+    ]
+    finished = [
+        e for e in events if isinstance(e, nobodywho.TokenEvent.ToolCallFinished)
+    ]
+    assert len(started) == 1
+    assert started[0].name == "sparklify"
+    assert json.loads(started[0].arguments) == {"text": "julemand"}
+
+    assert len(finished) == 1
+    assert finished[0].response == "✨JULEMAND✨"
+
+    # the tool events come before the text of the final answer
+    assert events.index(started[0]) < events.index(finished[0])
+    assert isinstance(events[-1], nobodywho.TokenEvent.Text)
+
+
+def test_tool_default_params_are_optional():
+    @nobodywho.tool(description="Searches the web")
+    def search(query: str, limit: int = 10, *, safe: bool = True) -> str:
+        return f"{limit} results for {query}"
+
+    schema = search.json_schema
+    assert set(schema["properties"]) == {"query", "limit", "safe"}
+    assert schema["required"] == ["query"]
+
+
 def test_tool_bad_parameters():
     with pytest.raises(TypeError):
 
@@ -331,6 +365,41 @@ def test_tool_with_dict(chat):
     assert tool_responses[0]["content"] == "6000.0"
 
 
+def test_tool_with_pydantic_model(model):
+    pydantic = pytest.importorskip("pydantic")
+
+    class Address(pydantic.BaseModel):
+        street: str
+        city: str
+
+    class Order(pydantic.BaseModel):
+        item: str
+        quantity: int
+        address: Address
+
+    received = []
+
+    @nobodywho.tool(description="Places an order for delivery")
+    def place_order(order: Order) -> str:
+        received.append(order)
+        return f"Sending {order.quantity}x {order.item} to {order.address.city}"
+
+    chat = nobodywho.Chat(
+        model,
+        system_prompt="You are a helpful assistant",
+        template_variables={"enable_thinking": False},
+        tools=[place_order],
+    )
+    chat.ask(
+        "Please order 3 pizzas to Main Street 1 in Copenhagen using the place_order tool"
+    ).completed()
+
+    assert len(received) >= 1
+    assert isinstance(received[0], Order)
+    assert isinstance(received[0].address, Address)
+    assert received[0].quantity == 3
+
+
 def test_python_tool(model):
     chat = nobodywho.Chat(
         model,
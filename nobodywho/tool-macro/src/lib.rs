@@ -0,0 +1,226 @@
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{format_ident, quote};
+use syn::parse::{Parse, ParseStream};
+use syn::punctuated::Punctuated;
+use syn::{Expr, FnArg, Ident, ItemFn, Lit, LitStr, Meta, Pat, Result, Token};
+
+// ---------------------------------------------------------------------------
+// Attribute arguments
+// ---------------------------------------------------------------------------
+
+/// `city = "The city name"`
+struct ParamDescription {
+    name: Ident,
+    description: LitStr,
+}
+
+impl Parse for ParamDescription {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let name = input.parse()?;
+        input.parse::<Token![=]>()?;
+        let description = input.parse()?;
+        Ok(Self { name, description })
+    }
+}
+
+/// The arguments of the attribute: nothing, or `params(city = "The city name", ...)`
+struct ToolArgs {
+    params: Vec<ParamDescription>,
+}
+
+impl Parse for ToolArgs {
+    fn parse(input: ParseStream) -> Result<Self> {
+        if input.is_empty() {
+            return Ok(Self { params: Vec::new() });
+        }
+
+        let key: Ident = input.parse()?;
+        if key != "params" {
+            return Err(syn::Error::new_spanned(
+                key,
+                "expected `params(name = \"description\", ...)`",
+            ));
+        }
+        let content;
+        syn::parenthesized!(content in input);
+        let params = Punctuated::<ParamDescription, Token![,]>::parse_terminated(&content)?
+            .into_iter()
+            .collect();
+
+        input.parse::<Option<Token![,]>>()?;
+        if !input.is_empty() {
+            return Err(input.error("unexpected tokens after `params(...)`"));
+        }
+        Ok(Self { params })
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Function inspection
+// ---------------------------------------------------------------------------
+
+/// The text of the doc comment on the function, which becomes the tool description.
+fn doc_comment(function: &ItemFn) -> String {
+    let lines: Vec<String> = function
+        .attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("doc"))
+        .filter_map(|attr| match &attr.meta {
+            Meta::NameValue(nv) => match &nv.value {
+                Expr::Lit(syn::ExprLit {
+                    lit: Lit::Str(s), ..
+                }) => Some(s.value()),
+                _ => None,
+            },
+            _ => None,
+        })
+        .map(|line| line.strip_prefix(' ').unwrap_or(&line).to_string())
+        .collect();
+    lines.join("\n").trim().to_string()
+}
+
+/// The names and types of the function parameters.
+fn parameters(function: &ItemFn) -> Result<Vec<(Ident, Box<syn::Type>)>> {
+    let sig = &function.sig;
+    if let Some(asyncness) = sig.asyncness {
+        return Err(syn::Error::new_spanned(
+            asyncness,
+            "tools must be synchronous functions",
+        ));
+    }
+    if !sig.generics.params.is_empty() {
+        return Err(syn::Error::new_spanned(
+            &sig.generics,
+            "tools can't be generic, since the JSON schema is generated from the parameter types",
+        ));
+    }
+
+    sig.inputs
+        .iter()
+        .map(|input| match input {
+            FnArg::Receiver(receiver) => Err(syn::Error::new_spanned(
+                receiver,
+                "tools must be free functions, not methods",
+            )),
+            FnArg::Typed(pat_type) => match &*pat_type.pat {
+                Pat::Ident(pat) => Ok((pat.ident.clone(), pat_type.ty.clone())),
+                pat => Err(syn::Error::new_spanned(
+                    pat,
+                    "tool parameters must be plain names, since they become the argument names the model uses",
+                )),
+            },
+        })
+        .collect()
+}
+
+fn expand(args: ToolArgs, function: ItemFn) -> Result<TokenStream2> {
+    let description = doc_comment(&function);
+    if description.is_empty() {
+        return Err(syn::Error::new_spanned(
+            &function.sig.ident,
+            "add a doc comment to the tool, it is the description shown to the model",
+        ));
+    }
+
+    let params = parameters(&function)?;
+    if let Some(unknown) = args
+        .params
+        .iter()
+        .find(|p| !params.iter().any(|(name, _)| *name == p.name))
+    {
+        return Err(syn::Error::new_spanned(
+            &unknown.name,
+            format!(
+                "the function has no parameter `{}` to describe",
+                unknown.name
+            ),
+        ));
+    }
+
+    let support = quote! { ::nobodywho::tool_calling::macro_support };
+    let schema_entries = params.iter().map(|(name, ty)| {
+        let name_str = name.to_string();
+        let param_description = match args.params.iter().find(|p| p.name == *name) {
+            Some(p) => {
+                let description = &p.description;
+                quote! { ::std::option::Option::Some(#description) }
+            }
+            None => quote! { ::std::option::Option::None },
+        };
+        quote! {
+            (
+                #name_str,
+                <#ty as #support::ToolParameter>::json_schema(),
+                <#ty as #support::ToolParameter>::REQUIRED,
+                #param_description,
+            )
+        }
+    });
+    let extract_arguments = params.iter().map(|(name, ty)| {
+        let name_str = name.to_string();
+        quote! { let #name = #support::argument::<#ty>(__arguments, #name_str)?; }
+    });
+    let argument_names = params.iter().map(|(name, _)| name);
+
+    let vis = &function.vis;
+    let fn_name = &function.sig.ident;
+    let tool_fn_name = format_ident!("{}_tool", fn_name);
+    let tool_name = fn_name.to_string();
+    let tool_doc = format!("The tool calling [`{fn_name}`], see `#[nobodywho_tool]`.");
+
+    Ok(quote! {
+        #function
+
+        #[doc = #tool_doc]
+        #vis fn #tool_fn_name() -> ::nobodywho::tool_calling::Tool {
+            ::nobodywho::tool_calling::Tool::new(
+                #tool_name,
+                #description,
+                #support::object_schema(::std::vec![#(#schema_entries),*]),
+                #support::tool_function(move |__arguments| {
+                    #(#extract_arguments)*
+                    ::std::result::Result::Ok(::std::string::ToString::to_string(
+                        &#fn_name(#(#argument_names),*),
+                    ))
+                }),
+            )
+        }
+    })
+}
+
+// ---------------------------------------------------------------------------
+// Proc macro entry point
+// ---------------------------------------------------------------------------
+
+/// Make a `Tool` out of a typed Rust function.
+///
+/// Next to the function, this generates a function of the same visibility named
+/// `<name>_tool` that returns the tool. The function name is the tool name and its doc
+/// comment is the description shown to the model. The JSON schema of the arguments is
+/// generated from the parameter types, which must implement `ToolParameter`; `Option`
+/// parameters may be left out by the model. The arguments are deserialized before the
+/// function is called, and its return value is turned into the tool response with
+/// `ToString`.
+///
+/// Parameters can be described to the model with `params(...)`:
+///
+/// ```ignore
+/// use nobodywho::tool_calling::nobodywho_tool;
+///
+/// /// Get the current weather for a city
+/// #[nobodywho_tool(params(city = "The city name", days = "How many days to forecast"))]
+/// fn get_weather(city: String, days: Option<u32>) -> String {
+///     format!("Weather in {city} for {} days: sunny", days.unwrap_or(1))
+/// }
+///
+/// let chat = ChatBuilder::new(model).with_tool(get_weather_tool()).build()?;
+/// ```
+#[proc_macro_attribute]
+pub fn nobodywho_tool(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let args = syn::parse_macro_input!(attr as ToolArgs);
+    let function = syn::parse_macro_input!(item as ItemFn);
+    expand(args, function)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
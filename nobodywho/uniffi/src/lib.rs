@@ -106,11 +106,16 @@ fn core_message_to_uniffi(m: &nobodywho::chat::Message) -> Message {
                 })
                 .collect(),
         },
+        // the thinking has no field of its own here, so it goes back into the content
         nobodywho::chat::Message::Assistant {
             content,
+            reasoning_content,
             tool_calls,
         } => Message::Assistant {
-            content: content.clone(),
+            content: match reasoning_content {
+                Some(thinking) => nobodywho::chat::join_thinking(thinking, content),
+                None => content.clone(),
+            },
             tool_calls: tool_calls.as_ref().map(|tcs| {
                 tcs.iter()
                     .map(|tc| ToolCall {
@@ -161,8 +166,10 @@ fn uniffi_message_to_core(m: &Message) -> Result<nobodywho::chat::Message, Nobod
                         .collect::<Result<Vec<_>, NobodyWhoError>>()
                 })
                 .transpose()?;
+            let (reasoning_content, content) = nobodywho::chat::split_thinking(content);
             Ok(nobodywho::chat::Message::Assistant {
-                content: content.clone(),
+                content: content.to_string(),
+                reasoning_content: reasoning_content.map(str::to_string),
                 tool_calls: tcs,
             })
         }